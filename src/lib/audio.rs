@@ -0,0 +1,240 @@
+// Copyright (c) 2014 Richard Diamond & contributors.
+//
+// This file is part of the Rust PPApi project.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Audio output, via `PPB_Audio` 1.1 or 1.0, and configuration queries. The queries can
+//! be used to size buffers before any audio resources are created.
+
+use std::cmp;
+use std::slice;
+use std::sync::{Arc, Mutex};
+
+use libc::c_void;
+
+use ffi;
+use ppb::{get_audio_config, AudioIf, AudioConfigIf};
+
+use super::{Instance, Resource, TimeDelta};
+
+/// The smallest sample frame count PPAPI will accept.
+pub const MIN_SAMPLE_FRAME_COUNT: u32 = ffi::PP_AUDIOMINSAMPLEFRAMECOUNT;
+/// The largest sample frame count PPAPI will accept.
+pub const MAX_SAMPLE_FRAME_COUNT: u32 = ffi::PP_AUDIOMAXSAMPLEFRAMECOUNT;
+
+#[derive(Hash, Eq, PartialEq, Debug, Copy, Clone)]
+pub enum SampleRate {
+    Rate44100,
+    Rate48000,
+}
+impl SampleRate {
+    #[doc(hidden)]
+    pub fn from_ffi(v: ffi::PP_AudioSampleRate) -> Option<SampleRate> {
+        match v {
+            ffi::PP_AUDIOSAMPLERATE_44100 => Some(SampleRate::Rate44100),
+            ffi::PP_AUDIOSAMPLERATE_48000 => Some(SampleRate::Rate48000),
+            _ => None,
+        }
+    }
+    #[doc(hidden)]
+    pub fn to_ffi(self) -> ffi::PP_AudioSampleRate {
+        match self {
+            SampleRate::Rate44100 => ffi::PP_AUDIOSAMPLERATE_44100,
+            SampleRate::Rate48000 => ffi::PP_AUDIOSAMPLERATE_48000,
+        }
+    }
+    /// The rate in Hz.
+    pub fn hz(self) -> u32 {
+        self.to_ffi() as u32
+    }
+}
+
+/// Clamp `count` into the range PPAPI accepts for sample frame counts.
+pub fn clamp_sample_frame_count(count: u32) -> u32 {
+    cmp::min(cmp::max(count, MIN_SAMPLE_FRAME_COUNT), MAX_SAMPLE_FRAME_COUNT)
+}
+
+/// Returns the sample frame count the browser recommends for the given rate,
+/// as close to `requested` as possible. `requested` is first clamped to
+/// `[MIN_SAMPLE_FRAME_COUNT, MAX_SAMPLE_FRAME_COUNT]`, so the result is always
+/// a valid count.
+pub fn recommend_sample_frame_count(instance: &Instance,
+                                    sample_rate: SampleRate,
+                                    requested: u32) -> u32 {
+    let requested = clamp_sample_frame_count(requested);
+    let count = get_audio_config()
+        .recommend_sample_frame_count(instance.unwrap(),
+                                      sample_rate.to_ffi(),
+                                      requested);
+    clamp_sample_frame_count(count)
+}
+
+/// Returns the sample rate of the audio output device, or 44100 Hz if the
+/// browser can't tell us.
+pub fn recommend_sample_rate(instance: &Instance) -> SampleRate {
+    let rate = get_audio_config()
+        .recommend_sample_rate(instance.unwrap());
+    SampleRate::from_ffi(rate)
+        .unwrap_or(SampleRate::Rate44100)
+}
+
+/// A sample rate and buffer size for `Audio`. Always stereo, 16 bit.
+#[derive(Hash, Eq, PartialEq, Debug)] pub struct AudioConfig(ffi::PP_Resource);
+impl_resource_for!(AudioConfig, ResourceType::AudioConfig);
+impl_clone_drop_for!(AudioConfig);
+
+impl AudioConfig {
+    /// `sample_frame_count` should come from `recommend_sample_frame_count`.
+    /// `None` if the browser rejects the combination.
+    pub fn new_stereo_16bit(instance: &Instance, sample_rate: SampleRate,
+                            sample_frame_count: u32) -> Option<AudioConfig> {
+        get_audio_config()
+            .create_stereo_16bit(instance.unwrap(), sample_rate.to_ffi(),
+                                 sample_frame_count)
+            .map(|res| AudioConfig::new(res) )
+    }
+    pub fn sample_rate(&self) -> Option<SampleRate> {
+        SampleRate::from_ffi(get_audio_config().get_sample_rate(self.unwrap()))
+    }
+    pub fn sample_frame_count(&self) -> u32 {
+        get_audio_config().get_sample_frame_count(self.unwrap())
+    }
+}
+
+struct AudioCallback {
+    f: Box<FnMut(&mut [i16], TimeDelta) + Send>,
+    latency: Arc<Mutex<Option<TimeDelta>>>,
+}
+
+extern "C" fn audio_callback(samples: *mut c_void, size: u32, latency: ffi::PP_TimeDelta,
+                             user_data: *mut c_void) {
+    let callback = unsafe { &mut *(user_data as *mut AudioCallback) };
+    // Never wait on the main thread here; a stale latency is fine.
+    if let Ok(mut last) = callback.latency.try_lock() {
+        *last = Some(latency);
+    }
+    let samples = unsafe {
+        slice::from_raw_parts_mut(samples as *mut i16, size as usize / 2)
+    };
+    (callback.f)(samples, latency);
+}
+// `PPB_Audio;1.0` doesn't give a latency, so the callback gets zero and
+// `get_output_latency` stays `None`.
+extern "C" fn audio_callback_1_0(samples: *mut c_void, size: u32, user_data: *mut c_void) {
+    let callback = unsafe { &mut *(user_data as *mut AudioCallback) };
+    let samples = unsafe {
+        slice::from_raw_parts_mut(samples as *mut i16, size as usize / 2)
+    };
+    (callback.f)(samples, 0.0);
+}
+
+#[derive(Copy, Clone)]
+enum Interface {
+    V1_1(&'static ::ppb::Audio),
+    V1_0(&'static ffi::Struct_PPB_Audio_1_0),
+}
+impl Interface {
+    fn get_current_config(self, audio: ffi::PP_Resource) -> Option<ffi::PP_Resource> {
+        match self {
+            Interface::V1_1(i) => i.get_current_config(audio),
+            Interface::V1_0(i) => i.get_current_config(audio),
+        }
+    }
+    fn start_playback(self, audio: ffi::PP_Resource) -> bool {
+        match self {
+            Interface::V1_1(i) => i.start_playback(audio),
+            Interface::V1_0(i) => i.start_playback(audio),
+        }
+    }
+    fn stop_playback(self, audio: ffi::PP_Resource) -> bool {
+        match self {
+            Interface::V1_1(i) => i.stop_playback(audio),
+            Interface::V1_0(i) => i.stop_playback(audio),
+        }
+    }
+}
+
+/// Plays sound produced by a callback, which PPAPI calls on its own audio
+/// thread with interleaved stereo samples to fill, and the latency from
+/// then until the samples are heard. Not `Clone`, since it owns the
+/// callback; dropping it stops playback.
+pub struct Audio {
+    res: ffi::PP_Resource,
+    interface: Interface,
+    playing: bool,
+    latency: Arc<Mutex<Option<TimeDelta>>>,
+    // Only touched by the audio thread while the resource lives.
+    _callback: Box<AudioCallback>,
+}
+impl Audio {
+    /// Creates a stopped output. `None` if the browser doesn't provide
+    /// `PPB_Audio` or rejects `config`. With only `PPB_Audio;1.0`, the
+    /// callback is always given a latency of zero.
+    pub fn new<F>(instance: &Instance, config: &AudioConfig, callback: F) -> Option<Audio>
+        where F: FnMut(&mut [i16], TimeDelta) + Send + 'static
+    {
+        use ppb::{get_audio_opt, get_audio_1_0_opt};
+
+        let latency = Arc::new(Mutex::new(None));
+        let mut data = box AudioCallback {
+            f: box callback,
+            latency: latency.clone(),
+        };
+        let user_data = &mut *data as *mut AudioCallback as *mut c_void;
+        let (interface, res) = if let Some(i) = get_audio_opt() {
+            (Interface::V1_1(i),
+             i.create(instance.unwrap(), config.unwrap(), Some(audio_callback), user_data))
+        } else if let Some(i) = get_audio_1_0_opt() {
+            (Interface::V1_0(i),
+             i.create(instance.unwrap(), config.unwrap(), Some(audio_callback_1_0), user_data))
+        } else {
+            return None;
+        };
+        res.map(move |res| Audio {
+            res: res,
+            interface: interface,
+            playing: false,
+            latency: latency,
+            _callback: data,
+        })
+    }
+
+    /// The config `self` was created with.
+    pub fn get_current_config(&self) -> AudioConfig {
+        let res = self.interface
+            .get_current_config(self.res)
+            .expect("audio resource without a config");
+        AudioConfig::new(res)
+    }
+    /// Returns false if playback couldn't be started.
+    pub fn start(&mut self) -> bool {
+        if self.playing { return true; }
+        self.playing = self.interface.start_playback(self.res);
+        self.playing
+    }
+    /// Returns false if playback couldn't be stopped.
+    pub fn stop(&mut self) -> bool {
+        if !self.playing { return true; }
+        let stopped = self.interface.stop_playback(self.res);
+        if stopped { self.playing = false; }
+        stopped
+    }
+    /// Whether `start` was the last to succeed. The browser never stops
+    /// playback by itself, so this is the real state.
+    pub fn is_playing(&self) -> bool { self.playing }
+    /// The latency PPAPI last gave the callback, for A/V sync. `None` until
+    /// the callback has been called, and always with `PPB_Audio;1.0`.
+    pub fn get_output_latency(&self) -> Option<TimeDelta> {
+        *self.latency.lock().unwrap()
+    }
+}
+impl Drop for Audio {
+    fn drop(&mut self) {
+        // The audio thread is joined before this returns, so the callback
+        // can be freed after.
+        (::ppb::get_core().ReleaseResource.unwrap())(self.res);
+    }
+}
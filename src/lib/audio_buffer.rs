@@ -0,0 +1,88 @@
+// Copyright (c) 2014 Richard Diamond & contributors.
+//
+// This file is part of the Rust PPApi project.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::cmp;
+use std::mem;
+
+use ffi;
+use ppb::{get_audio_buffer, get_core, AudioBufferIf, ResourceInterface};
+
+use {TimeDelta, Resource};
+
+/// A buffer of samples from an `AudioTrack`. Deliberately not `Clone`: see
+/// `AudioTrack::recycle_buffer`.
+#[derive(Hash, Eq, PartialEq, Debug)] pub struct AudioBuffer(ffi::PP_Resource);
+impl_resource_for!(AudioBuffer, ResourceType::AudioBuffer);
+impl Drop for AudioBuffer {
+    fn drop(&mut self) {
+        (get_core().ReleaseResource.unwrap())(self.unwrap());
+        track_resource!(AudioBuffer, -1);
+    }
+}
+
+#[doc(hidden)]
+impl From<ffi::PP_Resource> for AudioBuffer {
+    fn from(v: ffi::PP_Resource) -> AudioBuffer {
+        debug_assert!(get_audio_buffer().is(v));
+        AudioBuffer::new(v)
+    }
+}
+
+impl AudioBuffer {
+    pub fn get_timestamp(&self) -> TimeDelta {
+        get_audio_buffer()
+            .get_timestamp(self.unwrap())
+    }
+    pub fn set_timestamp(&self, ts: TimeDelta) {
+        get_audio_buffer()
+            .set_timestamp(self.unwrap(), ts)
+    }
+    /// In Hz. `None` if unknown.
+    pub fn sample_rate(&self) -> Option<u32> {
+        match get_audio_buffer().get_sample_rate(self.unwrap()) {
+            ffi::PP_AUDIOBUFFER_SAMPLERATE_UNKNOWN => None,
+            rate => Some(rate as u32),
+        }
+    }
+    /// In bytes. `None` if unknown.
+    pub fn sample_size(&self) -> Option<u32> {
+        match get_audio_buffer().get_sample_size(self.unwrap()) {
+            ffi::PP_AUDIOBUFFER_SAMPLESIZE_UNKNOWN => None,
+            size => Some(size as u32),
+        }
+    }
+    pub fn number_of_channels(&self) -> u32 {
+        get_audio_buffer()
+            .get_number_of_channels(self.unwrap())
+    }
+    /// Per channel.
+    pub fn number_of_samples(&self) -> u32 {
+        get_audio_buffer()
+            .get_number_of_samples(self.unwrap())
+    }
+    /// Calls `f` with the samples, channels interleaved. The slice holds
+    /// `number_of_channels() * number_of_samples()` samples, or fewer if the
+    /// data buffer is short. Returns `None` without calling `f` if the
+    /// samples aren't 16 bit.
+    pub fn with_samples<F, R>(&self, f: F) -> Option<R>
+        where F: FnOnce(&[i16]) -> R,
+    {
+        use std::slice::from_raw_parts;
+
+        if self.sample_size() != Some(mem::size_of::<i16>() as u32) { return None; }
+
+        let b = get_audio_buffer();
+        let data = b.get_data_buffer(self.unwrap());
+        if data.is_null() || data as usize % mem::align_of::<i16>() != 0 { return None; }
+
+        let expected = self.number_of_channels() as usize * self.number_of_samples() as usize;
+        let available = b.get_data_buffer_size(self.unwrap()) / mem::size_of::<i16>();
+        let len = cmp::min(expected, available);
+        Some(f(unsafe { from_raw_parts(data as *const i16, len) }))
+    }
+}
@@ -0,0 +1,266 @@
+// Copyright (c) 2014 Richard Diamond & contributors.
+//
+// This file is part of the Rust PPApi project.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Fonts via `PPB_BrowserFont_Trusted`, which is only exposed to trusted
+//! plugins. `AnyFont` and `get_font_families` use it when the browser
+//! provides it and quietly fall back to the dev font interface otherwise.
+
+use std::mem;
+use std::ptr;
+
+use super::{ppb, ffi};
+use super::{ToVar, ToStringVar, Resource, ToFFIBool, StringVar, Instance};
+use super::{Point, Rect};
+use super::ffi::{Struct_PP_BrowserFont_Trusted_Description,
+                 Struct_PP_BrowserFont_Trusted_Metrics,
+                 Struct_PP_BrowserFont_Trusted_TextRun,
+                 Struct_PP_FontDescription_Dev};
+use font::{self, Description, Metrics, FontLike};
+use imagedata;
+
+/// Returns true if the browser exposes `PPB_BrowserFont_Trusted` to us.
+pub fn is_available() -> bool {
+    ppb::get_browser_font_opt().is_some()
+}
+
+/// Lists the font families available, using the trusted interface if
+/// present and the dev font interface otherwise.
+pub fn get_font_families(instance: &Instance) -> Vec<StringVar> {
+    let families = match ppb::get_browser_font_opt() {
+        Some(interface) => (interface.GetFontFamilies.unwrap())(instance.unwrap()),
+        None => (ppb::get_font().GetFontFamilies.unwrap())(instance.unwrap()),
+    };
+    let families = StringVar::new_from_var(families);
+    families
+        .split('\0')
+        .filter(|family| !family.is_empty() )
+        .map(|family| StringVar::new(family) )
+        .collect()
+}
+
+// The trusted structs are field for field identical to their dev
+// counterparts, enum values included.
+unsafe fn description_to_ffi(desc: &Description) -> Struct_PP_BrowserFont_Trusted_Description {
+    mem::transmute(desc.to_ffi())
+}
+fn text_run<TStr: ToStringVar + ToVar>(text: &TStr,
+                                       rtl: bool,
+                                       override_direction: bool) ->
+    Struct_PP_BrowserFont_Trusted_TextRun
+{
+    Struct_PP_BrowserFont_Trusted_TextRun {
+        text: text.to_var(),
+        rtl: rtl.to_ffi_bool(),
+        override_direction: override_direction.to_ffi_bool(),
+    }
+}
+
+#[derive(Hash, Eq, PartialEq, Debug)] pub struct BrowserFont(ffi::PP_Resource);
+impl_resource_for!(BrowserFont, ResourceType::BrowserFont);
+impl_clone_drop_for!(BrowserFont);
+
+impl BrowserFont {
+    /// Returns `None` if the trusted interface isn't available or the browser
+    /// couldn't match `desc`.
+    pub fn create(instance: &Instance, desc: &Description) -> Option<BrowserFont> {
+        let interface = match ppb::get_browser_font_opt() {
+            Some(interface) => interface,
+            None => return None,
+        };
+        let desc = unsafe { description_to_ffi(desc) };
+        let res = (interface.Create.unwrap())
+            (instance.unwrap(),
+             &desc as *const Struct_PP_BrowserFont_Trusted_Description);
+        if res != 0 {
+            Some(BrowserFont::new(res))
+        } else {
+            None
+        }
+    }
+}
+impl FontLike for BrowserFont {
+    fn describe(&self) -> Option<(Description, Metrics)> {
+        let mut desc = Struct_PP_BrowserFont_Trusted_Description {
+            face: {super::NullVar}.to_var(),
+            .. unsafe { mem::uninitialized() }
+        };
+        let mut metr: Struct_PP_BrowserFont_Trusted_Metrics = unsafe { mem::uninitialized() };
+
+        match (ppb::get_browser_font().Describe.unwrap())
+            (self.unwrap(),
+             &mut desc as *mut Struct_PP_BrowserFont_Trusted_Description,
+             &mut metr as *mut Struct_PP_BrowserFont_Trusted_Metrics) {
+            0 => None,
+            _ => {
+                let desc: Struct_PP_FontDescription_Dev = unsafe { mem::transmute(desc) };
+                Some((Description::new_from_ffi(desc),
+                      unsafe { mem::transmute(metr) }))
+            }
+        }
+    }
+
+    fn measure_text<TStr: ToStringVar + ToVar>
+        (&self, text: &TStr, rtl: bool, override_direction: bool) -> Option<i32>
+    {
+        let text_run = text_run(text, rtl, override_direction);
+        let result = (ppb::get_browser_font().MeasureText.unwrap())
+            (self.unwrap(),
+             &text_run as *const Struct_PP_BrowserFont_Trusted_TextRun);
+        if result == -1 { None }
+        else            { Some(result) }
+    }
+
+    /// See `font::Font::draw_text`.
+    fn draw_text<TStr: ToStringVar + ToVar>
+        (&self,
+         image: &imagedata::ImageData,
+         text: &TStr,
+         rtl: bool,
+         override_direction: bool,
+         pos: Point,
+         color: u32,
+         clip: Option<Rect>,
+         image_data_is_opaque: bool) -> bool
+    {
+        let text_run = text_run(text, rtl, override_direction);
+        let pos: ffi::PP_Point = pos.into();
+        let clip: Option<ffi::PP_Rect> = clip.map(|c| c.into() );
+        let clip_ptr = if clip.is_some() {
+            clip.as_ref().unwrap() as *const _
+        } else {
+            ptr::null()
+        };
+        (ppb::get_browser_font().DrawTextAt.unwrap())
+            (self.unwrap(),
+             image.unwrap(),
+             &text_run as *const Struct_PP_BrowserFont_Trusted_TextRun,
+             &pos as *const ffi::PP_Point,
+             color,
+             clip_ptr,
+             image_data_is_opaque.to_ffi_bool()) != ffi::PP_FALSE
+    }
+
+    fn char_offset_for_pixel<TStr: ToStringVar + ToVar>
+        (&self, text: &TStr, rtl: bool, override_direction: bool,
+         position: i32) -> Option<u32>
+    {
+        let text_run = text_run(text, rtl, override_direction);
+        let result = (ppb::get_browser_font().CharacterOffsetForPixel.unwrap())
+            (self.unwrap(),
+             &text_run as *const Struct_PP_BrowserFont_Trusted_TextRun,
+             position);
+        if result as i32 == -1 { None }
+        else                   { Some(result) }
+    }
+
+    fn pixel_offset_for_character<TStr: ToStringVar + ToVar>
+        (&self, text: &TStr, rtl: bool, override_direction: bool,
+         char_offset: u32) -> Option<i32>
+    {
+        let text_run = text_run(text, rtl, override_direction);
+        let result = (ppb::get_browser_font().PixelOffsetForCharacter.unwrap())
+            (self.unwrap(),
+             &text_run as *const Struct_PP_BrowserFont_Trusted_TextRun,
+             char_offset);
+        if result == -1 { None }
+        else            { Some(result) }
+    }
+}
+
+/// A font backed by whichever font interface the browser provides.
+#[derive(Clone, Hash, Eq, PartialEq, Debug)]
+pub enum AnyFont {
+    Trusted(BrowserFont),
+    Dev(font::Font),
+}
+impl AnyFont {
+    /// Creates a font through `PPB_BrowserFont_Trusted` if it's available,
+    /// otherwise through `PPB_Font(Dev)`.
+    pub fn create(instance: &Instance, desc: &Description) -> Option<AnyFont> {
+        if is_available() {
+            BrowserFont::create(instance, desc)
+                .map(|f| AnyFont::Trusted(f) )
+        } else {
+            instance.create_font(desc)
+                .map(|f| AnyFont::Dev(f) )
+        }
+    }
+}
+impl Resource for AnyFont {
+    fn unwrap(&self) -> ffi::PP_Resource {
+        match self {
+            &AnyFont::Trusted(ref f) => f.unwrap(),
+            &AnyFont::Dev(ref f) => f.unwrap(),
+        }
+    }
+    fn type_of(&self) -> Option<super::ResourceType> {
+        match self {
+            &AnyFont::Trusted(ref f) => f.type_of(),
+            &AnyFont::Dev(ref f) => f.type_of(),
+        }
+    }
+}
+impl FontLike for AnyFont {
+    fn describe(&self) -> Option<(Description, Metrics)> {
+        match self {
+            &AnyFont::Trusted(ref f) => f.describe(),
+            &AnyFont::Dev(ref f) => FontLike::describe(f),
+        }
+    }
+    fn measure_text<TStr: ToStringVar + ToVar>
+        (&self, text: &TStr, rtl: bool, override_direction: bool) -> Option<i32>
+    {
+        match self {
+            &AnyFont::Trusted(ref f) => f.measure_text(text, rtl, override_direction),
+            &AnyFont::Dev(ref f) => FontLike::measure_text(f, text, rtl, override_direction),
+        }
+    }
+    fn draw_text<TStr: ToStringVar + ToVar>
+        (&self,
+         image: &imagedata::ImageData,
+         text: &TStr,
+         rtl: bool,
+         override_direction: bool,
+         pos: Point,
+         color: u32,
+         clip: Option<Rect>,
+         image_data_is_opaque: bool) -> bool
+    {
+        match self {
+            &AnyFont::Trusted(ref f) =>
+                f.draw_text(image, text, rtl, override_direction,
+                            pos, color, clip, image_data_is_opaque),
+            &AnyFont::Dev(ref f) =>
+                FontLike::draw_text(f, image, text, rtl, override_direction,
+                                    pos, color, clip, image_data_is_opaque),
+        }
+    }
+    fn char_offset_for_pixel<TStr: ToStringVar + ToVar>
+        (&self, text: &TStr, rtl: bool, override_direction: bool,
+         position: i32) -> Option<u32>
+    {
+        match self {
+            &AnyFont::Trusted(ref f) =>
+                f.char_offset_for_pixel(text, rtl, override_direction, position),
+            &AnyFont::Dev(ref f) =>
+                FontLike::char_offset_for_pixel(f, text, rtl, override_direction, position),
+        }
+    }
+    fn pixel_offset_for_character<TStr: ToStringVar + ToVar>
+        (&self, text: &TStr, rtl: bool, override_direction: bool,
+         char_offset: u32) -> Option<i32>
+    {
+        match self {
+            &AnyFont::Trusted(ref f) =>
+                f.pixel_offset_for_character(text, rtl, override_direction, char_offset),
+            &AnyFont::Dev(ref f) =>
+                FontLike::pixel_offset_for_character(f, text, rtl, override_direction,
+                                                     char_offset),
+        }
+    }
+}
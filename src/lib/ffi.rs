@@ -7342,6 +7342,196 @@ impl ::std::default::Default for Struct_PPP_Printing_Dev_0_6 {
     fn default() -> Self { unsafe { ::std::mem::zeroed() } }
 }
 pub type PPP_Printing_Dev = Struct_PPP_Printing_Dev_0_6;
+pub type Enum_Unnamed48 = ::libc::c_uint;
+pub const PP_BROWSERFONT_TRUSTED_FAMILY_DEFAULT: ::libc::c_uint = 0;
+pub const PP_BROWSERFONT_TRUSTED_FAMILY_SERIF: ::libc::c_uint = 1;
+pub const PP_BROWSERFONT_TRUSTED_FAMILY_SANSSERIF: ::libc::c_uint = 2;
+pub const PP_BROWSERFONT_TRUSTED_FAMILY_MONOSPACE: ::libc::c_uint = 3;
+pub type PP_BrowserFont_Trusted_Family = Enum_Unnamed48;
+pub type Enum_Unnamed49 = ::libc::c_uint;
+pub const PP_BROWSERFONT_TRUSTED_WEIGHT_100: ::libc::c_uint = 0;
+pub const PP_BROWSERFONT_TRUSTED_WEIGHT_200: ::libc::c_uint = 1;
+pub const PP_BROWSERFONT_TRUSTED_WEIGHT_300: ::libc::c_uint = 2;
+pub const PP_BROWSERFONT_TRUSTED_WEIGHT_400: ::libc::c_uint = 3;
+pub const PP_BROWSERFONT_TRUSTED_WEIGHT_500: ::libc::c_uint = 4;
+pub const PP_BROWSERFONT_TRUSTED_WEIGHT_600: ::libc::c_uint = 5;
+pub const PP_BROWSERFONT_TRUSTED_WEIGHT_700: ::libc::c_uint = 6;
+pub const PP_BROWSERFONT_TRUSTED_WEIGHT_800: ::libc::c_uint = 7;
+pub const PP_BROWSERFONT_TRUSTED_WEIGHT_900: ::libc::c_uint = 8;
+pub const PP_BROWSERFONT_TRUSTED_WEIGHT_NORMAL: ::libc::c_uint = 3;
+pub const PP_BROWSERFONT_TRUSTED_WEIGHT_BOLD: ::libc::c_uint = 6;
+pub type PP_BrowserFont_Trusted_Weight = Enum_Unnamed49;
+#[repr(C)]
+#[derive(Copy)]
+pub struct Struct_PP_BrowserFont_Trusted_Description {
+    pub face: Struct_PP_Var,
+    pub family: PP_BrowserFont_Trusted_Family,
+    pub size: uint32_t,
+    pub weight: PP_BrowserFont_Trusted_Weight,
+    pub italic: PP_Bool,
+    pub small_caps: PP_Bool,
+    pub letter_spacing: int32_t,
+    pub word_spacing: int32_t,
+    pub padding: int32_t,
+}
+impl ::std::clone::Clone for Struct_PP_BrowserFont_Trusted_Description {
+    fn clone(&self) -> Self { *self }
+}
+impl ::std::default::Default for Struct_PP_BrowserFont_Trusted_Description {
+    fn default() -> Self { unsafe { ::std::mem::zeroed() } }
+}
+#[repr(C)]
+#[derive(Copy)]
+pub struct Struct_PP_BrowserFont_Trusted_Metrics {
+    pub height: int32_t,
+    pub ascent: int32_t,
+    pub descent: int32_t,
+    pub line_spacing: int32_t,
+    pub x_height: int32_t,
+}
+impl ::std::clone::Clone for Struct_PP_BrowserFont_Trusted_Metrics {
+    fn clone(&self) -> Self { *self }
+}
+impl ::std::default::Default for Struct_PP_BrowserFont_Trusted_Metrics {
+    fn default() -> Self { unsafe { ::std::mem::zeroed() } }
+}
+#[repr(C)]
+#[derive(Copy)]
+pub struct Struct_PP_BrowserFont_Trusted_TextRun {
+    pub text: Struct_PP_Var,
+    pub rtl: PP_Bool,
+    pub override_direction: PP_Bool,
+}
+impl ::std::clone::Clone for Struct_PP_BrowserFont_Trusted_TextRun {
+    fn clone(&self) -> Self { *self }
+}
+impl ::std::default::Default for Struct_PP_BrowserFont_Trusted_TextRun {
+    fn default() -> Self { unsafe { ::std::mem::zeroed() } }
+}
+#[repr(C)]
+#[derive(Copy)]
+pub struct Struct_PPB_BrowserFont_Trusted_1_0 {
+    pub GetFontFamilies: ::std::option::Option<extern "C" fn(instance:
+                                                                 PP_Instance)
+                                                   -> Struct_PP_Var>,
+    pub Create: ::std::option::Option<extern "C" fn(instance: PP_Instance,
+                                                    description:
+                                                        *const Struct_PP_BrowserFont_Trusted_Description)
+                                          -> PP_Resource>,
+    pub IsFont: ::std::option::Option<extern "C" fn(resource: PP_Resource)
+                                          -> PP_Bool>,
+    pub Describe: ::std::option::Option<extern "C" fn(font: PP_Resource,
+                                                      description:
+                                                          *mut Struct_PP_BrowserFont_Trusted_Description,
+                                                      metrics:
+                                                          *mut Struct_PP_BrowserFont_Trusted_Metrics)
+                                            -> PP_Bool>,
+    pub DrawTextAt: ::std::option::Option<extern "C" fn(font: PP_Resource,
+                                                        image_data:
+                                                            PP_Resource,
+                                                        text:
+                                                            *const Struct_PP_BrowserFont_Trusted_TextRun,
+                                                        position:
+                                                            *const Struct_PP_Point,
+                                                        color: uint32_t,
+                                                        clip:
+                                                            *const Struct_PP_Rect,
+                                                        image_data_is_opaque:
+                                                            PP_Bool)
+                                              -> PP_Bool>,
+    pub MeasureText: ::std::option::Option<extern "C" fn(font: PP_Resource,
+                                                         text:
+                                                             *const Struct_PP_BrowserFont_Trusted_TextRun)
+                                               -> int32_t>,
+    pub CharacterOffsetForPixel: ::std::option::Option<extern "C" fn(font:
+                                                                         PP_Resource,
+                                                                     text:
+                                                                         *const Struct_PP_BrowserFont_Trusted_TextRun,
+                                                                     pixel_position:
+                                                                         int32_t)
+                                                           -> uint32_t>,
+    pub PixelOffsetForCharacter: ::std::option::Option<extern "C" fn(font:
+                                                                         PP_Resource,
+                                                                     text:
+                                                                         *const Struct_PP_BrowserFont_Trusted_TextRun,
+                                                                     char_offset:
+                                                                         uint32_t)
+                                                           -> int32_t>,
+}
+impl ::std::clone::Clone for Struct_PPB_BrowserFont_Trusted_1_0 {
+    fn clone(&self) -> Self { *self }
+}
+impl ::std::default::Default for Struct_PPB_BrowserFont_Trusted_1_0 {
+    fn default() -> Self { unsafe { ::std::mem::zeroed() } }
+}
+pub type PPB_BrowserFont_Trusted = Struct_PPB_BrowserFont_Trusted_1_0;
+#[repr(C)]
+#[derive(Copy)]
+pub struct Struct_PPB_UMA_Private_3_0 {
+    pub HistogramCustomTimes: ::std::option::Option<extern "C" fn(instance:
+                                                                      PP_Instance,
+                                                                  name:
+                                                                      Struct_PP_Var,
+                                                                  sample:
+                                                                      int64_t,
+                                                                  min: int64_t,
+                                                                  max: int64_t,
+                                                                  bucket_count:
+                                                                      uint32_t)>,
+    pub HistogramCustomCounts: ::std::option::Option<extern "C" fn(instance:
+                                                                       PP_Instance,
+                                                                   name:
+                                                                       Struct_PP_Var,
+                                                                   sample:
+                                                                       int32_t,
+                                                                   min: int32_t,
+                                                                   max: int32_t,
+                                                                   bucket_count:
+                                                                       uint32_t)>,
+    pub HistogramEnumeration: ::std::option::Option<extern "C" fn(instance:
+                                                                      PP_Instance,
+                                                                  name:
+                                                                      Struct_PP_Var,
+                                                                  sample:
+                                                                      int32_t,
+                                                                  boundary_value:
+                                                                      int32_t)>,
+    pub IsCrashReportingEnabled: ::std::option::Option<extern "C" fn(instance:
+                                                                         PP_Instance,
+                                                                     callback:
+                                                                         Struct_PP_CompletionCallback)
+                                                           -> int32_t>,
+}
+impl ::std::clone::Clone for Struct_PPB_UMA_Private_3_0 {
+    fn clone(&self) -> Self { *self }
+}
+impl ::std::default::Default for Struct_PPB_UMA_Private_3_0 {
+    fn default() -> Self { unsafe { ::std::mem::zeroed() } }
+}
+pub type PPB_UMA_Private = Struct_PPB_UMA_Private_3_0;
+pub type PP_IsolatedFileSystemType_Private = ::libc::c_uint;
+pub const PP_ISOLATEDFILESYSTEMTYPE_PRIVATE_INVALID: ::libc::c_uint = 0;
+pub const PP_ISOLATEDFILESYSTEMTYPE_PRIVATE_CRX: ::libc::c_uint = 1;
+pub const PP_ISOLATEDFILESYSTEMTYPE_PRIVATE_PLUGINPRIVATE: ::libc::c_uint = 2;
+#[repr(C)]
+#[derive(Copy)]
+pub struct Struct_PPB_IsolatedFileSystem_Private_0_2 {
+    pub Open: ::std::option::Option<extern "C" fn(instance: PP_Instance,
+                                                  _type:
+                                                      PP_IsolatedFileSystemType_Private,
+                                                  file_system:
+                                                      *mut PP_Resource,
+                                                  callback:
+                                                      Struct_PP_CompletionCallback)
+                                        -> int32_t>,
+}
+impl ::std::clone::Clone for Struct_PPB_IsolatedFileSystem_Private_0_2 {
+    fn clone(&self) -> Self { *self }
+}
+impl ::std::default::Default for Struct_PPB_IsolatedFileSystem_Private_0_2 {
+    fn default() -> Self { unsafe { ::std::mem::zeroed() } }
+}
+pub type PPB_IsolatedFileSystem_Private = Struct_PPB_IsolatedFileSystem_Private_0_2;
 pub type u_int8_t = __uint8_t;
 pub type u_int16_t = __uint16_t;
 pub type u_int32_t = __uint32_t;
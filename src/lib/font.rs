@@ -11,7 +11,7 @@ use std::ptr;
 use std::collections::HashSet;
 
 use super::{ppb, ffi};
-use super::{ToVar, Resource, ToFFIBool};
+use super::{ToVar, Resource, ToFFIBool, View};
 use super::ffi::{Struct_PP_FontMetrics_Dev, Struct_PP_TextRun_Dev,
                  Struct_PP_FontDescription_Dev, PP_FontFamily_Dev};
 use super::StringVar;
@@ -84,6 +84,16 @@ impl Weight {
         }
     }
 }
+/// The size in device pixels of a `css_size` pixel font at `scale` device
+/// pixels per CSS pixel, rounded to the nearest pixel. See
+/// `View::scale_font_size`.
+pub fn scale_font_size(css_size: u32, scale: f32) -> u32 {
+    if scale == 1.0 { return css_size; }
+    let scaled = css_size as f64 * scale as f64;
+    if scaled <= 0.0 { return 0; }
+    scaled.round() as u32
+}
+
 pub type Metrics = ffi::Struct_PP_FontMetrics_Dev;
 fn new_metrics_from_ffi(metrics: ffi::Struct_PP_FontMetrics_Dev) -> Metrics {
     metrics
@@ -115,7 +125,33 @@ impl Description {
         }
     }
 
-    fn new_from_ffi(v: Struct_PP_FontDescription_Dev) -> Description {
+    /// Asks for the face named `face`, eg `"Helvetica"`. `family` is used
+    /// if the face isn't available, though whether it is is up to the
+    /// browser.
+    pub fn face(mut self, face: &str) -> Description {
+        self.face = Some(StringVar::new(face));
+        self
+    }
+    pub fn get_face(&self) -> Option<&StringVar> {
+        self.face.as_ref()
+    }
+
+    /// The size in device pixels.
+    pub fn size(mut self, size: u32) -> Description {
+        self.size = size;
+        self
+    }
+    /// Sets the size from a CSS pixel size, scaled for `view` so the font is
+    /// crisp on high DPI screens.
+    pub fn size_css(self, css_size: u32, view: &View) -> Description {
+        self.size(view.scale_font_size(css_size))
+    }
+    pub fn get_size(&self) -> u32 {
+        self.size
+    }
+
+    #[doc(hidden)]
+    pub fn new_from_ffi(v: Struct_PP_FontDescription_Dev) -> Description {
         Description {
             face: Some(StringVar::new_from_var(v.face)),
             family: Family::new_from_ffi(v.family),
@@ -136,6 +172,8 @@ impl Description {
         desc.italic = self.italic.to_ffi_bool();
         desc.small_caps = self.small_caps.to_ffi_bool();
         desc.letter_spacing = self.letter_spacing;
+        desc.word_spacing = self.word_spacing;
+        desc.padding = 0;
         desc
     }
 }
@@ -281,6 +319,68 @@ impl Font {
         else                   { Some(result) }
     }
 }
+/// Operations common to the dev font interface and
+/// `browser_font::BrowserFont`, so code drawing text needn't care which
+/// interface backs the font it was given.
+pub trait FontLike: Resource {
+    fn describe(&self) -> Option<(Description, Metrics)>;
+    fn measure_text<TStr: super::ToStringVar + super::ToVar>
+        (&self, text: &TStr, rtl: bool, override_direction: bool) -> Option<i32>;
+    fn draw_text<TStr: super::ToStringVar + super::ToVar>
+        (&self,
+         image: &imagedata::ImageData,
+         text: &TStr,
+         rtl: bool,
+         override_direction: bool,
+         pos: super::Point,
+         color: u32,
+         clip: Option<super::Rect>,
+         image_data_is_opaque: bool) -> bool;
+    fn char_offset_for_pixel<TStr: super::ToStringVar + super::ToVar>
+        (&self, text: &TStr, rtl: bool, override_direction: bool,
+         position: i32) -> Option<u32>;
+    fn pixel_offset_for_character<TStr: super::ToStringVar + super::ToVar>
+        (&self, text: &TStr, rtl: bool, override_direction: bool,
+         char_offset: u32) -> Option<i32>;
+}
+impl FontLike for Font {
+    fn describe(&self) -> Option<(Description, Metrics)> {
+        Font::describe(self)
+    }
+    fn measure_text<TStr: super::ToStringVar + super::ToVar>
+        (&self, text: &TStr, rtl: bool, override_direction: bool) -> Option<i32>
+    {
+        Font::measure_text(self, text, rtl, override_direction)
+    }
+    fn draw_text<TStr: super::ToStringVar + super::ToVar>
+        (&self,
+         image: &imagedata::ImageData,
+         text: &TStr,
+         rtl: bool,
+         override_direction: bool,
+         pos: super::Point,
+         color: u32,
+         clip: Option<super::Rect>,
+         image_data_is_opaque: bool) -> bool
+    {
+        Font::draw_text(self, image, text, rtl, override_direction,
+                        pos, color, clip, image_data_is_opaque)
+    }
+    fn char_offset_for_pixel<TStr: super::ToStringVar + super::ToVar>
+        (&self, text: &TStr, rtl: bool, override_direction: bool,
+         position: i32) -> Option<u32>
+    {
+        Font::char_offset_for_pixel(self, text, rtl, override_direction, position)
+    }
+    fn pixel_offset_for_character<TStr: super::ToStringVar + super::ToVar>
+        (&self, text: &TStr, rtl: bool, override_direction: bool,
+         char_offset: u32) -> Option<i32>
+    {
+        Font::pixel_offset_for_character(self, text, rtl, override_direction,
+                                         char_offset)
+    }
+}
+
 pub trait FontFamilies {
     fn get_font_families(&self) -> HashSet<String>;
 }
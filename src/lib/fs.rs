@@ -277,13 +277,15 @@ mod impl_ {
     use super::common::{AsyncRead, AsyncWrite, AsyncFile, AsyncPath,
                         AsyncCommon, AsyncStream, Info, OpenFlags, MkDirFlags,
                         DirectoryEntry, FileView, SyncCommon, SyncFile,
-                        SyncPath};
+                        SyncPath, Type};
     use super::super::{Result, Callback, CallbackArgs, Code,
                        Resource, StorageToArgsMapper,
                        InPlaceArrayOutputStorage, Time,
                        BlockUntilComplete, ResourceType};
 
     use std::io::{self, Seek, Read, Write};
+    use std::rc::Rc;
+    use std::cell::RefCell;
 
     #[derive(Hash, Eq, PartialEq, Debug)] pub struct FileSystem(ffi::PP_Resource);
     #[derive(Hash, Eq, PartialEq, Debug)] pub struct FileRef(ffi::PP_Resource);
@@ -332,7 +334,7 @@ mod impl_ {
         pub fn create<T: AsRef<path::Path>>(&self, path: T) -> Option<FileRef> {
             let cstr = format!("{}\0", path.as_ref().display());
             get_file_ref().create(self.unwrap(), cstr.as_ptr() as *const _)
-                .map(|r| FileRef(r) )
+                .map(|r| FileRef::new(r) )
         }
     }
 
@@ -589,6 +591,478 @@ mod impl_ {
 
 
     // FileRef
+    impl FileRef {
+        fn same_file_system_type(&self, other: &FileRef) -> bool {
+            let i = get_file_ref();
+            i.get_file_system_type(self.unwrap()) == i.get_file_system_type(other.unwrap())
+        }
+
+        /// Convenience function which doesn't require `AsyncPath` be in scope.
+        ///
+        /// Renaming across file systems isn't supported and results in
+        /// `Code::Failed`.
+        pub fn rename<F>(&self, to: &FileRef, callback: CallbackArgs<F, ()>) -> Code<()>
+            where F: FnOnce(Code<()>)
+        {
+            self.async_rename(to.clone(), callback)
+        }
+        /// Convenience function which doesn't require `AsyncPath` be in scope.
+        ///
+        /// PPAPI doesn't recurse: deleting a non-empty directory results in
+        /// `Code::Failed`. Remove the entries from `async_read_directory_entries`
+        /// first, depth first, then the directory itself.
+        pub fn delete<F>(&self, callback: CallbackArgs<F, ()>) -> Code<()>
+            where F: FnOnce(Code<()>)
+        {
+            self.async_delete(callback)
+        }
+        /// Convenience function which doesn't require `AsyncCommon` be in scope.
+        pub fn touch<F>(&self, atime: Time, mtime: Time,
+                        callback: CallbackArgs<F, ()>) -> Code<()>
+            where F: FnOnce(Code<()>)
+        {
+            self.async_touch(atime, mtime, callback)
+        }
+
+        /// Delete this file or directory, and, if a directory, everything in
+        /// it. Children are deleted depth first, before their parent. The first
+        /// failure aborts the traversal and is reported to `callback`.
+        /// Directories nested deeper than `MAX_RECURSIVE_DELETE_DEPTH` result in
+        /// `Code::Failed`.
+        pub fn delete_recursive<F>(&self, callback: CallbackArgs<F, ()>) -> Code<()>
+            where F: FnOnce(Code<()>) + 'static
+        {
+            let state = RecursiveDelete {
+                stack: vec!((self.clone(), 0, Pending::Unknown)),
+                callback: Some(callback),
+            };
+            let state = Rc::new(RefCell::new(state));
+            let code = recursive_delete_step(state.clone());
+            if !code.completion_pending() {
+                // Like other async ops, don't call `callback` when we fail
+                // before ever getting to PPAPI.
+                state.borrow_mut().callback.take();
+            }
+            code
+        }
+    }
+
+    /// The maximum directory depth `FileRef::delete_recursive` will descend to.
+    pub const MAX_RECURSIVE_DELETE_DEPTH: usize = 64;
+
+    #[derive(Clone, Copy, Eq, PartialEq)]
+    enum Pending {
+        Unknown,
+        File,
+        Dir,
+        EmptiedDir,
+    }
+    struct RecursiveDelete<F>
+        where F: FnOnce(Code<()>),
+    {
+        stack: Vec<(FileRef, usize, Pending)>,
+        callback: Option<CallbackArgs<F, ()>>,
+    }
+    fn recursive_delete_finish<F>(state: Rc<RefCell<RecursiveDelete<F>>>, code: Code<()>)
+        where F: FnOnce(Code<()>) + 'static
+    {
+        let callback = state.borrow_mut().callback.take();
+        if let Some(callback) = callback {
+            callback.call_directly(code);
+        }
+    }
+    fn recursive_delete_continue<F>(state: Rc<RefCell<RecursiveDelete<F>>>)
+        where F: FnOnce(Code<()>) + 'static
+    {
+        let code = recursive_delete_step(state.clone());
+        if !code.completion_pending() && !code.is_ok() {
+            recursive_delete_finish(state, code);
+        }
+    }
+    /// Starts the next operation. Returns `Code::CompletionPending` if one was
+    /// started, `Code::Ok` if there was nothing left to do.
+    fn recursive_delete_step<F>(state: Rc<RefCell<RecursiveDelete<F>>>) -> Code<()>
+        where F: FnOnce(Code<()>) + 'static
+    {
+        let next = state.borrow_mut().stack.pop();
+        let (file, depth, pending) = match next {
+            Some(next) => next,
+            None => {
+                recursive_delete_finish(state, Code::Ok(()));
+                return Code::Ok(());
+            }
+        };
+
+        match pending {
+            Pending::Unknown => {
+                let file2 = file.clone();
+                let cb = CallbackArgs::new(move |code: Code<Info>| {
+                    match code {
+                        Code::Ok(info) => {
+                            let pending = if info._type == ffi::PP_FILETYPE_DIRECTORY {
+                                Pending::Dir
+                            } else {
+                                Pending::File
+                            };
+                            state.borrow_mut().stack.push((file2, depth, pending));
+                            recursive_delete_continue(state);
+                        },
+                        code => recursive_delete_finish(state, code.map_err()),
+                    }
+                });
+                file.async_query(cb).map_ok(|_| () )
+            },
+            Pending::Dir if depth >= MAX_RECURSIVE_DELETE_DEPTH => Code::Failed,
+            Pending::Dir => {
+                state.borrow_mut().stack.push((file.clone(), depth, Pending::EmptiedDir));
+                let cb = CallbackArgs::new(move |code: Code<Vec<DirectoryEntry>>| {
+                    match code {
+                        Code::Ok(entries) => {
+                            {
+                                let mut state = state.borrow_mut();
+                                for entry in entries.into_iter() {
+                                    let pending = if entry.ty == Type::Directory {
+                                        Pending::Dir
+                                    } else {
+                                        Pending::File
+                                    };
+                                    state.stack.push((entry.file, depth + 1, pending));
+                                }
+                            }
+                            recursive_delete_continue(state);
+                        },
+                        code => recursive_delete_finish(state, code.map_err()),
+                    }
+                });
+                file.async_read_directory_entries(cb).map_ok(|_| () )
+            },
+            Pending::File | Pending::EmptiedDir => {
+                let cb = CallbackArgs::new(move |code: Code<()>| {
+                    if code.is_ok() {
+                        recursive_delete_continue(state);
+                    } else {
+                        recursive_delete_finish(state, code);
+                    }
+                });
+                file.async_delete(cb)
+            },
+        }
+    }
+
+    /// The most reads `FileIo::read_ranges` will have outstanding at once.
+    pub const MAX_PARALLEL_RANGE_READS: usize = 4;
+
+    impl FileIo {
+        /// Reads up to `len` bytes at `offset` into a buffer allocated for
+        /// the read, which `callback` is given. Nothing needs to outlive the
+        /// call. The buffer is cut down to the bytes actually read, so it's
+        /// short at the end of the file. Reading zero bytes completes at once
+        /// with an empty buffer, without calling `callback`.
+        ///
+        /// For writes, `async_write` already takes ownership of a
+        /// `Cow::Owned` buffer.
+        pub fn read_owned<F>(&self, offset: u64, len: usize,
+                             callback: CallbackArgs<F, Vec<u8>>) -> Code<Vec<u8>>
+            where F: FnOnce(Code<Vec<u8>>)
+        {
+            use std::i32;
+
+            fn map_arg(raw: InPlaceArrayOutputStorage<u8>, status: Code) -> Vec<u8> {
+                let mut v: Vec<u8> = raw.into();
+                if let Code::Ok(read) = status {
+                    v.truncate(read);
+                }
+                v
+            }
+
+            if len == 0 { return Code::Ok(Vec::new()); }
+            if len > i32::MAX as usize { return Code::BadArgument; }
+
+            let raw_args: InPlaceArrayOutputStorage<u8> = Default::default();
+            let mut cc = callback.to_ffi_callback(raw_args, StorageToArgsMapper(map_arg));
+            let fficc = cc.cc;
+            let code = get_file_io()
+                .read_to_array(self.unwrap(), offset, len as u32,
+                               cc.as_mut(), fficc);
+            cc.drop_with_code(code)
+        }
+
+        /// Reads each `(offset, len)` range and gives `callback` the data, in
+        /// the same order as `ranges`. Ranges extending past the end of the
+        /// file are cut short.
+        ///
+        /// Up to `MAX_PARALLEL_RANGE_READS` reads are issued at once. If the
+        /// browser refuses a read because another one is in progress, the
+        /// remaining ranges are read one at a time instead. Once a read fails
+        /// no more are issued and, after the outstanding reads finish,
+        /// `callback` gets the first error.
+        pub fn read_ranges<F>(&self, ranges: &[(i64, usize)],
+                              callback: CallbackArgs<F, Vec<Vec<u8>>>) -> Code<Vec<Vec<u8>>>
+            where F: FnOnce(Code<Vec<Vec<u8>>>) + 'static
+        {
+            if ranges.iter().any(|&(offset, _)| offset < 0 ) {
+                return Code::BadArgument;
+            }
+            let state = RangeRead {
+                io: self.clone(),
+                ranges: ranges.to_vec(),
+                next: 0,
+                in_flight: 0,
+                max_in_flight: MAX_PARALLEL_RANGE_READS,
+                results: (0..ranges.len()).map(|_| None ).collect(),
+                error: None,
+                callback: Some(callback),
+            };
+            let state = Rc::new(RefCell::new(state));
+            range_read_pump(state.clone());
+
+            let mut s = state.borrow_mut();
+            if s.in_flight > 0 {
+                return Code::CompletionPending;
+            }
+            // Everything finished, or failed, synchronously. Like other async
+            // ops, `callback` isn't called.
+            s.callback.take();
+            match s.error {
+                Some(code) => code.map_err(),
+                None => Code::Ok(range_read_results(&mut *s)),
+            }
+        }
+    }
+
+    struct RangeRead<F>
+        where F: FnOnce(Code<Vec<Vec<u8>>>),
+    {
+        io: FileIo,
+        ranges: Vec<(i64, usize)>,
+        /// The next range to issue a read for.
+        next: usize,
+        in_flight: usize,
+        max_in_flight: usize,
+        results: Vec<Option<Vec<u8>>>,
+        error: Option<Code<()>>,
+        callback: Option<CallbackArgs<F, Vec<Vec<u8>>>>,
+    }
+    fn range_read_results<F>(state: &mut RangeRead<F>) -> Vec<Vec<u8>>
+        where F: FnOnce(Code<Vec<Vec<u8>>>)
+    {
+        state.results
+            .iter_mut()
+            .map(|r| r.take().unwrap_or_else(|| Vec::new() ) )
+            .collect()
+    }
+    /// Issues reads until the concurrency limit is hit, all ranges have been
+    /// issued, or a read fails.
+    fn range_read_pump<F>(state: Rc<RefCell<RangeRead<F>>>)
+        where F: FnOnce(Code<Vec<Vec<u8>>>) + 'static
+    {
+        loop {
+            let (mut io, index, offset, len) = {
+                let mut s = state.borrow_mut();
+                if s.error.is_some() || s.next >= s.ranges.len() ||
+                    s.in_flight >= s.max_in_flight
+                {
+                    return;
+                }
+                let index = s.next;
+                let (offset, len) = s.ranges[index];
+                (s.io.clone(), index, offset as u64, len)
+            };
+
+            let state2 = state.clone();
+            let cb = CallbackArgs::new(move |code: Code<Cow<'static, [u8]>>| {
+                range_read_finished(state2, index, code);
+            });
+            let code = io.async_read(offset, len, cb);
+
+            let mut s = state.borrow_mut();
+            match code {
+                Code::CompletionPending => {
+                    s.in_flight += 1;
+                    s.next += 1;
+                },
+                Code::Ok(data) => {
+                    s.results[index] = Some(data.into_owned());
+                    s.next += 1;
+                },
+                Code::InProgress if s.in_flight > 0 => {
+                    // Only one read at a time on this handle; issue the rest
+                    // as the outstanding ones finish.
+                    s.max_in_flight = s.in_flight;
+                    return;
+                },
+                code => {
+                    s.error = Some(code.map_err());
+                    return;
+                },
+            }
+        }
+    }
+    fn range_read_finished<F>(state: Rc<RefCell<RangeRead<F>>>, index: usize,
+                              code: Code<Cow<'static, [u8]>>)
+        where F: FnOnce(Code<Vec<Vec<u8>>>) + 'static
+    {
+        {
+            let mut s = state.borrow_mut();
+            s.in_flight -= 1;
+            match code {
+                Code::Ok(data) => s.results[index] = Some(data.into_owned()),
+                code => if s.error.is_none() { s.error = Some(code.map_err()); },
+            }
+        }
+        range_read_pump(state.clone());
+
+        let result = {
+            let mut s = state.borrow_mut();
+            if s.in_flight > 0 { return; }
+            match s.error {
+                Some(code) => code.map_err(),
+                None if s.next >= s.ranges.len() => Code::Ok(range_read_results(&mut *s)),
+                None => return,
+            }
+        };
+        let callback = state.borrow_mut().callback.take();
+        if let Some(callback) = callback {
+            callback.call_directly(result);
+        }
+    }
+
+    /// Replaces the contents of `path` with `data` without ever leaving a
+    /// partially written file at `path`: `data` is written to `<path>.tmp`,
+    /// flushed, then renamed over `path`. Renames within a file system are
+    /// atomic, so after a crash `path` has either its old or its new
+    /// contents. A temp file left behind by an earlier crash is overwritten.
+    ///
+    /// If any step fails, the temp file is deleted and `callback` is given
+    /// the error; `path` is left untouched. Like other async ops, if this
+    /// fails before reaching the browser, `callback` isn't called.
+    pub fn write_atomic<F>(instance: &super::super::Instance, fs: &FileSystem,
+                           path: &str, data: &[u8],
+                           callback: CallbackArgs<F, ()>) -> Code<()>
+        where F: FnOnce(Code<()>) + 'static
+    {
+        let (target, temp) = match (fs.create(path), fs.create(format!("{}.tmp", path))) {
+            (Some(target), Some(temp)) => (target, temp),
+            _ => return Code::BadArgument,
+        };
+        let state = AtomicWrite {
+            target: target,
+            temp: temp.clone(),
+            callback: Some(callback),
+        };
+        let state = Rc::new(RefCell::new(state));
+
+        let state2 = state.clone();
+        let data = data.to_vec();
+        let cb = CallbackArgs::new(move |code: Code<FileIo>| {
+            match code {
+                Code::Ok(io) => atomic_write_data(state2, io, 0, data),
+                // We didn't create anything to clean up.
+                code => atomic_write_finish(state2, code.map_err()),
+            }
+        });
+        let flags = OpenFlags::new().write(true).create(true).truncate(true);
+        let code = temp.async_open_io(*instance, flags, cb);
+        if !code.completion_pending() {
+            state.borrow_mut().callback.take();
+        }
+        code.map_ok(|_| () )
+    }
+
+    struct AtomicWrite<F>
+        where F: FnOnce(Code<()>),
+    {
+        target: FileRef,
+        temp: FileRef,
+        callback: Option<CallbackArgs<F, ()>>,
+    }
+    fn atomic_write_finish<F>(state: Rc<RefCell<AtomicWrite<F>>>, code: Code<()>)
+        where F: FnOnce(Code<()>) + 'static
+    {
+        let callback = state.borrow_mut().callback.take();
+        if let Some(callback) = callback {
+            callback.call_directly(code);
+        }
+    }
+    /// Deletes the temp file, then reports `code`.
+    fn atomic_write_abort<F>(state: Rc<RefCell<AtomicWrite<F>>>, code: Code<()>)
+        where F: FnOnce(Code<()>) + 'static
+    {
+        let temp = state.borrow().temp.clone();
+        let state2 = state.clone();
+        let cb = CallbackArgs::new(move |_: Code<()>| atomic_write_finish(state2, code) );
+        if !temp.async_delete(cb).completion_pending() {
+            atomic_write_finish(state, code);
+        }
+    }
+    /// Writes `data` at `offset`, continuing after short writes, then
+    /// flushes.
+    fn atomic_write_data<F>(state: Rc<RefCell<AtomicWrite<F>>>, mut io: FileIo,
+                            offset: u64, data: Vec<u8>)
+        where F: FnOnce(Code<()>) + 'static
+    {
+        if data.is_empty() {
+            return atomic_write_flush(state, io);
+        }
+
+        let state2 = state.clone();
+        let io2 = io.clone();
+        let cb = CallbackArgs::new(move |code: Code<(usize, Cow<'static, [u8]>)>| {
+            match code {
+                Code::Ok((0, _)) => atomic_write_abort(state2, Code::Failed),
+                Code::Ok((written, buf)) => {
+                    let rest = buf[written..].to_vec();
+                    atomic_write_data(state2, io2, offset + written as u64, rest);
+                },
+                code => atomic_write_abort(state2, code.map_err()),
+            }
+        });
+        let code = io.async_write(offset, Cow::Owned(data), cb);
+        if !code.completion_pending() {
+            atomic_write_abort(state, code.map_err());
+        }
+    }
+    fn atomic_write_flush<F>(state: Rc<RefCell<AtomicWrite<F>>>, mut io: FileIo)
+        where F: FnOnce(Code<()>) + 'static
+    {
+        let state2 = state.clone();
+        let io2 = io.clone();
+        let cb = CallbackArgs::new(move |code: Code<()>| {
+            // Close the temp file before it's renamed. Releasing it while
+            // the flush is pending would abort the flush.
+            drop(io2);
+            if code.is_ok() {
+                atomic_write_rename(state2);
+            } else {
+                atomic_write_abort(state2, code);
+            }
+        });
+        let code = io.async_flush(cb);
+        if !code.completion_pending() {
+            atomic_write_abort(state, code);
+        }
+    }
+    fn atomic_write_rename<F>(state: Rc<RefCell<AtomicWrite<F>>>)
+        where F: FnOnce(Code<()>) + 'static
+    {
+        let (temp, target) = {
+            let s = state.borrow();
+            (s.temp.clone(), s.target.clone())
+        };
+        let state2 = state.clone();
+        let cb = CallbackArgs::new(move |code: Code<()>| {
+            if code.is_ok() {
+                atomic_write_finish(state2, code);
+            } else {
+                atomic_write_abort(state2, code);
+            }
+        });
+        let code = temp.async_rename(target, cb);
+        if !code.completion_pending() {
+            atomic_write_abort(state, code);
+        }
+    }
+
     impl AsyncCommon for FileRef {
         fn async_touch<F>(&self, atime: Time, mtime: Time,
                           callback: CallbackArgs<F, ()>) ->
@@ -633,6 +1107,9 @@ mod impl_ {
             where F: FnOnce(Code<()>)
         {
             let cc = callback.to_ffi_callback((), Default::default());
+            if !self.same_file_system_type(&to) {
+                return cc.drop_with_code(Code::Failed);
+            }
             let code = get_file_ref()
                 .rename(self.unwrap(), to.unwrap(), cc.cc);
             cc.drop_with_code(code)
@@ -658,7 +1135,7 @@ mod impl_ {
         {
             impl super::super::InPlaceInit for FileIo { }
 
-            let mut cc = callback.to_ffi_callback(FileIo(0), Default::default());
+            let mut cc = callback.to_ffi_callback(FileIo::new(0), Default::default());
 
             let file_io = get_file_io().create(instance.unwrap());
             if file_io.is_none() { return cc.drop_with_code(Code::BadArgument); }
@@ -696,6 +1173,9 @@ mod impl_ {
                         BlockUntilComplete::new())
         }
         fn sync_rename(&self, to: FileRef) -> Code {
+            if !self.same_file_system_type(&to) {
+                return Code::Failed;
+            }
             get_file_ref()
                 .rename(self.unwrap(), to.unwrap(),
                         BlockUntilComplete::new())
@@ -721,7 +1201,7 @@ mod impl_ {
             get_file_io()
                 .open(self.unwrap(), file_io, flags.into(),
                       BlockUntilComplete::new())
-                .map_ok(move |_| FileIo(file_io) )
+                .map_ok(move |_| FileIo::new(file_io) )
         }
     }
 
@@ -13,14 +13,19 @@
 /// semantics to prevent a bound program from being overridden.
 
 use std::borrow::{Cow, ToOwned};
-use std::mem::{size_of, uninitialized};
-use std::{ptr};
+use std::collections::HashMap;
+use std::mem::{self, size_of, uninitialized};
+use std::sync::{Mutex, Once, ONCE_INIT};
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+use std::{cmp, iter, ptr};
 use std::default::Default;
 use std::fmt;
+use std::marker::PhantomData;
 
 use libc;
 use libc::c_void;
-use super::{Resource, CallbackArgs, Code, Rect};
+use super::{Resource, CallbackArgs, Code, Rect, TimeDelta};
+use super::imagedata::ImageData;
 use super::ppb;
 use super::ppb::get_gles2;
 use ffi;
@@ -347,18 +352,28 @@ impl fmt::Display for Context3d {
     // Framebuffer Object
     pub const FRAMEBUFFER:  c_uint = 0x8D40 as c_uint;
     pub const RENDERBUFFER: c_uint = 0x8D41 as c_uint;
+
+    // EXT_disjoint_timer_query
+    pub const QUERY_RESULT_EXT:           c_uint = 0x8866 as c_uint;
+    pub const QUERY_RESULT_AVAILABLE_EXT: c_uint = 0x8867 as c_uint;
+    pub const TIME_ELAPSED_EXT:           c_uint = 0x88BF as c_uint;
+    pub const GPU_DISJOINT_EXT:           c_uint = 0x8FBB as c_uint;
 }
 macro_rules! call_gl_fun(
     ($expr:expr => $fun:ident => ( $ctxt:expr, $($arg:expr),* ) ) => ({
         #[inline(never)] fn failure() -> ! {
             panic!("Interface function \"{}\" missing!", stringify!($fun))
         }
-        let mut s = "".to_string();
-        let v = vec!($((stringify!($arg), format!("{:?}", $arg))),*);
-        for (i, j) in v.into_iter() {
-            s = format!("{:}, {:} = {:}", s, i, j);
+        // Formatting the arguments costs far more than the call itself, so
+        // it's only done when asked for.
+        if cfg!(feature = "debug-gl-calls") {
+            let mut s = "".to_string();
+            let v = vec!($((stringify!($arg), format!("{:?}", $arg))),*);
+            for (i, j) in v.into_iter() {
+                s = format!("{:}, {:} = {:}", s, i, j);
+            }
+            println!("{}({:?}{})", stringify!($fun), $ctxt, s);
         }
-        println!("{}({:?}{})", stringify!($fun), $ctxt, s);
         let e = $expr.$fun;
         let f = if e.is_none() { failure() }
                 else { e.unwrap() };
@@ -1009,6 +1024,108 @@ impl_vert_attrib_type!(ShortType);
 impl_vert_attrib_type!(UShortType);
 impl_vert_attrib_type!(FloatType);
 
+/// One attribute of a `VertexLayout`.
+#[derive(Clone, Copy)]
+pub struct VertexAttrib<'a> {
+    pub locus: AttrLocus,
+    /// Components per vertex, 1 through 4.
+    pub count: usize,
+    pub ty: VertexAttribType,
+    pub normalize: bool,
+    /// Bytes from one vertex to the next; zero if tightly packed.
+    pub stride: usize,
+    /// Bytes from the start of the buffer to the first component.
+    pub offset: usize,
+    /// The buffer the attribute is read from. `None` uses whichever vertex
+    /// buffer is bound when the layout is applied.
+    pub buffer: Option<&'a VertexBuffer>,
+}
+impl<'a> VertexAttrib<'a> {
+    /// The size in bytes of one vertex's worth of this attribute.
+    pub fn byte_len(&self) -> usize {
+        use self::traits::BufferElementType;
+        self.count * self.ty.element_size()
+    }
+}
+
+/// Describes where each vertex attribute comes from, so it can be set up
+/// in one go with `Context3d::apply_vertex_layout`. Attributes can be
+/// interleaved in one buffer or spread over several:
+///
+/// ```rust
+/// let layout = VertexLayout::new()
+///     .attrib(pos, 2, VertexAttribType::Float, false, 12, 0)
+///     .attrib(color, 4, VertexAttribType::UByte, true, 12, 8);
+/// ```
+#[derive(Clone, Default)]
+pub struct VertexLayout<'a> {
+    pub attribs: Vec<VertexAttrib<'a>>,
+}
+impl<'a> VertexLayout<'a> {
+    pub fn new() -> VertexLayout<'a> {
+        VertexLayout { attribs: Vec::new() }
+    }
+    /// Adds an attribute read from the bound vertex buffer.
+    pub fn attrib(self, locus: AttrLocus, count: usize, ty: VertexAttribType,
+                  normalize: bool, stride: usize, offset: usize) -> VertexLayout<'a> {
+        self.push(locus, count, ty, normalize, stride, offset, None)
+    }
+    /// Adds an attribute read from `buffer`.
+    pub fn attrib_in(self, buffer: &'a VertexBuffer, locus: AttrLocus, count: usize,
+                     ty: VertexAttribType, normalize: bool, stride: usize,
+                     offset: usize) -> VertexLayout<'a> {
+        self.push(locus, count, ty, normalize, stride, offset, Some(buffer))
+    }
+    fn push(mut self, locus: AttrLocus, count: usize, ty: VertexAttribType,
+            normalize: bool, stride: usize, offset: usize,
+            buffer: Option<&'a VertexBuffer>) -> VertexLayout<'a> {
+        self.attribs.push(VertexAttrib {
+            locus: locus,
+            count: count,
+            ty: ty,
+            normalize: normalize,
+            stride: stride,
+            offset: offset,
+            buffer: buffer,
+        });
+        self
+    }
+
+    /// Describes what's obviously wrong with the layout, eg attributes which
+    /// don't fit in their stride. Empty if nothing is. GL accepts most of
+    /// these, but the results are garbage.
+    pub fn problems(&self) -> Vec<String> {
+        use self::traits::BufferElementType;
+        let mut problems = Vec::new();
+        for attrib in self.attribs.iter() {
+            if attrib.count < 1 || attrib.count > 4 {
+                problems.push(format!("attribute {:?} has {} components; GL allows 1 to 4",
+                                      attrib.locus, attrib.count));
+            }
+            let size = attrib.ty.element_size();
+            if attrib.offset % size != 0 || attrib.stride % size != 0 {
+                problems.push(format!("attribute {:?}'s offset {} or stride {} isn't a \
+                                       multiple of its component size {}",
+                                      attrib.locus, attrib.offset, attrib.stride, size));
+            }
+            if attrib.stride != 0 && attrib.stride < attrib.byte_len() {
+                problems.push(format!("attribute {:?} is {} bytes, more than its stride {}",
+                                      attrib.locus, attrib.byte_len(), attrib.stride));
+            } else if attrib.stride != 0 && attrib.offset % attrib.stride + attrib.byte_len() >
+                attrib.stride {
+                problems.push(format!("attribute {:?} at offset {} runs into the next vertex",
+                                      attrib.locus, attrib.offset));
+            }
+        }
+        for (i, attrib) in self.attribs.iter().enumerate() {
+            if self.attribs[..i].iter().any(|prev| prev.locus == attrib.locus ) {
+                problems.push(format!("attribute {:?} is given more than once", attrib.locus));
+            }
+        }
+        problems
+    }
+}
+
 macro_rules! impl_idx_elem_type(
     ($ty:ty) => {
         impl traits::IndexElementType for $ty { }
@@ -1265,18 +1382,7 @@ impl<'ctxt, T: ShaderUnwrap> CompileError<'ctxt, T> {
         DESC
     }
     pub fn detail(&self) -> String {
-        let info_len = self.ctxt.get_shader_param(&self.shader, consts::INFO_LOG_LENGTH);
-        let mut info_buf: Vec<u8> = Vec::with_capacity(info_len as usize);
-        let mut actual_len: types::Size = unsafe { uninitialized() };
-        call_gl_fun!(get_gles2() => GetShaderInfoLog
-                     => (self.ctxt,
-                         self.shader.unwrap(),
-                         info_buf.capacity() as types::Size,
-                         &mut actual_len as *mut types::Size,
-                         info_buf.as_mut_ptr() as *mut i8));
-        let actual_len: usize = actual_len as usize;
-        String::from_utf8_lossy(&info_buf[..actual_len])
-            .to_string()
+        self.ctxt.shader_info_log(&self.shader)
     }
 }
 #[derive(Eq, PartialEq, Ord, PartialOrd, Clone, Hash)]
@@ -1309,6 +1415,26 @@ pub struct VertexShader(types::UInt);
 #[allow(missing_copy_implementations)] #[derive(Eq, PartialEq, Ord, PartialOrd, Clone, Hash)]
 pub struct FragmentShader(types::UInt);
 
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum ShaderKind {
+    Vertex,
+    Fragment,
+}
+/// A compiled shader of either kind. See `Context3d::compile_shader`.
+#[derive(Eq, PartialEq, Ord, PartialOrd, Clone, Hash)]
+pub enum Shader {
+    Vertex(VertexShader),
+    Fragment(FragmentShader),
+}
+impl Shader {
+    pub fn kind(&self) -> ShaderKind {
+        match self {
+            &Shader::Vertex(_) => ShaderKind::Vertex,
+            &Shader::Fragment(_) => ShaderKind::Fragment,
+        }
+    }
+}
+
 #[allow(missing_copy_implementations)] #[derive(Eq, PartialEq, Ord, PartialOrd, Clone, Hash)]
 pub struct ShaderProgram(types::UInt);
 pub struct BoundShaderProgram<'a>(&'a ShaderProgram);
@@ -1334,18 +1460,7 @@ impl<'a, 'b> LinkError<'a, 'b> {
         DESC
     }
     pub fn detail(&self) -> String {
-        let info_len = (self.0).1.get_program_param(&(self.0).0, consts::INFO_LOG_LENGTH);
-        let mut info_buf: Vec<u8> = Vec::with_capacity(info_len as usize);
-        let mut actual_len: types::Size = unsafe { uninitialized() };
-        call_gl_fun!(get_gles2() => GetProgramInfoLog
-                     => ((self.0).1,
-                         unsafe { self.0.get_program().unwrap() },
-                         info_buf.capacity() as types::Size,
-                         &mut actual_len as *mut types::Size,
-                         info_buf.as_mut_ptr() as *mut i8));
-        let actual_len: usize = actual_len as usize;
-        String::from_utf8_lossy(&info_buf[..actual_len])
-            .to_string()
+        (self.0).1.program_info_log(&(self.0).0)
     }
 }
 impl<'a, 'b> fmt::Debug for LinkError<'a, 'b>
@@ -1436,6 +1551,19 @@ impl<'a> ShaderUnwrap for &'a FragmentShader {
         inner
     }
 }
+impl ShaderUnwrap for Shader {
+    fn unwrap(&self) -> types::UInt {
+        match self {
+            &Shader::Vertex(ref s) => s.unwrap(),
+            &Shader::Fragment(ref s) => s.unwrap(),
+        }
+    }
+}
+impl<'a> ShaderUnwrap for &'a Shader {
+    fn unwrap(&self) -> types::UInt {
+        (*self).unwrap()
+    }
+}
 impl<'a, T: ShaderUnwrap> ShaderUnwrap for &'a CompilingShader<T> {
     fn unwrap(&self) -> types::UInt {
         let & &CompilingShader(ref inner) = self;
@@ -1493,6 +1621,12 @@ impl ShaderProgram {
         UnlinkedShaderProgram(self, ctxt)
     }
 }
+#[doc(hidden)]
+impl From<types::UInt> for ShaderProgram {
+    fn from(id: types::UInt) -> ShaderProgram {
+        ShaderProgram(id)
+    }
+}
 
 
 pub trait Uniform {
@@ -1627,6 +1761,7 @@ impl<'a> UnlinkedShaderProgram<'a> {
 #[derive(Copy, Clone)] pub struct Renderer;
 #[derive(Copy, Clone)] pub struct Version;
 #[derive(Copy, Clone)] pub struct ShadingLanguageVersion;
+#[derive(Copy, Clone)] pub struct PackAlignment;
 
 /// INTERNEL
 pub trait GetQueryType {
@@ -1731,12 +1866,109 @@ impl_get_query_ret_type!(MaxCubeMapTextureSize =>        consts::MAX_CUBE_MAP_TE
 impl_get_query_ret_type!(MaxRenderBufferSize =>          consts::MAX_RENDER_BUFFER_SIZE);
 impl_get_query_ret_type!(MaxTextureSize =>               consts::MAX_TEXTURE_SIZE);
 impl_get_query_ret_type!(MaxColorAttachments =>          consts::MAX_COLOR_ATTACHMENTS);
+impl_get_query_ret_type!(PackAlignment =>                consts::PACK_ALIGNMENT);
 impl_get_query_ret_type!(Vendor                 => consts::VENDOR                   => &'static str);
 impl_get_query_ret_type!(Extensions             => consts::EXTENSIONS               => Vec<&'static str>);
 impl_get_query_ret_type!(Renderer               => consts::RENDERER                 => &'static str);
 impl_get_query_ret_type!(Version                => consts::VERSION                  => &'static str);
 impl_get_query_ret_type!(ShadingLanguageVersion => consts::SHADING_LANGUAGE_VERSION => &'static str);
 
+/// The strings which can be queried with `Context3d::get_string`.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum GLStringName {
+    Vendor,
+    Renderer,
+    Version,
+    ShadingLanguageVersion,
+    Extensions,
+}
+impl GLStringName {
+    pub fn to_ffi(self) -> types::Enum {
+        match self {
+            GLStringName::Vendor => consts::VENDOR,
+            GLStringName::Renderer => consts::RENDERER,
+            GLStringName::Version => consts::VERSION,
+            GLStringName::ShadingLanguageVersion => consts::SHADING_LANGUAGE_VERSION,
+            GLStringName::Extensions => consts::EXTENSIONS,
+        }
+    }
+}
+
+/// What a context supports, beyond what GLES2 requires. See
+/// `Context3d::capabilities`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Gl3dCaps {
+    /// `ANGLE_instanced_arrays`.
+    pub instanced_arrays: bool,
+    /// `OES_depth_texture`, or the ANGLE or CHROMIUM variants.
+    pub depth_texture: bool,
+    /// `OES_vertex_array_object`.
+    pub vertex_array_object: bool,
+    /// `OES_element_index_uint`.
+    pub element_index_uint: bool,
+    /// `OES_standard_derivatives`.
+    pub standard_derivatives: bool,
+    /// `OES_texture_float`.
+    pub texture_float: bool,
+    /// `OES_texture_half_float`.
+    pub texture_half_float: bool,
+    /// `EXT_texture_filter_anisotropic`.
+    pub texture_filter_anisotropic: bool,
+    /// `EXT_texture_compression_dxt1` or `EXT_texture_compression_s3tc`.
+    pub texture_compression_s3tc: bool,
+    /// `EXT_draw_buffers`.
+    pub draw_buffers: bool,
+    /// `EXT_disjoint_timer_query`. See `Context3d::time_frame`.
+    pub disjoint_timer_query: bool,
+    /// `EXT_texture_format_BGRA8888`. See
+    /// `Context3d::tex_image_from_image_data`.
+    pub texture_format_bgra8888: bool,
+
+    pub max_texture_size: u32,
+    pub max_vertex_attribs: u32,
+
+    /// Every extension advertised, without the `GL_` prefix.
+    pub extensions: Vec<String>,
+}
+impl Gl3dCaps {
+    /// Fills in the extension flags from a space separated extension list,
+    /// ie the `EXTENSIONS` string. The limits are left at zero.
+    pub fn from_extensions(extensions: &str) -> Gl3dCaps {
+        let mut caps: Gl3dCaps = Default::default();
+        caps.extensions = extensions
+            .split(' ')
+            .filter(|ext| !ext.is_empty() )
+            .map(|ext| {
+                if ext.starts_with("GL_") { &ext[3..] }
+                else { ext }
+            })
+            .map(|ext| ext.to_string() )
+            .collect();
+
+        caps.instanced_arrays = caps.has_extension("ANGLE_instanced_arrays");
+        caps.depth_texture = caps.has_extension("OES_depth_texture") ||
+            caps.has_extension("ANGLE_depth_texture") ||
+            caps.has_extension("CHROMIUM_depth_texture");
+        caps.vertex_array_object = caps.has_extension("OES_vertex_array_object");
+        caps.element_index_uint = caps.has_extension("OES_element_index_uint");
+        caps.standard_derivatives = caps.has_extension("OES_standard_derivatives");
+        caps.texture_float = caps.has_extension("OES_texture_float");
+        caps.texture_half_float = caps.has_extension("OES_texture_half_float");
+        caps.texture_filter_anisotropic = caps.has_extension("EXT_texture_filter_anisotropic");
+        caps.texture_compression_s3tc = caps.has_extension("EXT_texture_compression_dxt1") ||
+            caps.has_extension("EXT_texture_compression_s3tc");
+        caps.draw_buffers = caps.has_extension("EXT_draw_buffers");
+        caps.disjoint_timer_query = caps.has_extension("EXT_disjoint_timer_query");
+        caps.texture_format_bgra8888 = caps.has_extension("EXT_texture_format_BGRA8888");
+        caps
+    }
+    /// `name` may be given with or without the `GL_` prefix.
+    pub fn has_extension(&self, name: &str) -> bool {
+        let name = if name.starts_with("GL_") { &name[3..] } else { name };
+        self.extensions.iter().any(|ext| &ext[..] == name )
+    }
+}
+
 #[derive(Copy, Clone, Eq, PartialEq, Hash)]
 pub enum Context3dAttrib {
     Width(u32),
@@ -1769,15 +2001,169 @@ impl Context3dAttrib {
             &SwapBehaviour(v) => (ffi::PP_GRAPHICS3DATTRIB_SWAP_BEHAVIOR, v),
         }
     }
+    pub fn key(&self) -> AttribKey {
+        use self::Context3dAttrib::*;
+        match self {
+            &Width(_) => AttribKey::Width,
+            &Height(_) => AttribKey::Height,
+            &AlphaSize(_) => AttribKey::AlphaSize,
+            &RedSize(_) => AttribKey::RedSize,
+            &BlueSize(_) => AttribKey::BlueSize,
+            &GreenSize(_) => AttribKey::GreenSize,
+            &DepthSize(_) => AttribKey::DepthSize,
+            &StencilSize(_) => AttribKey::StencilSize,
+            &Samples(_) => AttribKey::Samples,
+            &SampleBuffers(_) => AttribKey::SampleBuffers,
+            &SwapBehaviour(_) => AttribKey::SwapBehaviour,
+        }
+    }
+}
+
+/// A `Context3dAttrib` without its value, for `Context3d::get_attribs`.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum AttribKey {
+    Width,
+    Height,
+    AlphaSize,
+    RedSize,
+    BlueSize,
+    GreenSize,
+    DepthSize,
+    StencilSize,
+    Samples,
+    SampleBuffers,
+    SwapBehaviour,
+}
+impl AttribKey {
+    pub fn to_ffi(&self) -> ffi::PP_Graphics3DAttrib {
+        match self {
+            &AttribKey::Width => ffi::PP_GRAPHICS3DATTRIB_WIDTH,
+            &AttribKey::Height => ffi::PP_GRAPHICS3DATTRIB_HEIGHT,
+            &AttribKey::AlphaSize => ffi::PP_GRAPHICS3DATTRIB_ALPHA_SIZE,
+            &AttribKey::RedSize => ffi::PP_GRAPHICS3DATTRIB_RED_SIZE,
+            &AttribKey::BlueSize => ffi::PP_GRAPHICS3DATTRIB_BLUE_SIZE,
+            &AttribKey::GreenSize => ffi::PP_GRAPHICS3DATTRIB_GREEN_SIZE,
+            &AttribKey::DepthSize => ffi::PP_GRAPHICS3DATTRIB_DEPTH_SIZE,
+            &AttribKey::StencilSize => ffi::PP_GRAPHICS3DATTRIB_STENCIL_SIZE,
+            &AttribKey::Samples => ffi::PP_GRAPHICS3DATTRIB_SAMPLES,
+            &AttribKey::SampleBuffers => ffi::PP_GRAPHICS3DATTRIB_SAMPLE_BUFFERS,
+            &AttribKey::SwapBehaviour => ffi::PP_GRAPHICS3DATTRIB_SWAP_BEHAVIOR,
+        }
+    }
 }
 
 impl_resource_for!(Context3d, ResourceType::Graphics3D);
 
+/// Reads an info log `log_len` bytes long, as given by `INFO_LOG_LENGTH`.
+/// `read` is given the buffer size, where to write the length and the buffer.
+fn read_info_log<F>(log_len: types::Int, read: F) -> String
+    where F: FnOnce(types::Size, *mut types::Size, *mut i8),
+{
+    if log_len <= 0 { return String::new(); }
+    let mut buf: Vec<u8> = iter::repeat(0u8).take(log_len as usize).collect();
+    let mut written: types::Size = 0;
+    read(buf.len() as types::Size, &mut written as *mut types::Size,
+         buf.as_mut_ptr() as *mut i8);
+    // `log_len` counts the NUL, `written` shouldn't; don't trust either.
+    let written = cmp::min(cmp::max(written, 0) as usize, buf.len());
+    buf.truncate(written);
+    while buf.last() == Some(&0) {
+        buf.pop();
+    }
+    String::from_utf8_lossy(&buf[..]).into_owned()
+}
+
 impl Context3d {
     pub fn mark_program_for_drop(&self, program: &ShaderProgram) {
         call_gl_fun!(get_gles2() => DeleteProgram => (self, program.unwrap()));
     }
 
+    /// Enables and points each attribute of `layout`, in order. Attributes
+    /// with a buffer bind it first, so afterwards the last of those is left
+    /// bound. Problems found by `VertexLayout::problems` are logged, but the
+    /// layout is applied regardless.
+    pub fn apply_vertex_layout(&self, layout: &VertexLayout) {
+        for problem in layout.problems().iter() {
+            warn!("vertex layout: {}", problem);
+        }
+        for attrib in layout.attribs.iter() {
+            if let Some(buffer) = attrib.buffer {
+                call_gl_fun!(get_gles2() => BindBuffer => (self,
+                                                           consts::ARRAY_BUFFER,
+                                                           buffer.0));
+            }
+            call_gl_fun!(get_gles2() => EnableVertexAttribArray => (self, attrib.locus.0));
+            call_gl_fun!(get_gles2() => VertexAttribPointer => (self,
+                                                                attrib.locus.0,
+                                                                attrib.count as types::Int,
+                                                                attrib.ty.into(),
+                                                                attrib.normalize as types::Boolean,
+                                                                attrib.stride as types::Size,
+                                                                attrib.offset.to_ptr_offset()));
+        }
+    }
+
+    /// Creates, sources and compiles a shader. If it doesn't compile, the
+    /// shader is deleted and its info log returned.
+    pub fn compile_shader(&self, kind: ShaderKind, source: &str) -> Result<Shader, String> {
+        use libc::c_char;
+        let shader = match kind {
+            ShaderKind::Vertex => Shader::Vertex(self.gen_vert_shader()),
+            ShaderKind::Fragment => Shader::Fragment(self.gen_frag_shader()),
+        };
+        // The length is given, so `source` needn't be null terminated.
+        let mut src_ptr = source.as_ptr() as *const c_char;
+        let src_len = source.len() as types::Int;
+        call_gl_fun!(get_gles2() => ShaderSource => (self,
+                                                     shader.unwrap(),
+                                                     1,
+                                                     &mut src_ptr as *mut *const c_char,
+                                                     &src_len as *const types::Int));
+        call_gl_fun!(get_gles2() => CompileShader => (self, shader.unwrap()));
+
+        if self.get_shader_param(&shader, consts::COMPILE_STATUS) == consts::TRUE as i32 {
+            Ok(shader)
+        } else {
+            let log = self.shader_info_log(&shader);
+            call_gl_fun!(get_gles2() => DeleteShader => (self, shader.unwrap()));
+            Err(log)
+        }
+    }
+    /// Creates a program from `shaders` and links it. If it doesn't link,
+    /// the program is deleted and its info log returned. The shaders are
+    /// left alone either way.
+    pub fn link_program(&self, shaders: &[&Shader]) -> Result<ShaderProgram, String> {
+        let program = self.gen_shader_program();
+        for shader in shaders.iter() {
+            call_gl_fun!(get_gles2() => AttachShader => (self,
+                                                         program.unwrap(),
+                                                         shader.unwrap()));
+        }
+        call_gl_fun!(get_gles2() => LinkProgram => (self, program.unwrap()));
+
+        if self.get_program_param(&program, consts::LINK_STATUS) == consts::TRUE as i32 {
+            Ok(program)
+        } else {
+            let log = self.program_info_log(&program);
+            self.mark_program_for_drop(&program);
+            Err(log)
+        }
+    }
+    fn shader_info_log<T: ShaderUnwrap>(&self, shader: &T) -> String {
+        let log_len = self.get_shader_param(shader, consts::INFO_LOG_LENGTH);
+        read_info_log(log_len, |size, len, buf| {
+            call_gl_fun!(get_gles2() => GetShaderInfoLog => (self, shader.unwrap(),
+                                                             size, len, buf));
+        })
+    }
+    fn program_info_log<T: InnerProgram>(&self, program: &T) -> String {
+        let log_len = self.get_program_param(program, consts::INFO_LOG_LENGTH);
+        read_info_log(log_len, |size, len, buf| {
+            call_gl_fun!(get_gles2() => GetProgramInfoLog => (self, program.inner().unwrap(),
+                                                              size, len, buf));
+        })
+    }
+
     fn gen_vert_shader(&self) -> VertexShader {
         let handle = call_gl_fun!(get_gles2() => CreateShader => (self, consts::VERTEX_SHADER));
         VertexShader(handle)
@@ -1867,6 +2253,234 @@ impl Context3d {
         call_gl_fun!(get_gles2() => Clear => (self,
                                               mask))
     }
+    pub fn clear_color(&self, red: f32, green: f32, blue: f32, alpha: f32) {
+        call_gl_fun!(get_gles2() => ClearColor => (self,
+                                                   red,
+                                                   green,
+                                                   blue,
+                                                   alpha))
+    }
+
+    /// Reads the pixels in `rect` from the current framebuffer as unsigned
+    /// bytes. The context must be current, and this should be called after
+    /// rendering but before `swap_buffers`. GLES2 only guarantees
+    /// `TexFormat::Rgba` is readable.
+    ///
+    /// Rows are laid out bottom to top, each padded to GL's
+    /// `PACK_ALIGNMENT`, which defaults to 4 bytes; so with `TexFormat::Rgb`
+    /// a row's stride may be larger than `width * 3`. The alignment is queried,
+    /// so a value set through `BoundTexBuffer::pixel_store` is honored.
+    ///
+    /// Any GL errors pending before the read are discarded; if the read
+    /// itself sets an error, `Code::Failed` is returned.
+    pub fn read_pixels(&self, rect: Rect, format: TexFormat) -> Result<Vec<u8>, Code> {
+        self.discard_gl_errors();
+
+        let alignment: types::Int = self.get(PackAlignment);
+        let alignment = if alignment > 0 { alignment as usize } else { 4 };
+        let row_len = rect.size.width as usize * format.elements_len();
+        let stride = (row_len + alignment - 1) / alignment * alignment;
+        let len = stride * rect.size.height as usize;
+
+        let mut dest: Vec<u8> = Vec::with_capacity(len);
+        call_gl_fun!(get_gles2() => ReadPixels => (self, rect.point.x as types::Int,
+                                                   rect.point.y as types::Int,
+                                                   rect.size.width as types::Int,
+                                                   rect.size.height as types::Int,
+                                                   format.to_ffi(),
+                                                   consts::UNSIGNED_BYTE,
+                                                   dest.as_mut_ptr() as *mut libc::c_void));
+        if self.gl_error() != consts::NO_ERROR {
+            return Err(Code::Failed);
+        }
+
+        unsafe { dest.set_len(len); }
+        Ok(dest)
+    }
+    /// Uploads `image` as mip level `level` of the texture bound to `target`,
+    /// eg `consts::TEXTURE_2D`, sized to match. The image's first row ends up
+    /// at t = 0. Its pixels are premultiplied, as `ImageData`'s always are.
+    ///
+    /// An `RGBA` image is uploaded as `RGBA`. A `BGRA` image, the native
+    /// format on most platforms, needs `EXT_texture_format_BGRA8888` to be
+    /// uploaded without swizzling; without it `Code::NotSupported` is
+    /// returned and nothing is uploaded. Create an `RGBA` image to avoid
+    /// this, if `imagedata::Format::RGBA.is_supported()`.
+    ///
+    /// Any GL errors pending beforehand are discarded; if the upload itself
+    /// sets an error, `Code::Failed` is returned.
+    pub fn tex_image_from_image_data(&self, target: types::Enum, level: types::Int,
+                                     image: &ImageData) -> Code<()> {
+        use imagedata::{Format, MappedSlice};
+
+        let desc = match image.describe() {
+            Some(desc) => desc,
+            None => return Code::BadResource,
+        };
+        // GLES2 requires the internal format to match the format.
+        let format = match desc.format {
+            Format::RGBA => consts::RGBA,
+            Format::BGRA => {
+                if !self.cached_capabilities().texture_format_bgra8888 {
+                    warn!("can't upload a BGRA image: the context doesn't support \
+                           EXT_texture_format_BGRA8888");
+                    return Code::NotSupported;
+                }
+                // EXT_texture_format_BGRA8888 reuses desktop GL's value.
+                consts::BGRA
+            }
+        };
+
+        let width = desc.size.width as usize;
+        let height = desc.size.height as usize;
+        let row_len = width * 4;
+        let mapped = image.map();
+        let pixels = mapped.as_imm_slice();
+        // GLES2 has no UNPACK_ROW_LENGTH, so padded rows are packed first.
+        // Rows of four byte pixels always satisfy the default alignment.
+        let packed: Vec<u8>;
+        let pixels = if desc.line_stride as usize == row_len {
+            &pixels[..row_len * height]
+        } else {
+            let stride = desc.line_stride as usize;
+            packed = (0..height)
+                .flat_map(|row| pixels[row * stride..row * stride + row_len].iter().cloned() )
+                .collect();
+            &packed[..]
+        };
+
+        self.discard_gl_errors();
+        call_gl_fun!(get_gles2() => TexImage2D => (self,
+                                                   target,
+                                                   level,
+                                                   format as types::Int,
+                                                   width as types::Int,
+                                                   height as types::Int,
+                                                   0i32,
+                                                   format,
+                                                   consts::UNSIGNED_BYTE,
+                                                   pixels.as_ptr() as *const c_void));
+        if self.gl_error() != consts::NO_ERROR {
+            return Code::Failed;
+        }
+        Code::Ok(())
+    }
+    fn gl_error(&self) -> types::Enum {
+        call_gl_fun!(get_gles2() => GetError => (self))
+    }
+    /// GL keeps at most one pending error per kind, so there are only a
+    /// handful to discard. This gives up after that many, in case a lost
+    /// context keeps reporting one.
+    fn discard_gl_errors(&self) {
+        for _ in 0..16 {
+            if self.gl_error() == consts::NO_ERROR { return; }
+        }
+        warn!("GL errors are still pending; was the context lost?");
+    }
+
+    /// Unlike `get(Vendor)` etc, this doesn't panic if GL returns null, as it
+    /// does when the context has been lost; an empty string is returned
+    /// instead.
+    pub fn get_string(&self, name: GLStringName) -> String {
+        use std::ffi::CStr;
+        let str_ptr = call_gl_fun!(get_gles2() => GetString => (self,
+                                                                name.to_ffi())) as *const i8;
+        if str_ptr.is_null() {
+            warn!("glGetString({:?}) returned null; was the context lost?", name);
+            return String::new();
+        }
+        let str = unsafe { CStr::from_ptr(str_ptr) };
+        String::from_utf8_lossy(str.to_bytes()).into_owned()
+    }
+    /// Queries the extensions and a few limits. This makes several GL calls,
+    /// so hold onto the result.
+    pub fn capabilities(&self) -> Gl3dCaps {
+        let mut caps = Gl3dCaps::from_extensions(&self.get_string(GLStringName::Extensions)[..]);
+        let max_texture_size: types::Int = self.get(MaxTextureSize);
+        let max_vertex_attribs: types::Int = self.get(MaxVertexAttribs);
+        caps.max_texture_size = max_texture_size as u32;
+        caps.max_vertex_attribs = max_vertex_attribs as u32;
+        caps
+    }
+    /// `capabilities`, queried once per context, and again after any context
+    /// is lost.
+    fn cached_capabilities(&self) -> Gl3dCaps {
+        let losses = CONTEXT_LOSSES.load(Ordering::Relaxed);
+        match caps_cache().lock().unwrap().get(&self.unwrap()) {
+            Some(&(queried_at, ref caps)) if queried_at == losses => return caps.clone(),
+            _ => {}
+        }
+        let caps = self.capabilities();
+        caps_cache().lock().unwrap().insert(self.unwrap(), (losses, caps.clone()));
+        caps
+    }
+    /// Runs `f` and returns how long the GPU took to execute the commands it
+    /// issued. `None` if the context doesn't support
+    /// `EXT_disjoint_timer_query`, or if the timing was disturbed, eg by the
+    /// GPU changing clock speed, and so thrown away.
+    ///
+    /// This waits for the GPU to finish `f`'s commands, stalling the
+    /// pipeline, so it's for profiling rather than every frame. Timings of
+    /// over about 4 seconds wrap, since PPAPI only reads back 32 bits.
+    pub fn time_frame<F: FnOnce()>(&self, f: F) -> Option<TimeDelta> {
+        let query_if = match ppb::get_gles2_query_opt() {
+            Some(query_if) if self.capabilities().disjoint_timer_query => query_if,
+            _ => {
+                f();
+                return None;
+            }
+        };
+        let is_disjoint = || -> bool {
+            let mut disjoint: types::Int = 0;
+            call_gl_fun!(get_gles2() => GetIntegerv => (self,
+                                                        consts::GPU_DISJOINT_EXT,
+                                                        &mut disjoint as *mut types::Int));
+            disjoint != 0
+        };
+
+        let mut query: types::UInt = 0;
+        call_gl_fun!(query_if => GenQueriesEXT => (self, 1, &mut query as *mut types::UInt));
+        // Reading the flag clears it, so only disjoint events during `f`
+        // count below.
+        let _ = is_disjoint();
+        call_gl_fun!(query_if => BeginQueryEXT => (self, consts::TIME_ELAPSED_EXT, query));
+        f();
+        call_gl_fun!(query_if => EndQueryEXT => (self, consts::TIME_ELAPSED_EXT));
+
+        // Asking for the result blocks until it's available.
+        let mut elapsed_ns: types::UInt = 0;
+        call_gl_fun!(query_if => GetQueryObjectuivEXT => (self,
+                                                          query,
+                                                          consts::QUERY_RESULT_EXT,
+                                                          &mut elapsed_ns as *mut types::UInt));
+        let disjoint = is_disjoint();
+        call_gl_fun!(query_if => DeleteQueriesEXT => (self, 1, &query as *const types::UInt));
+
+        if disjoint {
+            None
+        } else {
+            Some(elapsed_ns as TimeDelta / 1_000_000_000.0)
+        }
+    }
+
+    /// Reads back the values the context was actually given for `keys`,
+    /// which may differ from what was asked for, eg a 16 bit depth buffer
+    /// when 24 bits were requested.
+    pub fn get_attribs(&self, keys: &[AttribKey]) -> super::Result<Vec<(AttribKey, i32)>> {
+        use ppb::Graphics3DIf;
+        let values = try!(ppb::get_graphics_3d()
+                          .attribs(self.unwrap(), keys.iter().map(|k| k.to_ffi() ).collect()));
+        Ok(keys.iter()
+           .cloned()
+           .zip(values.into_iter().map(|v| v as i32 ))
+           .collect())
+    }
+    /// The context's error state, eg `Code::ContextLost`; `Code::Ok` if it's
+    /// usable. This is `PPB_Graphics3D`'s error, not `glGetError`'s.
+    pub fn get_error(&self) -> Code {
+        use ppb::Graphics3DIf;
+        ppb::get_graphics_3d().status(self.unwrap())
+    }
     // Out of memory errors will be provided on the next swap_buffers callback.
     pub fn resize_buffers(&self, width: u32, height: u32) -> super::Code {
         use libc;
@@ -1882,8 +2496,233 @@ impl Context3d {
         use ppb::Graphics3DIf;
         let interface = ppb::get_graphics_3d();
 
+        let ctxt = self.unwrap();
+        // Let frames requested with `Instance::request_frame` run once this
+        // swap completes.
+        super::frame_swap_started(ctxt);
+        let CallbackArgs { optional, f, .. } = next_frame;
+        let next_frame = CallbackArgs {
+            optional: optional,
+            f: move |code: Code<()>| {
+                f.call_once((code, ));
+                super::frame_swap_finished(ctxt);
+            },
+            _1: PhantomData,
+        };
         let cc = next_frame.to_ffi_callback((), Default::default());
-        let r = interface.swap_buffers(self.unwrap(), cc.cc());
-        cc.drop_with_code(r)
+        let r = interface.swap_buffers(ctxt, cc.cc());
+        let r = cc.drop_with_code(r);
+        if !r.completion_pending() {
+            // The callback won't be called.
+            super::frame_swap_finished(ctxt);
+        }
+        r
+    }
+}
+
+/// The state changes a `StateCache` passes on. `Context3d` makes the GL
+/// calls; anything else, eg a recorder in tests, can stand in for it.
+pub trait StateTarget {
+    fn use_program(&self, program: types::UInt);
+    /// `target` is eg `consts::ARRAY_BUFFER`.
+    fn bind_buffer(&self, target: types::Enum, buffer: types::UInt);
+    fn bind_framebuffer(&self, framebuffer: types::UInt);
+    fn bind_renderbuffer(&self, renderbuffer: types::UInt);
+    /// `unit` is eg `consts::TEXTURE0`.
+    fn active_texture(&self, unit: types::Enum);
+    fn bind_texture(&self, target: types::Enum, texture: types::UInt);
+    fn set_enabled(&self, cap: types::Enum, enabled: bool);
+}
+impl StateTarget for Context3d {
+    fn use_program(&self, program: types::UInt) {
+        call_gl_fun!(get_gles2() => UseProgram => (self, program))
+    }
+    fn bind_buffer(&self, target: types::Enum, buffer: types::UInt) {
+        call_gl_fun!(get_gles2() => BindBuffer => (self, target, buffer))
+    }
+    fn bind_framebuffer(&self, framebuffer: types::UInt) {
+        call_gl_fun!(get_gles2() => BindFramebuffer => (self, consts::FRAMEBUFFER,
+                                                        framebuffer))
+    }
+    fn bind_renderbuffer(&self, renderbuffer: types::UInt) {
+        call_gl_fun!(get_gles2() => BindRenderbuffer => (self, consts::RENDERBUFFER,
+                                                         renderbuffer))
+    }
+    fn active_texture(&self, unit: types::Enum) {
+        call_gl_fun!(get_gles2() => ActiveTexture => (self, unit))
+    }
+    fn bind_texture(&self, target: types::Enum, texture: types::UInt) {
+        call_gl_fun!(get_gles2() => BindTexture => (self, target, texture))
+    }
+    fn set_enabled(&self, cap: types::Enum, enabled: bool) {
+        if enabled {
+            call_gl_fun!(get_gles2() => Enable => (self, cap))
+        } else {
+            call_gl_fun!(get_gles2() => Disable => (self, cap))
+        }
+    }
+}
+
+static CONTEXT_LOSSES: AtomicUsize = ATOMIC_USIZE_INIT;
+/// Called when any context is lost, so `StateCache`s forget what they knew.
+#[doc(hidden)]
+pub fn note_context_lost() {
+    CONTEXT_LOSSES.fetch_add(1, Ordering::Relaxed);
+}
+
+// Each context's capabilities, with the number of losses when they were
+// queried. An entry is dropped when a context is created: a context which was
+// just dropped leaves its entry behind, and its id may be handed out again.
+static CAPS_CACHE_INIT: Once = ONCE_INIT;
+static mut CAPS_CACHE: *const Mutex<HashMap<ffi::PP_Resource, (usize, Gl3dCaps)>> =
+    0 as *const Mutex<HashMap<ffi::PP_Resource, (usize, Gl3dCaps)>>;
+fn caps_cache() -> &'static Mutex<HashMap<ffi::PP_Resource, (usize, Gl3dCaps)>> {
+    CAPS_CACHE_INIT.call_once(|| unsafe {
+        CAPS_CACHE = mem::transmute(Box::new(Mutex::new(HashMap::new())));
+    });
+    unsafe { &*CAPS_CACHE }
+}
+/// Called with each newly created context, so it doesn't inherit the
+/// capabilities of a dropped context which had the same id.
+#[doc(hidden)]
+pub fn note_created(ctxt: ffi::PP_Resource) {
+    caps_cache().lock().unwrap().remove(&ctxt);
+}
+
+/// Remembers the bound program, buffers and textures, and which caps are
+/// enabled, so setting them to what they already are doesn't reach GL. Only
+/// state changed through the cache is known: call `invalidate` after
+/// changing any of it some other way, including by deleting a bound
+/// object. Everything is forgotten when a context is lost.
+pub struct StateCache<T: StateTarget = Context3d> {
+    target: T,
+    losses: usize,
+    program: Option<types::UInt>,
+    buffers: HashMap<types::Enum, types::UInt>,
+    framebuffer: Option<types::UInt>,
+    renderbuffer: Option<types::UInt>,
+    active_texture: Option<types::Enum>,
+    /// Keyed by texture unit, `None` being whichever unit was active when
+    /// the cache was last invalidated, and target.
+    textures: HashMap<(Option<types::Enum>, types::Enum), types::UInt>,
+    caps: HashMap<types::Enum, bool>,
+}
+impl StateCache<Context3d> {
+    pub fn new(ctxt: Context3d) -> StateCache<Context3d> {
+        StateCache::with_target(ctxt)
+    }
+}
+impl<T: StateTarget> StateCache<T> {
+    pub fn with_target(target: T) -> StateCache<T> {
+        StateCache {
+            target: target,
+            losses: CONTEXT_LOSSES.load(Ordering::Relaxed),
+            program: None,
+            buffers: HashMap::new(),
+            framebuffer: None,
+            renderbuffer: None,
+            active_texture: None,
+            textures: HashMap::new(),
+            caps: HashMap::new(),
+        }
+    }
+    pub fn target(&self) -> &T { &self.target }
+
+    /// Forgets all state, so the next change of each is passed on.
+    pub fn invalidate(&mut self) {
+        self.program = None;
+        self.buffers.clear();
+        self.framebuffer = None;
+        self.renderbuffer = None;
+        self.active_texture = None;
+        self.textures.clear();
+        self.caps.clear();
+    }
+    fn check_lost(&mut self) {
+        let losses = CONTEXT_LOSSES.load(Ordering::Relaxed);
+        if losses != self.losses {
+            self.losses = losses;
+            self.invalidate();
+        }
+    }
+
+    pub fn use_program(&mut self, program: &ShaderProgram) {
+        self.check_lost();
+        let program = program.unwrap();
+        if self.program == Some(program) { return; }
+        self.target.use_program(program);
+        self.program = Some(program);
+    }
+    pub fn bind_vertex_buffer(&mut self, buffer: &VertexBuffer) {
+        use self::traits::Buffer;
+        self.bind_buffer(consts::ARRAY_BUFFER, buffer.unwrap());
+    }
+    pub fn bind_index_buffer(&mut self, buffer: &IndexBuffer) {
+        use self::traits::Buffer;
+        self.bind_buffer(consts::ELEMENT_ARRAY_BUFFER, buffer.unwrap());
+    }
+    fn bind_buffer(&mut self, target: types::Enum, buffer: types::UInt) {
+        self.check_lost();
+        if self.buffers.get(&target) == Some(&buffer) { return; }
+        self.target.bind_buffer(target, buffer);
+        self.buffers.insert(target, buffer);
+    }
+    pub fn bind_framebuffer(&mut self, framebuffer: &FrameBuffer) {
+        use self::traits::Buffer;
+        self.check_lost();
+        let framebuffer = framebuffer.unwrap();
+        if self.framebuffer == Some(framebuffer) { return; }
+        self.target.bind_framebuffer(framebuffer);
+        self.framebuffer = Some(framebuffer);
+    }
+    pub fn bind_renderbuffer(&mut self, renderbuffer: &RenderBuffer) {
+        use self::traits::Buffer;
+        self.check_lost();
+        let renderbuffer = renderbuffer.unwrap();
+        if self.renderbuffer == Some(renderbuffer) { return; }
+        self.target.bind_renderbuffer(renderbuffer);
+        self.renderbuffer = Some(renderbuffer);
+    }
+    /// `slot` is added to `consts::TEXTURE0`, as with
+    /// `Context3d::activate_tex_slot`.
+    pub fn activate_tex_slot(&mut self, slot: types::Enum) {
+        self.check_lost();
+        let unit = slot + consts::TEXTURE0;
+        if self.active_texture == Some(unit) { return; }
+        self.target.active_texture(unit);
+        if self.active_texture.is_none() {
+            // We no longer know which unit those were bound to.
+            let unknown: Vec<_> = self.textures.keys()
+                .filter(|&&(unit, _)| unit.is_none() )
+                .cloned()
+                .collect();
+            for key in unknown.iter() {
+                self.textures.remove(key);
+            }
+        }
+        self.active_texture = Some(unit);
+    }
+    /// Binds `texture` to `target` of the active texture unit.
+    pub fn bind_texture(&mut self, target: types::Enum, texture: &TextureBuffer) {
+        use self::traits::Buffer;
+        self.check_lost();
+        let texture = texture.unwrap();
+        let key = (self.active_texture, target);
+        if self.textures.get(&key) == Some(&texture) { return; }
+        self.target.bind_texture(target, texture);
+        self.textures.insert(key, texture);
+    }
+    /// `cap` is eg `consts::BLEND`.
+    pub fn enable(&mut self, cap: types::Enum) {
+        self.set_enabled(cap, true);
+    }
+    pub fn disable(&mut self, cap: types::Enum) {
+        self.set_enabled(cap, false);
+    }
+    fn set_enabled(&mut self, cap: types::Enum, enabled: bool) {
+        self.check_lost();
+        if self.caps.get(&cap) == Some(&enabled) { return; }
+        self.target.set_enabled(cap, enabled);
+        self.caps.insert(cap, enabled);
     }
 }
@@ -15,7 +15,10 @@ use super::ppb::{get_url_loader, get_url_loader_opt,
 use ppb::{self, URLRequestInfoIf, URLResponseInfoIf, URLLoaderIf};
 
 use std::borrow::Cow;
+use std::cell::RefCell;
 use std::io;
+use std::mem;
+use std::rc::Rc;
 use std::str::FromStr;
 use std::ops::Deref;
 
@@ -91,11 +94,38 @@ impl Into<ffi::PP_URLRequestProperty> for RequestProperties_ {
     }
 }
 
+/// A part of a file to upload: the file, the offset to start at, and how
+/// many bytes to send. A `None` offset starts at the beginning and a `None`
+/// length sends everything to the end of the file.
+#[derive(Clone, Hash, Eq, PartialEq, Debug)]
+pub struct FileSliceRef(pub fs::FileRef, pub Option<i64>, pub Option<i64>);
+impl FileSliceRef {
+    /// `Code::BadArgument` if the offset or length is negative.
+    fn check(&self) -> Code<()> {
+        match (self.1, self.2) {
+            (Some(start), _) if start < 0 => Code::BadArgument,
+            (_, Some(len)) if len < 0 => Code::BadArgument,
+            _ => Code::Ok(()),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum Body {
     File(SliceIo, Option<super::Time>),
+    /// Streams a slice of a file. If a time is given, the upload fails unless
+    /// the file was last modified at that time.
+    FileSlice(FileSliceRef, Option<super::Time>),
     Blob(Vec<u8>),
 }
+impl Body {
+    fn check(&self) -> Code<()> {
+        match self {
+            &Body::FileSlice(ref slice, _) => slice.check(),
+            _ => Code::Ok(()),
+        }
+    }
+}
 
 pub type Method = hyper::method::Method;
 
@@ -188,6 +218,15 @@ impl RequestInfo {
         was_set
     }
 
+    /// Adds `body` after any bodies already added. Returns
+    /// `Code::BadArgument`, leaving the request as is, if `body` is a
+    /// `FileSlice` with a negative offset or length.
+    pub fn append_body(&mut self, body: Body) -> Code<()> {
+        try_code!(body.check());
+        self.bodies.push(body);
+        Code::Ok(())
+    }
+
     pub fn follow_redirects(mut self) -> RequestInfo {
         self.set_prop_value(RequestProperties_::FollowRedirects, true);
         self
@@ -225,6 +264,10 @@ impl RequestInfo {
         let RequestInfo {
             bodies, headers, url, method, ..
         } = self;
+        // `bodies` is public, so these might not have been checked yet.
+        for body in bodies.iter() {
+            try_code!(body.check());
+        }
         for body in bodies.into_iter() {
             let success = match body {
                 Body::File(ref slice, time) => {
@@ -234,6 +277,13 @@ impl RequestInfo {
                                                 slice.view_len().map(|v| v as i64 ),
                                                 time)
                 }
+                Body::FileSlice(FileSliceRef(ref file, start, len), time) => {
+                    request.append_file_to_body(res.unwrap(),
+                                                file.unwrap(),
+                                                start,
+                                                len,
+                                                time)
+                }
                 Body::Blob(ref blob) => {
                     request.append_blob_to_body(res.unwrap(),
                                                 blob)
@@ -401,7 +451,7 @@ impl From<ffi::PP_Resource> for Loader {
 
         Loader {
             res: From::from(loader),
-            info: ResponseInfo(info),
+            info: ResponseInfo::new(info),
         }
     }
 }
@@ -417,7 +467,7 @@ impl Loader {
         let mut total = unsafe { mem::uninitialized() };
 
         let f = get_url_loader().GetDownloadProgress.unwrap();
-        if !f(self.unwrap(), &mut bytes, &mut total) != 0 {
+        if f(self.unwrap(), &mut bytes, &mut total) == ffi::PP_FALSE {
             None
         } else {
             if total == -1 {
@@ -433,7 +483,7 @@ impl Loader {
         let mut total = unsafe { mem::uninitialized() };
 
         let f = get_url_loader().GetUploadProgress.unwrap();
-        if !f(self.unwrap(), &mut bytes, &mut total) != 0 {
+        if f(self.unwrap(), &mut bytes, &mut total) == ffi::PP_FALSE {
             None
         } else {
             Some((bytes as u64, total as u64))
@@ -442,7 +492,31 @@ impl Loader {
 
     pub fn finish_streaming_to_file(&self) { unimplemented!() }
 
+    /// The response the loader was opened with. See `get_response_info`
+    /// for the response after following redirects.
     pub fn info(&self) -> ResponseInfo { self.info.clone() }
+    /// The loader's current response. If the request doesn't set
+    /// `FollowRedirects`, this is the redirect response while the loader is
+    /// paused at one.
+    pub fn get_response_info(&self) -> Option<ResponseInfo> {
+        get_url_loader()
+            .get_response_info(self.unwrap())
+            .map(|info| ResponseInfo::new(info) )
+    }
+    /// Follows the redirect the loader is paused at. Returns `Code::Failed`
+    /// if it isn't at a redirect.
+    pub fn follow_redirect<F>(&self, callback: CallbackArgs<F, ()>) -> Code<()>
+        where F: FnOnce(Code<()>)
+    {
+        let at_redirect = self.get_response_info()
+            .map(|info| info.raw_redirect_url() != "" )
+            .unwrap_or(false);
+        if !at_redirect { return Code::Failed; }
+
+        let cc = callback.to_ffi_callback((), Default::default());
+        let code = get_url_loader().follow_redirect(self.unwrap(), cc.cc());
+        cc.drop_with_code(code)
+    }
 
     /// Completes when the response headers are received.
     pub fn async_open<F>(instance: Instance, info: UrlRequestInfo,
@@ -469,6 +543,44 @@ impl Loader {
                                         BlockUntilComplete::new()));
         Code::Ok(From::from(loader))
     }
+
+    /// Cancels the request. A pending open, redirect or read completes with
+    /// `Code::Aborted`, as do reads started afterwards, so `read_to_end`,
+    /// `get` and friends finish instead of waiting on the rest of the body.
+    /// Closing a closed loader does nothing. The resource itself is
+    /// released once every clone of the loader is dropped.
+    pub fn close(&self) {
+        get_url_loader().close(self.unwrap());
+    }
+
+    /// Reads the rest of the body, `BODY_READ_SIZE` bytes at a time. If a
+    /// read fails, eg because the loader was closed, `callback` gets that
+    /// error and what was read is dropped.
+    pub fn read_to_end<F>(&self, callback: CallbackArgs<F, Vec<u8>>) -> Code<Vec<u8>>
+        where F: FnOnce(Code<Vec<u8>>) + 'static
+    {
+        use fs::AsyncRead;
+
+        let mut loader = self.clone();
+        let state = Rc::new(RefCell::new(ReadToEnd {
+            loader: self.clone(),
+            body: Vec::new(),
+            callback: Some(callback),
+        }));
+        let state2 = state.clone();
+        let cb = CallbackArgs::new(move |code: Code<Cow<'static, [u8]>>| {
+            read_to_end_finished(state2, code);
+        });
+        match loader.async_read(0, BODY_READ_SIZE, cb) {
+            Code::CompletionPending => Code::CompletionPending,
+            Code::Ok(data) => {
+                read_to_end_finished(state, Code::Ok(data));
+                Code::CompletionPending
+            }
+            // Like other async ops, `callback` isn't called.
+            code => code.map_err(),
+        }
+    }
 }
 
 impl fs::AsyncRead for Loader {
@@ -479,11 +591,14 @@ impl fs::AsyncRead for Loader {
         use super::StorageToArgsMapper;
         if offset != 0 { return Code::BadArgument; }
 
-        fn map_arg<'a>(raw: Vec<u8>, _status: Code) -> Cow<'a, [u8]> {
+        fn map_arg<'a>(mut raw: Vec<u8>, status: Code) -> Cow<'a, [u8]> {
+            if let Code::Ok(read) = status {
+                raw.truncate(read);
+            }
             Cow::Owned(raw)
         }
 
-        let raw_args: Vec<u8> = Default::default();
+        let raw_args: Vec<u8> = vec![0; size];
         let mapper = StorageToArgsMapper(map_arg);
         let mut cc = callback.to_ffi_callback(raw_args, mapper);
         let fficc = cc.cc();
@@ -513,3 +628,366 @@ impl io::Read for Loader {
         Ok(read as usize)
     }
 }
+
+/// How much of a response body `get` and `post` ask for per read.
+pub const BODY_READ_SIZE: usize = 64 * 1024;
+
+/// A whole response, as delivered by `get` and `post`.
+#[derive(Clone, Debug)]
+pub struct Response {
+    /// The final status, after redirects. Statuses outside of 2xx aren't
+    /// treated as errors.
+    pub status: u16,
+    pub headers: Headers,
+    pub body: Vec<u8>,
+    /// Set if reading the body failed partway; `body` has what was read
+    /// before the failure.
+    pub body_error: Option<Code<()>>,
+}
+impl Response {
+    pub fn is_success(&self) -> bool {
+        self.status / 100 == 2 && self.body_error.is_none()
+    }
+}
+
+/// Fetches `url`, following redirects, and reads the whole response.
+/// `callback` gets an error code only if the request couldn't be made, eg a
+/// network error; see `Response` for everything else.
+pub fn get<F>(instance: &Instance, url: &str,
+              callback: CallbackArgs<F, Response>) -> Code<Response>
+    where F: FnOnce(Code<Response>) + 'static
+{
+    let url = match url.parse() {
+        Ok(url) => url,
+        Err(_) => return Code::BadArgument,
+    };
+    let request = RequestInfo::new(url, Method::Get, None, None)
+        .follow_redirects();
+    fetch(*instance, request, callback)
+}
+/// Like `get`, but POSTs `body` as `content_type`.
+pub fn post<F>(instance: &Instance, url: &str, body: &[u8], content_type: &str,
+               callback: CallbackArgs<F, Response>) -> Code<Response>
+    where F: FnOnce(Code<Response>) + 'static
+{
+    let url = match url.parse() {
+        Ok(url) => url,
+        Err(_) => return Code::BadArgument,
+    };
+    let mut headers = Headers::new();
+    headers.set_raw("Content-Type", vec![content_type.as_bytes().to_vec()]);
+    let request = RequestInfo::new(url, Method::Post, Some(Body::Blob(body.to_vec())),
+                                   Some(headers))
+        .follow_redirects();
+    fetch(*instance, request, callback)
+}
+
+struct Fetch<F>
+    where F: FnOnce(Code<Response>),
+{
+    loader: Option<Loader>,
+    response: Option<Response>,
+    callback: Option<CallbackArgs<F, Response>>,
+}
+
+fn fetch<F>(instance: Instance, request: RequestInfo,
+            callback: CallbackArgs<F, Response>) -> Code<Response>
+    where F: FnOnce(Code<Response>) + 'static
+{
+    let request = try_code!(request.create_resource(instance));
+    let state = Rc::new(RefCell::new(Fetch {
+        loader: None,
+        response: None,
+        callback: Some(callback),
+    }));
+
+    let state2 = state.clone();
+    let code = Loader::async_open(instance, request,
+                                  CallbackArgs::new(move |loader: Code<Loader>| {
+        match loader {
+            Code::Ok(loader) => fetch_opened(state2, loader),
+            code => fetch_done(state2, code.map_err()),
+        }
+    }));
+    if code.completion_pending() {
+        Code::CompletionPending
+    } else {
+        // Like other async ops, `callback` isn't called.
+        state.borrow_mut().callback.take();
+        match code {
+            Code::Ok(_) => Code::Failed,
+            code => code.map_err(),
+        }
+    }
+}
+fn fetch_opened<F>(state: Rc<RefCell<Fetch<F>>>, loader: Loader)
+    where F: FnOnce(Code<Response>) + 'static
+{
+    // Redirects have been followed, so this is the final response.
+    let info = loader.get_response_info().unwrap_or_else(|| loader.info() );
+    {
+        let mut s = state.borrow_mut();
+        s.response = Some(Response {
+            status: info.raw_status_code(),
+            headers: info.headers(),
+            body: Vec::new(),
+            body_error: None,
+        });
+        s.loader = Some(loader);
+    }
+    fetch_read(state);
+}
+fn fetch_read<F>(state: Rc<RefCell<Fetch<F>>>)
+    where F: FnOnce(Code<Response>) + 'static
+{
+    use fs::AsyncRead;
+
+    let mut loader = state.borrow().loader.clone().unwrap();
+    let state2 = state.clone();
+    let cb = CallbackArgs::new(move |code: Code<Cow<'static, [u8]>>| {
+        fetch_read_finished(state2, code);
+    });
+    match loader.async_read(0, BODY_READ_SIZE, cb) {
+        Code::CompletionPending => {},
+        code => fetch_read_finished(state, code),
+    }
+}
+fn fetch_read_finished<F>(state: Rc<RefCell<Fetch<F>>>, code: Code<Cow<'static, [u8]>>)
+    where F: FnOnce(Code<Response>) + 'static
+{
+    match code {
+        Code::Ok(ref data) if data.is_empty() => {},
+        Code::Ok(data) => {
+            state.borrow_mut().response.as_mut().unwrap()
+                .body.extend(data.iter().cloned());
+            return fetch_read(state);
+        }
+        code => {
+            state.borrow_mut().response.as_mut().unwrap()
+                .body_error = Some(code.map_err());
+        }
+    }
+    let response = state.borrow_mut().response.take().unwrap();
+    fetch_done(state, Code::Ok(response));
+}
+fn fetch_done<F>(state: Rc<RefCell<Fetch<F>>>, code: Code<Response>)
+    where F: FnOnce(Code<Response>) + 'static
+{
+    let callback = {
+        let mut s = state.borrow_mut();
+        s.loader.take();
+        s.callback.take()
+    };
+    if let Some(callback) = callback {
+        callback.call_directly(code);
+    }
+}
+
+struct ReadToEnd<F>
+    where F: FnOnce(Code<Vec<u8>>),
+{
+    loader: Loader,
+    body: Vec<u8>,
+    callback: Option<CallbackArgs<F, Vec<u8>>>,
+}
+
+fn read_to_end_next<F>(state: Rc<RefCell<ReadToEnd<F>>>)
+    where F: FnOnce(Code<Vec<u8>>) + 'static
+{
+    use fs::AsyncRead;
+
+    let mut loader = state.borrow().loader.clone();
+    let state2 = state.clone();
+    let cb = CallbackArgs::new(move |code: Code<Cow<'static, [u8]>>| {
+        read_to_end_finished(state2, code);
+    });
+    match loader.async_read(0, BODY_READ_SIZE, cb) {
+        Code::CompletionPending => {},
+        code => read_to_end_finished(state, code),
+    }
+}
+fn read_to_end_finished<F>(state: Rc<RefCell<ReadToEnd<F>>>, code: Code<Cow<'static, [u8]>>)
+    where F: FnOnce(Code<Vec<u8>>) + 'static
+{
+    let code = match code {
+        Code::Ok(ref data) if data.is_empty() => {
+            Code::Ok(mem::replace(&mut state.borrow_mut().body, Vec::new()))
+        }
+        Code::Ok(data) => {
+            state.borrow_mut().body.extend(data.iter().cloned());
+            return read_to_end_next(state);
+        }
+        code => code.map_err(),
+    };
+    let callback = state.borrow_mut().callback.take();
+    if let Some(callback) = callback {
+        callback.call_directly(code);
+    }
+}
+
+/// How much a `Loader::tee` buffers for its slower reader before it stops
+/// reading the body.
+pub const TEE_BUFFER_LIMIT: usize = 1024 * 1024;
+
+struct TeeState {
+    loader: Loader,
+    limit: usize,
+    reading: bool,
+    /// `Ok` once the whole body has been read.
+    finished: Option<Code<()>>,
+    buffers: [Vec<u8>; 2],
+    closed: [bool; 2],
+    waiting: [Option<Box<FnMut(Code<Vec<u8>>)>>; 2],
+}
+
+/// One side of a `Loader::tee`.
+pub struct BodyReader {
+    state: Rc<RefCell<TeeState>>,
+    side: usize,
+}
+
+impl Loader {
+    /// Splits the response body into two readers which each get all of it,
+    /// eg one for a cache and one for a parser. The body is only read once
+    /// from PPAPI, so the faster reader's data is buffered for the slower
+    /// one, up to `TEE_BUFFER_LIMIT`, past which reading pauses until the
+    /// slower reader catches up. Don't read the body through `self` after
+    /// this.
+    ///
+    /// Since a reader that's never read from stalls the other, drop readers
+    /// which won't be used.
+    pub fn tee(&self) -> (BodyReader, BodyReader) {
+        self.tee_with_limit(TEE_BUFFER_LIMIT)
+    }
+    /// Like `tee`, but reading pauses once `limit` bytes are buffered for
+    /// either reader. At most `limit` plus `BODY_READ_SIZE` bytes are
+    /// buffered per reader.
+    pub fn tee_with_limit(&self, limit: usize) -> (BodyReader, BodyReader) {
+        let state = Rc::new(RefCell::new(TeeState {
+            loader: self.clone(),
+            limit: limit,
+            reading: false,
+            finished: None,
+            buffers: [Vec::new(), Vec::new()],
+            closed: [false, false],
+            waiting: [None, None],
+        }));
+        (BodyReader { state: state.clone(), side: 0 },
+         BodyReader { state: state, side: 1 })
+    }
+}
+
+impl BodyReader {
+    /// Gets the next piece of the body. An empty piece means the whole body
+    /// has been read. Completes immediately with whatever is buffered, if
+    /// anything.
+    pub fn read<F>(&self, callback: CallbackArgs<F, Vec<u8>>) -> Code<Vec<u8>>
+        where F: FnOnce(Code<Vec<u8>>) + 'static
+    {
+        let code = {
+            let mut s = self.state.borrow_mut();
+            if s.waiting[self.side].is_some() { return Code::InProgress; }
+            if !s.buffers[self.side].is_empty() {
+                Code::Ok(mem::replace(&mut s.buffers[self.side], Vec::new()))
+            } else {
+                let finished = s.finished;
+                match finished {
+                    Some(Code::Ok(())) => Code::Ok(Vec::new()),
+                    Some(code) => code.map_err(),
+                    None => {
+                        let mut callback = Some(callback);
+                        s.waiting[self.side] = Some(box move |code| {
+                            callback.take().unwrap().call_directly(code);
+                        });
+                        Code::CompletionPending
+                    }
+                }
+            }
+        };
+        // Either we've made room or we're waiting on more.
+        tee_pump(self.state.clone());
+        code
+    }
+    /// How many bytes are buffered for this reader.
+    pub fn buffered(&self) -> usize {
+        self.state.borrow().buffers[self.side].len()
+    }
+}
+impl Drop for BodyReader {
+    fn drop(&mut self) {
+        {
+            let mut s = self.state.borrow_mut();
+            s.closed[self.side] = true;
+            s.buffers[self.side] = Vec::new();
+            s.waiting[self.side] = None;
+        }
+        // The other reader might have been waiting on this one.
+        tee_pump(self.state.clone());
+    }
+}
+
+fn tee_pump(state: Rc<RefCell<TeeState>>) {
+    use fs::AsyncRead;
+
+    let mut loader = {
+        let s = state.borrow();
+        let behind = (0..2)
+            .filter(|&side| !s.closed[side] )
+            .map(|side| s.buffers[side].len() )
+            .max();
+        let behind = match behind {
+            Some(behind) => behind,
+            // Nobody's reading.
+            None => return,
+        };
+        if s.reading || s.finished.is_some() || behind >= s.limit { return; }
+        s.loader.clone()
+    };
+    state.borrow_mut().reading = true;
+
+    let state2 = state.clone();
+    let cb = CallbackArgs::new(move |code: Code<Cow<'static, [u8]>>| {
+        tee_read_finished(state2, code);
+    });
+    match loader.async_read(0, BODY_READ_SIZE, cb) {
+        Code::CompletionPending => {},
+        code => tee_read_finished(state, code),
+    }
+}
+fn tee_read_finished(state: Rc<RefCell<TeeState>>, code: Code<Cow<'static, [u8]>>) {
+    let mut ready = Vec::new();
+    {
+        let mut s = state.borrow_mut();
+        s.reading = false;
+        match code {
+            Code::Ok(ref data) if data.is_empty() => s.finished = Some(Code::Ok(())),
+            Code::Ok(data) => {
+                for side in 0..2 {
+                    if !s.closed[side] {
+                        s.buffers[side].extend(data.iter().cloned());
+                    }
+                }
+            }
+            code => s.finished = Some(code.map_err()),
+        }
+
+        for side in 0..2 {
+            if s.waiting[side].is_none() { continue; }
+            let code = if !s.buffers[side].is_empty() {
+                Code::Ok(mem::replace(&mut s.buffers[side], Vec::new()))
+            } else {
+                let finished = s.finished;
+                match finished {
+                    Some(Code::Ok(())) => Code::Ok(Vec::new()),
+                    Some(code) => code.map_err(),
+                    None => continue,
+                }
+            };
+            ready.push((s.waiting[side].take().unwrap(), code));
+        }
+    }
+    for (mut callback, code) in ready.into_iter() {
+        callback(code);
+    }
+    tee_pump(state);
+}
@@ -7,10 +7,14 @@
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
 use libc::c_void;
+use std::cmp;
+use std::iter;
 use std::ops;
+use std::ptr;
+use std::slice;
 
 use super::ffi;
-use super::{Resource};
+use super::{Resource, Code, Point, Rect, Size};
 use super::ppb;
 use ppb::ImageDataIf;
 
@@ -19,7 +23,7 @@ use ppb::ImageDataIf;
 impl_resource_for!(ImageData, ResourceType::ImageData);
 impl_clone_drop_for!(ImageData);
 
-#[derive(Eq, PartialEq, Hash, Clone, Copy)]
+#[derive(Eq, PartialEq, Hash, Clone, Copy, Debug)]
 pub enum Format {
     BGRA = ffi::PP_IMAGEDATAFORMAT_BGRA_PREMUL as isize,
     RGBA = ffi::PP_IMAGEDATAFORMAT_RGBA_PREMUL as isize,
@@ -39,8 +43,7 @@ impl Format {
         }
     }
     pub fn is_supported(&self) -> bool {
-        let ffi_val = *self as ffi::PP_ImageDataFormat;
-        (ppb::get_image_data().IsImageDataFormatSupported.unwrap())(ffi_val) != 0
+        is_image_data_format_supported(*self)
     }
 }
 
@@ -77,10 +80,17 @@ pub trait MappedSlice<'a> {
 impl<'a> MappedSlice<'a> for MappedImage<'a> {
     fn as_imm_slice(&self) -> &'a [u8] {
         use std::slice::from_raw_parts;
-        use std::mem::transmute;
         let size = (self.desc.size.height * self.desc.line_stride) as usize;
 
-        unsafe { from_raw_parts(transmute(&self.ptr), size) }
+        unsafe { from_raw_parts(self.ptr as *const u8, size) }
+    }
+}
+impl<'a> MappedImage<'a> {
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        use std::slice::from_raw_parts_mut;
+        let size = (self.desc.size.height * self.desc.line_stride) as usize;
+
+        unsafe { from_raw_parts_mut(self.ptr as *mut u8, size) }
     }
 }
 impl<'a> ops::Drop for MappedImage<'a> {
@@ -89,9 +99,14 @@ impl<'a> ops::Drop for MappedImage<'a> {
     }
 }
 
+/// The browser's preferred format; painting images in any other format into
+/// a 2D context will fail.
 pub fn native_image_data_format() -> Format {
     Format::from_ffi(ppb::get_image_data().native_image_data_format())
 }
+pub fn is_image_data_format_supported(format: Format) -> bool {
+    ppb::get_image_data().is_image_data_format_supported(format.to_ffi())
+}
 
 impl ImageData {
     pub fn describe(&self) -> Option<Description> {
@@ -107,3 +122,114 @@ impl ImageData {
         }
     }
 }
+
+/// How `ImageData::blit` combines the source pixels with the destination's.
+#[derive(Eq, PartialEq, Hash, Clone, Copy, Debug)]
+pub enum BlendMode {
+    /// Replaces the destination pixels.
+    Copy,
+    /// Draws the source over the destination. Both formats are
+    /// premultiplied, so each channel is `src + dst * (1 - src_alpha)`.
+    SrcOver,
+}
+
+impl ImageData {
+    /// Copies the pixels of `src` inside `src_rect` to `dst` in this image.
+    /// The copy is clipped to both images. Returns `Code::BadArgument` if the
+    /// images don't share a format. `src` may be this image.
+    pub fn blit(&mut self, src: &ImageData, src_rect: Rect, dst: Point,
+                blend: BlendMode) -> Code<()> {
+        let dst_map = self.map();
+        let src_map = src.map();
+        if dst_map.desc.format != src_map.desc.format { return Code::BadArgument; }
+
+        let (width, height) = clip(dst_map.desc.size, dst, src_map.desc.size, src_rect);
+        if width == 0 || height == 0 { return Code::Ok(()); }
+
+        // Go bottom up if we'd otherwise overwrite source rows before
+        // reading them.
+        let same = self.unwrap() == src.unwrap();
+        let rows: Vec<u32> = if same && dst.y > src_rect.point.y {
+            (0..height).rev().collect()
+        } else {
+            (0..height).collect()
+        };
+
+        let row_len = width as usize * 4;
+        // Each source row is copied out first, for the same reason.
+        let mut src_row: Vec<u8> = iter::repeat(0u8).take(row_len).collect();
+        for row in rows.into_iter() {
+            let src_offset = (src_rect.point.y + row) as usize * src_map.desc.line_stride as usize +
+                src_rect.point.x as usize * 4;
+            let dst_offset = (dst.y + row) as usize * dst_map.desc.line_stride as usize +
+                dst.x as usize * 4;
+            let dst_row = unsafe {
+                ptr::copy((src_map.ptr as *const u8).offset(src_offset as isize),
+                          src_row.as_mut_ptr(), row_len);
+                slice::from_raw_parts_mut((dst_map.ptr as *mut u8).offset(dst_offset as isize),
+                                          row_len)
+            };
+            match blend {
+                BlendMode::Copy => {
+                    for (d, &s) in dst_row.iter_mut().zip(src_row.iter()) {
+                        *d = s;
+                    }
+                }
+                BlendMode::SrcOver => {
+                    for (d, s) in dst_row.chunks_mut(4).zip(src_row.chunks(4)) {
+                        // Alpha is last in both formats.
+                        let inv_alpha = 255 - s[3] as u32;
+                        for i in 0..4 {
+                            let blended = s[i] as u32 + (d[i] as u32 * inv_alpha + 127) / 255;
+                            d[i] = cmp::min(blended, 255) as u8;
+                        }
+                    }
+                }
+            }
+        }
+        Code::Ok(())
+    }
+}
+
+/// The width and height of `src_rect` left after clipping a copy of it to
+/// `dst_point` against both images.
+fn clip(dst_size: Size, dst_point: Point, src_size: Size, src_rect: Rect) -> (u32, u32) {
+    let x = src_rect.point.x;
+    let y = src_rect.point.y;
+    let width = cmp::min(cmp::min(src_rect.size.width,
+                                  src_size.width.saturating_sub(x)),
+                         dst_size.width.saturating_sub(dst_point.x));
+    let height = cmp::min(cmp::min(src_rect.size.height,
+                                   src_size.height.saturating_sub(y)),
+                          dst_size.height.saturating_sub(dst_point.y));
+    (width, height)
+}
+
+/// Copies the pixels of `src` inside `src_rect` into `dst` at `dst_point`.
+/// The copy is clipped to both images. Returns false, copying nothing, if the
+/// images don't share a format. `dst` and `src` must not be the same image.
+#[doc(hidden)]
+pub fn copy_pixels(dst: &ImageData, dst_point: Point,
+                   src: &ImageData, src_rect: Rect) -> bool {
+    let dst_map = dst.map();
+    let src_map = src.map();
+    if dst_map.desc.format != src_map.desc.format { return false; }
+
+    let x = src_rect.point.x;
+    let y = src_rect.point.y;
+    let (width, height) = clip(dst_map.desc.size, dst_point, src_map.desc.size, src_rect);
+
+    let row_len = width as usize * 4;
+    for row in 0..height {
+        let src_offset = (y + row) as usize * src_map.desc.line_stride as usize +
+            x as usize * 4;
+        let dst_offset = (dst_point.y + row) as usize * dst_map.desc.line_stride as usize +
+            dst_point.x as usize * 4;
+        unsafe {
+            ptr::copy((src_map.ptr as *const u8).offset(src_offset as isize),
+                      (dst_map.ptr as *mut u8).offset(dst_offset as isize),
+                      row_len);
+        }
+    }
+    true
+}
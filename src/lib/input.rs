@@ -6,7 +6,7 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
-use super::{Resource, ResourceType, Ticks, FloatPoint,
+use super::{Resource, ResourceType, Ticks, TimeDelta, FloatPoint,
             StringVar, Point, TouchPoint};
 use super::{ppb, ffi};
 use ppb::{InputEventIf, KeyboardInputEventIf, MouseInputEventIf,
@@ -35,9 +35,112 @@ impl_clone_drop_for!(KeyboardInputEvent);
 impl_clone_drop_for!(MouseInputEvent);
 impl_clone_drop_for!(WheelInputEvent);
 impl_clone_drop_for!(TouchInputEvent);
-impl_clone_drop_for!(IMEInputEvent);
+// `IMEInputEvent` owns a `String`, so it can't be bitwise copied like the
+// others.
+impl clone::Clone for IMEInputEvent {
+    fn clone(&self) -> IMEInputEvent {
+        (ppb::get_core().AddRefResource.unwrap())(self.res);
+        IMEInputEvent {
+            res: self.res,
+            string: self.string.clone(),
+            segments_len: self.segments_len,
+        }
+    }
+}
+impl Drop for IMEInputEvent {
+    fn drop(&mut self) {
+        (ppb::get_core().ReleaseResource.unwrap())(self.res);
+    }
+}
+
+impl KeyboardInputEvent {
+    /// Creates a synthetic key event, eg for testing. `event_type` should be
+    /// one of the key event types. Returns `None` if the browser refuses.
+    pub fn create(instance: &super::Instance, event_type: EventType, timestamp: Ticks,
+                  modifiers: &Modifiers, key_code: u32) -> Option<KeyboardInputEvent> {
+        use super::ToVar;
+        let text = StringVar::new("");
+        let res = (ppb::get_keyboard_event().Create.unwrap())
+            (instance.unwrap(), event_type.to_ffi(), timestamp,
+             modifiers_to_bitset(modifiers), key_code, text.to_var(), text.to_var());
+        if res != 0 {
+            Some(KeyboardInputEvent::new(res))
+        } else {
+            None
+        }
+    }
+}
+
+impl MouseInputEvent {
+    /// Creates a synthetic mouse event, eg for testing. `event_type` should be
+    /// one of the mouse event types. Returns `None` if the browser refuses.
+    pub fn create(instance: &super::Instance, event_type: EventType, timestamp: Ticks,
+                  modifiers: &Modifiers, button: MouseButton, position: Point,
+                  click_count: i32, movement: Point) -> Option<MouseInputEvent> {
+        let position: ffi::PP_Point = position.into();
+        let movement: ffi::PP_Point = movement.into();
+        let res = (ppb::get_mouse_event().Create.unwrap())
+            (instance.unwrap(), event_type.to_ffi(), timestamp,
+             modifiers_to_bitset(modifiers), button.to_ffi(),
+             &position as *const _, click_count, &movement as *const _);
+        if res != 0 {
+            Some(MouseInputEvent::new(res))
+        } else {
+            None
+        }
+    }
+}
+impl WheelInputEvent {
+    /// Creates a synthetic wheel event. Returns `None` if the browser refuses.
+    pub fn create(instance: &super::Instance, timestamp: Ticks, modifiers: &Modifiers,
+                  delta: FloatPoint, ticks: FloatPoint,
+                  scroll_by_page: bool) -> Option<WheelInputEvent> {
+        use super::ToFFIBool;
+        let res = (ppb::get_wheel_event().Create.unwrap())
+            (instance.unwrap(), timestamp, modifiers_to_bitset(modifiers),
+             &delta as *const _, &ticks as *const _, scroll_by_page.to_ffi_bool());
+        if res != 0 {
+            Some(WheelInputEvent::new(res))
+        } else {
+            None
+        }
+    }
+}
+impl TouchInputEvent {
+    /// Creates a synthetic touch event without any touch points. `event_type`
+    /// should be one of the touch event types. Returns `None` if the browser
+    /// refuses.
+    pub fn create(instance: &super::Instance, event_type: EventType, timestamp: Ticks,
+                  modifiers: &Modifiers) -> Option<TouchInputEvent> {
+        let res = (ppb::get_touch_event().Create.unwrap())
+            (instance.unwrap(), event_type.to_ffi(), timestamp,
+             modifiers_to_bitset(modifiers));
+        if res != 0 {
+            Some(TouchInputEvent::new(res))
+        } else {
+            None
+        }
+    }
+}
 
 impl IMEInputEvent {
+    /// Creates a synthetic IME event with a single segment covering `text`.
+    /// `event_type` should be one of the IME event types. Returns `None` if the
+    /// browser refuses.
+    pub fn create(instance: &super::Instance, event_type: EventType, timestamp: Ticks,
+                  text: &str) -> Option<IMEInputEvent> {
+        use super::ToVar;
+        let var = StringVar::new(text);
+        let offsets = [0u32, text.len() as u32];
+        let res = (ppb::get_ime_event().Create.unwrap())
+            (instance.unwrap(), event_type.to_ffi(), timestamp, var.to_var(),
+             1, offsets.as_ptr(), 0, 0, 0);
+        if res != 0 {
+            Some(IMEInputEvent::new(res))
+        } else {
+            None
+        }
+    }
     pub fn new(res: ffi::PP_Resource) -> IMEInputEvent {
         let var = (ppb::get_ime_event().GetText.unwrap())(res);
         let string = StringVar::new_from_var(var).to_string();
@@ -95,11 +198,12 @@ impl Class {
         // are actually the same. We need this so as to bypass transmute
         // on value types (which fails when the types aren't the same
         // size).
-        fn cast_to_expected<T, U>(mut res: T) -> U {
-            use std::mem::{replace, uninitialized, transmute};
+        fn cast_to_expected<T, U>(res: T) -> U {
+            use std::ptr;
             unsafe {
-                let res_ptr: &mut U = transmute(&mut res);
-                replace(res_ptr, uninitialized())
+                let cast = ptr::read(&res as *const T as *const U);
+                mem::forget(res);
+                cast
             }
         }
 
@@ -110,13 +214,13 @@ impl Class {
         //let ime_event = ppb::get_ime_event();
         //let touch_event = ppb::get_touch_event();
 
-        match input_event.type_of(&res.unwrap()) {
-            ffi::PP_INPUTEVENT_TYPE_MOUSEDOWN => {
+        match From::from(input_event.type_of(&res.unwrap())) {
+            EventType::MouseDown => {
                 Class::Mouse(Event {
                     event: MouseEvent::Press(Press::Down,
                                       MouseClickEvent {
                                           point: mouse_event.point(&res.unwrap()),
-                                          button: MouseButton::from_ffi
+                                          button: From::from
                                               (mouse_event.button(&res.unwrap())),
                                           click_count: mouse_event.click_count(&res.unwrap()),
                                       }),
@@ -125,12 +229,12 @@ impl Class {
                     mods: modifiers,
                 })
             }
-            ffi::PP_INPUTEVENT_TYPE_MOUSEUP => {
+            EventType::MouseUp => {
                 Class::Mouse(Event {
                     event: MouseEvent::Press(Press::Up,
                                       MouseClickEvent {
                                           point: mouse_event.point(&res.unwrap()),
-                                          button: MouseButton::from_ffi
+                                          button: From::from
                                               (mouse_event.button(&res.unwrap())),
                                           click_count: mouse_event.click_count(&res.unwrap()),
                                       }),
@@ -139,7 +243,7 @@ impl Class {
                     mods: modifiers,
                 })
             }
-            ffi::PP_INPUTEVENT_TYPE_MOUSEMOVE => {
+            EventType::MouseMove => {
                 Class::Mouse(Event {
                     event: MouseEvent::Move(Move::Move,
                                      MouseMoveEvent {
@@ -152,7 +256,7 @@ impl Class {
                     mods: modifiers,
                 })
             }
-            ffi::PP_INPUTEVENT_TYPE_MOUSEENTER => {
+            EventType::MouseEnter => {
                 Class::Mouse(Event {
                     event: MouseEvent::Move(Move::Enter,
                                      MouseMoveEvent {
@@ -165,7 +269,7 @@ impl Class {
                     mods: modifiers,
                 })
             }
-            ffi::PP_INPUTEVENT_TYPE_MOUSELEAVE => {
+            EventType::MouseLeave => {
                 Class::Mouse(Event {
                     event: MouseEvent::Move(Move::Leave,
                                      MouseMoveEvent {
@@ -178,10 +282,40 @@ impl Class {
                     mods: modifiers,
                 })
             }
-            ffi::PP_INPUTEVENT_TYPE_WHEEL => {
-                unreachable!()
+            EventType::Wheel => {
+                let wheel_event = ppb::get_wheel_event();
+                Class::Wheel(Event {
+                    event: WheelEvent {
+                        delta: wheel_event.delta(&res.unwrap()),
+                        ticks: wheel_event.ticks(&res.unwrap()),
+                        by_page: wheel_event.scroll_by_page(&res.unwrap()),
+                    },
+                    res: cast_to_expected(res),
+                    timestamp: ticks,
+                    mods: modifiers,
+                })
+            }
+            EventType::TouchStart | EventType::TouchMove |
+            EventType::TouchEnd | EventType::TouchCancel => {
+                let res: TouchInputEvent = cast_to_expected(res);
+                Class::Touch(Event {
+                    event: res.clone(),
+                    res: res,
+                    timestamp: ticks,
+                    mods: modifiers,
+                })
+            }
+            EventType::ImeCompositionStart | EventType::ImeCompositionUpdate |
+            EventType::ImeCompositionEnd | EventType::ImeText => {
+                let res: IMEInputEvent = cast_to_expected(res);
+                Class::IME(Event {
+                    event: res.clone(),
+                    res: res,
+                    timestamp: ticks,
+                    mods: modifiers,
+                })
             }
-            ffi::PP_INPUTEVENT_TYPE_KEYDOWN => {
+            EventType::KeyDown => {
                 Class::Keyboard(Event {
                     event: KeyboardEvent::Press(Press::Down,
                                     kb_event.key_code(&res.unwrap()) as i32),
@@ -190,7 +324,7 @@ impl Class {
                     mods: modifiers,
                 })
             }
-            ffi::PP_INPUTEVENT_TYPE_KEYUP => {
+            EventType::KeyUp => {
                 Class::Keyboard(Event {
                     event: KeyboardEvent::Press(Press::Up,
                                     kb_event.key_code(&res.unwrap()) as i32),
@@ -199,10 +333,8 @@ impl Class {
                     mods: modifiers,
                 })
             }
-            ffi::PP_INPUTEVENT_TYPE_CHAR => {
-                let char_var = StringVar((unsafe {
-                    ffi::id_from_var(kb_event.text(&res.unwrap()))
-                }) as i64);
+            EventType::Char => {
+                let char_var = StringVar::adopt(kb_event.text(&res.unwrap()));
                 let str = char_var.to_string();
                 if str.len() != 1 {
                     error!("character input event does not have a length of one: \
@@ -215,7 +347,7 @@ impl Class {
                     event: KeyboardEvent::Char(str.chars().next().unwrap()),
                 })
             }
-            ffi::PP_INPUTEVENT_TYPE_CONTEXTMENU => {
+            EventType::ContextMenu => {
                 Class::Mouse(Event {
                     event: MouseEvent::ContextMenu(MouseClickEvent {
                         point: mouse_event.point(&res.unwrap()),
@@ -233,6 +365,47 @@ impl Class {
         }
     }
 }
+impl Class {
+    /// Same as `InputEvent::event_type`, without needing the trait in scope.
+    pub fn event_type(&self) -> EventType {
+        From::from(ppb::get_input_event().type_of(&self.unwrap()))
+    }
+    /// When the event happened. Read once, when the event was received.
+    pub fn timestamp(&self) -> Ticks {
+        InputEvent::timestamp(self)
+    }
+
+    pub fn as_keyboard(&self) -> Option<&KeyboardInputEvent> {
+        match self {
+            &Class::Keyboard(ref e) => Some(&e.res),
+            _ => None,
+        }
+    }
+    pub fn as_mouse(&self) -> Option<&MouseInputEvent> {
+        match self {
+            &Class::Mouse(ref e) => Some(&e.res),
+            _ => None,
+        }
+    }
+    pub fn as_wheel(&self) -> Option<&WheelInputEvent> {
+        match self {
+            &Class::Wheel(ref e) => Some(&e.res),
+            _ => None,
+        }
+    }
+    pub fn as_touch(&self) -> Option<&TouchInputEvent> {
+        match self {
+            &Class::Touch(ref e) => Some(&e.res),
+            _ => None,
+        }
+    }
+    pub fn as_ime(&self) -> Option<&IMEInputEvent> {
+        match self {
+            &Class::IME(ref e) => Some(&e.res),
+            _ => None,
+        }
+    }
+}
 impl Resource for Class {
     fn unwrap(&self) -> ffi::PP_Resource {
         match self {
@@ -272,6 +445,9 @@ impl InputEvent for Class {
             &Class::IME     (Event { timestamp: ts, .. }) => ts,
         }
     }
+    fn event_type(&self) -> EventType {
+        From::from(ppb::get_input_event().type_of(&self.unwrap()))
+    }
 }
 #[derive(Clone, Debug)]
 pub struct Event<Res, Class> {
@@ -295,6 +471,9 @@ impl<Res: Resource, Class> InputEvent for Event<Res, Class> {
     fn timestamp(&self) -> Ticks {
         self.timestamp
     }
+    fn event_type(&self) -> EventType {
+        From::from(ppb::get_input_event().type_of(&self.res.unwrap()))
+    }
 }
 #[derive(Clone, Hash, Eq, PartialEq, Debug, Copy)]
 pub enum Modifiers_ {
@@ -350,6 +529,9 @@ impl CLike for Modifiers_ {
     }
 }
 pub type Modifiers = EnumSet<Modifiers_>;
+fn modifiers_to_bitset(set: &Modifiers) -> u32 {
+    set.iter().fold(0, |bits, m| bits | (1 << m.to_usize()) )
+}
 fn modifiers_from_bitset(set: u32) -> Modifiers {
     let mut e: Modifiers = enum_set::EnumSet::new();
     if set & 0b0000000000001 != 0 { e.insert(Modifiers_::ShiftKey); }
@@ -398,19 +580,116 @@ impl MouseEvent {
         }
     }
 }
-#[derive(Clone, Eq, PartialEq, Debug, Copy)]
+#[derive(Clone, Hash, Eq, PartialEq, Debug, Copy)]
 pub enum MouseButton {
+    /// The event didn't involve a button, eg a mouse move.
+    NoButton,
     Left,
     Middle,
     Right,
+    /// A value this crate doesn't know about.
+    Unknown(i32),
 }
-impl MouseButton {
-    fn from_ffi(v: i32) -> MouseButton {
+impl From<ffi::PP_InputEvent_MouseButton> for MouseButton {
+    fn from(v: ffi::PP_InputEvent_MouseButton) -> MouseButton {
         match v {
+            ffi::PP_INPUTEVENT_MOUSEBUTTON_NONE   => MouseButton::NoButton,
             ffi::PP_INPUTEVENT_MOUSEBUTTON_LEFT   => MouseButton::Left,
             ffi::PP_INPUTEVENT_MOUSEBUTTON_MIDDLE => MouseButton::Middle,
             ffi::PP_INPUTEVENT_MOUSEBUTTON_RIGHT  => MouseButton::Right,
-            _ => unreachable!(),
+            v => MouseButton::Unknown(v),
+        }
+    }
+}
+impl MouseButton {
+    pub fn to_ffi(&self) -> ffi::PP_InputEvent_MouseButton {
+        match self {
+            &MouseButton::NoButton => ffi::PP_INPUTEVENT_MOUSEBUTTON_NONE,
+            &MouseButton::Left     => ffi::PP_INPUTEVENT_MOUSEBUTTON_LEFT,
+            &MouseButton::Middle   => ffi::PP_INPUTEVENT_MOUSEBUTTON_MIDDLE,
+            &MouseButton::Right    => ffi::PP_INPUTEVENT_MOUSEBUTTON_RIGHT,
+            &MouseButton::Unknown(v) => v,
+        }
+    }
+}
+
+/// The type of an input event, shared by every event class.
+#[derive(Clone, Hash, Eq, PartialEq, Debug, Copy)]
+pub enum EventType {
+    Undefined,
+    MouseDown,
+    MouseUp,
+    MouseMove,
+    MouseEnter,
+    MouseLeave,
+    Wheel,
+    RawKeyDown,
+    KeyDown,
+    KeyUp,
+    Char,
+    ContextMenu,
+    ImeCompositionStart,
+    ImeCompositionUpdate,
+    ImeCompositionEnd,
+    ImeText,
+    TouchStart,
+    TouchMove,
+    TouchEnd,
+    TouchCancel,
+    /// A value this crate doesn't know about.
+    Unknown(i32),
+}
+impl From<ffi::PP_InputEvent_Type> for EventType {
+    fn from(v: ffi::PP_InputEvent_Type) -> EventType {
+        match v {
+            ffi::PP_INPUTEVENT_TYPE_UNDEFINED => EventType::Undefined,
+            ffi::PP_INPUTEVENT_TYPE_MOUSEDOWN => EventType::MouseDown,
+            ffi::PP_INPUTEVENT_TYPE_MOUSEUP => EventType::MouseUp,
+            ffi::PP_INPUTEVENT_TYPE_MOUSEMOVE => EventType::MouseMove,
+            ffi::PP_INPUTEVENT_TYPE_MOUSEENTER => EventType::MouseEnter,
+            ffi::PP_INPUTEVENT_TYPE_MOUSELEAVE => EventType::MouseLeave,
+            ffi::PP_INPUTEVENT_TYPE_WHEEL => EventType::Wheel,
+            ffi::PP_INPUTEVENT_TYPE_RAWKEYDOWN => EventType::RawKeyDown,
+            ffi::PP_INPUTEVENT_TYPE_KEYDOWN => EventType::KeyDown,
+            ffi::PP_INPUTEVENT_TYPE_KEYUP => EventType::KeyUp,
+            ffi::PP_INPUTEVENT_TYPE_CHAR => EventType::Char,
+            ffi::PP_INPUTEVENT_TYPE_CONTEXTMENU => EventType::ContextMenu,
+            ffi::PP_INPUTEVENT_TYPE_IME_COMPOSITION_START => EventType::ImeCompositionStart,
+            ffi::PP_INPUTEVENT_TYPE_IME_COMPOSITION_UPDATE => EventType::ImeCompositionUpdate,
+            ffi::PP_INPUTEVENT_TYPE_IME_COMPOSITION_END => EventType::ImeCompositionEnd,
+            ffi::PP_INPUTEVENT_TYPE_IME_TEXT => EventType::ImeText,
+            ffi::PP_INPUTEVENT_TYPE_TOUCHSTART => EventType::TouchStart,
+            ffi::PP_INPUTEVENT_TYPE_TOUCHMOVE => EventType::TouchMove,
+            ffi::PP_INPUTEVENT_TYPE_TOUCHEND => EventType::TouchEnd,
+            ffi::PP_INPUTEVENT_TYPE_TOUCHCANCEL => EventType::TouchCancel,
+            v => EventType::Unknown(v),
+        }
+    }
+}
+impl EventType {
+    pub fn to_ffi(&self) -> ffi::PP_InputEvent_Type {
+        match self {
+            &EventType::Undefined => ffi::PP_INPUTEVENT_TYPE_UNDEFINED,
+            &EventType::MouseDown => ffi::PP_INPUTEVENT_TYPE_MOUSEDOWN,
+            &EventType::MouseUp => ffi::PP_INPUTEVENT_TYPE_MOUSEUP,
+            &EventType::MouseMove => ffi::PP_INPUTEVENT_TYPE_MOUSEMOVE,
+            &EventType::MouseEnter => ffi::PP_INPUTEVENT_TYPE_MOUSEENTER,
+            &EventType::MouseLeave => ffi::PP_INPUTEVENT_TYPE_MOUSELEAVE,
+            &EventType::Wheel => ffi::PP_INPUTEVENT_TYPE_WHEEL,
+            &EventType::RawKeyDown => ffi::PP_INPUTEVENT_TYPE_RAWKEYDOWN,
+            &EventType::KeyDown => ffi::PP_INPUTEVENT_TYPE_KEYDOWN,
+            &EventType::KeyUp => ffi::PP_INPUTEVENT_TYPE_KEYUP,
+            &EventType::Char => ffi::PP_INPUTEVENT_TYPE_CHAR,
+            &EventType::ContextMenu => ffi::PP_INPUTEVENT_TYPE_CONTEXTMENU,
+            &EventType::ImeCompositionStart => ffi::PP_INPUTEVENT_TYPE_IME_COMPOSITION_START,
+            &EventType::ImeCompositionUpdate => ffi::PP_INPUTEVENT_TYPE_IME_COMPOSITION_UPDATE,
+            &EventType::ImeCompositionEnd => ffi::PP_INPUTEVENT_TYPE_IME_COMPOSITION_END,
+            &EventType::ImeText => ffi::PP_INPUTEVENT_TYPE_IME_TEXT,
+            &EventType::TouchStart => ffi::PP_INPUTEVENT_TYPE_TOUCHSTART,
+            &EventType::TouchMove => ffi::PP_INPUTEVENT_TYPE_TOUCHMOVE,
+            &EventType::TouchEnd => ffi::PP_INPUTEVENT_TYPE_TOUCHEND,
+            &EventType::TouchCancel => ffi::PP_INPUTEVENT_TYPE_TOUCHCANCEL,
+            &EventType::Unknown(v) => v,
         }
     }
 }
@@ -433,6 +712,42 @@ pub enum KeyboardEvent {
     Char(char),
 }
 
+/// Matches keyboard events by key code and the held Shift, Control, Alt and
+/// Meta keys. Other modifiers (eg Caps Lock or auto repeat) are ignored. See
+/// `Instance::set_input_passthrough`.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct KeyMatcher {
+    /// The DOM key code, eg 116 for F5.
+    pub key_code: u32,
+    /// Exactly these chord modifiers must be held.
+    pub modifiers: Modifiers,
+}
+static CHORD_MODIFIERS: [Modifiers_; 4] = [Modifiers_::ShiftKey, Modifiers_::ControlKey,
+                                           Modifiers_::AltKey, Modifiers_::MetaKey];
+impl KeyMatcher {
+    /// Matches `key_code` pressed without any chord modifiers.
+    pub fn new(key_code: u32) -> KeyMatcher {
+        KeyMatcher {
+            key_code: key_code,
+            modifiers: EnumSet::new(),
+        }
+    }
+    pub fn with(mut self, modifier: Modifiers_) -> KeyMatcher {
+        self.modifiers.insert(modifier);
+        self
+    }
+    pub fn matches(&self, key_code: u32, modifiers: &Modifiers) -> bool {
+        self.key_code == key_code &&
+            CHORD_MODIFIERS.iter().all(|m| {
+                self.modifiers.contains(m) == modifiers.contains(m)
+            })
+    }
+    #[doc(hidden)]
+    pub fn matches_raw(&self, key_code: u32, modifiers: u32) -> bool {
+        self.key_code == key_code && self.matches(key_code, &modifiers_from_bitset(modifiers))
+    }
+}
+
 #[derive(Clone, Eq, PartialEq, Debug, Copy)]
 pub struct WheelEvent {
     delta: FloatPoint,
@@ -443,6 +758,7 @@ pub struct WheelEvent {
 pub trait InputEvent {
     fn modifiers(&self) -> Modifiers;
     fn timestamp(&self) -> Ticks;
+    fn event_type(&self) -> EventType;
 }
 
 macro_rules! impl_input_event_for(
@@ -455,6 +771,9 @@ macro_rules! impl_input_event_for(
             fn timestamp(&self) -> Ticks {
                 ppb::get_input_event().timestamp(&self.unwrap())
             }
+            fn event_type(&self) -> EventType {
+                From::from(ppb::get_input_event().type_of(&self.unwrap()))
+            }
         }
     )
 );
@@ -475,8 +794,8 @@ impl KeyboardInputEvent {
     }
 }
 impl MouseInputEvent {
-    pub fn get_button(&self) -> ffi::PP_InputEvent_MouseButton {
-        (ppb::get_mouse_event().GetButton.unwrap())(self.unwrap())
+    pub fn get_button(&self) -> MouseButton {
+        From::from((ppb::get_mouse_event().GetButton.unwrap())(self.unwrap()))
     }
     pub fn get_position(&self) -> Point {
         From::from((ppb::get_mouse_event().GetPosition.unwrap())(self.unwrap()))
@@ -678,3 +997,248 @@ impl<'a> iter::RandomAccessIterator for IMESegmentIterator<'a> {
         self.event.segment_str(index)
     }
 }
+
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum SwipeDirection {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Gesture {
+    Tap { position: FloatPoint },
+    /// A tap shortly after, and near, a previous tap. Replaces the second
+    /// `Tap`.
+    DoubleTap { position: FloatPoint },
+    LongPress { position: FloatPoint },
+    /// A single finger moved by `delta` since the last event.
+    Pan { delta: FloatPoint },
+    /// The distance between the first two fingers changed by a factor of
+    /// `scale` since the last event.
+    Pinch { scale: f32 },
+    Swipe { direction: SwipeDirection },
+}
+
+/// Thresholds used by `GestureRecognizer`. Distances are in DIPs.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct GestureConfig {
+    /// How far a finger may move before it no longer counts as a tap or a
+    /// long press.
+    pub tap_slop: f32,
+    /// How long a finger must be held to make a long press.
+    pub long_press: TimeDelta,
+    /// The largest gap between two taps which still makes a double tap.
+    pub double_tap_interval: TimeDelta,
+    pub swipe_min_distance: f32,
+    pub swipe_max_duration: TimeDelta,
+}
+impl Default for GestureConfig {
+    fn default() -> GestureConfig {
+        GestureConfig {
+            tap_slop: 10.0,
+            long_press: 0.5,
+            double_tap_interval: 0.3,
+            swipe_min_distance: 50.0,
+            swipe_max_duration: 0.5,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct TrackedTouch {
+    id: u32,
+    start: FloatPoint,
+    position: FloatPoint,
+}
+
+fn distance(a: FloatPoint, b: FloatPoint) -> f32 {
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    (dx * dx + dy * dy).sqrt()
+}
+
+/// Turns a stream of touch events into `Gesture`s. Feed it every touch
+/// event, in order, with `feed`.
+///
+/// Fingers are tracked by id; `Pan` is only reported while one finger is
+/// down and `Pinch` only while two or more are. Touch events aren't sent
+/// while fingers are still, so to get a `LongPress` before the finger is
+/// lifted call `poll` periodically, eg once per frame. Lifting every finger,
+/// or a cancelled touch, resets everything except the double tap state.
+#[derive(Clone, Debug)]
+pub struct GestureRecognizer {
+    config: GestureConfig,
+    touches: Vec<TrackedTouch>,
+    started: Option<(Ticks, FloatPoint)>,
+    moved: bool,
+    multi_touch: bool,
+    long_pressed: bool,
+    last_tap: Option<(Ticks, FloatPoint)>,
+}
+impl GestureRecognizer {
+    pub fn new(config: GestureConfig) -> GestureRecognizer {
+        GestureRecognizer {
+            config: config,
+            touches: Vec::new(),
+            started: None,
+            moved: false,
+            multi_touch: false,
+            long_pressed: false,
+            last_tap: None,
+        }
+    }
+    pub fn config(&self) -> &GestureConfig { &self.config }
+    pub fn set_config(&mut self, config: GestureConfig) { self.config = config; }
+
+    /// Forgets any gesture in progress.
+    pub fn reset(&mut self) {
+        self.touches.clear();
+        self.started = None;
+        self.moved = false;
+        self.multi_touch = false;
+        self.long_pressed = false;
+    }
+
+    pub fn feed(&mut self, event: &TouchInputEvent) -> Vec<Gesture> {
+        let touches: Vec<TouchPoint> = event
+            .get_touch_list(TouchListType::Touches)
+            .iter()
+            .collect();
+        self.feed_touches(event.event_type(), event.timestamp(), &touches[..])
+    }
+    /// Like `feed`, but takes the event's contents directly. `touches` is
+    /// every finger still down after the event, ie the
+    /// `TouchListType::Touches` list. Non-touch event types are ignored.
+    pub fn feed_touches(&mut self, event_type: EventType, timestamp: Ticks,
+                        touches: &[TouchPoint]) -> Vec<Gesture> {
+        let mut gestures = Vec::new();
+        match event_type {
+            EventType::TouchStart | EventType::TouchMove | EventType::TouchEnd => {}
+            EventType::TouchCancel => {
+                self.reset();
+                self.last_tap = None;
+                return gestures;
+            }
+            _ => return gestures,
+        }
+
+        if self.started.is_none() {
+            if touches.is_empty() { return gestures; }
+            self.started = Some((timestamp, touches[0].position));
+        }
+        if touches.len() > 1 {
+            self.multi_touch = true;
+        }
+
+        let previous = mem::replace(&mut self.touches, Vec::new());
+        for touch in touches.iter() {
+            let start = previous.iter()
+                .find(|t| t.id == touch.id )
+                .map(|t| t.start )
+                .unwrap_or(touch.position);
+            if distance(start, touch.position) > self.config.tap_slop {
+                self.moved = true;
+            }
+            self.touches.push(TrackedTouch {
+                id: touch.id,
+                start: start,
+                position: touch.position,
+            });
+        }
+
+        if event_type == EventType::TouchMove {
+            let common: Vec<(FloatPoint, FloatPoint)> = self.touches.iter()
+                .filter_map(|now| {
+                    previous.iter()
+                        .find(|before| before.id == now.id )
+                        .map(|before| (before.position, now.position) )
+                })
+                .collect();
+            if common.len() == 1 && self.touches.len() == 1 && self.moved {
+                let (before, now) = common[0];
+                let delta = FloatPoint { x: now.x - before.x, y: now.y - before.y };
+                if delta.x != 0.0 || delta.y != 0.0 {
+                    gestures.push(Gesture::Pan { delta: delta });
+                }
+            } else if common.len() >= 2 {
+                let before = distance(common[0].0, common[1].0);
+                let now = distance(common[0].1, common[1].1);
+                if before > 0.0 && now != before {
+                    gestures.push(Gesture::Pinch { scale: now / before });
+                }
+            }
+        }
+
+        if let Some(long_press) = self.poll(timestamp) {
+            gestures.push(long_press);
+        }
+
+        if touches.is_empty() {
+            // The lifted finger isn't in `touches`, so use where it was last.
+            let end = previous.first().map(|t| t.position );
+            if let Some(gesture) = self.finish(timestamp, end) {
+                gestures.push(gesture);
+            }
+            self.reset();
+        }
+        gestures
+    }
+    /// Reports a `LongPress` if a single finger has been held still for
+    /// `long_press` as of `now`. Each press is only reported once.
+    pub fn poll(&mut self, now: Ticks) -> Option<Gesture> {
+        let (started, position) = match self.started {
+            Some(started) => started,
+            None => return None,
+        };
+        if self.moved || self.multi_touch || self.long_pressed ||
+            now - started < self.config.long_press
+        {
+            return None;
+        }
+        self.long_pressed = true;
+        Some(Gesture::LongPress { position: position })
+    }
+
+    fn finish(&mut self, now: Ticks, end: Option<FloatPoint>) -> Option<Gesture> {
+        let (started, start) = match self.started {
+            Some(started) => started,
+            None => return None,
+        };
+        if self.multi_touch || self.long_pressed { return None; }
+
+        let end = end.unwrap_or(start);
+        if !self.moved {
+            let double = match self.last_tap {
+                Some((at, position)) => now - at <= self.config.double_tap_interval &&
+                    distance(position, start) <= self.config.tap_slop,
+                None => false,
+            };
+            return if double {
+                self.last_tap = None;
+                Some(Gesture::DoubleTap { position: start })
+            } else {
+                self.last_tap = Some((now, start));
+                Some(Gesture::Tap { position: start })
+            };
+        }
+
+        self.last_tap = None;
+        if now - started > self.config.swipe_max_duration ||
+            distance(start, end) < self.config.swipe_min_distance
+        {
+            return None;
+        }
+        let (dx, dy) = (end.x - start.x, end.y - start.y);
+        let direction = if dx.abs() >= dy.abs() {
+            if dx < 0.0 { SwipeDirection::Left } else { SwipeDirection::Right }
+        } else {
+            if dy < 0.0 { SwipeDirection::Up } else { SwipeDirection::Down }
+        };
+        Some(Gesture::Swipe { direction: direction })
+    }
+}
+impl Default for GestureRecognizer {
+    fn default() -> GestureRecognizer {
+        GestureRecognizer::new(Default::default())
+    }
+}
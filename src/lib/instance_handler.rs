@@ -0,0 +1,110 @@
+// Copyright (c) 2014 Richard Diamond & contributors.
+//
+// This file is part of the Rust PPApi project.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! `InstanceHandler`, an instance's callbacks gathered in one trait object,
+//! as an alternative to the `ppapi_on_*` symbols.
+//!
+//! A handler is set with `Instance::set_handler`, usually from
+//! `ppapi_instance_created`, and lives on the instance's thread, where all of
+//! its methods are called. The `ppapi_on_*` symbols keep working alongside
+//! it: for each event the handler is called first, then the symbol. Events
+//! which report whether they were handled only go on to the symbol if the
+//! handler returned false.
+//!
+//! Events for one instance are delivered in the order the browser sent them,
+//! and `on_destroy` is always the last, just before
+//! `ppapi_instance_destroyed`.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use ffi;
+use http;
+use input;
+
+use super::{AnyVar, Instance, View};
+
+/// All methods do nothing by default, so only the interesting ones need to
+/// be implemented.
+pub trait InstanceHandler {
+    /// A message from the page's `postMessage`. Only called for messages
+    /// which weren't taken by a `MessageHandler`, by backpressure acks or
+    /// by a `messages::Channel`.
+    fn on_message(&mut self, _message: AnyVar) { }
+    fn on_change_view(&mut self, _view: View) { }
+    fn on_change_focus(&mut self, _has_focus: bool) { }
+    /// The page became visible, or is visible at the first view change.
+    /// Called after `on_change_view`, and only once a change has lasted
+    /// `VISIBILITY_DEBOUNCE_MS`; see `Instance::update_visibility`.
+    fn on_visible(&mut self) { }
+    /// Like `on_visible`, but for the page being hidden, eg in a background
+    /// tab. A good time to stop rendering.
+    fn on_hidden(&mut self) { }
+    /// Returns true if the handler took the loader; see
+    /// `ppapi_on_document_loaded`.
+    fn on_document_load(&mut self, _loader: http::Loader) -> bool { false }
+    /// Returns true if the event was handled; see
+    /// `Instance::request_filtering_input_events` for what that changes.
+    fn on_input(&mut self, _event: input::Class) -> bool { false }
+    fn on_graphics_context_lost(&mut self) { }
+    fn on_mouse_lock_lost(&mut self) { }
+    /// The handler is dropped after this returns.
+    fn on_destroy(&mut self) { }
+}
+
+// Each instance's handler, or `None` while it's running; see `dispatch`.
+thread_local!(static HANDLERS: RefCell<HashMap<ffi::PP_Instance, Option<Box<InstanceHandler>>>> =
+              RefCell::new(HashMap::new()));
+
+/// Sets the handler of `instance`, replacing any previous one. Must be
+/// called from the instance's thread.
+pub fn set_handler(instance: &Instance, handler: Box<InstanceHandler>) {
+    HANDLERS.with(|h| h.borrow_mut().insert(instance.unwrap(), Some(handler)) );
+}
+/// Removes `instance`'s handler, returning true if it had one. Its
+/// `on_destroy` isn't called.
+pub fn clear_handler(instance: &Instance) -> bool {
+    HANDLERS.with(|h| h.borrow_mut().remove(&instance.unwrap()) ).is_some()
+}
+pub fn has_handler(instance: &Instance) -> bool {
+    HANDLERS.with(|h| h.borrow().contains_key(&instance.unwrap()) )
+}
+
+/// Calls `f` with `instance`'s handler, or returns `None` if it doesn't have
+/// one. The handler is taken out of the map while it runs, so it can replace
+/// or clear itself; it's only put back if it did neither.
+#[doc(hidden)]
+pub fn dispatch<F, T>(instance: ffi::PP_Instance, f: F) -> Option<T>
+    where F: FnOnce(&mut InstanceHandler) -> T,
+{
+    let handler = HANDLERS.with(|h| {
+        h.borrow_mut().get_mut(&instance).and_then(|slot| slot.take() )
+    });
+    let mut handler = match handler {
+        Some(handler) => handler,
+        None => return None,
+    };
+    let ret = f(&mut *handler);
+    HANDLERS.with(|h| {
+        if let Some(slot) = h.borrow_mut().get_mut(&instance) {
+            if slot.is_none() {
+                *slot = Some(handler);
+            }
+        }
+    });
+    Some(ret)
+}
+
+/// Removes `instance`'s handler and calls its `on_destroy`.
+#[doc(hidden)]
+pub fn destroy(instance: ffi::PP_Instance) {
+    let handler = HANDLERS.with(|h| h.borrow_mut().remove(&instance) );
+    if let Some(Some(mut handler)) = handler {
+        handler.on_destroy();
+    }
+}
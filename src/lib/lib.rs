@@ -21,11 +21,14 @@ pub extern fn ppapi_instance_destroyed() {
 }
 ```
 
+`args` are the attributes of the embed element; `InstanceArgs` wraps them
+with typed lookups.
+
 All instances will be created from a new task/thread. The task takes its
 name from the id attribute on the embed object used to create the
 instance. Failing will cause rust-ppapi to cleanup the task, though the
-plugin will continue to run. Currently there is no way to automatically
-restart an instance if it fails.
+plugin will continue to run, unless the instance opted into restarts with
+`Instance::set_restart_policy`.
 
 The other callbacks consist of:
 
@@ -49,6 +52,10 @@ pub extern fn ppapi_on_graphics_context_lost() {
 
 These are all optional. If implemented, they will be called from the instance's task.
 
+Instead of (or as well as) these, an `InstanceHandler` can be given to
+`Instance::set_handler` from `ppapi_instance_created`; it also gets
+`postMessage` messages and mouse lock loss. See the `instance_handler` module.
+
 More info:
 
  * [ppapi_instance_created](https://developer.chrome.com/native-client/pepper_stable/c/struct_p_p_p___instance__1__1.html#aff2dd72f7aab6335cacf8bc3a66ccbba)
@@ -75,6 +82,7 @@ More info:
 #![feature(enumset)]
 #![feature(iter_idx)]
 #![feature(catch_panic)]
+#![feature(duration)]
 
 #![allow(dead_code)]
 #![allow(non_shorthand_field_patterns)]
@@ -89,12 +97,23 @@ extern crate libc;
 
 use std::{cmp};
 use std::mem::{self, transmute};
+use std::iter;
 use std::ops;
 use std::clone;
 use std::result;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque, BTreeMap};
 use std::fmt::{self, Display};
 use std::marker::PhantomData;
+use std::any::{Any, TypeId};
+use std::thread;
+use std::cell::{Cell, RefCell};
+use std::sync::{Arc, Mutex, Once, ONCE_INIT};
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+use std::rc::Rc;
+use std::slice;
+use std::str::FromStr;
+use std::time::Duration;
 
 use log::LogRecord;
 
@@ -103,6 +122,27 @@ use ppb::{ViewIf, MessageLoopIf, VarIf, ImageDataIf, URLLoaderIf,
           ConsoleInterface};
 
 pub use http as url;
+pub use instance_handler::InstanceHandler;
+
+#[cfg(feature = "debug-resource-tracking")]
+macro_rules! track_resource(
+    ($ty:ty, $delta:expr) => ({
+        let ty = <$ty as ::TypedResource>::resource_type();
+        ::resource_tracking::adjust(ty, $delta)
+    })
+);
+#[cfg(not(feature = "debug-resource-tracking"))]
+macro_rules! track_resource(
+    ($ty:ty, $delta:expr) => (())
+);
+#[cfg(feature = "debug-resource-tracking")]
+macro_rules! track_var(
+    ($delta:expr) => (::var::adjust_live_vars($delta))
+);
+#[cfg(not(feature = "debug-resource-tracking"))]
+macro_rules! track_var(
+    ($delta:expr) => (())
+);
 
 macro_rules! impl_resource_for(
     ($ty:ty, $type_:expr) => (
@@ -123,15 +163,46 @@ macro_rules! impl_resource_for(
         impl $ty {
             #[doc(hidden)]
             pub fn new(res: ::ffi::PP_Resource) -> $ty {
+                track_resource!($ty, 1);
                 unsafe {
                     ::std::mem::transmute_copy(&res)
                 }
             }
         }
+        impl ::TypedResource for $ty {
+            fn resource_type() -> ::ResourceType {
+                use ::ResourceType;
+                $type_
+            }
+            fn from_resource(res: ::ffi::PP_Resource) -> $ty {
+                <$ty>::new(res)
+            }
+        }
     )
 );
 macro_rules! impl_clone_drop_for(
     ($ty:ty) => (
+        impl Clone for $ty {
+            fn clone(&self) -> $ty {
+                use ::Resource;
+                (::ppb::get_core().AddRefResource.unwrap())(self.unwrap());
+                track_resource!($ty, 1);
+                unsafe {
+                    ::std::mem::transmute_copy(self)
+                }
+            }
+        }
+        impl Drop for $ty {
+            fn drop(&mut self) {
+                use ::Resource;
+                (::ppb::get_core().ReleaseResource.unwrap())(self.unwrap());
+                track_resource!($ty, -1);
+            }
+        }
+    );
+    // For wrappers which aren't a `TypedResource`, and so can't be counted by
+    // `debug-resource-tracking`.
+    ($ty:ty, untracked) => (
         impl Clone for $ty {
             fn clone(&self) -> $ty {
                 use ::Resource;
@@ -161,6 +232,60 @@ macro_rules! try_code(
         return $cc.drop_with_code(code);
     })
 );
+/// Like `try_code!`, but for functions returning a `Result`. Takes a
+/// `Code<T>` or a `Result<T, Code>`; on success evaluates to the `T`, and
+/// otherwise returns `Err` with the code, converted with `From`.
+///
+/// `Code::CompletionPending` is an error here: it's returned as
+/// `Err(Code::CompletionPending)`, so callers passing non-blocking callbacks
+/// should check for it with `completion_pending`.
+#[macro_export]
+macro_rules! try_code_result(
+    ($expr:expr) => ({
+        match $crate::IntoCodeResult::into_code_result($expr) {
+            ::std::result::Result::Ok(v) => v,
+            ::std::result::Result::Err(code) =>
+                return ::std::result::Result::Err(::std::convert::From::from(code)),
+        }
+    })
+);
+
+#[cfg(feature = "debug-thread-checks")]
+macro_rules! assert_loop_thread(
+    ($what:expr) => ({
+        if !::MessageLoop::is_attached() {
+            panic!("`{}` must be called from a thread with an attached message loop", $what);
+        }
+    })
+);
+#[cfg(not(feature = "debug-thread-checks"))]
+macro_rules! assert_loop_thread(
+    ($what:expr) => (())
+);
+#[cfg(feature = "debug-thread-checks")]
+macro_rules! assert_main_thread(
+    ($what:expr) => ({
+        if !::is_main_thread() {
+            panic!("`{}` may only be called from the main thread", $what);
+        }
+    })
+);
+#[cfg(not(feature = "debug-thread-checks"))]
+macro_rules! assert_main_thread(
+    ($what:expr) => (())
+);
+#[cfg(feature = "debug-thread-checks")]
+macro_rules! assert_off_main_thread(
+    ($what:expr) => ({
+        if ::is_main_thread() {
+            panic!("`{}` blocks and so may not be called from the main thread", $what);
+        }
+    })
+);
+#[cfg(not(feature = "debug-thread-checks"))]
+macro_rules! assert_off_main_thread(
+    ($what:expr) => (())
+);
 
 #[allow(missing_docs)] pub mod ffi;
 pub mod ppp;
@@ -168,14 +293,26 @@ pub mod pp;
 pub mod ppb;
 pub mod gles;
 pub mod font;
+pub mod browser_font;
 pub mod imagedata;
 pub mod input;
 pub mod http;
 pub mod fs;
 pub mod media_stream_video_track;
 pub mod video_frame;
+pub mod media_stream_audio_track;
+pub mod audio_buffer;
 pub mod video_decoder;
+pub mod video_encoder;
 pub mod message_handler;
+pub mod audio;
+pub mod net;
+pub mod metrics;
+pub mod surface;
+pub mod var;
+pub mod printing;
+pub mod instance_handler;
+#[macro_use] pub mod messages;
 
 #[cfg(feature = "pepper")]
 #[link(name = "helper", kind = "static")]
@@ -218,6 +355,10 @@ impl ToFFIBool for bool {
     }
 }
 
+/// `Code::BadInstance`'s encoding in `Code::to_wire`. PPAPI has no code for
+/// it.
+pub const WIRE_BAD_INSTANCE: i32 = -1000;
+
 #[derive(Clone, Eq, PartialEq, Copy, Debug)]
 #[must_use]
 pub enum Code<T = usize> {
@@ -242,6 +383,9 @@ pub enum Code<T = usize> {
     ConnectionReset,   // = ffi::PP_ERROR_CONNECTION_RESET,
     ConnectionAborted, // = ffi::PP_ERROR_CONNECTION_ABORTED,
     ConnectionClosed,  // = ffi::PP_ERROR_CONNECTION_CLOSED,
+    ConnectionFailed,  // = ffi::PP_ERROR_CONNECTION_FAILED,
+    AddressUnreachable, // = ffi::PP_ERROR_ADDRESS_UNREACHABLE,
+    NameNotResolved,   // = ffi::PP_ERROR_NAME_NOT_RESOLVED,
     TimedOut,          // = ffi::PP_ERROR_TIMEDOUT,
     NoMessageLoop,     // = ffi::PP_ERROR_NO_MESSAGE_LOOP,
 
@@ -253,6 +397,14 @@ pub enum Code<T = usize> {
     /// The instance handle is no longer valid. This will happen after the
     /// instance is destroyed.
     BadInstance,
+    /// An error code this crate doesn't know about, kept as is.
+    Unknown(i32),
+}
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum ErrorGroup {
+    Network,
+    Fs,
+    Resource,
 }
 impl<T: fmt::Display> fmt::Display for Code<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -277,6 +429,9 @@ impl<T: fmt::Display> fmt::Display for Code<T> {
             &Code::ConnectionReset => "connection reset",
             &Code::ConnectionAborted => "connection aborted",
             &Code::ConnectionClosed => "connection closed",
+            &Code::ConnectionFailed => "connection attempt failed",
+            &Code::AddressUnreachable => "address unreachable",
+            &Code::NameNotResolved => "host name not resolved",
             &Code::TimedOut    => "operation timed out",
             &Code::NotSupported => "operation not supported/implemented",
             &Code::NoMessageLoop =>
@@ -284,13 +439,27 @@ impl<T: fmt::Display> fmt::Display for Code<T> {
             &Code::NoInterface => "missing PPAPI interface",
             &Code::BadInstance => "instance destroyed",
             &Code::Aborted => "callback aborted",
+            &Code::Unknown(code) => {
+                return write!(f, "unknown error code ({})", code);
+            },
         };
         write!(f, "{}", desc)
     }
 }
 impl From<i32> for Code {
     fn from(v: i32) -> Code {
-        match v {
+        match Code::from_known_i32(v) {
+            Some(code) => code,
+            None => {
+                warn!("unknown error code `{}`", v);
+                Code::Unknown(v)
+            },
+        }
+    }
+}
+impl Code {
+    fn from_known_i32(v: i32) -> Option<Code> {
+        let code = match v {
             v if v >= 0 => Code::Ok(v as usize),
             ffi::PP_OK_COMPLETIONPENDING => Code::CompletionPending,
             ffi::PP_ERROR_BADRESOURCE => Code::BadResource,
@@ -301,6 +470,8 @@ impl From<i32> for Code {
             ffi::PP_ERROR_NOTSUPPORTED => Code::NotSupported,
             ffi::PP_ERROR_NOMEMORY => Code::NoMemory,
             ffi::PP_ERROR_CONTEXT_LOST => Code::ContextLost,
+            ffi::PP_ERROR_NOSPACE => Code::NoSpace,
+            ffi::PP_ERROR_NOQUOTA => Code::NoQuota,
             ffi::PP_ERROR_FILENOTFOUND => Code::FileNotFound,
             ffi::PP_ERROR_FILEEXISTS => Code::FileExists,
             ffi::PP_ERROR_NOACCESS => Code::NoAccess,
@@ -308,14 +479,17 @@ impl From<i32> for Code {
             ffi::PP_ERROR_CONNECTION_RESET => Code::ConnectionReset,
             ffi::PP_ERROR_CONNECTION_ABORTED => Code::ConnectionAborted,
             ffi::PP_ERROR_CONNECTION_CLOSED => Code::ConnectionClosed,
+            ffi::PP_ERROR_CONNECTION_FAILED => Code::ConnectionFailed,
+            ffi::PP_ERROR_ADDRESS_UNREACHABLE => Code::AddressUnreachable,
+            ffi::PP_ERROR_NAME_NOT_RESOLVED => Code::NameNotResolved,
             ffi::PP_ERROR_TIMEDOUT | ffi::PP_ERROR_CONNECTION_TIMEDOUT =>
                 Code::TimedOut,
             ffi::PP_ERROR_NO_MESSAGE_LOOP => Code::NoMessageLoop,
             ffi::PP_ERROR_NOINTERFACE => Code::NoInterface,
             ffi::PP_ERROR_ABORTED => Code::Aborted,
-
-            _ => unreachable!("unexpected invalid or unknown code: `{}`", v),
-        }
+            _ => return None,
+        };
+        Some(code)
     }
 }
 impl<'a, T> From<&'a Result<T>> for Code {
@@ -347,6 +521,9 @@ impl ::std::error::Error for Code {
             &Code::ConnectionReset => "connection reset",
             &Code::ConnectionAborted => "connection aborted",
             &Code::ConnectionClosed => "connection closed",
+            &Code::ConnectionFailed => "connection attempt failed",
+            &Code::AddressUnreachable => "address unreachable",
+            &Code::NameNotResolved => "host name not resolved",
             &Code::TimedOut    => "operation timed out",
             &Code::NotSupported => "operation not supported/implemented",
             &Code::NoMessageLoop =>
@@ -354,9 +531,69 @@ impl ::std::error::Error for Code {
             &Code::NoInterface => "missing PPAPI interface",
             &Code::BadInstance => "instance destroyed",
             &Code::Aborted => "callback aborted",
+            &Code::Unknown(_) => "unknown error code",
+        }
+    }
+}
+
+/// Logs errors on their way past, so ignored `Code`s don't hide failures:
+///
+/// ```rust
+/// let context = try!(instance.create_2d_context(size, true)
+///                    .log_err("creating the back buffer"));
+/// ```
+///
+/// Errors are logged as warnings to the current instance's console. Without a
+/// current instance nothing is logged.
+pub trait ResultCodeExt: Sized {
+    /// Logs an error, if `self` is one, as `context: error`, and returns
+    /// `self` unchanged.
+    fn log_err(self, context: &str) -> Self {
+        match Instance::opt_current() {
+            Some(instance) => self.log_err_to(&instance.console(), context),
+            None => self,
+        }
+    }
+    /// Like `log_err`, but logs to `console`.
+    fn log_err_to<C: ConsoleInterface>(self, console: &C, context: &str) -> Self;
+}
+fn log_code_err<C: ConsoleInterface>(console: &C, context: &str, code: Code) {
+    console.log(ffi::PP_LOGLEVEL_WARNING,
+                format!("{}: {}", context, code).to_string_var());
+}
+impl<T> ResultCodeExt for result::Result<T, Code> {
+    fn log_err_to<C: ConsoleInterface>(self, console: &C, context: &str) -> Self {
+        if let Err(code) = self.as_ref() {
+            log_code_err(console, context, *code);
+        }
+        self
+    }
+}
+impl<T> ResultCodeExt for Code<T> {
+    fn log_err_to<C: ConsoleInterface>(self, console: &C, context: &str) -> Self {
+        if self.is_err() {
+            log_code_err(console, context, self.map_err());
+        }
+        self
+    }
+}
+/// What `try_code_result!` accepts.
+#[doc(hidden)]
+pub trait IntoCodeResult<T> {
+    fn into_code_result(self) -> result::Result<T, Code>;
+}
+impl<T> IntoCodeResult<T> for Code<T> {
+    fn into_code_result(self) -> result::Result<T, Code> {
+        if self.is_ok() {
+            Ok(self.unwrap())
+        } else {
+            Err(self.map_err())
         }
     }
 }
+impl<T> IntoCodeResult<T> for result::Result<T, Code> {
+    fn into_code_result(self) -> result::Result<T, Code> { self }
+}
 impl Into<::std::io::Error> for Code {
     fn into(self) -> ::std::io::Error {
         use std::io::{ErrorKind, Error};
@@ -407,14 +644,41 @@ impl Code {
             Code::ConnectionReset => ffi::PP_ERROR_CONNECTION_RESET,
             Code::ConnectionAborted => ffi::PP_ERROR_CONNECTION_ABORTED,
             Code::ConnectionClosed => ffi::PP_ERROR_CONNECTION_CLOSED,
+            Code::ConnectionFailed => ffi::PP_ERROR_CONNECTION_FAILED,
+            Code::AddressUnreachable => ffi::PP_ERROR_ADDRESS_UNREACHABLE,
+            Code::NameNotResolved => ffi::PP_ERROR_NAME_NOT_RESOLVED,
             Code::TimedOut    => ffi::PP_ERROR_TIMEDOUT,
             Code::NoMessageLoop => ffi::PP_ERROR_NO_MESSAGE_LOOP,
             Code::NoInterface => ffi::PP_ERROR_NOINTERFACE,
             Code::Aborted     => ffi::PP_ERROR_ABORTED,
 
             Code::BadInstance => ffi::PP_ERROR_RESOURCE_FAILED,
+            Code::Unknown(v)  => v,
+        }
+    }
+
+    /// Encodes `self` for storing or sending elsewhere, eg to JS or another
+    /// thread. Unlike `to_i32`, every variant survives `from_wire`, and the
+    /// encoding won't change between versions of this crate: PPAPI's value
+    /// for codes which have one, `WIRE_BAD_INSTANCE` for `BadInstance`, the
+    /// value itself for `Ok` and `Unknown`.
+    ///
+    /// `Ok` values over `i32::MAX`, and `Unknown` values which are the
+    /// encoding of another variant, can't be told apart from what they wrap
+    /// to and so don't round trip.
+    pub fn to_wire(self) -> i32 {
+        match self {
+            Code::BadInstance => WIRE_BAD_INSTANCE,
+            code => code.to_i32(),
         }
     }
+    /// Decodes a value from `to_wire`. Values which aren't the encoding of
+    /// any variant become `Code::Unknown`, silently; they're expected from
+    /// newer peers.
+    pub fn from_wire(v: i32) -> Code {
+        if v == WIRE_BAD_INSTANCE { return Code::BadInstance; }
+        Code::from_known_i32(v).unwrap_or(Code::Unknown(v))
+    }
     pub fn to_empty_result(self) -> Result<()> {
         if self.is_ok() {
             result::Result::Ok(())
@@ -476,11 +740,15 @@ impl<T> Code<T> {
             Code::ConnectionReset => Code::ConnectionReset,
             Code::ConnectionAborted => Code::ConnectionAborted,
             Code::ConnectionClosed => Code::ConnectionClosed,
+            Code::ConnectionFailed => Code::ConnectionFailed,
+            Code::AddressUnreachable => Code::AddressUnreachable,
+            Code::NameNotResolved => Code::NameNotResolved,
             Code::TimedOut    => Code::TimedOut,
             Code::NoMessageLoop => Code::NoMessageLoop,
             Code::NoInterface => Code::NoInterface,
             Code::BadInstance => Code::BadInstance,
             Code::Aborted => Code::Aborted,
+            Code::Unknown(v) => Code::Unknown(v),
         }
     }
     pub fn map_err<U>(&self) -> Code<U> {
@@ -502,16 +770,128 @@ impl<T> Code<T> {
             &Code::ConnectionReset => Code::ConnectionReset,
             &Code::ConnectionAborted => Code::ConnectionAborted,
             &Code::ConnectionClosed => Code::ConnectionClosed,
+            &Code::ConnectionFailed => Code::ConnectionFailed,
+            &Code::AddressUnreachable => Code::AddressUnreachable,
+            &Code::NameNotResolved => Code::NameNotResolved,
             &Code::TimedOut    => Code::TimedOut,
             &Code::NoMessageLoop => Code::NoMessageLoop,
             &Code::NoInterface => Code::NoInterface,
             &Code::BadInstance => Code::BadInstance,
             &Code::Aborted => Code::Aborted,
             &Code::CompletionPending => Code::CompletionPending,
+            &Code::Unknown(v) => Code::Unknown(v),
 
             &Code::Ok(_) => unreachable!(),
         }
     }
+    /// A longer explanation than `Display`'s, with the usual causes and
+    /// what to try, for developer facing error reports.
+    pub fn describe_verbose(&self) -> &'static str {
+        // No wildcard: new variants need an entry here.
+        match self {
+            &Code::Ok(_) =>
+                "The operation succeeded.",
+            &Code::CompletionPending =>
+                "The operation was started and will finish asynchronously; its \
+                 callback will be called with the result. This isn't an error, \
+                 unless the callback was optional.",
+            &Code::BadResource =>
+                "A resource argument was invalid: it was never created, has been \
+                 released, belongs to another instance, or is of the wrong type \
+                 for this call. Check that the resource is still held and that \
+                 it was created for this instance.",
+            &Code::BadArgument =>
+                "An argument was out of range or otherwise invalid, eg a negative \
+                 offset or a size of zero. Check the call's documented limits.",
+            &Code::WrongThread =>
+                "This PPAPI call must run on the main thread, or on a thread with \
+                 an attached message loop. Blocking calls can't be made on the \
+                 main thread. Consider Instance::spawn_message_loop, or posting \
+                 the work to the right loop with MessageLoop::post_work.",
+            &Code::InProgress =>
+                "Another operation of this kind is still pending on the same \
+                 resource, eg a second read on a FileIo. Wait for the first \
+                 one's callback before starting the next.",
+            &Code::Failed =>
+                "The browser reported a generic failure. The resource may not \
+                 be in the right state for this call, eg not opened or not \
+                 initialized yet; the browser's console may say more.",
+            &Code::NotSupported =>
+                "The browser or platform doesn't support this operation, or not \
+                 with these arguments. Check Instance::supports before relying \
+                 on optional features.",
+            &Code::NoMemory =>
+                "The browser couldn't allocate memory for the operation. Try \
+                 smaller buffers or images, and release resources which are no \
+                 longer needed.",
+            &Code::NoSpace =>
+                "The file system is out of space. Free some, or ask for a larger \
+                 quota when opening the file system.",
+            &Code::NoQuota =>
+                "The file system's quota is used up. Ask for a larger expected \
+                 size when opening a persistent file system, or delete files.",
+            &Code::ContextLost =>
+                "The graphics context was lost, eg because the GPU was reset. \
+                 Create and bind a new context and reupload its resources; \
+                 ppapi_on_graphics_context_lost is called when this happens.",
+            &Code::FileNotFound =>
+                "The file or directory doesn't exist. Check the path, which is \
+                 absolute within the file system, and that the file system was \
+                 opened.",
+            &Code::FileExists =>
+                "The file or directory already exists. Open it without the \
+                 exclusive flag, or delete it first.",
+            &Code::NoAccess =>
+                "Access was denied. The page may lack permission for this, eg \
+                 the manifest doesn't ask for it, or the file system type isn't \
+                 available to this plugin.",
+            &Code::ConnectionRefused =>
+                "The remote host refused the connection. Check the address and \
+                 port, and that something is listening there.",
+            &Code::ConnectionReset =>
+                "The remote host reset the connection. It may have crashed or \
+                 dropped the connection; reconnect and retry.",
+            &Code::ConnectionAborted =>
+                "The connection was aborted locally, eg by a network change or \
+                 a closed socket. Reconnect and retry.",
+            &Code::ConnectionClosed =>
+                "The connection was closed by the other end. Reconnect if more \
+                 data needs to be sent.",
+            &Code::ConnectionFailed =>
+                "The connection attempt failed for a reason other than being \
+                 refused or timing out. Check the network, then retry.",
+            &Code::AddressUnreachable =>
+                "There's no route to the remote address. Check the address, and \
+                 that the network is up.",
+            &Code::NameNotResolved =>
+                "The host name couldn't be resolved. Check its spelling, and \
+                 that DNS is reachable.",
+            &Code::TimedOut =>
+                "The operation didn't finish in time. The network or the other \
+                 end may be slow or unreachable; retry, perhaps with a longer \
+                 timeout.",
+            &Code::NoMessageLoop =>
+                "This thread has no attached message loop, which asynchronous \
+                 calls need for their callbacks. Attach one with \
+                 MessageLoop::attach_to_current_thread, or use \
+                 Instance::spawn_message_loop.",
+            &Code::Aborted =>
+                "The operation was cancelled before it finished, eg because its \
+                 resource was closed or released, or its message loop was shut \
+                 down.",
+            &Code::NoInterface =>
+                "The browser doesn't provide a PPAPI interface this call needs, \
+                 eg because it's too old or the interface is private or dev \
+                 only. Check Instance::supports first.",
+            &Code::BadInstance =>
+                "The instance has been destroyed, or the instance handle is \
+                 invalid. Stop work for the instance once it's destroyed.",
+            &Code::Unknown(_) =>
+                "The browser returned an error code this crate doesn't know, \
+                 possibly from a newer PPAPI. See ppapi/c/pp_errors.h for what \
+                 the number means.",
+        }
+    }
     pub fn ok(self) -> Option<T> {
         match self {
             Code::Ok(v) => Some(v),
@@ -546,6 +926,51 @@ impl<T> Code<T> {
     pub fn is_err(&self) -> bool {
         !self.is_ok() && !self.completion_pending()
     }
+    /// Returns true for errors that may go away if the operation is simply
+    /// tried again: `ConnectionReset`, `ConnectionRefused`, `TimedOut` and
+    /// `InProgress`.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            &Code::ConnectionReset | &Code::ConnectionRefused |
+            &Code::TimedOut | &Code::InProgress => true,
+            _ => false,
+        }
+    }
+    /// `ConnectionRefused`, `ConnectionReset`, `ConnectionAborted`,
+    /// `ConnectionClosed`, `ConnectionFailed`, `AddressUnreachable`,
+    /// `NameNotResolved` and `TimedOut`.
+    pub fn is_network_error(&self) -> bool {
+        self.error_group() == Some(ErrorGroup::Network)
+    }
+    /// `FileNotFound`, `FileExists`, `NoAccess`, `NoSpace` and `NoQuota`.
+    pub fn is_fs_error(&self) -> bool {
+        self.error_group() == Some(ErrorGroup::Fs)
+    }
+    /// `BadResource`, `ContextLost` and `BadInstance`: the resource or
+    /// instance used is gone or was never valid.
+    pub fn is_resource_error(&self) -> bool {
+        self.error_group() == Some(ErrorGroup::Resource)
+    }
+    // No wildcard here, so a new variant has to be classified.
+    fn error_group(&self) -> Option<ErrorGroup> {
+        match self {
+            &Code::ConnectionRefused | &Code::ConnectionReset |
+            &Code::ConnectionAborted | &Code::ConnectionClosed |
+            &Code::ConnectionFailed | &Code::AddressUnreachable |
+            &Code::NameNotResolved | &Code::TimedOut => Some(ErrorGroup::Network),
+
+            &Code::FileNotFound | &Code::FileExists | &Code::NoAccess |
+            &Code::NoSpace | &Code::NoQuota => Some(ErrorGroup::Fs),
+
+            &Code::BadResource | &Code::ContextLost |
+            &Code::BadInstance => Some(ErrorGroup::Resource),
+
+            &Code::Ok(_) | &Code::CompletionPending | &Code::BadArgument |
+            &Code::WrongThread | &Code::InProgress | &Code::Failed |
+            &Code::NotSupported | &Code::NoMemory | &Code::NoMessageLoop |
+            &Code::Aborted | &Code::NoInterface | &Code::Unknown(_) => None,
+        }
+    }
     pub fn unwrap(self) -> T {
         if let Code::Ok(v) = self {
             v
@@ -554,6 +979,167 @@ impl<T> Code<T> {
             panic!("unexpected error code `{:?}`", code)
         }
     }
+
+    // Like `ok`, these treat `CompletionPending` as not having a value.
+
+    pub fn unwrap_or(self, default: T) -> T {
+        match self {
+            Code::Ok(v) => v,
+            _ => default,
+        }
+    }
+    /// `f` is given the non-ok code.
+    pub fn unwrap_or_else<F>(self, f: F) -> T where F: FnOnce(Code) -> T {
+        match self {
+            Code::Ok(v) => v,
+            code => f(code.map_err()),
+        }
+    }
+    pub fn map_or<U, F>(self, default: U, f: F) -> U where F: FnOnce(T) -> U {
+        match self {
+            Code::Ok(v) => f(v),
+            _ => default,
+        }
+    }
+    pub fn ok_or_default(self) -> T where T: Default {
+        self.unwrap_or_else(|_| Default::default() )
+    }
+}
+
+/// Runs `op` on the current message loop until it returns something other
+/// than a transient error (see `Code::is_transient`), or until it has been
+/// tried `max_attempts` times, then calls `callback` with the last code. The
+/// first attempt is posted immediately; each retry is posted with twice the
+/// delay of the one before it, starting at `initial_delay_ms`. Nothing blocks.
+///
+/// Returns `Code::CompletionPending` if the first attempt was posted. If it
+/// couldn't be posted (eg there's no message loop) that error is returned
+/// instead and `callback` isn't called.
+pub fn retry_with_backoff<F, C>(op: F, max_attempts: u32, initial_delay_ms: u64,
+                                callback: C) -> Code
+    where F: FnMut() -> Code + 'static, C: FnOnce(Code) + 'static,
+{
+    struct Retry<F, C> {
+        op: F,
+        callback: C,
+        attempts: u32,
+        max_attempts: u32,
+        delay_ms: u64,
+    }
+    impl<F, C> Retry<F, C>
+        where F: FnMut() -> Code + 'static, C: FnOnce(Code) + 'static,
+    {
+        fn attempt(mut self, status: Code<()>) {
+            if !status.is_ok() {
+                // Our work was aborted, probably by the loop shutting down.
+                return self.callback.call_once((status.map_err(), ));
+            }
+            self.attempts += 1;
+            let code = (self.op)();
+            if !code.is_transient() || self.attempts >= self.max_attempts {
+                return self.callback.call_once((code, ));
+            }
+            let delay = self.delay_ms;
+            self.delay_ms = self.delay_ms.saturating_mul(2);
+            // Shared so we get the callback back if the post fails.
+            let this = Rc::new(RefCell::new(Some(self)));
+            let this2 = this.clone();
+            let posted = MessageLoop::post_to_self(move |status| {
+                let this = this2.borrow_mut().take();
+                if let Some(this) = this { this.attempt(status) }
+            }, delay);
+            if !posted.is_ok() {
+                let this = this.borrow_mut().take();
+                if let Some(this) = this { this.callback.call_once((code, )) }
+            }
+        }
+    }
+
+    let retry = Retry {
+        op: op,
+        callback: callback,
+        attempts: 0,
+        max_attempts: cmp::max(max_attempts, 1),
+        delay_ms: initial_delay_ms,
+    };
+    let posted = MessageLoop::post_to_self(move |status| retry.attempt(status), 0);
+    if posted.is_ok() {
+        Code::CompletionPending
+    } else {
+        posted
+    }
+}
+
+/// Calls `issue` with a callback standing in for `cc`, and gives up on the
+/// operation it starts if it hasn't completed `timeout_ms` later: `cc` is
+/// then called with `Code::TimedOut`, on `ml`, and the operation's own
+/// completion, should it ever come, is ignored. Exactly one of the two
+/// reaches `cc`. If the timeout can't be posted the operation just runs
+/// without one. eg:
+///
+/// ```rust
+/// with_timeout(&ml, 5000, |cc| socket.read(buf, cc), cc)
+/// ```
+///
+/// Returns what `issue` does. Unless that's `Code::CompletionPending`, `cc`
+/// is never called, as with the operation itself.
+pub fn with_timeout<T, F, I>(ml: &MessageLoop, timeout_ms: u64, issue: I,
+                             cc: CallbackArgs<F, T>) -> Code<T>
+    where F: FnOnce(Code<T>) + Send + 'static,
+          I: FnOnce(CallbackArgs<TimeoutCallback<F, T>, T>) -> Code<T>,
+{
+    let state = Arc::new(TimeoutState {
+        resolved: AtomicBool::new(false),
+        f: Mutex::new(Some(cc.f)),
+    });
+    let wrapped = CallbackArgs {
+        optional: cc.optional,
+        f: TimeoutCallback {
+            state: state.clone(),
+            _1: PhantomData,
+        },
+        _1: PhantomData,
+    };
+    let code = issue(wrapped);
+    if !code.completion_pending() {
+        // Either it finished synchronously or it failed; `cc` is done with.
+        state.take();
+        return code;
+    }
+    let _ = ml.post_work(move |status: Code<()>| {
+        if let Some(f) = state.take() {
+            // If the loop is going away, say so instead.
+            let code = if status.is_ok() { Code::TimedOut } else { status.map_err() };
+            f.call_once((code, ));
+        }
+    }, timeout_ms);
+    code
+}
+struct TimeoutState<F> {
+    resolved: AtomicBool,
+    f: Mutex<Option<F>>,
+}
+impl<F> TimeoutState<F> {
+    /// The callback, if nothing else has claimed it yet.
+    fn take(&self) -> Option<F> {
+        if self.resolved.swap(true, Ordering::SeqCst) { None }
+        else { self.f.lock().unwrap().take() }
+    }
+}
+/// The callback `with_timeout` gives the operation in place of the caller's.
+pub struct TimeoutCallback<F, T> {
+    state: Arc<TimeoutState<F>>,
+    _1: PhantomData<fn(Code<T>)>,
+}
+impl<F, T> FnOnce<(Code<T>, )> for TimeoutCallback<F, T>
+    where F: FnOnce(Code<T>),
+{
+    type Output = ();
+    extern "rust-call" fn call_once(self, (code, ): (Code<T>, )) {
+        if let Some(f) = self.state.take() {
+            f.call_once((code, ));
+        }
+    }
 }
 
 impl ops::Add for ffi::Struct_PP_Point {
@@ -655,6 +1241,48 @@ pub type Ticks = ffi::PP_TimeTicks;
 pub type Time = ffi::PP_Time;
 pub type TimeDelta = ffi::PP_TimeDelta;
 
+/// The current wall clock time.
+pub fn now() -> Time {
+    use ppb::CoreIf;
+    ppb::get_core().get_time()
+}
+/// The current tick count. Ticks only ever increase, so use these, not
+/// `now`, to measure elapsed time.
+pub fn now_ticks() -> Ticks {
+    use ppb::CoreIf;
+    ppb::get_core().get_time_ticks()
+}
+
+/// Conversions between `TimeDelta`s and `Duration`s. `Time`, `Ticks` and
+/// `TimeDelta` are all seconds as an `f64`, so the usual arithmetic applies:
+/// `Ticks - Ticks` is a `TimeDelta`, and `Time + TimeDelta` is a `Time`.
+pub trait TimeDeltaExt {
+    /// `Duration`s can't be negative; negative and NaN deltas become a zero
+    /// `Duration`, and deltas too long to represent are clamped.
+    fn to_duration(self) -> Duration;
+    fn from_duration(d: Duration) -> Self;
+    /// The time elapsed since `earlier`, according to `now_ticks`.
+    fn since(earlier: Ticks) -> Self;
+}
+impl TimeDeltaExt for TimeDelta {
+    fn to_duration(self) -> Duration {
+        if !(self > 0.0) {
+            Duration::new(0, 0)
+        } else if self >= u64::max_value() as f64 {
+            Duration::new(u64::max_value(), 999_999_999)
+        } else {
+            let nanos = cmp::min((self.fract() * 1.0e9).round() as u32, 999_999_999);
+            Duration::new(self.trunc() as u64, nanos)
+        }
+    }
+    fn from_duration(d: Duration) -> TimeDelta {
+        d.as_secs() as f64 + d.subsec_nanos() as f64 / 1.0e9
+    }
+    fn since(earlier: Ticks) -> TimeDelta {
+        now_ticks() - earlier
+    }
+}
+
 // duplicated here so we don't have such a long name for this.
 #[derive(Eq, PartialEq, Hash, Clone, Copy)]
 pub struct Size {
@@ -668,6 +1296,36 @@ impl Size {
             height: height,
         }
     }
+    /// The size in device pixels of something `self` CSS pixels big at
+    /// `device_scale` (see `View::device_scale`). Fractional pixels are
+    /// rounded up, so an image this size painted at a scale of
+    /// `1 / device_scale` covers the whole area without leaving a gap.
+    pub fn to_device_pixels(&self, device_scale: f32) -> Size {
+        fn scale(v: u32, device_scale: f32) -> u32 {
+            // Keeps float error, eg 110.00001, from adding a whole pixel.
+            const EPSILON: f64 = 0.001;
+            let scaled = v as f64 * device_scale as f64;
+            if scaled <= 0.0 { return 0; }
+            (scaled - EPSILON).ceil() as u32
+        }
+        Size::new(scale(self.width, device_scale), scale(self.height, device_scale))
+    }
+
+    /// `None` on overflow. Unlike the `ops` impls on `ffi::PP_Size`, these
+    /// are safe to use with sizes from untrusted sources, eg JS messages.
+    pub fn checked_add(&self, rhs: Size) -> Option<Size> {
+        match (self.width.checked_add(rhs.width), self.height.checked_add(rhs.height)) {
+            (Some(width), Some(height)) => Some(Size::new(width, height)),
+            _ => None,
+        }
+    }
+    /// Scales both dimensions by `scale`. `None` on overflow.
+    pub fn checked_mul_scalar(&self, scale: u32) -> Option<Size> {
+        match (self.width.checked_mul(scale), self.height.checked_mul(scale)) {
+            (Some(width), Some(height)) => Some(Size::new(width, height)),
+            _ => None,
+        }
+    }
 }
 impl From<ffi::PP_Size> for Size {
     fn from(v: ffi::PP_Size) -> Size {
@@ -695,6 +1353,35 @@ impl Rect {
             size: size,
         }
     }
+    /// The point just past the bottom right corner. `None` on overflow.
+    pub fn checked_end(&self) -> Option<Point> {
+        self.point.checked_add(Point::new(self.size.width, self.size.height))
+    }
+    /// True if the rects share any area. Empty rects intersect nothing.
+    pub fn intersects(&self, rhs: &Rect) -> bool {
+        let empty = |r: &Rect| r.size.width == 0 || r.size.height == 0;
+        if empty(self) || empty(rhs) { return false; }
+        // Overflowing rects extend past anything we could compare them to.
+        let max = Point::new(u32::max_value(), u32::max_value());
+        let a = self.checked_end().unwrap_or(max);
+        let b = rhs.checked_end().unwrap_or(max);
+        self.point.x < b.x && rhs.point.x < a.x &&
+            self.point.y < b.y && rhs.point.y < a.y
+    }
+    /// The smallest rect containing both. `None` if it can't be
+    /// represented.
+    pub fn checked_union(&self, rhs: &Rect) -> Option<Rect> {
+        use std::cmp::{max, min};
+        let (a, b) = match (self.checked_end(), rhs.checked_end()) {
+            (Some(a), Some(b)) => (a, b),
+            _ => return None,
+        };
+        let left = min(self.point.x, rhs.point.x);
+        let top = min(self.point.y, rhs.point.y);
+        let right = max(a.x, b.x);
+        let bottom = max(a.y, b.y);
+        Some(Rect::new(Point::new(left, top), Size::new(right - left, bottom - top)))
+    }
 }
 impl From<ffi::PP_Rect> for Rect {
     fn from(v: ffi::PP_Rect) -> Rect {
@@ -721,6 +1408,13 @@ impl Point {
             y: y,
         }
     }
+    /// `None` on overflow.
+    pub fn checked_add(&self, rhs: Point) -> Option<Point> {
+        match (self.x.checked_add(rhs.x), self.y.checked_add(rhs.y)) {
+            (Some(x), Some(y)) => Some(Point::new(x, y)),
+            _ => None,
+        }
+    }
 }
 impl From<ffi::PP_Point> for Point {
     fn from(v: ffi::PP_Point) -> Point {
@@ -759,6 +1453,7 @@ pub enum ResourceType {
     Graphics3D,
     Graphics2D,
     Font,
+    BrowserFont,
     FileSystem,
     FileRef,
     FileIo,
@@ -767,12 +1462,79 @@ pub enum ResourceType {
     VideoTrack,
     VideoFrame,
     VideoDecoder,
+    VideoEncoder,
+    AudioTrack,
+    AudioBuffer,
 }
 
-#[derive(Eq, PartialEq, Debug, Hash)]
-pub struct GenericResource(ffi::PP_Resource);
+/// Counts of live resource wrappers, per type, for finding reference leaks
+/// in the wrappers. See `Instance::resource_stats`.
+#[cfg(feature = "debug-resource-tracking")]
+#[doc(hidden)]
+pub mod resource_tracking {
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicIsize, Ordering, ATOMIC_ISIZE_INIT};
+    use super::ResourceType;
+
+    const TYPES: [ResourceType; 34] = [
+        ResourceType::WheelInputEvent, ResourceType::WebSocket,
+        ResourceType::View, ResourceType::UrlResponseInfo,
+        ResourceType::UrlRequestInfo, ResourceType::UrlLoader,
+        ResourceType::UdpSocket, ResourceType::TrueTypeFont,
+        ResourceType::TouchInputEvent, ResourceType::TcpSocket,
+        ResourceType::NetworkMonitor, ResourceType::NetworkList,
+        ResourceType::NetworkAddress, ResourceType::MouseInputEvent,
+        ResourceType::MessageLoop, ResourceType::KeyboardInputEvent,
+        ResourceType::ImageData, ResourceType::IMEInputEvent,
+        ResourceType::HostResolver, ResourceType::Graphics3D,
+        ResourceType::Graphics2D, ResourceType::Font,
+        ResourceType::BrowserFont, ResourceType::FileSystem,
+        ResourceType::FileRef, ResourceType::FileIo,
+        ResourceType::AudioConfig, ResourceType::Audio,
+        ResourceType::VideoTrack, ResourceType::VideoFrame,
+        ResourceType::VideoDecoder, ResourceType::VideoEncoder,
+        ResourceType::AudioTrack, ResourceType::AudioBuffer,
+    ];
+    // Indexed like `TYPES`.
+    static COUNTS: [AtomicIsize; 34] = [
+        ATOMIC_ISIZE_INIT, ATOMIC_ISIZE_INIT, ATOMIC_ISIZE_INIT, ATOMIC_ISIZE_INIT,
+        ATOMIC_ISIZE_INIT, ATOMIC_ISIZE_INIT, ATOMIC_ISIZE_INIT, ATOMIC_ISIZE_INIT,
+        ATOMIC_ISIZE_INIT, ATOMIC_ISIZE_INIT, ATOMIC_ISIZE_INIT, ATOMIC_ISIZE_INIT,
+        ATOMIC_ISIZE_INIT, ATOMIC_ISIZE_INIT, ATOMIC_ISIZE_INIT, ATOMIC_ISIZE_INIT,
+        ATOMIC_ISIZE_INIT, ATOMIC_ISIZE_INIT, ATOMIC_ISIZE_INIT, ATOMIC_ISIZE_INIT,
+        ATOMIC_ISIZE_INIT, ATOMIC_ISIZE_INIT, ATOMIC_ISIZE_INIT, ATOMIC_ISIZE_INIT,
+        ATOMIC_ISIZE_INIT, ATOMIC_ISIZE_INIT, ATOMIC_ISIZE_INIT, ATOMIC_ISIZE_INIT,
+        ATOMIC_ISIZE_INIT, ATOMIC_ISIZE_INIT, ATOMIC_ISIZE_INIT, ATOMIC_ISIZE_INIT,
+        ATOMIC_ISIZE_INIT, ATOMIC_ISIZE_INIT,
+    ];
+
+    pub fn adjust(ty: ResourceType, delta: isize) {
+        let idx = TYPES.iter().position(|&t| t == ty ).unwrap();
+        COUNTS[idx].fetch_add(delta, Ordering::Relaxed);
+    }
+    pub fn stats() -> HashMap<ResourceType, i64> {
+        TYPES.iter()
+            .zip(COUNTS.iter())
+            .map(|(&ty, count)| (ty, count.load(Ordering::Relaxed) as i64) )
+            .collect()
+    }
+}
+
+/// A resource of any type. Also holds the resource var made for it by
+/// `to_var`, if any, so the var lives as long as the wrapper does.
+pub struct GenericResource(ffi::PP_Resource, Cell<Option<ffi::PP_Var>>);
 unsafe impl Send for GenericResource { }
 impl GenericResource {
+    /// Takes ownership of `res`; no reference is added.
+    fn adopt(res: ffi::PP_Resource) -> GenericResource {
+        GenericResource(res, Cell::new(None))
+    }
+    fn release_var(&self) {
+        if let Some(var) = self.1.get() {
+            self.1.set(None);
+            ppb::get_var().remove_ref(var);
+        }
+    }
     pub fn is_graphics3d(&self) -> bool {
         use ppb::*;
         get_graphics_3d_opt().is(self.unwrap())
@@ -787,8 +1549,56 @@ impl GenericResource {
             None
         }
     }
+    pub fn media_stream_audio_track(&self) ->
+        Option<media_stream_audio_track::AudioTrack>
+    {
+        use ppb::*;
+        if get_media_stream_audio_track_opt().is(self.unwrap()) {
+            Some(From::from(self.unwrap()))
+        } else {
+            None
+        }
+    }
+    /// Convert this resource into the concrete wrapper `T`. Ownership of our
+    /// reference is transferred to the new wrapper, so the refcount is left
+    /// untouched. If this resource isn't a `T`, it is handed back unchanged.
+    pub fn downcast<T: TypedResource>(self) -> result::Result<T, GenericResource> {
+        if self.type_of() != Some(<T as TypedResource>::resource_type()) {
+            return Err(self);
+        }
+
+        let res = self.0;
+        self.release_var();
+        mem::forget(self);
+        Ok(<T as TypedResource>::from_resource(res))
+    }
+}
+impl Clone for GenericResource {
+    fn clone(&self) -> GenericResource {
+        (ppb::get_core().AddRefResource.unwrap())(self.0);
+        GenericResource::adopt(self.0)
+    }
+}
+impl Drop for GenericResource {
+    fn drop(&mut self) {
+        self.release_var();
+        (ppb::get_core().ReleaseResource.unwrap())(self.0);
+    }
+}
+impl PartialEq for GenericResource {
+    fn eq(&self, rhs: &GenericResource) -> bool { self.0 == rhs.0 }
+}
+impl Eq for GenericResource { }
+impl ::std::hash::Hash for GenericResource {
+    fn hash<H: ::std::hash::Hasher>(&self, state: &mut H) {
+        ::std::hash::Hash::hash(&self.0, state)
+    }
+}
+impl fmt::Debug for GenericResource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "GenericResource({:?})", self.0)
+    }
 }
-impl_clone_drop_for!(GenericResource);
 impl Resource for GenericResource {
     fn unwrap(&self) -> ffi::PP_Resource { self.0 }
 
@@ -798,6 +1608,8 @@ impl Resource for GenericResource {
 
         if get_graphics_3d_opt().is(self.0) {
             t = Some(ResourceType::Graphics3D);
+        } else if get_graphics_2d_opt().is(self.0) {
+            t = Some(ResourceType::Graphics2D);
         } else if get_keyboard_event_opt().is(self.0) {
             t = Some(ResourceType::KeyboardInputEvent);
         } else if get_mouse_event_opt().is(self.0) {
@@ -822,6 +1634,28 @@ impl Resource for GenericResource {
             t = Some(ResourceType::VideoTrack);
         } else if get_video_frame_opt().is(self.0) {
             t = Some(ResourceType::VideoFrame);
+        } else if get_video_decoder_opt().is(self.0) {
+            t = Some(ResourceType::VideoDecoder);
+        } else if get_video_encoder_opt().is(self.0) {
+            t = Some(ResourceType::VideoEncoder);
+        } else if get_media_stream_audio_track_opt().is(self.0) {
+            t = Some(ResourceType::AudioTrack);
+        } else if get_audio_buffer_opt().is(self.0) {
+            t = Some(ResourceType::AudioBuffer);
+        } else if get_file_ref_opt().is(self.0) {
+            t = Some(ResourceType::FileRef);
+        } else if get_file_io_opt().is(self.0) {
+            t = Some(ResourceType::FileIo);
+        } else if get_image_data_opt().is(self.0) {
+            t = Some(ResourceType::ImageData);
+        } else if get_font_opt().is(self.0) {
+            t = Some(ResourceType::Font);
+        } else if get_browser_font_opt().is(self.0) {
+            t = Some(ResourceType::BrowserFont);
+        } else if get_audio_config_opt().is(self.0) {
+            t = Some(ResourceType::AudioConfig);
+        } else if get_audio_opt().is(self.0) || get_audio_1_0_opt().is(self.0) {
+            t = Some(ResourceType::Audio);
         } else {
             t = None;
         }
@@ -830,14 +1664,32 @@ impl Resource for GenericResource {
     }
 }
 impl ToVar for GenericResource {
+    /// A resource var, which holds its own reference to the resource. It's
+    /// made on first use and kept until this wrapper is dropped. Undefined
+    /// if the browser's `PPB_Var` is older than 1.2.
     fn to_var(&self) -> ffi::PP_Var {
-        unsafe { ffi::resource_id_to_var(self.unwrap()) }
+        if let Some(var) = self.1.get() {
+            return var;
+        }
+        match ppb::get_var().var_from_resource(self.unwrap()) {
+            Some(var) => {
+                self.1.set(Some(var));
+                var
+            }
+            None => {
+                warn!("resource vars need PPB_Var 1.2");
+                {UndefinedVar}.to_var()
+            }
+        }
+    }
+    fn to_any(&self) -> AnyVar {
+        AnyVar::Resource(self.clone())
     }
 }
 #[doc(hidden)]
 impl From<ffi::PP_Resource> for GenericResource {
     fn from(v: ffi::PP_Resource) -> GenericResource {
-        GenericResource(v)
+        GenericResource::adopt(v)
     }
 }
 
@@ -845,13 +1697,49 @@ pub trait Resource: Clone + Send {
     #[doc(hidden)] fn unwrap(&self) -> ffi::PP_Resource;
 
     fn type_of(&self) -> Option<ResourceType>;
+
+    /// This resource as a var, eg to post to JS. The var holds its own
+    /// reference, so the resource lives as long as JS keeps it. JS can post
+    /// it back, where it decodes as `AnyVar::Resource`.
+    fn to_resource_var(&self) -> AnyVar {
+        let res = self.unwrap();
+        (ppb::get_core().AddRefResource.unwrap())(res);
+        AnyVar::Resource(GenericResource::adopt(res))
+    }
+
+    /// Adds a reference to the resource outside of `Clone` and `Drop`, eg to
+    /// keep it alive for C code which will release it later. Normal code
+    /// should `clone` instead.
+    ///
+    /// Unsafe because nothing owns the new reference: it must be released
+    /// exactly once, with `release`, by C code, or by a wrapper which adopts
+    /// it, or the resource leaks.
+    unsafe fn add_ref(&self) {
+        (ppb::get_core().AddRefResource.unwrap())(self.unwrap());
+    }
+    /// Releases a reference added with `add_ref`, or one C code handed over
+    /// without a wrapper. Normal code should let wrappers drop instead.
+    ///
+    /// Unsafe because releasing a reference some wrapper owns leaves it
+    /// dangling: its drop releases a reference it no longer has, and the
+    /// resource may be destroyed while it's still in use.
+    unsafe fn release(&self) {
+        (ppb::get_core().ReleaseResource.unwrap())(self.unwrap());
+    }
+}
+/// A resource whose concrete type is known statically.
+pub trait TypedResource: Resource {
+    fn resource_type() -> ResourceType;
+
+    /// Takes ownership of `res`; no reference is added.
+    #[doc(hidden)] fn from_resource(res: ffi::PP_Resource) -> Self;
 }
 pub trait ContextResource: Resource {
     fn get_device(&self) -> ffi::PP_Resource;
 }
 #[derive(Hash, Eq, PartialEq, Debug)] pub struct Context2d(ffi::PP_Resource);
 #[derive(Hash, Eq, PartialEq, Debug)] pub struct View(ffi::PP_Resource);
-#[derive(Hash, Eq, PartialEq, Debug)] pub struct MessageLoop(ffi::PP_Resource);
+#[derive(Hash, Eq, PartialEq)] pub struct MessageLoop(ffi::PP_Resource);
 
 impl_resource_for!(Context2d, ResourceType::Graphics2D);
 impl_clone_drop_for!(Context2d);
@@ -865,6 +1753,277 @@ impl ContextResource for Context2d {
         self.unwrap()
     }
 }
+/// For rebinding a device returned by `Instance::bind_context_returning_prev`.
+impl ContextResource for GenericResource {
+    fn get_device(&self) -> ffi::PP_Resource {
+        self.unwrap()
+    }
+}
+impl Context2d {
+    /// Returns the size of the context and whether it was created as always
+    /// opaque.
+    pub fn describe(&self) -> Option<(Size, bool)> {
+        use ppb::Graphics2DIf;
+        ppb::get_graphics_2d()
+            .describe(self.unwrap())
+            .map(|(size, opaque)| (From::from(size), opaque) )
+    }
+
+    // PPAPI silently ignores images whose format differs from the context's,
+    // which is always the native format.
+    fn check_image_format(image: &imagedata::ImageData) -> Code<()> {
+        match image.describe() {
+            None => Code::BadResource,
+            Some(desc) if desc.format != imagedata::native_image_data_format() =>
+                Code::BadArgument,
+            Some(_) => Code::Ok(()),
+        }
+    }
+
+    /// Enqueues a paint of `image` at `top_left`. If `src_rect` is provided,
+    /// only that part of the image is painted. Returns `Code::BadArgument`
+    /// without painting if the image isn't in the native format. Nothing is
+    /// visible until the next `flush`.
+    pub fn paint_image_data(&self,
+                            image: &imagedata::ImageData,
+                            top_left: Point,
+                            src_rect: Option<Rect>) -> Code<()> {
+        use ppb::Graphics2DIf;
+        try_code!(Context2d::check_image_format(image));
+        ppb::get_graphics_2d()
+            .paint_image_data(self.unwrap(),
+                              image.unwrap(),
+                              top_left.into(),
+                              src_rect.map(|r| r.into() ));
+        readback_record(self.unwrap(), Paint2d::Paint(image.clone(), top_left, src_rect));
+        Code::Ok(())
+    }
+    /// Enqueues replacing the whole backing store with `image`. The same
+    /// format check as `paint_image_data` applies.
+    pub fn replace_contents(&self, image: &imagedata::ImageData) -> Code<()> {
+        use ppb::Graphics2DIf;
+        try_code!(Context2d::check_image_format(image));
+        ppb::get_graphics_2d()
+            .replace_contents(self.unwrap(), image.unwrap());
+        readback_record(self.unwrap(), Paint2d::Replace(image.clone()));
+        Code::Ok(())
+    }
+    pub fn flush<F>(&self, callback: CallbackArgs<F, ()>) -> Code<()>
+        where F: FnOnce(Code<()>),
+    {
+        use ppb::Graphics2DIf;
+        let res = self.unwrap();
+        if !readback_stage(res) {
+            let cc = callback.to_ffi_callback((), Default::default());
+            let r = ppb::get_graphics_2d().flush(res, cc.cc());
+            return cc.drop_with_code(r);
+        }
+
+        let CallbackArgs { optional, f, .. } = callback;
+        let callback = CallbackArgs {
+            optional: optional,
+            f: move |code: Code<()>| {
+                readback_flushed(res, code.is_ok());
+                f.call_once((code, ));
+            },
+            _1: PhantomData,
+        };
+        let cc = callback.to_ffi_callback((), Default::default());
+        let r = ppb::get_graphics_2d().flush(res, cc.cc());
+        let r = cc.drop_with_code(r);
+        if !r.completion_pending() {
+            // The callback won't be called.
+            readback_flushed(res, r.is_ok());
+        }
+        r
+    }
+
+    /// Starts tracking what this context displays so `read_pixels` can be
+    /// used. PPAPI has no way to read a 2D context's backing store, so this
+    /// keeps a CPU side copy, in the native format, updated with every paint
+    /// and replace made on this thread. While tracking is enabled each flush
+    /// costs an extra composite and a full frame copy.
+    ///
+    /// Paints made on other threads, or before tracking was enabled, aren't
+    /// seen. Changes to an image after it was given to `replace_contents` are
+    /// only seen if they happen before the next flush.
+    pub fn enable_readback(&self, instance: &Instance) -> Code<()> {
+        let size = match self.describe() {
+            Some((size, _)) => size,
+            None => return Code::BadResource,
+        };
+        let shadow = match instance.create_image(None, size, true) {
+            Some(shadow) => shadow,
+            None => return Code::NoMemory,
+        };
+        READBACK_2D.with(|rb| {
+            rb.borrow_mut().insert(self.unwrap(), Readback2d {
+                instance: *instance,
+                size: size,
+                shadow: shadow,
+                pending: Vec::new(),
+                in_flight: VecDeque::new(),
+                committed: None,
+                waiting: Vec::new(),
+            });
+        });
+        Code::Ok(())
+    }
+    /// Stops tracking this context. Pending `read_pixels` callbacks are
+    /// dropped without being called.
+    pub fn disable_readback(&self) {
+        let removed = READBACK_2D.with(|rb| rb.borrow_mut().remove(&self.unwrap()) );
+        drop(removed);
+    }
+    /// Reads back a copy of what the most recently completed flush displayed.
+    /// Paints which haven't been flushed aren't included; if a flush is in
+    /// progress, the callback is called once it completes. Requires
+    /// `enable_readback`, otherwise returns `Code::NotSupported`. See
+    /// `enable_readback` for the limitations.
+    pub fn read_pixels<F>(&self, callback: CallbackArgs<F, imagedata::ImageData>) ->
+        Code<imagedata::ImageData>
+        where F: FnOnce(Code<imagedata::ImageData>) + 'static,
+    {
+        let res = self.unwrap();
+        let mut callback = Some(callback);
+        let now = READBACK_2D.with(|rb| {
+            let mut rb = rb.borrow_mut();
+            match rb.get_mut(&res) {
+                None => Err(Code::NotSupported),
+                Some(state) => {
+                    if state.in_flight.is_empty() {
+                        Ok(state.copy_committed())
+                    } else {
+                        let mut callback = callback.take();
+                        state.waiting.push(box move |code: Code<imagedata::ImageData>| {
+                            callback.take().unwrap().call_directly(code);
+                        });
+                        Err(Code::CompletionPending)
+                    }
+                }
+            }
+        });
+        let image = match now {
+            Ok(Some(image)) => image,
+            Ok(None) => return Code::NoMemory,
+            Err(code) => return code,
+        };
+
+        let callback = callback.take().unwrap();
+        if callback.optional {
+            return Code::Ok(image);
+        }
+        let posted = MessageLoop::post_to_self(move |_| {
+            callback.call_directly(Code::Ok(image));
+        }, 0);
+        if posted.is_ok() {
+            Code::CompletionPending
+        } else {
+            posted.map_err()
+        }
+    }
+}
+
+enum Paint2d {
+    Paint(imagedata::ImageData, Point, Option<Rect>),
+    Replace(imagedata::ImageData),
+}
+struct Readback2d {
+    instance: Instance,
+    size: Size,
+    /// Every paint staged by a flush, composited.
+    shadow: imagedata::ImageData,
+    /// Paints made since the last flush.
+    pending: Vec<Paint2d>,
+    /// Snapshots of `shadow`, one per flush which hasn't completed yet.
+    in_flight: VecDeque<imagedata::ImageData>,
+    committed: Option<imagedata::ImageData>,
+    waiting: Vec<Box<FnMut(Code<imagedata::ImageData>)>>,
+}
+impl Readback2d {
+    fn copy_image(&self, image: &imagedata::ImageData) -> Option<imagedata::ImageData> {
+        let copy = match self.instance.create_image(None, self.size, false) {
+            Some(copy) => copy,
+            None => return None,
+        };
+        imagedata::copy_pixels(&copy, Point::new(0, 0),
+                               image, Rect::new(Point::new(0, 0), self.size));
+        Some(copy)
+    }
+    fn copy_committed(&self) -> Option<imagedata::ImageData> {
+        match self.committed {
+            Some(ref committed) => self.copy_image(committed),
+            // Nothing has been flushed; the backing store starts out zeroed.
+            None => self.instance.create_image(None, self.size, true),
+        }
+    }
+}
+thread_local!(static READBACK_2D: RefCell<HashMap<ffi::PP_Resource, Readback2d>> =
+              RefCell::new(HashMap::new()));
+
+fn readback_record(ctxt: ffi::PP_Resource, paint: Paint2d) {
+    READBACK_2D.with(|rb| {
+        if let Some(state) = rb.borrow_mut().get_mut(&ctxt) {
+            state.pending.push(paint);
+        }
+    })
+}
+/// Composites the pending paints and snapshots the result. Returns false if
+/// `ctxt` isn't tracked.
+fn readback_stage(ctxt: ffi::PP_Resource) -> bool {
+    READBACK_2D.with(|rb| {
+        let mut rb = rb.borrow_mut();
+        let state = match rb.get_mut(&ctxt) {
+            Some(state) => state,
+            None => return false,
+        };
+        for paint in mem::replace(&mut state.pending, Vec::new()).into_iter() {
+            match paint {
+                Paint2d::Paint(image, top_left, src_rect) => {
+                    let src_rect = src_rect.unwrap_or_else(|| {
+                        let size = image.describe()
+                            .map(|desc| desc.size )
+                            .unwrap_or(Size::new(0, 0));
+                        Rect::new(Point::new(0, 0), size)
+                    });
+                    // Past the end of any shadow if it overflows.
+                    if let Some(dst) = top_left.checked_add(src_rect.point) {
+                        imagedata::copy_pixels(&state.shadow, dst, &image, src_rect);
+                    }
+                }
+                Paint2d::Replace(image) => {
+                    imagedata::copy_pixels(&state.shadow, Point::new(0, 0), &image,
+                                           Rect::new(Point::new(0, 0), state.size));
+                }
+            }
+        }
+        let snapshot = state.copy_image(&state.shadow)
+            // Keep flushes and snapshots paired up even if we're out of memory.
+            .unwrap_or_else(|| state.shadow.clone() );
+        state.in_flight.push_back(snapshot);
+        true
+    })
+}
+fn readback_flushed(ctxt: ffi::PP_Resource, ok: bool) {
+    let waiting: Vec<_> = READBACK_2D.with(|rb| {
+        let mut rb = rb.borrow_mut();
+        let state = match rb.get_mut(&ctxt) {
+            Some(state) => state,
+            None => return Vec::new(),
+        };
+        let snapshot = state.in_flight.pop_front();
+        if ok {
+            state.committed = snapshot;
+        }
+        mem::replace(&mut state.waiting, Vec::new())
+            .into_iter()
+            .map(|waiter| (waiter, state.copy_committed()) )
+            .collect()
+    });
+    for (mut waiter, image) in waiting.into_iter() {
+        waiter(image.map(|image| Code::Ok(image) ).unwrap_or(Code::NoMemory));
+    }
+}
 impl View {
     pub fn rect(&self) -> Option<Rect> {
         ppb::get_view()
@@ -885,56 +2044,377 @@ impl View {
             .clip_rect(self.unwrap())
             .map(|rect| From::from(rect) )
     }
+    /// 1.0 if the browser's `PPB_View` is too old to say.
     pub fn device_scale(&self) -> f32 {
-        ppb::get_view().device_scale(self.unwrap())
+        ppb::get_view().device_scale(self.unwrap()).unwrap_or(1.0)
     }
+    /// 1.0 if the browser's `PPB_View` is too old to say.
     pub fn css_scale(&self) -> f32 {
-        ppb::get_view().css_scale(self.unwrap())
+        ppb::get_view().css_scale(self.unwrap()).unwrap_or(1.0)
+    }
+    /// The device pixel size of a `css_size` pixel font in this view, ie
+    /// scaled by both `device_scale` and `css_scale`. See
+    /// `font::scale_font_size`.
+    pub fn scale_font_size(&self, css_size: u32) -> u32 {
+        font::scale_font_size(css_size, self.device_scale() * self.css_scale())
+    }
+    /// The scroll offset of the page containing this instance. See
+    /// `ViewGeometry::scroll_offset`; this is zero if nothing is known.
+    pub fn scroll_offset(&self) -> Point {
+        self.geometry().scroll_offset()
+    }
+    /// Snapshots the rects and scroll offset of this view. Missing values are
+    /// zeroed.
+    pub fn geometry(&self) -> ViewGeometry {
+        let zero = Rect::new(Point::new(0, 0), Size::new(0, 0));
+        ViewGeometry {
+            rect: self.rect().unwrap_or(zero),
+            clip_rect: self.clip_rect().unwrap_or(zero),
+            scroll_offset: ppb::get_view()
+                .scroll_offset(self.unwrap())
+                .map(|offset| From::from(offset) ),
+        }
     }
-}
-impl Messaging {
-    pub fn post<T: ToVar>(&self, message: T) {
-        use ppb::MessagingIf;
-        ppb::get_messaging().post_message(self.unwrap(), message.to_var())
+    /// Maps an instance local point to page coordinates, eg for placing a
+    /// popup. See `ViewGeometry::to_page`.
+    pub fn to_page(&self, local: Point) -> Point {
+        self.geometry().to_page(local)
     }
 }
-impl MessageLoop {
-    fn get_main_loop() -> MessageLoop {
-        MessageLoop::new((ppb::get_message_loop().GetForMainThread.unwrap())())
-    }
-    pub fn is_attached() -> bool {
-        unsafe {
-            (ppb::get_message_loop().GetCurrent.unwrap())() != mem::transmute(0i32)
+
+/// The geometry of an instance at the time of a view change. Can be built by
+/// hand, which is mostly useful for testing.
+#[derive(Eq, PartialEq, Hash, Clone, Copy)]
+pub struct ViewGeometry {
+    /// The instance's rect, relative to the top left of the viewport. The
+    /// origin goes negative (ie wraps) once the instance's top left corner is
+    /// scrolled out of view.
+    pub rect: Rect,
+    /// The visible part of the instance, in instance local coordinates.
+    pub clip_rect: Rect,
+    /// The page's scroll offset, as reported by the browser, if it did.
+    pub scroll_offset: Option<Point>,
+}
+impl ViewGeometry {
+    /// The page's scroll offset. If the browser didn't report it, it's
+    /// approximated by how much of the instance is scrolled out of the top
+    /// left of the viewport, which is the origin of the clip rect when the
+    /// instance is at least partially visible. That's a lower bound: it's
+    /// exact only when the instance sits at the top left of the page, and it's
+    /// zero whenever the instance's top left corner is in view.
+    pub fn scroll_offset(&self) -> Point {
+        match self.scroll_offset {
+            Some(offset) => offset,
+            None if self.clip_rect.size.width != 0 &&
+                self.clip_rect.size.height != 0 => self.clip_rect.point,
+            None => Point::new(0, 0),
         }
     }
-    pub fn current() -> Option<MessageLoop> {
+    /// Maps an instance local point to viewport coordinates.
+    pub fn to_viewport(&self, local: Point) -> Point {
+        // The rect origin may be "negative", hence wrapping.
+        Point::new(local.x.wrapping_add(self.rect.point.x),
+                   local.y.wrapping_add(self.rect.point.y))
+    }
+    /// Maps an instance local point to page coordinates.
+    pub fn to_page(&self, local: Point) -> Point {
+        let viewport = self.to_viewport(local);
+        let scroll = self.scroll_offset();
+        Point::new(viewport.x.wrapping_add(scroll.x),
+                   viewport.y.wrapping_add(scroll.y))
+    }
+}
+/// The key of the message JS sends to acknowledge posts when backpressure is
+/// on: `{ "ppapi_ack": <count> }`. See `Messaging::set_backpressure`.
+pub const MESSAGE_ACK_KEY: &'static str = "ppapi_ack";
+
+// Outstanding posts per instance with backpressure on. Posts may be made
+// from any thread, so this isn't thread local. `BACKPRESSURE_INSTANCES`
+// counts the entries so the message path can skip the lock when it's empty.
+static BACKPRESSURE_INSTANCES: AtomicUsize = ATOMIC_USIZE_INIT;
+static BACKPRESSURE_INIT: Once = ONCE_INIT;
+static mut BACKPRESSURE: *const Mutex<HashMap<ffi::PP_Instance, usize>> =
+    0 as *const Mutex<HashMap<ffi::PP_Instance, usize>>;
+fn backpressure() -> &'static Mutex<HashMap<ffi::PP_Instance, usize>> {
+    BACKPRESSURE_INIT.call_once(|| unsafe {
+        BACKPRESSURE = mem::transmute(Box::new(Mutex::new(HashMap::new())));
+    });
+    unsafe { &*BACKPRESSURE }
+}
+
+impl Messaging {
+    pub fn post<T: ToVar>(&self, message: T) {
+        self.post_to(ppb::get_messaging(), message);
+    }
+    /// Like `post`, but posts through `messaging` instead of the browser's
+    /// `PPB_Messaging`.
+    pub fn post_to<M: ppb::MessagingIf, T: ToVar>(&self, messaging: &M, message: T) {
+        messaging.post_message(self.unwrap(), message.to_var());
+        if BACKPRESSURE_INSTANCES.load(Ordering::SeqCst) != 0 {
+            if let Some(pending) = backpressure().lock().unwrap().get_mut(&self.unwrap()) {
+                *pending += 1;
+            }
+        }
+    }
+
+    /// Turns on or off counting of posts JS hasn't acknowledged yet, so
+    /// producers can throttle themselves with `pending_posts`. Off by
+    /// default. While on, JS is expected to post `{ "ppapi_ack": n }` after
+    /// handling `n` messages; these acks are consumed before reaching a
+    /// `MessageHandler`, a message channel or `InstanceHandler::on_message`.
+    /// Turning it off resets the count and stops looking for acks.
+    pub fn set_backpressure(&self, enabled: bool) {
+        let mut instances = backpressure().lock().unwrap();
+        if enabled {
+            if !instances.contains_key(&self.unwrap()) {
+                instances.insert(self.unwrap(), 0);
+                BACKPRESSURE_INSTANCES.fetch_add(1, Ordering::SeqCst);
+            }
+        } else if instances.remove(&self.unwrap()).is_some() {
+            BACKPRESSURE_INSTANCES.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+    /// Posts not yet acknowledged by JS. Always 0 unless backpressure is on.
+    pub fn pending_posts(&self) -> usize {
+        if BACKPRESSURE_INSTANCES.load(Ordering::SeqCst) == 0 { return 0; }
+        backpressure().lock().unwrap().get(&self.unwrap()).cloned().unwrap_or(0)
+    }
+    /// If backpressure is on and `message` is an ack, counts it and returns
+    /// true; the message should then be dropped. This is done for every
+    /// message the browser hands the plugin, whichever way it's dispatched;
+    /// it's public for plugins forwarding messages between instances.
+    pub fn intercept_ack(&self, message: &AnyVar) -> bool {
+        if BACKPRESSURE_INSTANCES.load(Ordering::SeqCst) == 0 { return false; }
+        let acked = match message {
+            &AnyVar::Dictionary(ref dict) if dict.len() == 1 => match dict.get(MESSAGE_ACK_KEY) {
+                AnyVar::I32(n) if n > 0 => n as usize,
+                _ => return false,
+            },
+            _ => return false,
+        };
+        match backpressure().lock().unwrap().get_mut(&self.unwrap()) {
+            Some(pending) => {
+                *pending = pending.saturating_sub(acked);
+                true
+            }
+            None => false,
+        }
+    }
+    /// Posts the var tree `value.to_field()` builds, eg for an `OwnedVar` or a
+    /// `Vec<String>`. The tree is released as soon as the browser has its
+    /// copy.
+    pub fn post_field<T: messages::MessageField>(&self, value: &T) {
+        self.post_field_to(ppb::get_messaging(), value);
+    }
+    /// Like `post_field`, but posts through `messaging` instead of the
+    /// browser's `PPB_Messaging`.
+    pub fn post_field_to<M, T>(&self, messaging: &M, value: &T)
+        where M: ppb::MessagingIf, T: messages::MessageField,
+    {
+        self.post_to(messaging, value.to_field().to_any());
+    }
+    /// Posts `Messaging::error_message(code, context)`, so JS can handle
+    /// every error the plugin reports the same way.
+    pub fn post_error<T>(&self, code: Code<T>, context: &str) {
+        self.post(Messaging::error_message(code, context));
+    }
+    /// The message `post_error` sends:
+    /// `{ "type": "error", "code": <i32>, "message": <code's Display>,
+    /// "context": <context> }`.
+    pub fn error_message<T>(code: Code<T>, context: &str) -> DictionaryVar {
+        let code: Code = match code {
+            Code::Ok(_) => Code::Ok(0),
+            code => code.map_err(),
+        };
+        let dict = DictionaryVar::new();
+        dict.set("type", StringVar::new("error"));
+        dict.set("code", code.to_i32());
+        dict.set("message", StringVar::new(code.to_string()));
+        dict.set("context", StringVar::new(context));
+        dict
+    }
+}
+// Loops are used from several threads, so their labels can't be thread local.
+static LOOP_LABELS_INIT: Once = ONCE_INIT;
+static mut LOOP_LABELS: *const Mutex<HashMap<ffi::PP_Resource, String>> =
+    0 as *const Mutex<HashMap<ffi::PP_Resource, String>>;
+fn loop_labels() -> &'static Mutex<HashMap<ffi::PP_Resource, String>> {
+    LOOP_LABELS_INIT.call_once(|| unsafe {
+        LOOP_LABELS = mem::transmute(Box::new(Mutex::new(HashMap::new())));
+    });
+    unsafe { &*LOOP_LABELS }
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+enum LoopShutdown {
+    /// `shutdown_graceful` was called; queued work still runs.
+    Draining,
+    /// The grace period is over; queued work is aborted.
+    Expired,
+}
+static LOOP_SHUTDOWNS_INIT: Once = ONCE_INIT;
+static mut LOOP_SHUTDOWNS: *const Mutex<HashMap<ffi::PP_Resource, LoopShutdown>> =
+    0 as *const Mutex<HashMap<ffi::PP_Resource, LoopShutdown>>;
+fn loop_shutdowns() -> &'static Mutex<HashMap<ffi::PP_Resource, LoopShutdown>> {
+    LOOP_SHUTDOWNS_INIT.call_once(|| unsafe {
+        LOOP_SHUTDOWNS = mem::transmute(Box::new(Mutex::new(HashMap::new())));
+    });
+    unsafe { &*LOOP_SHUTDOWNS }
+}
+fn loop_shutdown(msg_loop: ffi::PP_Resource) -> Option<LoopShutdown> {
+    loop_shutdowns().lock().unwrap().get(&msg_loop).cloned()
+}
+/// The code queued work on `msg_loop` should run with instead of `code`.
+fn shutdown_code(msg_loop: ffi::PP_Resource, code: Code<()>) -> Code<()> {
+    if code.is_ok() && loop_shutdown(msg_loop) == Some(LoopShutdown::Expired) {
+        Code::Aborted
+    } else {
+        code
+    }
+}
+
+impl fmt::Debug for MessageLoop {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.label() {
+            Some(label) => write!(f, "MessageLoop({}, {:?})", self.0, label),
+            None => write!(f, "MessageLoop({})", self.0),
+        }
+    }
+}
+
+impl MessageLoop {
+    /// The label given to `Instance::create_named_message_loop`, if any.
+    pub fn label(&self) -> Option<String> {
+        loop_labels().lock().unwrap().get(&self.0).cloned()
+    }
+    fn set_label(&self, label: Option<&str>) {
+        let mut labels = loop_labels().lock().unwrap();
+        match label {
+            Some(label) => { labels.insert(self.0, label.to_string()); }
+            // Resource ids are reused, so clear any left by a dead loop.
+            None => { labels.remove(&self.0); }
+        }
+    }
+
+    fn get_main_loop() -> MessageLoop {
+        MessageLoop::new((ppb::get_message_loop().GetForMainThread.unwrap())())
+    }
+    pub fn is_attached() -> bool {
+        unsafe {
+            (ppb::get_message_loop().GetCurrent.unwrap())() != mem::transmute(0i32)
+        }
+    }
+    pub fn current() -> Option<MessageLoop> {
         ppb::get_message_loop()
             .get_current()
             .map(|current| MessageLoop::new(current) )
     }
+    /// Returns true if this is the loop attached to the calling thread.
+    /// Loops are compared by resource, like `==`.
+    pub fn is_current(&self) -> bool {
+        ppb::get_message_loop()
+            .get_current()
+            .map(|current| current == self.unwrap() )
+            .unwrap_or(false)
+    }
+    /// Panics unless this is the loop attached to the calling thread. For
+    /// code which is given a loop and must be driven from it.
+    pub fn assert_current(&self) {
+        if !self.is_current() {
+            let current = ppb::get_message_loop().get_current();
+            panic!("expected to be running on message loop {:?}, but {}", self,
+                   match current {
+                       // Not wrapped in a `MessageLoop`: we don't own a reference.
+                       Some(current) => format!("the current loop is MessageLoop({})", current),
+                       None => "this thread has no message loop".to_string(),
+                   });
+        }
+    }
     pub fn attach_to_current_thread(&self) -> Code {
-        Code::from_i32((ppb::get_message_loop().AttachToCurrentThread.unwrap())(self.unwrap()))
+        let code = Code::from_i32((ppb::get_message_loop().AttachToCurrentThread.unwrap())
+                                  (self.unwrap()));
+        if code == Code::WrongThread {
+            warn!("couldn't attach {:?}: this thread already has a message loop, or is \
+                   the main thread", self);
+        }
+        code
     }
     /// Blocking
     pub fn run_loop(&self) -> Code {
-        Code::from_i32((ppb::get_message_loop().Run.unwrap())(self.unwrap()))
+        let code = Code::from_i32((ppb::get_message_loop().Run.unwrap())(self.unwrap()));
+        if code == Code::WrongThread {
+            warn!("couldn't run {:?}: it isn't attached to this thread", self);
+        }
+        code
     }
+    /// Returns `Code::Failed`, without running `work`, once
+    /// `shutdown_graceful` has been called.
     pub fn post_work<F>(&self, work: F, delay: u64) -> Code
         where F: FnOnce(Code<()>) + Send
+    {
+        let msg_loop = self.unwrap();
+        if loop_shutdown(msg_loop).is_some() {
+            return Code::Failed;
+        }
+        self.post_work_unchecked(move |code: Code<()>| {
+            work.call_once((shutdown_code(msg_loop, code), ));
+        }, delay)
+    }
+    /// `post_work`, minus the shutdown checks.
+    fn post_work_unchecked<F>(&self, work: F, delay: u64) -> Code
+        where F: FnOnce(Code<()>) + Send
     {
         let work = CallbackArgs::new(work);
-        let cc = work.to_ffi_callback((), Default::default());
+        // Work may be posted from any thread.
+        let cc = work.to_ffi_callback_unchecked((), Default::default());
         match ppb::get_message_loop().post_work(&self.unwrap(), cc.cc(), delay as i64) {
             ffi::PP_ERROR_BADARGUMENT => panic!("internal error: completion callback was null?"),
-            c => Code::from_i32(c),
+            c => {
+                let code = Code::from_i32(c);
+                if code.is_err() {
+                    // The work won't run; free it.
+                    let _ = cc.drop_with_code(code);
+                }
+                code
+            }
+        }
+    }
+    /// Posts `work` like `post_work`, but catches a panic in it instead of
+    /// letting it unwind out of the loop. `work`'s result, or the panic's
+    /// payload, is sent through the returned channel.
+    ///
+    /// This is an escape hatch. Normally a panic in posted work tears down the
+    /// instance, which is what should happen for bugs; use this only for work
+    /// whose failure the caller is prepared to handle. If the work can't be
+    /// posted, or the loop is destroyed before running it, nothing is sent and
+    /// the channel hangs up.
+    pub fn post_work_catch<F, T>(&self, work: F) -> Receiver<thread::Result<T>>
+        where F: FnOnce() -> T + Send + 'static, T: Send + 'static
+    {
+        let (tx, rx) = channel();
+        let code = self.post_work(move |code: Code<()>| {
+            if code.is_ok() {
+                // Nobody may be listening anymore; that's fine.
+                let _ = tx.send(thread::catch_panic(work));
+            }
+        }, 0);
+        if code.is_err() {
+            warn!("couldn't post work to {:?}: {}", self, code);
         }
+        rx
     }
     pub fn post_to_self<F>(work: F, delay: u64) -> Code
         where F: FnOnce(Code<()>)
     {
         MessageLoop::current()
             .map(move |m| {
-                let work = CallbackArgs::new(work);
+                let msg_loop = m.unwrap();
+                if loop_shutdown(msg_loop).is_some() {
+                    return Code::Failed;
+                }
+                let work = CallbackArgs::new(move |code: Code<()>| {
+                    work.call_once((shutdown_code(msg_loop, code), ));
+                });
                 let cc = work.to_ffi_callback((), Default::default());
                 let code = ppb::get_message_loop().post_work(&m.unwrap(), cc.cc(),
                                                              delay as i64);
@@ -953,6 +2433,68 @@ impl MessageLoop {
         message_handler::unregister_handlers(self);
         Code::from_i32((ppb::get_message_loop().PostQuit.unwrap())(self.unwrap(), ffi::PP_TRUE))
     }
+
+    /// Shuts the loop down once the work already queued has run, giving it
+    /// at most `grace_ms` milliseconds. From now on `post_work` and
+    /// `post_to_self` reject new work with `Code::Failed`, rather than
+    /// accepting work the quit would drop.
+    ///
+    /// Queued work still waiting when the grace period is over is run with
+    /// `Code::Aborted` instead. `callback` is called on the loop just before
+    /// it quits, with `Code::Ok` if all the queued work ran normally or
+    /// `Code::TimedOut` if some was aborted. Delayed work not yet due when
+    /// the queue drains is dropped, as with `queue_shutdown`.
+    ///
+    /// Returns `Code::InProgress` if the loop is already shutting down.
+    pub fn shutdown_graceful<F>(&self, grace_ms: u64, callback: CallbackArgs<F, ()>) -> Code<()>
+        where F: FnOnce(Code<()>) + Send + 'static,
+    {
+        let msg_loop = self.unwrap();
+        {
+            let mut shutdowns = loop_shutdowns().lock().unwrap();
+            if shutdowns.contains_key(&msg_loop) {
+                return Code::InProgress;
+            }
+            shutdowns.insert(msg_loop, LoopShutdown::Draining);
+        }
+
+        // Queued after everything already posted, so this runs once the
+        // queue has drained or the grace period has aborted the rest.
+        let this = self.clone();
+        let code = self.post_work_unchecked(move |code: Code<()>| {
+            let expired = loop_shutdowns().lock().unwrap()
+                .remove(&msg_loop) == Some(LoopShutdown::Expired);
+            if code.is_err() {
+                // The loop was destroyed out from under us.
+                callback.call_directly(code);
+                return;
+            }
+            callback.call_directly(if expired { Code::TimedOut } else { Code::Ok(()) });
+            let code = this.queue_shutdown();
+            if code.is_err() {
+                warn!("couldn't shut down {:?} after draining it: {}", this, code);
+            }
+        }, 0);
+        if code.is_err() {
+            loop_shutdowns().lock().unwrap().remove(&msg_loop);
+            return code.map_err();
+        }
+
+        let code = self.post_work_unchecked(move |code: Code<()>| {
+            // Aborted if the loop quit first, after which its id may be
+            // reused.
+            if code.is_err() { return; }
+            let mut shutdowns = loop_shutdowns().lock().unwrap();
+            if let Some(state) = shutdowns.get_mut(&msg_loop) {
+                *state = LoopShutdown::Expired;
+            }
+        }, grace_ms);
+        if code.is_err() {
+            // Without the timer the queue simply drains in full.
+            warn!("couldn't start the grace period for {:?}: {}", self, code);
+        }
+        Code::CompletionPending
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -1100,11 +2642,13 @@ macro_rules! impl_clone_drop_for(
         impl Drop for $ty {
             fn drop(&mut self) {
                 (ppb::get_var().Release.unwrap())(self.to_var());
+                track_var!(-1);
             }
         }
         impl clone::Clone for $ty {
             fn clone(&self) -> $ty {
                 (ppb::get_var().AddRef.unwrap())(self.to_var());
+                track_var!(1);
                 unsafe {
                     mem::transmute_copy(self)
                 }
@@ -1123,7 +2667,7 @@ macro_rules! impl_clone_drop_for(
             fn from_var(var: ffi::PP_Var) -> Option<$ty> {
                 if unsafe { var.$is_true_name() && ffi::id_from_var(var) != 0 } {
                     let v: $ty = VarCtor::ctor(var);
-                    mem::forget(v.clone());
+                    ppb::get_var().add_ref(&var);
                     Some(v)
                 } else {
                     None
@@ -1435,6 +2979,26 @@ impl UndefinedVar {
         UndefinedVar
     }
 }
+/// Limits on the shape of vars received from JS, so hostile or buggy pages
+/// can't make walking a message exhaust the stack or memory. `None` means
+/// unlimited, which is the default. See `AnyVar::new_checked` and
+/// `Instance::set_var_decode_limits`.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug, Default)]
+pub struct VarDecodeLimits {
+    /// How deeply arrays and dictionaries may nest. A flat array has depth 1.
+    pub max_depth: Option<usize>,
+    /// The total number of array elements and dictionary entries.
+    pub max_elements: Option<usize>,
+    /// The total length of all strings, dictionary keys included.
+    pub max_string_bytes: Option<usize>,
+}
+impl VarDecodeLimits {
+    pub fn is_unlimited(&self) -> bool {
+        self.max_depth.is_none() && self.max_elements.is_none() &&
+            self.max_string_bytes.is_none()
+    }
+}
+
 impl AnyVar {
     fn new(var: ffi::PP_Var) -> AnyVar {
         if var.is_null() {
@@ -1448,28 +3012,106 @@ impl AnyVar {
         } else if var.is_a_f64() {
             AnyVar::F64(unsafe { ffi::f64_from_var(var) })
         } else if var.is_a_string() {
-            AnyVar::String(StringVar(unsafe { ffi::id_from_var(var) }))
+            AnyVar::String(StringVar::adopt(var))
         } else if var.is_an_object() {
-            AnyVar::Object(ObjectVar(unsafe { ffi::id_from_var(var) }))
+            AnyVar::Object(ObjectVar::new_from_var(var))
         } else if var.is_an_array() {
-            AnyVar::Array(ArrayVar(unsafe { ffi::id_from_var(var) }))
+            AnyVar::Array(ArrayVar::new_from_var(var))
         } else if var.is_a_dictionary() {
-            AnyVar::Dictionary(DictionaryVar(unsafe { ffi::id_from_var(var) }))
+            AnyVar::Dictionary(DictionaryVar::new_from_var(var))
         } else if var.is_an_array_buffer() {
-            AnyVar::ArrayBuffer(ArrayBufferVar(unsafe { ffi::id_from_var(var) }))
+            AnyVar::ArrayBuffer(ArrayBufferVar::new_from_var(var))
         } else if var.is_a_resource() {
-            AnyVar::Undefined
+            // Unlike the other vars, the var isn't the resource: take a
+            // reference to the resource and give up ours to the var.
+            let v = AnyVar::from_resource_var(var);
+            ppb::get_var().remove_ref(var);
+            v
         } else {
             error!("Var doesn't have a known type");
             AnyVar::Undefined
         }
     }
     fn new_bumped(var: ffi::PP_Var) -> AnyVar {
+        if var.is_a_resource() {
+            // Already a new reference, and to the resource, not the var.
+            return AnyVar::from_resource_var(var);
+        }
         let v = AnyVar::new(var);
         // bump the ref count:
-        mem::forget(v.clone());
+        ppb::get_var().add_ref(&var);
         v
     }
+    fn from_resource_var(var: ffi::PP_Var) -> AnyVar {
+        match ppb::get_var().var_to_resource(&var) {
+            Some(res) if res != 0 => AnyVar::Resource(GenericResource::adopt(res)),
+            Some(_) => AnyVar::Undefined,
+            None => {
+                warn!("resource vars need PPB_Var 1.2");
+                AnyVar::Undefined
+            }
+        }
+    }
+    /// Like taking a new reference to `var`, but first walks it and fails
+    /// with `Code::NoMemory`, logging which limit tripped, if its shape
+    /// exceeds `limits`. The walk stops as soon as a limit is exceeded, and
+    /// doesn't recurse, so hostile vars can't exhaust the stack.
+    pub fn new_checked(var: ffi::PP_Var, limits: &VarDecodeLimits) -> result::Result<AnyVar, Code> {
+        if limits.is_unlimited() {
+            return Ok(AnyVar::new_bumped(var));
+        }
+
+        fn exceeded(limit: &str, max: usize) -> result::Result<AnyVar, Code> {
+            warn!("var exceeds the `{}` decode limit of {}", limit, max);
+            Err(Code::NoMemory)
+        }
+
+        let root = AnyVar::new_bumped(var);
+        let mut elements = 0usize;
+        let mut string_bytes = 0usize;
+        let mut stack = vec![(root.clone(), 0usize)];
+        while let Some((var, depth)) = stack.pop() {
+            match var {
+                AnyVar::String(ref s) => {
+                    string_bytes = string_bytes.saturating_add(s.len());
+                }
+                AnyVar::Array(_) | AnyVar::Dictionary(_) => {
+                    if let Some(max) = limits.max_depth {
+                        if depth + 1 > max { return exceeded("max_depth", max); }
+                    }
+                }
+                _ => {}
+            }
+            let len = match var {
+                AnyVar::Array(ref a) => a.len(),
+                AnyVar::Dictionary(ref d) => d.len(),
+                _ => 0,
+            };
+            elements = elements.saturating_add(len);
+            if let Some(max) = limits.max_elements {
+                if elements > max { return exceeded("max_elements", max); }
+            }
+            if let Some(max) = limits.max_string_bytes {
+                if string_bytes > max { return exceeded("max_string_bytes", max); }
+            }
+
+            match var {
+                AnyVar::Array(ref a) => {
+                    for index in 0..len {
+                        stack.push((a.get(index), depth + 1));
+                    }
+                }
+                AnyVar::Dictionary(ref d) => {
+                    for (key, value) in d.entries() {
+                        string_bytes = string_bytes.saturating_add(key.len());
+                        stack.push((value, depth + 1));
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(root)
+    }
     pub fn is_ref_counted(&self) -> bool {
         self.is_a_string() ||
             self.is_an_object() ||
@@ -1524,6 +3166,177 @@ impl AnyVar {
             None
         }
     }
+
+    /// Returns the element at `index` if this is an array and `index` is in
+    /// bounds.
+    pub fn index(&self, index: usize) -> Option<AnyVar> {
+        match self {
+            &AnyVar::Array(ref a) if index < a.len() => {
+                // The browser hands us a reference with the returned var, so
+                // don't bump it again.
+                let v = ppb::get_array().get(&a.to_var(), index as libc::uint32_t);
+                Some(AnyVar::new(v))
+            }
+            _ => None,
+        }
+    }
+    fn key(&self, key: &str) -> Option<AnyVar> {
+        match self {
+            &AnyVar::Dictionary(ref d) => {
+                let key = StringVar::new_from_str(key);
+                let v = ppb::get_dictionary().get(&d.to_var(), &key.to_var());
+                match AnyVar::new(v) {
+                    AnyVar::Undefined => None,
+                    v => Some(v),
+                }
+            }
+            _ => None,
+        }
+    }
+    /// Walks nested dictionaries, ie `msg.path(&["user", "name"])` is
+    /// `msg["user"]["name"]`. Returns `None` if any step isn't a dictionary
+    /// or is missing the key. Intermediate vars are released as the walk
+    /// proceeds.
+    pub fn path(&self, path: &[&str]) -> Option<AnyVar> {
+        let mut path = path.iter();
+        let mut current = match path.next() {
+            Some(key) => match self.key(*key) {
+                Some(v) => v,
+                None => return None,
+            },
+            None => return Some(self.clone()),
+        };
+        for key in path {
+            current = match current.key(*key) {
+                Some(v) => v,
+                None => return None,
+            };
+        }
+        Some(current)
+    }
+
+    /// Deep copies this var into an `OwnedVar`, which is plain Rust data and
+    /// so can be sent to other threads. Objects and resources can't be
+    /// detached from the browser; they're logged and become `OwnedVar::Null`.
+    pub fn into_owned(self) -> OwnedVar {
+        match self {
+            AnyVar::Null => OwnedVar::Null,
+            AnyVar::Undefined => OwnedVar::Undefined,
+            AnyVar::Bool(v) => OwnedVar::Bool(v),
+            AnyVar::I32(v) => OwnedVar::I32(v),
+            AnyVar::F64(v) => OwnedVar::F64(v),
+            AnyVar::String(v) => OwnedVar::String(v.as_str().to_string()),
+            AnyVar::Array(v) => OwnedVar::Array(v.iter().map(|v| v.into_owned() ).collect()),
+            AnyVar::Dictionary(v) => {
+                OwnedVar::Dict(v.entries()
+                               .map(|(k, v)| (k.as_str().to_string(), v.into_owned()) )
+                               .collect())
+            }
+            AnyVar::ArrayBuffer(v) => OwnedVar::Bytes(v.to_vec()),
+            AnyVar::Object(v) => {
+                warn!("into_owned: `{:?}` can't be owned, using null instead", v);
+                OwnedVar::Null
+            }
+            AnyVar::Resource(v) => {
+                warn!("into_owned: resource `{:?}` can't be owned, using null instead", v);
+                OwnedVar::Null
+            }
+        }
+    }
+}
+
+/// A var tree which owns all of its data, independent of the browser. See
+/// `AnyVar::into_owned`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum OwnedVar {
+    Null,
+    Undefined,
+    Bool(bool),
+    I32(i32),
+    F64(f64),
+    String(String),
+    Array(Vec<OwnedVar>),
+    Dict(BTreeMap<String, OwnedVar>),
+    Bytes(Vec<u8>),
+}
+impl OwnedVar {
+    /// Rebuilds the browser side var tree. Var creation is thread safe, so
+    /// this works on any thread, with or without a message loop. `Bytes`
+    /// become an `ArrayBufferVar`. `OwnedVar` isn't `ToVar`: nothing would
+    /// own the var `to_var` returned, so pass the result of this instead.
+    pub fn to_any(&self) -> AnyVar {
+        match self {
+            &OwnedVar::Null => AnyVar::Null,
+            &OwnedVar::Undefined => AnyVar::Undefined,
+            &OwnedVar::Bool(v) => AnyVar::Bool(v),
+            &OwnedVar::I32(v) => AnyVar::I32(v),
+            &OwnedVar::F64(v) => AnyVar::F64(v),
+            &OwnedVar::String(ref v) => AnyVar::String(StringVar::new(v)),
+            &OwnedVar::Array(ref v) => {
+                let mut array = ArrayVar::new();
+                array.resize(v.len());
+                for (index, value) in v.iter().enumerate() {
+                    array.set(index, &value.to_any());
+                }
+                AnyVar::Array(array)
+            }
+            &OwnedVar::Dict(ref v) => {
+                let dict = DictionaryVar::new();
+                for (key, value) in v.iter() {
+                    dict.set(&key[..], value.to_any());
+                }
+                AnyVar::Dictionary(dict)
+            }
+            &OwnedVar::Bytes(ref v) => AnyVar::ArrayBuffer(ArrayBufferVar::new_from_slice(&v[..])),
+        }
+    }
+}
+
+fn dictionary_from_entries<'a, K, V, I>(entries: I) -> DictionaryVar
+    where K: ToStringVar + 'a, V: ToVar + 'a, I: Iterator<Item = (&'a K, &'a V)>,
+{
+    let dict = DictionaryVar::new();
+    for (key, value) in entries {
+        dict.set(key.to_string_var(), value.to_any());
+    }
+    dict
+}
+/// Entries are set in the map's iteration order, which JS sees as the
+/// property order; for a `HashMap` that order is arbitrary, and may differ
+/// between runs.
+impl<'a, K, V> From<&'a HashMap<K, V>> for DictionaryVar
+    where K: ToStringVar + Eq + ::std::hash::Hash, V: ToVar,
+{
+    fn from(map: &'a HashMap<K, V>) -> DictionaryVar {
+        dictionary_from_entries(map.iter())
+    }
+}
+/// Entries are set in key order.
+impl<'a, K, V> From<&'a BTreeMap<K, V>> for DictionaryVar
+    where K: ToStringVar + Ord, V: ToVar,
+{
+    fn from(map: &'a BTreeMap<K, V>) -> DictionaryVar {
+        dictionary_from_entries(map.iter())
+    }
+}
+impl<'a, A: ToVar, B: ToVar> From<&'a (A, B)> for ArrayVar {
+    fn from(tuple: &'a (A, B)) -> ArrayVar {
+        let mut array = ArrayVar::new();
+        assert!(array.resize(2));
+        array.set(0, &tuple.0);
+        array.set(1, &tuple.1);
+        array
+    }
+}
+impl<'a, A: ToVar, B: ToVar, C: ToVar> From<&'a (A, B, C)> for ArrayVar {
+    fn from(tuple: &'a (A, B, C)) -> ArrayVar {
+        let mut array = ArrayVar::new();
+        assert!(array.resize(3));
+        array.set(0, &tuple.0);
+        array.set(1, &tuple.1);
+        array.set(2, &tuple.2);
+        array
+    }
 }
 
 impl fmt::Debug for StringVar {
@@ -1575,25 +3388,59 @@ impl StringVar {
         let var = (ppb::get_var().VarFromUtf8.unwrap())
                 (v.as_ptr() as *const i8,
                  len as u32);
-        return StringVar(unsafe { ffi::id_from_var(var) } );
+        return StringVar::adopt(var);
     }
     fn new_from_var(v: ffi::PP_Var) -> StringVar {
         let v: StringVar = From::from(v);
-        mem::forget(v.clone());
+        ppb::get_var().add_ref(&v.to_var());
         v
     }
+    /// Wraps `v`, taking over a reference the caller owns.
+    fn adopt(v: ffi::PP_Var) -> StringVar {
+        track_var!(1);
+        StringVar(unsafe { ffi::id_from_var(v) })
+    }
     pub fn as_str(&self) -> &str { &*self }
 }
+
+/// Accumulates a string on the Rust side so it can be copied into a
+/// `StringVar` once, instead of creating a var per piece. Use `write!` on it
+/// or `push_str`.
+#[derive(Clone, Debug, Default)]
+pub struct StringVarBuilder(String);
+impl StringVarBuilder {
+    pub fn new() -> StringVarBuilder {
+        StringVarBuilder(String::new())
+    }
+    pub fn with_capacity(capacity: usize) -> StringVarBuilder {
+        StringVarBuilder(String::with_capacity(capacity))
+    }
+    pub fn push_str(&mut self, s: &str) {
+        self.0.push_str(s);
+    }
+    pub fn len(&self) -> usize { self.0.len() }
+    pub fn is_empty(&self) -> bool { self.0.is_empty() }
+    /// Creates the var, freeing the accumulated string.
+    pub fn finish(self) -> StringVar {
+        StringVar::new_from_str(&self.0[..])
+    }
+}
+impl fmt::Write for StringVarBuilder {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push_str(s);
+        Ok(())
+    }
+}
 #[doc(hidden)]
 impl From<ffi::PP_Var> for StringVar {
     fn from(v: ffi::PP_Var) -> StringVar {
-        let v: StringVar = StringVar(unsafe { ffi::id_from_var(v) });
-        mem::forget(v.clone());
-        v
+        ppb::get_var().add_ref(&v);
+        StringVar::adopt(v)
     }
 }
 impl ObjectVar {
     fn new_from_var(v: ffi::PP_Var) -> ObjectVar {
+        track_var!(1);
         ObjectVar(unsafe { ffi::id_from_var(v) })
     }
 }
@@ -1619,6 +3466,7 @@ impl<'a> Iterator for ArrayVarIter<'a> {
 
 impl ArrayVar {
     fn new_from_var(v: ffi::PP_Var) -> ArrayVar {
+        track_var!(1);
         ArrayVar(unsafe { ffi::id_from_var(v) })
     }
     pub fn new() -> ArrayVar {
@@ -1647,6 +3495,67 @@ impl ArrayVar {
         ppb::get_array().set(&self.to_var(), index as u32, &value.to_var())
     }
 
+    // PPAPI arrays aren't contiguous, so these still go element by element,
+    // but they size the array once and skip wrapping each element in an
+    // `AnyVar`. For large binary data, use an `ArrayBufferVar` instead.
+
+    pub fn from_i32_slice(v: &[i32]) -> ArrayVar {
+        let mut this = ArrayVar::new();
+        assert!(this.resize(v.len()));
+        let array = ppb::get_array();
+        let var = this.to_var();
+        for (index, &value) in v.iter().enumerate() {
+            array.set(&var, index as u32, &unsafe { ffi::i32_to_var(value) });
+        }
+        this
+    }
+    pub fn from_f64_slice(v: &[f64]) -> ArrayVar {
+        let mut this = ArrayVar::new();
+        assert!(this.resize(v.len()));
+        let array = ppb::get_array();
+        let var = this.to_var();
+        for (index, &value) in v.iter().enumerate() {
+            array.set(&var, index as u32, &unsafe { ffi::f64_to_var(value) });
+        }
+        this
+    }
+    /// Returns `None` if any element isn't an `i32`.
+    pub fn to_i32_vec(&self) -> Option<Vec<i32>> {
+        self.to_primitive_vec(|v| {
+            if v.is_an_i32() { Some(unsafe { ffi::i32_from_var(v) }) }
+            else             { None }
+        })
+    }
+    /// Returns `None` if any element isn't a number. `i32` elements are
+    /// converted.
+    pub fn to_f64_vec(&self) -> Option<Vec<f64>> {
+        self.to_primitive_vec(|v| {
+            if v.is_a_f64()       { Some(unsafe { ffi::f64_from_var(v) }) }
+            else if v.is_an_i32() { Some(unsafe { ffi::i32_from_var(v) } as f64) }
+            else                  { None }
+        })
+    }
+    fn to_primitive_vec<T, F>(&self, f: F) -> Option<Vec<T>>
+        where F: Fn(ffi::PP_Var) -> Option<T>,
+    {
+        let array = ppb::get_array();
+        let var = self.to_var();
+        let len = self.len();
+        let mut out = Vec::with_capacity(len);
+        for index in 0..len {
+            let element = array.get(&var, index as u32);
+            let value = f(element);
+            // We're given a reference, which only matters for the element
+            // types we reject.
+            ppb::get_var().remove_ref(element);
+            match value {
+                Some(value) => out.push(value),
+                None => return None,
+            }
+        }
+        Some(out)
+    }
+
     pub fn iter<'a>(&'a self) -> ArrayVarIter<'a> {
         ArrayVarIter {
             var: self,
@@ -1655,6 +3564,40 @@ impl ArrayVar {
         }
     }
 }
+/// Moves the elements out of an `ArrayVar`, which is kept alive until the
+/// iterator is dropped.
+pub struct ArrayVarIntoIter {
+    var: ArrayVar,
+    index: usize,
+    len: usize,
+}
+impl Iterator for ArrayVarIntoIter {
+    type Item = AnyVar;
+    fn next(&mut self) -> Option<AnyVar> {
+        if self.index >= self.len { None }
+        else {
+            let v = self.var.get(self.index);
+            self.index += 1;
+            Some(v)
+        }
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let left = self.len - self.index;
+        (left, Some(left))
+    }
+}
+impl iter::IntoIterator for ArrayVar {
+    type Item = AnyVar;
+    type IntoIter = ArrayVarIntoIter;
+    fn into_iter(self) -> ArrayVarIntoIter {
+        let len = self.len();
+        ArrayVarIntoIter {
+            var: self,
+            index: 0,
+            len: len,
+        }
+    }
+}
 pub struct DictEntries<'a> {
     dict: &'a DictionaryVar,
     keys: ArrayVar,
@@ -1680,9 +3623,32 @@ impl<'a> Iterator for DictEntries<'a> {
         (self.len, Some(self.len))
     }
 }
+/// Moves the entries out of a `DictionaryVar`, which is kept alive until the
+/// iterator is dropped. Entries come in the same order as `entries`.
+pub struct DictIntoEntries {
+    dict: DictionaryVar,
+    keys: ArrayVarIntoIter,
+}
+impl Iterator for DictIntoEntries {
+    type Item = (StringVar, AnyVar);
+    fn next(&mut self) -> Option<(StringVar, AnyVar)> {
+        self.keys.next().map(|k| {
+            let k = match k {
+                AnyVar::String(k) => k,
+                k => unreachable!("dictionary keys should always be stored as strings: `{:?}` was not.", k),
+            };
+            let v = self.dict.get(&k);
+            (k, v)
+        })
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.keys.size_hint()
+    }
+}
 
 impl DictionaryVar {
     fn new_from_var(v: ffi::PP_Var) -> DictionaryVar {
+        track_var!(1);
         DictionaryVar(unsafe { ffi::id_from_var(v) })
     }
 
@@ -1707,7 +3673,7 @@ impl DictionaryVar {
     pub fn keys(&self) -> ArrayVar {
         let v = ppb::get_dictionary().get_keys(&self.to_var());
         let v = ArrayVar::new_from_var(v);
-        mem::forget(v.clone());
+        ppb::get_var().add_ref(&v.to_var());
         v
     }
     pub fn entries<'a>(&'a self) -> DictEntries<'a> {
@@ -1720,23 +3686,163 @@ impl DictionaryVar {
             len: keys_len,
         }
     }
+
+    /// Copies every entry of `other` into this dictionary, eg to apply a
+    /// patch style message to some state. Existing keys are overwritten.
+    ///
+    /// With `deep`, a dictionary in `other` whose key holds a dictionary here
+    /// too is merged into that one instead, recursively. Dictionaries copied
+    /// over are copied too, so later merges into this dictionary can't reach
+    /// back into `other`. Other values, arrays included, always overwrite.
+    ///
+    /// `other` isn't changed. Returns false if a deep merge found `other`
+    /// containing itself; the cyclic entries are skipped.
+    pub fn merge(&self, other: &DictionaryVar, deep: bool) -> bool {
+        if !deep {
+            for (key, value) in other.entries() {
+                self.set(&key, value);
+            }
+            return true;
+        }
+        let mut path = Vec::new();
+        merge_dictionaries(self, other, &mut path)
+    }
+    /// Shallow merges `other` into this dictionary, like a JS
+    /// `Object.assign`. See `merge`.
+    pub fn update_from(&self, other: &DictionaryVar) {
+        self.merge(other, false);
+    }
+}
+impl iter::IntoIterator for DictionaryVar {
+    type Item = (StringVar, AnyVar);
+    type IntoIter = DictIntoEntries;
+    fn into_iter(self) -> DictIntoEntries {
+        let keys = self.keys().into_iter();
+        DictIntoEntries {
+            dict: self,
+            keys: keys,
+        }
+    }
+}
+/// Deep merges `src` into `dst`. `path` holds the ids of the dictionaries of
+/// the source being merged, outermost first.
+fn merge_dictionaries(dst: &DictionaryVar, src: &DictionaryVar, path: &mut Vec<i64>) -> bool {
+    // Merging a dictionary into itself changes nothing.
+    if dst.get_id() == src.get_id() { return true; }
+    path.push(src.get_id());
+    let mut complete = true;
+    for (key, value) in src.entries() {
+        let nested = match value {
+            AnyVar::Dictionary(nested) => nested,
+            value => {
+                dst.set(&key, value);
+                continue;
+            }
+        };
+        let target = match dst.get(&key) {
+            AnyVar::Dictionary(target) => target,
+            _ => {
+                let target = DictionaryVar::new();
+                dst.set(&key, target.clone());
+                target
+            }
+        };
+        // Either would have us loop forever, or write into the source.
+        if path.contains(&nested.get_id()) || path.contains(&target.get_id()) {
+            warn!("not merging `{}`: the dictionary contains itself", key.as_str());
+            complete = false;
+            continue;
+        }
+        complete = merge_dictionaries(&target, &nested, path) && complete;
+    }
+    path.pop();
+    complete
 }
 impl ArrayBufferVar {
     fn new_from_var(v: ffi::PP_Var) -> ArrayBufferVar {
+        track_var!(1);
         ArrayBufferVar(unsafe { ffi::id_from_var(v) })
     }
-}
-
-#[derive(Clone, Eq, PartialEq, Copy)]
-pub struct Messaging(ffi::PP_Instance);
-impl Messaging {
-    fn unwrap(&self) -> ffi::PP_Instance {
-        let &Messaging(inst) = self;
-        inst
+    /// Creates a buffer holding a copy of `bytes`.
+    pub fn new_from_slice(bytes: &[u8]) -> ArrayBufferVar {
+        use std::slice::from_raw_parts_mut;
+        use ppb::VarArrayBufferIf;
+        let interface = ppb::get_array_buffer();
+        let this = ArrayBufferVar::new_from_var(interface.create(bytes.len()));
+        if bytes.len() != 0 {
+            let var = this.to_var();
+            let buf = interface.map(&var);
+            if !buf.is_null() {
+                let dest = unsafe { from_raw_parts_mut(buf as *mut u8, bytes.len()) };
+                for (d, s) in dest.iter_mut().zip(bytes.iter()) {
+                    *d = *s;
+                }
+                interface.unmap(&var);
+            }
+        }
+        this
     }
-}
-
-#[derive(Clone, Eq, PartialEq, Copy)]
+    pub fn len(&self) -> usize {
+        use ppb::VarArrayBufferIf;
+        ppb::get_array_buffer()
+            .byte_len(&self.to_var())
+            .unwrap_or(0)
+    }
+    /// Copies the buffer's contents out.
+    pub fn to_vec(&self) -> Vec<u8> {
+        use std::slice::from_raw_parts;
+        use ppb::VarArrayBufferIf;
+        let interface = ppb::get_array_buffer();
+        let len = self.len();
+        if len == 0 { return Vec::new(); }
+        let var = self.to_var();
+        let buf = interface.map(&var);
+        if buf.is_null() { return Vec::new(); }
+        let v = unsafe { from_raw_parts(buf as *const u8, len) }.to_vec();
+        interface.unmap(&var);
+        v
+    }
+    /// Calls `f` with consecutive windows of the buffer's contents, each
+    /// `chunk_size` bytes except possibly the last, for parsers which work
+    /// incrementally. The buffer is mapped once and nothing is copied. It's
+    /// unmapped afterwards even if `f` panics. Panics if `chunk_size` is 0.
+    pub fn read_chunks<F>(&self, chunk_size: usize, mut f: F)
+        where F: FnMut(&[u8]),
+    {
+        use std::slice::from_raw_parts;
+        use ppb::VarArrayBufferIf;
+
+        struct Unmap(ffi::PP_Var);
+        impl Drop for Unmap {
+            fn drop(&mut self) {
+                ppb::get_array_buffer().unmap(&self.0);
+            }
+        }
+
+        assert!(chunk_size != 0, "chunk size must be nonzero");
+        let len = self.len();
+        if len == 0 { return; }
+        let var = self.to_var();
+        let buf = ppb::get_array_buffer().map(&var);
+        if buf.is_null() { return; }
+        let _unmap = Unmap(var);
+        let bytes = unsafe { from_raw_parts(buf as *const u8, len) };
+        for chunk in bytes.chunks(chunk_size) {
+            f(chunk);
+        }
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Copy)]
+pub struct Messaging(ffi::PP_Instance);
+impl Messaging {
+    fn unwrap(&self) -> ffi::PP_Instance {
+        let &Messaging(inst) = self;
+        inst
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Copy)]
 pub struct Console(ffi::PP_Instance);
 impl Console {
     fn unwrap(&self) -> ffi::PP_Instance {
@@ -1765,6 +3871,85 @@ impl Console {
             .to_string_var();
         self.log_with_source(ffi::PP_LOGLEVEL_WARNING, source, msg)
     }
+
+    /// Starts a timer named `label`, like JS's `console.time`. Timers are
+    /// kept per instance on the calling thread, and differently named timers
+    /// run independently. Starting a running timer restarts it.
+    pub fn time(&self, label: &str) {
+        use ppb::CoreIf;
+        let now = ppb::get_core().get_time_ticks();
+        CONSOLE_TIMERS.with(|timers| {
+            timers.borrow_mut()
+                .entry(self.unwrap())
+                .or_insert_with(|| HashMap::new() )
+                .insert(label.to_string(), now);
+        });
+    }
+    /// Stops the timer `label` and logs `label: <elapsed>ms` at tip level.
+    /// Returns the elapsed milliseconds, or `None`, after logging a warning,
+    /// if no such timer is running.
+    pub fn time_end(&self, label: &str) -> Option<f64> {
+        use ppb::CoreIf;
+        let now = ppb::get_core().get_time_ticks();
+        let started = CONSOLE_TIMERS.with(|timers| {
+            let mut timers = timers.borrow_mut();
+            let started = timers.get_mut(&self.unwrap())
+                .and_then(|labels| labels.remove(label) );
+            if timers.get(&self.unwrap()).map(|labels| labels.is_empty() ).unwrap_or(false) {
+                timers.remove(&self.unwrap());
+            }
+            started
+        });
+        match started {
+            Some(started) => {
+                let elapsed_ms = (now - started) * 1000.0;
+                self.print(format!("{}: {}ms", label, elapsed_ms).to_string_var());
+                Some(elapsed_ms)
+            }
+            None => {
+                self.warn(format!("no such timer: `{}`", label).to_string_var());
+                None
+            }
+        }
+    }
+}
+thread_local!(static CONSOLE_TIMERS: RefCell<HashMap<ffi::PP_Instance, HashMap<String, Ticks>>> =
+              RefCell::new(HashMap::new()));
+
+/// Log lines waiting to be sent to a console. See
+/// `Instance::set_console_buffering`.
+#[derive(Clone, Debug, Default)]
+pub struct ConsoleBuffer {
+    lines: Vec<(ffi::PP_LogLevel, String)>,
+}
+impl ConsoleBuffer {
+    pub fn new() -> ConsoleBuffer {
+        ConsoleBuffer { lines: Vec::new() }
+    }
+    pub fn push(&mut self, level: ffi::PP_LogLevel, line: String) {
+        self.lines.push((level, line));
+    }
+    pub fn len(&self) -> usize { self.lines.len() }
+    pub fn is_empty(&self) -> bool { self.lines.is_empty() }
+    /// Empties the buffer into `sink`, in order. Consecutive lines with the
+    /// same level are joined with newlines and sent as one message.
+    pub fn flush<F>(&mut self, mut sink: F) where F: FnMut(ffi::PP_LogLevel, &str) {
+        let mut batch = String::new();
+        let mut batch_level = None;
+        for (level, line) in mem::replace(&mut self.lines, Vec::new()).into_iter() {
+            if batch_level.is_some() && batch_level != Some(level) {
+                sink(batch_level.unwrap(), &batch[..]);
+                batch.clear();
+            } else if batch_level.is_some() {
+                batch.push('\n');
+            }
+            batch_level = Some(level);
+            batch.push_str(&line[..]);
+        }
+        if let Some(level) = batch_level {
+            sink(level, &batch[..]);
+        }
+    }
 }
 
 /// Update any internal self referential pointers/refs. This is only called
@@ -1899,6 +4084,8 @@ impl<F: Sized> Callback for F
 {
     type Fun = F;
     fn to_ffi_callback(self) -> CallbackCompletion<F> {
+        assert_loop_thread!("asynchronous completion callback");
+
         extern "C" fn work_callback<F: Sized>(user: *mut libc::c_void, status: i32)
             where F : FnOnce(Result<()>) + Send
         {
@@ -2022,6 +4209,14 @@ impl<F, OutArgs> CallbackArgs<F, OutArgs>
                                     args_mapper: StorageToArgsMapper<RawArgs, OutArgs>) ->
         CallbackArgsCompletion<F, OutArgs, RawArgs>
         where RawArgs: InPlaceInit
+    {
+        assert_loop_thread!("asynchronous completion callback");
+        self.to_ffi_callback_unchecked(args, args_mapper)
+    }
+    fn to_ffi_callback_unchecked<RawArgs>(self, args: RawArgs,
+                                          args_mapper: StorageToArgsMapper<RawArgs, OutArgs>) ->
+        CallbackArgsCompletion<F, OutArgs, RawArgs>
+        where RawArgs: InPlaceInit
     {
         extern "C" fn work_callback<F, RawArgs, OutArgs>(user: *mut libc::c_void,
                                                          status: i32)
@@ -2096,6 +4291,7 @@ struct BlockUntilComplete;
 impl Callback for BlockUntilComplete {
     type Fun = ();
     fn to_ffi_callback(self) -> CallbackCompletion<()> {
+        assert_off_main_thread!("blocking call");
         let cc = unsafe {
             ffi::block_until_complete()
         };
@@ -2107,6 +4303,7 @@ impl Callback for BlockUntilComplete {
 }
 impl BlockUntilComplete {
     fn new() -> ffi::Struct_PP_CompletionCallback {
+        assert_off_main_thread!("blocking call");
         unsafe {
             ffi::block_until_complete()
         }
@@ -2124,10 +4321,6 @@ impl ConsoleLogger {
             current_filter: filter,
         }
     }
-    fn console(&self) -> Option<Console> {
-        Instance::opt_current()
-            .map(|c| c.console() )
-    }
     fn current_instance_filter_level(&self) -> log::LogLevelFilter {
         Instance::opt_current()
             .and_then(|instance| {
@@ -2153,7 +4346,6 @@ impl log::Log for ConsoleLogger {
         }
     }
     fn log(&self, record: &LogRecord) {
-        use self::ppb::ConsoleInterface;
         use log::LogLevel;
         let level = match record.level() {
             LogLevel::Error => ffi::PP_LOGLEVEL_ERROR,
@@ -2168,22 +4360,605 @@ impl log::Log for ConsoleLogger {
                           loc.module_path(),
                           loc.file(),
                           loc.line(),
-                          record.args())
-            .to_string_var();
-        match self.console() {
-            Some(console) => console.log(level, str),
-            None => {},
-        }
+                          record.args());
+        log_line(level, str);
     }
 }
+/// Where `ConsoleLogger` sends a formatted record: the current instance's
+/// console, or its console buffer if buffering is on. Public so tests can
+/// log without the `log` macros.
+#[doc(hidden)]
+pub fn log_line(level: ffi::PP_LogLevel, line: String) {
+    let instance = match Instance::opt_current() {
+        Some(instance) => instance,
+        None => return,
+    };
+    if let Some(line) = buffer_console_line(instance, level, line) {
+        instance.console().log(level, line.to_string_var());
+    }
+}
+
+/// Optional browser capabilities, for use with `Instance::supports`. More
+/// may be added, so don't match on this exhaustively.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Feature {
+    Graphics2D,
+    Graphics3D,
+    OpenGLES2,
+    ImageData,
+    Font,
+    BrowserFont,
+    AudioConfig,
+    Audio,
+    FileSystem,
+    UrlLoader,
+    TouchInput,
+    ImeInput,
+    MediaStreamVideoTrack,
+    VideoFrame,
+    VideoDecoder,
+    NetworkProxy,
+    UmaPrivate,
+    IsolatedFileSystem,
+    MediaStreamAudioTrack,
+    AudioBuffer,
+    Fullscreen,
+    VideoEncoder,
+    Printing,
+
+    #[doc(hidden)]
+    __NonExhaustive,
+}
 
 scoped_thread_local!(static CURRENT_INSTANCE: Instance);
+struct ConsoleBuffering {
+    buffer: ConsoleBuffer,
+    interval_ms: u64,
+    flush_scheduled: bool,
+}
+thread_local!(static INPUT_PASSTHROUGH: RefCell<HashMap<ffi::PP_Instance, Vec<input::KeyMatcher>>> =
+              RefCell::new(HashMap::new()));
+
+// The input event classes each instance has requested filtered, as a mask.
+thread_local!(static FILTERING_INPUT: RefCell<HashMap<ffi::PP_Instance, ffi::PP_InputEvent_Class>> =
+              RefCell::new(HashMap::new()));
+fn set_filtering_input(instance: ffi::PP_Instance, classes: ffi::PP_InputEvent_Class,
+                       filtering: bool) {
+    FILTERING_INPUT.with(|f| {
+        let mut f = f.borrow_mut();
+        let mask = {
+            let mask = f.entry(instance).or_insert(0);
+            if filtering { *mask |= classes; } else { *mask &= !classes; }
+            *mask
+        };
+        if mask == 0 { f.remove(&instance); }
+    });
+}
+
+/// The class of input event `event` is, or 0 if it isn't one we know.
+fn input_event_class(event: ffi::PP_Resource) -> ffi::PP_InputEvent_Class {
+    let is = |f: Option<extern "C" fn(ffi::PP_Resource) -> ffi::PP_Bool>| {
+        f.unwrap()(event) != ffi::PP_FALSE
+    };
+    if is(ppb::get_mouse_event().IsMouseInputEvent) {
+        ffi::PP_INPUTEVENT_CLASS_MOUSE
+    } else if is(ppb::get_keyboard_event().IsKeyboardInputEvent) {
+        ffi::PP_INPUTEVENT_CLASS_KEYBOARD
+    } else if is(ppb::get_wheel_event().IsWheelInputEvent) {
+        ffi::PP_INPUTEVENT_CLASS_WHEEL
+    } else if is(ppb::get_touch_event().IsTouchInputEvent) {
+        ffi::PP_INPUTEVENT_CLASS_TOUCH
+    } else if is(ppb::get_ime_event().IsIMEInputEvent) {
+        ffi::PP_INPUTEVENT_CLASS_IME
+    } else {
+        0
+    }
+}
+/// Whether PPAPI passes `event` on to the page when the plugin doesn't
+/// handle it, ie whether its class was requested with
+/// `request_filtering_input_events`. Unhandled events of classes requested
+/// with `request_input_events` are dropped. Call from the instance's thread.
+#[doc(hidden)]
+pub fn unhandled_input_goes_to_page(instance: ffi::PP_Instance, event: ffi::PP_Resource) -> bool {
+    let class = input_event_class(event);
+    FILTERING_INPUT.with(|f| {
+        f.borrow().get(&instance).map(|&mask| mask & class != 0 )
+    }).unwrap_or(false)
+}
+
+/// True if `event` is a key event matching one of `instance`'s passthrough
+/// matchers. Only key events with a key code are considered.
+fn input_passes_through(instance: ffi::PP_Instance, event: ffi::PP_Resource) -> bool {
+    use ppb::{InputEventIf, KeyboardInputEventIf};
+    INPUT_PASSTHROUGH.with(|p| {
+        let p = p.borrow();
+        let matchers = match p.get(&instance) {
+            Some(matchers) => matchers,
+            None => return false,
+        };
+        let kb = ppb::get_keyboard_event();
+        if (kb.IsKeyboardInputEvent.unwrap())(event) == ffi::PP_FALSE {
+            return false;
+        }
+        match input::EventType::from(ppb::get_input_event().type_of(&event)) {
+            input::EventType::RawKeyDown | input::EventType::KeyDown |
+            input::EventType::KeyUp => {},
+            _ => return false,
+        }
+        let key_code = kb.key_code(&event);
+        let modifiers = ppb::get_input_event().modifiers(&event);
+        matchers.iter().any(|m| m.matches_raw(key_code, modifiers) )
+    })
+}
+
+thread_local!(static USER_GESTURE: Cell<Option<ffi::PP_Instance>> = Cell::new(None));
+
+/// Marks the calling thread as handling a user gesture for an instance, as
+/// the input trampoline does for the duration of `ppapi_on_input`, until
+/// dropped. Dropping restores the previous state, even while unwinding.
+#[doc(hidden)]
+pub struct UserGestureScope {
+    previous: Option<ffi::PP_Instance>,
+}
+impl UserGestureScope {
+    pub fn enter(instance: &Instance) -> UserGestureScope {
+        let previous = USER_GESTURE.with(|g| {
+            let previous = g.get();
+            g.set(Some(instance.instance));
+            previous
+        });
+        UserGestureScope { previous: previous }
+    }
+}
+impl Drop for UserGestureScope {
+    fn drop(&mut self) {
+        let previous = self.previous;
+        USER_GESTURE.with(|g| g.set(previous) );
+    }
+}
+
+/// The pace of `Instance::request_frame`'s fallback timer, 60Hz.
+const FRAME_INTERVAL: TimeDelta = 1.0 / 60.0;
+
+#[derive(Default)]
+struct FrameRequests {
+    callbacks: Vec<Box<FnMut(Ticks)>>,
+    /// The bound 3D context, if any. While it has a swap in flight, frames
+    /// are run when the swap completes.
+    context_3d: Option<ffi::PP_Resource>,
+    swap_pending: bool,
+    timer_scheduled: bool,
+    /// When the fallback timer should next fire. Deadlines advance by
+    /// `FRAME_INTERVAL` from the previous deadline, not from when the timer
+    /// actually ran, so rounding and scheduling delays don't accumulate.
+    next_deadline: Option<Ticks>,
+}
+// The instance each 3D context was created for. Kept out of `Context3d`
+// itself, which must stay a bare resource id. Wrappers are cloned and sent
+// between threads, so there's no single drop to remove an entry at. Instead
+// creating a context overwrites any entry a destroyed context with the same
+// id left, and an instance's entries are removed when it's destroyed.
+static CONTEXT_3D_OWNERS_INIT: Once = ONCE_INIT;
+static mut CONTEXT_3D_OWNERS: *const Mutex<HashMap<ffi::PP_Resource, ffi::PP_Instance>> =
+    0 as *const Mutex<HashMap<ffi::PP_Resource, ffi::PP_Instance>>;
+fn context_3d_owners() -> &'static Mutex<HashMap<ffi::PP_Resource, ffi::PP_Instance>> {
+    CONTEXT_3D_OWNERS_INIT.call_once(|| unsafe {
+        CONTEXT_3D_OWNERS = mem::transmute(Box::new(Mutex::new(HashMap::new())));
+    });
+    unsafe { &*CONTEXT_3D_OWNERS }
+}
+/// Returns `None` if the context wasn't made by `create_3d_context`.
+fn context_3d_owner(ctxt: ffi::PP_Resource) -> Option<ffi::PP_Instance> {
+    context_3d_owners().lock().unwrap().get(&ctxt).map(|&inst| inst )
+}
+
+fn fullscreen_interface() -> Option<&'static ppb::Fullscreen> {
+    ppb::get_fullscreen_opt().or_else(|| ppb::get_flash_fullscreen_opt() )
+}
+
+/// The device each instance last bound on this thread. Holds a reference, so
+/// the device stays valid for rebinding after it's replaced.
+thread_local!(static BOUND_DEVICES: RefCell<HashMap<ffi::PP_Instance, GenericResource>> =
+              RefCell::new(HashMap::new()));
+fn bound_device(instance: ffi::PP_Instance) -> Option<ffi::PP_Resource> {
+    BOUND_DEVICES.with(|b| b.borrow().get(&instance).map(|device| device.unwrap() ) )
+}
+
+/// Drops the per-instance state kept in this thread's tables. These are
+/// thread local, so this has to run on the instance's own thread once it's
+/// been destroyed; `did_destroy` runs on the main thread and would only see
+/// that thread's (empty) tables.
+fn forget_instance_state(instance: ffi::PP_Instance) {
+    let bound = BOUND_DEVICES.with(|b| b.borrow_mut().remove(&instance) );
+    drop(bound);
+    FILTERING_INPUT.with(|f| f.borrow_mut().remove(&instance) );
+    let resized = AUTO_RESIZE_3D.with(|a| a.borrow_mut().remove(&instance) );
+    drop(resized);
+    VISIBILITY.with(|v| v.borrow_mut().remove(&instance) );
+}
+
+/// The 3D context each instance has asked to have resized with its view, on
+/// this thread. See `Instance::auto_resize_3d_context`. Holds a reference, so
+/// the id can't be reused by another resource while it's here.
+thread_local!(static AUTO_RESIZE_3D: RefCell<HashMap<ffi::PP_Instance, gles::Context3d>> =
+              RefCell::new(HashMap::new()));
+
+/// How long, in milliseconds, a change in page visibility has to last
+/// before `InstanceHandler::on_visible` or `on_hidden` is called.
+pub const VISIBILITY_DEBOUNCE_MS: u64 = 100;
+
+struct Visibility {
+    /// What the handler was last told; `None` until the first view.
+    reported: Option<bool>,
+    current: bool,
+    settle_scheduled: bool,
+}
+thread_local!(static VISIBILITY: RefCell<HashMap<ffi::PP_Instance, Visibility>> =
+              RefCell::new(HashMap::new()));
+
+fn report_visibility(instance: ffi::PP_Instance, visible: bool) {
+    instance_handler::dispatch(instance, |h| {
+        if visible { h.on_visible() }
+        else       { h.on_hidden() }
+    });
+}
+fn settle_visibility(instance: ffi::PP_Instance) {
+    let changed = VISIBILITY.with(|v| {
+        let mut v = v.borrow_mut();
+        let state = match v.get_mut(&instance) {
+            Some(state) => state,
+            None => return None,
+        };
+        state.settle_scheduled = false;
+        if state.reported == Some(state.current) { return None; }
+        state.reported = Some(state.current);
+        Some(state.current)
+    });
+    if let Some(visible) = changed {
+        report_visibility(instance, visible);
+    }
+}
+
+/// Whether, and how often, to start an instance over after its thread
+/// panics. See `Instance::set_restart_policy`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct RestartPolicy {
+    /// How many times to restart before giving up. Zero never restarts.
+    pub max_restarts: u32,
+    /// The delay before the first restart; each one after waits twice as
+    /// long as the one before it.
+    pub backoff_ms: u64,
+}
+impl RestartPolicy {
+    pub fn new(max_restarts: u32, backoff_ms: u64) -> RestartPolicy {
+        RestartPolicy {
+            max_restarts: max_restarts,
+            backoff_ms: backoff_ms,
+        }
+    }
+    /// The delay before the `restart`th restart, counting from one, or
+    /// `None` if this policy doesn't allow that many.
+    pub fn delay_for(&self, restart: u32) -> Option<u64> {
+        if restart == 0 || restart > self.max_restarts { return None; }
+        let mut delay = self.backoff_ms;
+        for _ in (1..cmp::min(restart, 64)) {
+            delay = delay.saturating_mul(2);
+        }
+        Some(delay)
+    }
+}
+
+// The policy and the restarts so far, on the instance's thread. Carried
+// over to the new thread by a restart.
+thread_local!(static RESTARTS: RefCell<HashMap<ffi::PP_Instance, (RestartPolicy, u32)>> =
+              RefCell::new(HashMap::new()));
+
+thread_local!(static FRAME_REQUESTS: RefCell<HashMap<ffi::PP_Instance, FrameRequests>> =
+              RefCell::new(HashMap::new()));
+
+fn with_frame_requests<R, F>(instance: ffi::PP_Instance, f: F) -> R
+    where F: FnOnce(&mut FrameRequests) -> R,
+{
+    FRAME_REQUESTS.with(|r| {
+        let mut r = r.borrow_mut();
+        f(r.entry(instance).or_insert_with(|| Default::default() ))
+    })
+}
+fn run_frame_callbacks(instance: ffi::PP_Instance) {
+    use ppb::CoreIf;
+    let callbacks = with_frame_requests(instance, |r| mem::replace(&mut r.callbacks, Vec::new()) );
+    if callbacks.is_empty() { return; }
+    let now = ppb::get_core().get_time_ticks();
+    for mut callback in callbacks.into_iter() {
+        callback(now);
+    }
+}
+fn schedule_frame_timer(instance: ffi::PP_Instance) -> Code<()> {
+    use ppb::CoreIf;
+    let now = ppb::get_core().get_time_ticks();
+    let deadline = with_frame_requests(instance, |r| {
+        if r.timer_scheduled { return None; }
+        let mut deadline = r.next_deadline.unwrap_or(now);
+        if deadline < now - FRAME_INTERVAL {
+            // We were idle, or fell more than a frame behind; resync.
+            deadline = now;
+        }
+        r.timer_scheduled = true;
+        Some(deadline)
+    });
+    let deadline = match deadline {
+        Some(deadline) => deadline,
+        None => return Code::Ok(()),
+    };
+    let delay_ms = if deadline > now {
+        ((deadline - now) * 1000.0).round() as u64
+    } else {
+        0
+    };
+    let posted = MessageLoop::post_to_self(move |_| {
+        let run = with_frame_requests(instance, |r| {
+            r.timer_scheduled = false;
+            r.next_deadline = Some(deadline + FRAME_INTERVAL);
+            // A swap which will run the frame is on its way.
+            !(r.context_3d.is_some() && r.swap_pending)
+        });
+        if run {
+            run_frame_callbacks(instance);
+        }
+    }, delay_ms);
+    if !posted.is_ok() {
+        with_frame_requests(instance, |r| r.timer_scheduled = false );
+    }
+    posted.map_ok(|_| () )
+}
+#[doc(hidden)]
+pub fn frame_swap_started(context: ffi::PP_Resource) {
+    FRAME_REQUESTS.with(|r| {
+        for state in r.borrow_mut().values_mut() {
+            if state.context_3d == Some(context) {
+                state.swap_pending = true;
+            }
+        }
+    });
+}
+/// Called when a swap completes, successfully or not. Runs the frame
+/// callbacks of the instances `context` is bound to.
+#[doc(hidden)]
+pub fn frame_swap_finished(context: ffi::PP_Resource) {
+    let instances: Vec<ffi::PP_Instance> = FRAME_REQUESTS.with(|r| {
+        r.borrow_mut()
+            .iter_mut()
+            .filter(|&(_, ref state)| state.context_3d == Some(context) )
+            .map(|(&instance, state)| {
+                state.swap_pending = false;
+                instance
+            })
+            .collect()
+    });
+    for instance in instances.into_iter() {
+        run_frame_callbacks(instance);
+    }
+}
+
+thread_local!(static VAR_DECODE_LIMITS: RefCell<HashMap<ffi::PP_Instance, VarDecodeLimits>> =
+              RefCell::new(HashMap::new()));
+/// Decodes a message var sent to `instance`, applying its decode limits.
+fn decode_message_var(instance: ffi::PP_Instance, var: ffi::PP_Var) -> result::Result<AnyVar, Code> {
+    let limits = VAR_DECODE_LIMITS.with(|l| l.borrow().get(&instance).map(|l| *l ) );
+    match limits {
+        Some(limits) => AnyVar::new_checked(var, &limits),
+        None => Ok(AnyVar::new_bumped(var)),
+    }
+}
+
+thread_local!(static CONSOLE_BUFFERING: RefCell<HashMap<ffi::PP_Instance, ConsoleBuffering>> =
+              RefCell::new(HashMap::new()));
+
+/// Buffers `line` if `instance` has console buffering enabled on this thread,
+/// otherwise gives it back.
+fn buffer_console_line(instance: Instance, level: ffi::PP_LogLevel,
+                       line: String) -> Option<String> {
+    let schedule = CONSOLE_BUFFERING.with(|b| {
+        let mut b = b.borrow_mut();
+        match b.get_mut(&instance.instance) {
+            Some(state) => {
+                state.buffer.push(level, line);
+                if state.flush_scheduled {
+                    Ok(None)
+                } else {
+                    state.flush_scheduled = true;
+                    Ok(Some(state.interval_ms))
+                }
+            }
+            None => Err(line),
+        }
+    });
+    match schedule {
+        Ok(Some(interval_ms)) => {
+            let posted = MessageLoop::post_to_self(move |_| instance.flush_console(),
+                                                   interval_ms);
+            if !posted.is_ok() {
+                // No loop to flush on; don't hold onto anything.
+                instance.flush_console();
+            }
+            None
+        }
+        Ok(None) => None,
+        Err(line) => Some(line),
+    }
+}
+/// How long, in milliseconds, after the first message dropped by a console
+/// rate limit the number dropped is logged.
+pub const CONSOLE_SUPPRESSION_REPORT_MS: u64 = 1000;
+
+/// A token bucket for one log level.
+struct ConsoleBucket {
+    tokens: f64,
+    last_refill: Ticks,
+    /// Dropped since the last report.
+    suppressed: u64,
+}
+struct ConsoleRateLimit {
+    per_second: u32,
+    buckets: HashMap<ffi::PP_LogLevel, ConsoleBucket>,
+    report_scheduled: bool,
+}
+type ConsoleRateLimits = Mutex<HashMap<ffi::PP_Instance, ConsoleRateLimit>>;
+// Each instance's limit. Worker threads logging through the instance's
+// console share it, so this isn't thread local.
+static CONSOLE_RATE_LIMITS_INIT: Once = ONCE_INIT;
+static mut CONSOLE_RATE_LIMITS: *const ConsoleRateLimits = 0 as *const ConsoleRateLimits;
+fn console_rate_limits() -> &'static ConsoleRateLimits {
+    CONSOLE_RATE_LIMITS_INIT.call_once(|| unsafe {
+        CONSOLE_RATE_LIMITS = mem::transmute(Box::new(Mutex::new(HashMap::new())));
+    });
+    unsafe { &*CONSOLE_RATE_LIMITS }
+}
+
+/// Takes a token from the bucket for `level` if `instance` has a console rate
+/// limit. Returns false, counting the message as suppressed, if the bucket is
+/// empty.
+fn console_rate_allows(instance: ffi::PP_Instance, level: ffi::PP_LogLevel) -> bool {
+    use ppb::CoreIf;
+    let (allowed, schedule) = {
+        let mut l = console_rate_limits().lock().unwrap();
+        let limit = match l.get_mut(&instance) {
+            Some(limit) => limit,
+            None => return true,
+        };
+        let now = ppb::get_core().get_time_ticks();
+        let per_second = limit.per_second as f64;
+        let bucket = limit.buckets
+            .entry(level)
+            .or_insert_with(|| ConsoleBucket {
+                tokens: per_second,
+                last_refill: now,
+                suppressed: 0,
+            });
+        // Bursts of up to a second's worth are let through.
+        let refilled = bucket.tokens + (now - bucket.last_refill) * per_second;
+        bucket.tokens = if refilled > per_second { per_second } else { refilled };
+        bucket.last_refill = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            (true, false)
+        } else {
+            bucket.suppressed += 1;
+            let schedule = !limit.report_scheduled;
+            limit.report_scheduled = true;
+            (false, schedule)
+        }
+    };
+    if schedule {
+        let inst = Instance::new(instance);
+        let code = MessageLoop::post_to_self(move |_| report_console_suppressed(inst),
+                                             CONSOLE_SUPPRESSION_REPORT_MS);
+        if !code.is_ok() {
+            // No loop on this thread. The count is logged by `flush_console`,
+            // or scheduled by the next message dropped on a thread with one.
+            let mut l = console_rate_limits().lock().unwrap();
+            if let Some(limit) = l.get_mut(&instance) {
+                limit.report_scheduled = false;
+            }
+        }
+    }
+    allowed
+}
+/// The summary lines for the messages `instance`'s console rate limit has
+/// dropped since the last report, one per level, resetting the counts.
+fn take_console_suppressed(instance: ffi::PP_Instance) -> Vec<(ffi::PP_LogLevel, StringVar)> {
+    let mut counts: Vec<(ffi::PP_LogLevel, u64)> =
+        match console_rate_limits().lock().unwrap().get_mut(&instance) {
+            Some(limit) => {
+                limit.report_scheduled = false;
+                limit.buckets
+                    .iter_mut()
+                    .filter(|&(_, ref bucket)| bucket.suppressed > 0 )
+                    .map(|(&level, bucket)| (level, mem::replace(&mut bucket.suppressed, 0)) )
+                    .collect()
+            }
+            None => Vec::new(),
+        };
+    counts.sort();
+    counts.into_iter()
+        .map(|(level, count)| {
+            (level, format!("{} messages suppressed by the console rate limit", count)
+             .to_string_var())
+        })
+        .collect()
+}
+/// Logs, for each level, how many messages `instance`'s console rate limit
+/// has dropped since the last report. The reports themselves aren't limited.
+fn report_console_suppressed(instance: Instance) {
+    let console = instance.console();
+    for &(level, ref line) in take_console_suppressed(instance.instance).iter() {
+        (ppb::get_console().Log.unwrap())(console.unwrap(), level, line.to_var());
+    }
+}
+
+thread_local!(static INSTANCE_LOCALS: RefCell<HashMap<(ffi::PP_Instance, TypeId), Box<Any>>> =
+              RefCell::new(HashMap::new()));
+
+/// Drops every value stored via `Instance::with_local` for `instance` on this
+/// thread. The values are removed from the map before they're dropped, so
+/// their destructors may use `Instance::with_local` themselves.
+fn clear_instance_locals(instance: ffi::PP_Instance) {
+    let removed: Vec<Box<Any>> = INSTANCE_LOCALS.with(|locals| {
+        let mut locals = locals.borrow_mut();
+        let keys: Vec<(ffi::PP_Instance, TypeId)> = locals
+            .keys()
+            .filter(|&&(i, _)| i == instance )
+            .map(|k| *k )
+            .collect();
+        keys.into_iter()
+            .filter_map(|k| locals.remove(&k) )
+            .collect()
+    });
+    drop(removed);
+}
 static mut FIRST_INSTANCE: Option<Instance> = None;
 
 pub fn is_main_thread() -> bool {
     Some(MessageLoop::get_main_loop()) == MessageLoop::current()
 }
 
+/// The attributes of the embed element an instance was created from, as
+/// given to `ppapi_instance_created`. Lookups use the first occurrence of a
+/// repeated key, like HTML does for repeated attributes.
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct InstanceArgs(Vec<(String, String)>);
+impl InstanceArgs {
+    pub fn new(args: Vec<(String, String)>) -> InstanceArgs { InstanceArgs(args) }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|&&(ref k, _)| k == key )
+            .map(|&(_, ref v)| &v[..] )
+    }
+    /// Returns `None` if `key` is missing or its value doesn't parse.
+    pub fn get_parsed<T: FromStr>(&self, key: &str) -> Option<T> {
+        self.get(key).and_then(|v| v.parse().ok() )
+    }
+    /// `"true"`, `"1"`, `"yes"` and the empty value of a bare attribute (eg
+    /// `<embed autoplay>`) are true; `"false"`, `"0"` and `"no"` are false.
+    /// Anything else is `None`.
+    pub fn get_bool(&self, key: &str) -> Option<bool> {
+        match self.get(key) {
+            Some("") | Some("true") | Some("1") | Some("yes") => Some(true),
+            Some("false") | Some("0") | Some("no") => Some(false),
+            _ => None,
+        }
+    }
+    /// Every attribute, in order, repeats included.
+    pub fn iter(&self) -> slice::Iter<(String, String)> { self.0.iter() }
+
+    pub fn into_inner(self) -> Vec<(String, String)> { self.0 }
+}
+impl From<Vec<(String, String)>> for InstanceArgs {
+    fn from(args: Vec<(String, String)>) -> InstanceArgs { InstanceArgs(args) }
+}
+
 #[derive(Clone, Hash, Eq, PartialEq, Copy)]
 pub struct Instance {
     instance: ffi::PP_Instance,
@@ -2206,10 +4981,282 @@ impl Instance {
     fn check_current(&self) {
         assert!(Instance::current() == *self);
     }
-    fn unwrap(&self) -> ffi::PP_Instance {
+
+    /// Turns buffering of log records (from the `log` macros) on or off for
+    /// this instance on this thread. While on, records are queued and sent to
+    /// the console in batches, at most `flush_interval_ms` after the first
+    /// queued record, which is far cheaper than a console call per line.
+    /// Turning it off flushes what's queued. Queued records are also flushed
+    /// when the instance is destroyed.
+    pub fn set_console_buffering(&self, enabled: bool, flush_interval_ms: u64) {
+        if enabled {
+            CONSOLE_BUFFERING.with(|b| {
+                let mut b = b.borrow_mut();
+                let state = b.entry(self.instance)
+                    .or_insert_with(|| ConsoleBuffering {
+                        buffer: ConsoleBuffer::new(),
+                        interval_ms: flush_interval_ms,
+                        flush_scheduled: false,
+                    });
+                state.interval_ms = flush_interval_ms;
+            });
+        } else {
+            self.flush_console();
+            CONSOLE_BUFFERING.with(|b| b.borrow_mut().remove(&self.instance) );
+        }
+    }
+    /// Lets key events matching any of `matchers` go to the browser: the
+    /// input event trampoline reports them as unhandled without calling
+    /// `ppapi_on_input`. Use this to keep browser shortcuts and function keys
+    /// working while filtering input events. Replaces any previous list; an
+    /// empty list turns this off.
+    ///
+    /// Like input events, this is per instance thread, so call it from the
+    /// instance's thread.
+    pub fn set_input_passthrough(&self, matchers: &[input::KeyMatcher]) {
+        INPUT_PASSTHROUGH.with(|p| {
+            let mut p = p.borrow_mut();
+            if matchers.is_empty() {
+                p.remove(&self.instance);
+            } else {
+                p.insert(self.instance, matchers.to_vec());
+            }
+        });
+    }
+    /// Returns true if `event` would be passed through to the browser. See
+    /// `set_input_passthrough`.
+    pub fn input_passes_through(&self, event: &input::KeyboardInputEvent) -> bool {
+        input_passes_through(self.instance, event.unwrap())
+    }
+
+    /// Sets the limits applied to messages received by this instance's
+    /// message handlers on the calling thread. Messages exceeding them are
+    /// dropped, and blocking messages get an undefined response. The default
+    /// is no limits.
+    pub fn set_var_decode_limits(&self, limits: VarDecodeLimits) {
+        VAR_DECODE_LIMITS.with(|l| {
+            let mut l = l.borrow_mut();
+            if limits.is_unlimited() {
+                l.remove(&self.instance);
+            } else {
+                l.insert(self.instance, limits);
+            }
+        });
+    }
+
+    /// Limits this instance's console to `per_second` messages per second
+    /// for each log level, with bursts of up to a second's worth. The limit
+    /// is shared by every thread logging through the instance. This applies
+    /// to `Console` and to the `log` macros; records batched by console
+    /// buffering count as one message per batch.
+    ///
+    /// Excess messages are dropped. The number dropped is logged
+    /// `CONSOLE_SUPPRESSION_REPORT_MS` after the first of them, if that
+    /// thread has a message loop, or by `flush_console`. Zero, the default, turns the limit off, logging any
+    /// count not yet reported.
+    pub fn set_console_rate_limit(&self, per_second: u32) {
+        if per_second == 0 {
+            report_console_suppressed(*self);
+            console_rate_limits().lock().unwrap().remove(&self.instance);
+            return;
+        }
+        {
+            let mut l = console_rate_limits().lock().unwrap();
+            let limit = l.entry(self.instance)
+                .or_insert_with(|| ConsoleRateLimit {
+                    per_second: per_second,
+                    buckets: HashMap::new(),
+                    report_scheduled: false,
+                });
+            limit.per_second = per_second;
+            let cap = per_second as f64;
+            for bucket in limit.buckets.values_mut() {
+                if bucket.tokens > cap { bucket.tokens = cap; }
+            }
+        }
+    }
+    /// How many console messages the rate limit has dropped, on any thread,
+    /// that haven't been reported yet.
+    pub fn console_messages_suppressed(&self) -> u64 {
+        console_rate_limits().lock().unwrap()
+            .get(&self.instance)
+            .map(|limit| limit.buckets.values().fold(0, |n, b| n + b.suppressed ) )
+            .unwrap_or(0)
+    }
+    /// Reports messages dropped by the console rate limit, as
+    /// `flush_console` does, but to `console`.
+    pub fn report_console_suppressed_to<C: ConsoleInterface>(&self, console: &C) {
+        for &(level, ref line) in take_console_suppressed(self.instance).iter() {
+            console.log_to_browser(level, line.to_var());
+        }
+    }
+
+    /// Sends any records queued by console buffering now, and reports any
+    /// messages dropped by the console rate limit.
+    pub fn flush_console(&self) {
+        self.flush_console_to(&self.console());
+        report_console_suppressed(*self);
+    }
+    /// Like `flush_console`, but sends the queued records to `console`, and
+    /// doesn't report dropped messages.
+    pub fn flush_console_to<C: ConsoleInterface>(&self, console: &C) {
+        let buffer = CONSOLE_BUFFERING.with(|b| {
+            b.borrow_mut()
+                .get_mut(&self.instance)
+                .map(|state| {
+                    state.flush_scheduled = false;
+                    mem::replace(&mut state.buffer, ConsoleBuffer::new())
+                })
+        });
+        if let Some(mut buffer) = buffer {
+            buffer.flush(|level, lines| console.log(level, lines.to_string_var()) );
+        }
+    }
+
+    /// Calls `f` with the current time ticks at the next display frame, like
+    /// JS's `requestAnimationFrame`. If a 3D context is bound and has a swap
+    /// in flight, `f` is called when that swap completes, so a render loop
+    /// which swaps and then requests its next frame is paced by the display.
+    /// Otherwise a timer targeting 60Hz is used.
+    ///
+    /// Frames are per instance and thread; call this from a thread with a
+    /// message loop. Requests made before the context is lost are still
+    /// called, from the timer.
+    pub fn request_frame<F>(&self, f: F) -> Code<()>
+        where F: FnOnce(Ticks) + 'static
+    {
+        let mut f = Some(f);
+        let wait_for_swap = with_frame_requests(self.instance, |r| {
+            r.callbacks.push(box move |ticks| {
+                f.take().unwrap().call_once((ticks,));
+            });
+            r.context_3d.is_some() && r.swap_pending
+        });
+        if wait_for_swap {
+            Code::Ok(())
+        } else {
+            let code = schedule_frame_timer(self.instance);
+            if !code.is_ok() {
+                with_frame_requests(self.instance, |r| r.callbacks.pop() );
+            }
+            code
+        }
+    }
+
+    /// Records a time sample into a UMA histogram. A no-op if the browser
+    /// doesn't give us `PPB_UMA_Private`. See
+    /// `metrics::histogram_custom_times`.
+    pub fn record_time(&self, name: &str, sample_ms: i64, min_ms: i64, max_ms: i64,
+                       bucket_count: u32) -> Code<()> {
+        metrics::histogram_custom_times(self, name, sample_ms, min_ms, max_ms, bucket_count)
+    }
+    /// See `metrics::histogram_custom_counts`.
+    pub fn record_count(&self, name: &str, sample: i32, min: i32, max: i32,
+                        bucket_count: u32) -> Code<()> {
+        metrics::histogram_custom_counts(self, name, sample, min, max, bucket_count)
+    }
+    /// See `metrics::histogram_enumeration`.
+    pub fn record_enum(&self, name: &str, sample: i32, boundary: i32) -> Code<()> {
+        metrics::histogram_enumeration(self, name, sample, boundary)
+    }
+
+    /// Calls `f` with this thread's instance local state of type `T`, which
+    /// starts out as `None`. State is per-thread: a message loop spawned with
+    /// `spawn_message_loop` starts with none, and its state is dropped when
+    /// `thread_local_setup` returns. `f` may use the state of other types, but
+    /// using `T`'s again from within `f` panics.
+    ///
+    /// Panics if this instance isn't the current instance of this thread.
+    pub fn with_local<T, U, F>(&self, f: F) -> U
+        where T: Any, F: FnOnce(&mut Option<T>) -> U
+    {
+        // Stands in for a slot while it's lent out.
+        struct InUse;
+        // Puts the slot back, even if `f` panics.
+        struct Restore<T: Any>(ffi::PP_Instance, Option<Box<Option<T>>>);
+        impl<T: Any> Drop for Restore<T> {
+            fn drop(&mut self) {
+                let slot: Box<Any> = self.1.take().unwrap();
+                INSTANCE_LOCALS.with(|locals| {
+                    locals.borrow_mut().insert((self.0, TypeId::of::<T>()), slot);
+                });
+            }
+        }
+
+        let is_current = CURRENT_INSTANCE.is_set() &&
+            CURRENT_INSTANCE.with(|i| i.instance == self.instance );
+        assert!(is_current,
+                "Instance::with_local: instance {} is not current on this thread",
+                self.instance);
+
+        let slot = INSTANCE_LOCALS.with(|locals| {
+            let in_use: Box<Any> = box InUse;
+            locals.borrow_mut().insert((self.instance, TypeId::of::<T>()), in_use)
+        });
+        let slot: Box<Option<T>> = match slot {
+            None => box None,
+            Some(slot) => match slot.downcast() {
+                Ok(slot) => slot,
+                Err(_) => panic!("Instance::with_local: this state is already in use"),
+            },
+        };
+
+        let mut restore = Restore(self.instance, Some(slot));
+        f(&mut **restore.1.as_mut().unwrap())
+    }
+    /// The raw `PP_Instance`, eg to drive the `entry` functions by hand.
+    pub fn unwrap(&self) -> ffi::PP_Instance {
         self.instance
     }
 
+    /// The number of live wrappers of each resource type, over all instances
+    /// and threads: wrappers created or cloned, less those dropped. A count
+    /// which doesn't go back down once everything using a type is dropped
+    /// means a leaked reference. Requires the `debug-resource-tracking`
+    /// feature, which costs an atomic add per wrapper created or dropped.
+    #[cfg(feature = "debug-resource-tracking")]
+    pub fn resource_stats(&self) -> HashMap<ResourceType, i64> {
+        resource_tracking::stats()
+    }
+
+    /// Returns true if the browser provides the interfaces needed for
+    /// `feature`. Interfaces are looked up once when the module is
+    /// initialized, so this is cheap.
+    pub fn supports(&self, feature: Feature) -> bool {
+        use ppb::*;
+        match feature {
+            Feature::Graphics2D => get_graphics_2d_opt().is_some(),
+            Feature::Graphics3D => get_graphics_3d_opt().is_some() && get_gles2_opt().is_some(),
+            Feature::OpenGLES2 => get_gles2_opt().is_some(),
+            Feature::ImageData => get_image_data_opt().is_some(),
+            Feature::Font => get_font_opt().is_some(),
+            Feature::BrowserFont => get_browser_font_opt().is_some(),
+            Feature::AudioConfig => get_audio_config_opt().is_some(),
+            Feature::Audio => (get_audio_opt().is_some() || get_audio_1_0_opt().is_some()) &&
+                get_audio_config_opt().is_some(),
+            Feature::FileSystem => get_file_system_opt().is_some() &&
+                get_file_ref_opt().is_some() && get_file_io_opt().is_some(),
+            Feature::UrlLoader => get_url_loader_opt().is_some() &&
+                get_url_request_opt().is_some() && get_url_response_opt().is_some(),
+            Feature::TouchInput => get_touch_event_opt().is_some(),
+            Feature::ImeInput => get_ime_event_opt().is_some(),
+            Feature::MediaStreamVideoTrack => get_media_stream_video_track_opt().is_some(),
+            Feature::VideoFrame => get_video_frame_opt().is_some(),
+            Feature::VideoDecoder => get_video_decoder_opt().is_some(),
+            Feature::VideoEncoder => get_video_encoder_opt().is_some(),
+            Feature::Printing => get_printing_opt().is_some(),
+            Feature::NetworkProxy => get_network_proxy_opt().is_some(),
+            Feature::UmaPrivate => get_uma_private_opt().is_some(),
+            Feature::IsolatedFileSystem => get_isolated_file_system_opt().is_some() &&
+                get_file_ref_opt().is_some() && get_file_io_opt().is_some(),
+            Feature::MediaStreamAudioTrack => get_media_stream_audio_track_opt().is_some(),
+            Feature::AudioBuffer => get_audio_buffer_opt().is_some(),
+            Feature::Fullscreen => get_fullscreen_opt().is_some() ||
+                get_flash_fullscreen_opt().is_some(),
+            Feature::__NonExhaustive => false,
+        }
+    }
+
     pub fn initialize_nacl_io(&self) {
         unsafe {
             ffi::nacl_io_init_ppapi(self.instance,
@@ -2224,7 +5271,26 @@ impl Instance {
     pub fn messaging(&self) -> Messaging {
         return Messaging(self.instance);
     }
+    /// A typed channel to the page under `topic`; see `messages::Channel`.
+    /// Must be called from the instance's thread.
+    pub fn channel<Req, Resp>(&self, topic: &str) -> messages::Channel<Req, Resp>
+        where Req: ToVar, Resp: FromVar + 'static,
+    {
+        messages::Channel::new(*self, topic)
+    }
 
+    pub fn create_2d_context(&self,
+                             size: Size,
+                             is_always_opaque: bool) -> result::Result<Context2d, Code> {
+        use ppb::Graphics2DIf;
+        ppb::get_graphics_2d()
+            .create(self.instance, size.into(), is_always_opaque)
+            .map(|res| Context2d::new(res) )
+            .ok_or(Code::Failed)
+    }
+    /// Returns `Code::BadArgument` if `share_with` was created by another
+    /// instance, which PPAPI doesn't allow. Only contexts created on this
+    /// thread can be checked.
     pub fn create_3d_context(&self,
                              share_with: Option<gles::Context3d>,
                              attribs: &[gles::Context3dAttrib]) -> result::Result<gles::Context3d, Code> {
@@ -2245,6 +5311,17 @@ impl Instance {
             })
             .unwrap_or_else(|| 0i32 );
 
+        if share_with != 0 {
+            match context_3d_owner(share_with) {
+                Some(owner) if owner != self.instance => {
+                    error!("can't share with 3D context {}: it belongs to instance {}, not {}",
+                           share_with, owner, self.instance);
+                    return result::Result::Err(Code::BadArgument);
+                }
+                _ => {}
+            }
+        }
+
         let graphics = ppb::get_graphics_3d();
 
         let raw_cxt  = (graphics.Create.unwrap())(self.instance,
@@ -2254,14 +5331,47 @@ impl Instance {
         if raw_cxt == 0i32 {
             result::Result::Err(Code::Failed)
         } else {
+            context_3d_owners().lock().unwrap().insert(raw_cxt, self.instance);
+            gles::note_created(raw_cxt);
             result::Result::Ok(gles::Context3d::new(raw_cxt))
         }
     }
     pub fn bind_context<T: ContextResource>(&self, cxt: &T) -> Code {
+        self.bind_context_returning_prev(cxt)
+            .map_ok(|_| 0 )
+    }
+    /// Like `bind_context`, but also returns the device which was bound
+    /// before, so it can be restored later. The previous device is returned
+    /// with its own reference. Only bindings made through this instance on
+    /// the calling thread are known; `None` means nothing was.
+    pub fn bind_context_returning_prev<T: ContextResource>(&self, cxt: &T) ->
+        Code<Option<GenericResource>>
+    {
+        use ppb::ResourceInterface;
         match (ppb::get_instance().BindGraphics.unwrap())
             (self.instance,
              cxt.get_device()) {
-            ffi::PP_TRUE => Code::Ok(0),
+            ffi::PP_TRUE => {
+                let device = cxt.get_device();
+                let is_3d = ppb::get_graphics_3d_opt().is(device);
+                with_frame_requests(self.instance, |r| {
+                    r.context_3d = if is_3d { Some(device) } else { None };
+                    r.swap_pending = false;
+                });
+                let bound = {
+                    let borrowed = GenericResource::adopt(device);
+                    let bound = borrowed.clone();
+                    mem::forget(borrowed);
+                    bound
+                };
+                let prev = BOUND_DEVICES.with(|b| {
+                    b.borrow_mut().insert(self.instance, bound)
+                });
+                Code::Ok(prev.and_then(|prev| {
+                    if prev.unwrap() == device { None }
+                    else                       { Some(prev) }
+                }))
+            }
             ffi::PP_FALSE => Code::Failed,
             other => {
                 error!("unknown truthy value: {:}", other);
@@ -2270,22 +5380,203 @@ impl Instance {
         }
     }
 
+    /// Resizes `ctxt`'s buffers to the view's size in device pixels, ie its
+    /// rect's size scaled by `device_scale`, whenever the view changes. The
+    /// resize happens before the change is given to `ppapi_on_change_view`
+    /// or the instance's handler. Replaces any context set before.
+    ///
+    /// `ctxt` must be bound to this instance, from this thread. Resizing
+    /// stops once it isn't: when another device is bound, or the context is
+    /// lost. A bound context which is otherwise dropped is kept alive by the
+    /// binding, and is still resized. Returns `Code::Failed` if `ctxt` isn't
+    /// bound.
+    pub fn auto_resize_3d_context(&self, ctxt: &gles::Context3d) -> Code<()> {
+        if bound_device(self.instance) != Some(ctxt.unwrap()) {
+            return Code::Failed;
+        }
+        let old = AUTO_RESIZE_3D.with(|a| a.borrow_mut().insert(self.instance, ctxt.clone()) );
+        drop(old);
+        Code::Ok(())
+    }
+    /// Stops resizing the context set with `auto_resize_3d_context`.
+    pub fn stop_auto_resize_3d_context(&self) {
+        let old = AUTO_RESIZE_3D.with(|a| a.borrow_mut().remove(&self.instance) );
+        drop(old);
+    }
+    /// Does what a view change of `css_size` at `device_scale` does to the
+    /// context set with `auto_resize_3d_context`, for plugins which track
+    /// the view themselves. Returns the new size in device pixels, or `None`
+    /// if nothing was resized.
+    pub fn auto_resize_3d_to(&self, css_size: Size, device_scale: f32) -> Option<Size> {
+        let ctxt = match AUTO_RESIZE_3D.with(|a| a.borrow().get(&self.instance).map(|c| c.clone() ) ) {
+            Some(ctxt) => ctxt,
+            None => return None,
+        };
+        if bound_device(self.instance) != Some(ctxt.unwrap()) {
+            // Unbound since; the view isn't ours to follow anymore.
+            self.stop_auto_resize_3d_context();
+            return None;
+        }
+
+        let size = css_size.to_device_pixels(device_scale);
+        if size.width == 0 || size.height == 0 { return None; }
+        let code = {
+            use ppb::Graphics3DIf;
+            ppb::get_graphics_3d().resize_buffers(ctxt.unwrap(), size.width as i32, size.height as i32)
+        };
+        if code.is_ok() {
+            Some(size)
+        } else {
+            warn!("couldn't resize a 3D context to {}x{}: {:?}",
+                  size.width, size.height, code);
+            None
+        }
+    }
+
+    /// Does what a view change with the page `visible`, or not, does to the
+    /// instance handler's `on_visible` and `on_hidden`, for plugins which
+    /// track the view themselves. The first call reports `visible` right
+    /// away. After that, a change is only reported if it still holds
+    /// `VISIBILITY_DEBOUNCE_MS` later, so a page that's hidden and shown
+    /// again quickly doesn't call either.
+    pub fn update_visibility(&self, visible: bool) {
+        let inst = self.instance;
+        let (initial, schedule) = VISIBILITY.with(|v| {
+            let mut v = v.borrow_mut();
+            let state = v.entry(inst).or_insert(Visibility {
+                reported: None,
+                current: visible,
+                settle_scheduled: false,
+            });
+            state.current = visible;
+            if state.reported.is_none() {
+                state.reported = Some(visible);
+                (true, false)
+            } else if state.reported != Some(visible) && !state.settle_scheduled {
+                state.settle_scheduled = true;
+                (false, true)
+            } else {
+                (false, false)
+            }
+        });
+        if initial {
+            report_visibility(inst, visible);
+        }
+        if schedule {
+            let code = MessageLoop::post_to_self(move |_| settle_visibility(inst),
+                                                 VISIBILITY_DEBOUNCE_MS);
+            if !code.is_ok() {
+                // Better late than debounced.
+                settle_visibility(inst);
+            }
+        }
+    }
+
+    /// Restarts this instance under `policy` if its thread panics: after
+    /// the policy's delay a new thread and message loop are made and
+    /// `ppapi_instance_created` is called again, with the same args. A
+    /// restart whose `ppapi_instance_created` panics counts too. Once
+    /// `policy.max_restarts` is used up the instance is torn down as if
+    /// no policy were set. Each restart is logged.
+    ///
+    /// This must be called from the instance's thread; it is usually done
+    /// in `ppapi_instance_created`, which will set it again on restart
+    /// (the count isn't reset). State kept on the old thread is lost, as
+    /// are any events the page sends while the restart waits.
+    pub fn set_restart_policy(&self, policy: RestartPolicy) {
+        RESTARTS.with(|r| {
+            r.borrow_mut().entry(self.instance).or_insert((policy, 0)).0 = policy;
+        });
+    }
+    /// How many times this instance has been restarted. This must be called
+    /// from the instance's thread.
+    pub fn restart_count(&self) -> u32 {
+        RESTARTS.with(|r| {
+            r.borrow().get(&self.instance).map(|&(_, count)| count ).unwrap_or(0)
+        })
+    }
+
+    /// Asks for `classes` of input events, unfiltered: they're given to
+    /// `ppapi_on_input`, and its result is ignored; the page doesn't see
+    /// them either way.
     pub fn request_input_events(&self, classes: ffi::PP_InputEvent_Class) -> Code {
-        Code::from_i32((ppb::get_input_event().RequestInputEvents.unwrap())
-                       (self.instance,
-                        classes))
+        let code = Code::from_i32((ppb::get_input_event().RequestInputEvents.unwrap())
+                                  (self.instance,
+                                   classes));
+        if code.is_ok() { set_filtering_input(self.instance, classes, false); }
+        code
     }
+    /// Asks for `classes` of input events, filtered: they're given to
+    /// `ppapi_on_input`, and those it returns false for go on to the page.
+    /// Filtering is slower, so only request it for events which may be
+    /// passed on.
     pub fn request_filtering_input_events(&self, classes: ffi::PP_InputEvent_Class) -> Code {
-        Code::from_i32((ppb::get_input_event().RequestFilteringInputEvents.unwrap())
-                       (self.instance,
-                        classes))
+        let code = Code::from_i32((ppb::get_input_event().RequestFilteringInputEvents.unwrap())
+                                  (self.instance,
+                                   classes));
+        if code.is_ok() { set_filtering_input(self.instance, classes, true); }
+        code
     }
     pub fn clear_input_event_request(&self, classes: ffi::PP_InputEvent_Class) {
         (ppb::get_input_event().ClearInputEventRequest.unwrap())
             (self.instance,
              classes);
+        set_filtering_input(self.instance, classes, false);
+    }
+    /// Returns true if all of `classes` were last requested with
+    /// `request_filtering_input_events`. Call from the instance's thread.
+    pub fn is_filtering_input_events(&self, classes: ffi::PP_InputEvent_Class) -> bool {
+        FILTERING_INPUT.with(|f| {
+            f.borrow().get(&self.instance).map(|&mask| mask & classes == classes )
+        }).unwrap_or(false)
+    }
+
+    /// Returns true while this instance's `ppapi_on_input` is running on the
+    /// calling thread, which is when the browser allows things like entering
+    /// fullscreen. Every input event counts, though the browser only treats
+    /// some (clicks and key presses, not mouse moves) as user gestures.
+    pub fn in_user_gesture(&self) -> bool {
+        USER_GESTURE.with(|g| g.get() == Some(self.instance) )
+    }
+    pub fn is_fullscreen(&self) -> bool {
+        use ppb::FullscreenIf;
+        fullscreen_interface()
+            .map(|f| f.is_fullscreen(self.instance) )
+            .unwrap_or(false)
+    }
+    /// Enters or leaves fullscreen. The change is asynchronous; a view change
+    /// follows if it happens. Entering fullscreen is only allowed while
+    /// handling a user gesture (see `in_user_gesture`), so doing so outside
+    /// of one logs a warning; it'll almost certainly fail.
+    ///
+    /// On Flash hosts without `PPB_Fullscreen`, `PPB_FlashFullscreen` is used
+    /// instead. Returns `Code::NoInterface` if neither is available.
+    pub fn set_fullscreen(&self, fullscreen: bool) -> Code<()> {
+        use ppb::FullscreenIf;
+        let interface = match fullscreen_interface() {
+            Some(interface) => interface,
+            None => return Code::NoInterface,
+        };
+        if fullscreen && !self.in_user_gesture() {
+            warn!("set_fullscreen(true) called outside of a user gesture; the \
+                   browser only allows entering fullscreen from an input event \
+                   handler");
+        }
+        if interface.set_fullscreen(self.instance, fullscreen) {
+            Code::Ok(())
+        } else {
+            Code::Failed
+        }
     }
 
+    /// Returns true if the browser exposes the `PPB_Flash` interfaces. Flash
+    /// only interfaces are never used elsewhere; see `ppb::is_flash_host`.
+    pub fn is_flash_host(&self) -> bool {
+        ppb::is_flash_host()
+    }
+
+    /// Returns `None` if `format` isn't supported by the browser. If
+    /// `init_to_zero` is true, every byte of the new image is zero.
     pub fn create_image(&self,
                         format: Option<imagedata::Format>, // uses native format if None
                         size: Size,
@@ -2294,7 +5585,12 @@ impl Instance {
         let interface = ppb::get_image_data();
         let format = format.unwrap_or_else(|| {
             imagedata::native_image_data_format()
-        }).to_ffi();
+        });
+        if !imagedata::is_image_data_format_supported(format) {
+            warn!("create_image: image data format {:?} isn't supported", format);
+            return None;
+        }
+        let format = format.to_ffi();
         let res = unsafe {
             interface.create(self.instance,
                              format,
@@ -2303,6 +5599,53 @@ impl Instance {
         };
         res.map(|res| imagedata::ImageData::new(res) )
     }
+    /// Creates a native format image holding a copy of `pixels`, which are
+    /// `size.width` pixels to a row with no padding, in native format byte
+    /// order. Returns `None` if `pixels` isn't exactly `width * height` long,
+    /// or the image couldn't be created.
+    pub fn image_data_from_pixels(&self, size: Size,
+                                  pixels: &[u32]) -> Option<imagedata::ImageData> {
+        use std::ptr;
+        let expected = (size.width as usize).checked_mul(size.height as usize);
+        if expected != Some(pixels.len()) {
+            warn!("image_data_from_pixels: {} pixels given for a {}x{} image",
+                  pixels.len(), size.width, size.height);
+            return None;
+        }
+        // Every pixel is about to be overwritten.
+        let image = match self.create_image(None, size, false) {
+            Some(image) => image,
+            None => return None,
+        };
+        let row_len = size.width as usize;
+        if row_len != 0 {
+            let mut mapped = image.map();
+            // Rows may be padded.
+            let stride = mapped.desc.line_stride as usize;
+            let dst = mapped.as_mut_slice().as_mut_ptr();
+            for (y, row) in pixels.chunks(row_len).enumerate() {
+                unsafe {
+                    ptr::copy_nonoverlapping(row.as_ptr() as *const u8,
+                                             dst.offset((y * stride) as isize),
+                                             row_len * 4);
+                }
+            }
+        }
+        Some(image)
+    }
+    /// Creates a native format image covering `view` in device pixels, ie
+    /// its size scaled by `View::device_scale`. See
+    /// `Size::to_device_pixels` for the rounding. Returns `None` if the view
+    /// has no area.
+    pub fn create_view_sized_image(&self, view: &View,
+                                   init_to_zero: bool) -> Option<imagedata::ImageData> {
+        let size = match view.rect() {
+            Some(rect) => rect.size.to_device_pixels(view.device_scale()),
+            None => return None,
+        };
+        if size.width == 0 || size.height == 0 { return None; }
+        self.create_image(None, size, init_to_zero)
+    }
 
     pub fn create_font(&self,
                        desc: &font::Description) -> Option<font::Font> {
@@ -2316,18 +5659,103 @@ impl Instance {
         }
     }
 
+    /// Tries each of `descs` in turn, returning the first font created.
+    /// `None` if none could be, or `descs` is empty. Failures are logged at
+    /// debug level; `PPB_Font(Dev)` doesn't say why creation failed.
+    pub fn create_font_with_fallback(&self,
+                                     descs: &[font::Description]) -> Option<font::Font> {
+        for (i, desc) in descs.iter().enumerate() {
+            if let Some(font) = self.create_font(desc) {
+                return Some(font);
+            }
+            debug!("create_font_with_fallback: description {} (face {:?}) failed",
+                   i, desc.get_face().map(|face| face.as_str() ));
+        }
+        None
+    }
+
+    /// Creates a font through `PPB_BrowserFont_Trusted` when the browser
+    /// provides it, falling back to `PPB_Font(Dev)` otherwise.
+    pub fn browser_font(&self,
+                        desc: &font::Description) -> Option<browser_font::AnyFont> {
+        browser_font::AnyFont::create(self, desc)
+    }
+    /// Lists font families through whichever font interface is present.
+    pub fn browser_font_families(&self) -> Vec<StringVar> {
+        browser_font::get_font_families(self)
+    }
+
+    /// Lets the plugin print itself. See the `printing` module.
+    pub fn set_print_handler<H>(&self, handler: H)
+        where H: printing::PrintHandler + Send + 'static
+    {
+        printing::set_print_handler(self, handler)
+    }
+    pub fn clear_print_handler(&self) -> bool {
+        printing::clear_print_handler(self)
+    }
+
+    /// Sends this instance's events to `handler` too, eg from
+    /// `ppapi_instance_created`. See the `instance_handler` module.
+    pub fn set_handler(&self, handler: Box<InstanceHandler>) {
+        instance_handler::set_handler(self, handler)
+    }
+    pub fn clear_handler(&self) -> bool {
+        instance_handler::clear_handler(self)
+    }
+    pub fn has_handler(&self) -> bool {
+        instance_handler::has_handler(self)
+    }
+    pub fn get_print_settings<F>(&self, callback: CallbackArgs<F, printing::PrintSettings>) ->
+        Code<printing::PrintSettings> where F: FnOnce(Code<printing::PrintSettings>)
+    {
+        printing::get_print_settings(self, callback)
+    }
+
     pub fn create_message_loop(&self) -> MessageLoop {
-        MessageLoop(ppb::get_message_loop().create(&self.unwrap()))
+        let msg_loop = MessageLoop::new(ppb::get_message_loop().create(&self.unwrap()));
+        msg_loop.set_label(None);
+        msg_loop
+    }
+    /// Like `create_message_loop`, but the loop is labeled with `label` in
+    /// its `Debug` output and in warnings about it, to tell loops apart.
+    pub fn create_named_message_loop(&self, label: &str) -> MessageLoop {
+        let msg_loop = MessageLoop::new(ppb::get_message_loop().create(&self.unwrap()));
+        msg_loop.set_label(Some(label));
+        msg_loop
     }
 
-    /// Creates a new message loop and runs it inside a new thread. Once
-    /// instance local data is added, this will ensure the new thread has
-    /// access. This does not require the message loop to be shutdown until you
+    /// Creates a new message loop and runs it inside a new thread. The
+    /// instance is current on the new thread, so `Instance::with_local` may be
+    /// used from it. This does not require the message loop to be shutdown until you
     /// return from `thread_local_setup`. FYI, `MessageLoop::is_attached()` will
     /// return false only after the queue is shutdown.
     pub fn spawn_message_loop<F>(&self,
                                  thread_local_setup: F) -> (MessageLoop, ::std::thread::JoinHandle<()>)
         where F: FnOnce(fn() -> Code) + Send + 'static,
+    {
+        self.spawn_loop_thread(thread_local_setup)
+    }
+    /// Like `spawn_message_loop`, but `thread_local_setup` returns how the
+    /// loop exited, usually the code from `run_loop`, and joining the thread
+    /// gives it back: `Ok(Ok(()))` for a clean shutdown, `Ok(Err(code))`
+    /// for any other code, and `Err(_)` if the thread panicked. The loop
+    /// must still be shut down before `thread_local_setup` returns, error
+    /// or not.
+    pub fn spawn_message_loop_with_result<F>(&self, thread_local_setup: F) ->
+        (MessageLoop, ::std::thread::JoinHandle<result::Result<(), Code>>)
+        where F: FnOnce(fn() -> Code) -> Code + Send + 'static,
+    {
+        self.spawn_loop_thread(move |run_loop| {
+            match thread_local_setup(run_loop) {
+                Code::Ok(_) => result::Result::Ok(()),
+                code => result::Result::Err(code),
+            }
+        })
+    }
+    fn spawn_loop_thread<F, T>(&self, thread_local_setup: F) ->
+        (MessageLoop, ::std::thread::JoinHandle<T>)
+        where F: FnOnce(fn() -> Code) -> T + Send + 'static, T: Send + 'static,
     {
         fn run_loop() -> Code {
             MessageLoop::current()
@@ -2340,12 +5768,16 @@ impl Instance {
         let join = ::std::thread::spawn(move || {
             msg_loop.attach_to_current_thread()
                 .unwrap();
-            CURRENT_INSTANCE.set(&instance, || {
-                thread_local_setup(run_loop)
+            let ret = CURRENT_INSTANCE.set(&instance, || {
+                let ret = thread_local_setup(run_loop);
+                instance.set_console_buffering(false, 0);
+                ret
             });
+            clear_instance_locals(instance.instance);
 
             assert!(!MessageLoop::is_attached(),
                     "please stop (or shutdown) loop");
+            ret
         });
         (msg_loop2, join)
     }
@@ -2357,6 +5789,33 @@ impl Instance {
             .map(|fs| fs::FileSystem::new(fs) )
     }
 
+    /// Opens this plugin's private isolated file system, which persists
+    /// between sessions without the quota prompts of
+    /// `Kind::LocalPersistent`. Unlike `create_file_system`, the file system
+    /// given to `callback` is already open. Returns `Code::NoInterface` if
+    /// the browser doesn't provide `PPB_IsolatedFileSystem_Private`.
+    pub fn create_isolated_file_system<F>(&self, callback: CallbackArgs<F, fs::FileSystem>) ->
+        Code<fs::FileSystem> where F: FnOnce(Code<fs::FileSystem>)
+    {
+        use ppb::{get_isolated_file_system_opt, IsolatedFileSystemIf};
+
+        fn map(fs: ffi::PP_Resource, _status: Code) -> fs::FileSystem {
+            // We're given a reference with the resource.
+            fs::FileSystem::new(fs)
+        }
+
+        let interface = match get_isolated_file_system_opt() {
+            Some(interface) => interface,
+            None => return Code::NoInterface,
+        };
+        let mut cc = callback.to_ffi_callback(0 as ffi::PP_Resource, StorageToArgsMapper(map));
+        let fficc = cc.cc();
+        let code = interface.open(self.unwrap(),
+                                  ffi::PP_ISOLATEDFILESYSTEMTYPE_PRIVATE_PLUGINPRIVATE,
+                                  &mut *cc, fficc);
+        cc.drop_with_code(code)
+    }
+
     pub fn create_video_decoder(&self) -> Option<video_decoder::VideoDecoder> {
         use ppb::{get_video_decoder_opt, VideoDecoderIf};
         get_video_decoder_opt()
@@ -2364,9 +5823,34 @@ impl Instance {
             .map(|r| From::from(r) )
     }
 
+    pub fn create_video_encoder(&self) -> Option<video_encoder::VideoEncoder> {
+        use ppb::{get_video_encoder_opt, VideoEncoderIf};
+        get_video_encoder_opt()
+            .and_then(|i| i.create(self.unwrap()) )
+            .map(|r| {
+                video_encoder::note_created(r);
+                video_encoder::VideoEncoder::new(r)
+            })
+    }
+
     pub fn post_message<T: ToVar>(&self, msg: T) {
         self.messaging().post(msg);
     }
+    /// See `Messaging::post_field`.
+    pub fn post_field<T: messages::MessageField>(&self, value: &T) {
+        self.messaging().post_field(value);
+    }
+
+    /// See `audio::recommend_sample_frame_count`.
+    pub fn audio_config_recommend_sample_frame_count(&self,
+                                                     sample_rate: audio::SampleRate,
+                                                     requested: u32) -> u32 {
+        audio::recommend_sample_frame_count(self, sample_rate, requested)
+    }
+    /// See `audio::recommend_sample_rate`.
+    pub fn audio_config_recommend_sample_rate(&self) -> audio::SampleRate {
+        audio::recommend_sample_rate(self)
+    }
 }
 
 impl MessageLoop {
@@ -2376,9 +5860,16 @@ impl MessageLoop {
 
     fn on_destroy(&self) {
         fn work(_: Code<()>) {
+            if let Some(instance) = Instance::opt_current() {
+                instance_handler::destroy(instance.unwrap());
+            }
             unsafe {
                 ppapi_instance_destroyed();
             }
+            if let Some(instance) = Instance::opt_current() {
+                instance.flush_console();
+                forget_instance_state(instance.unwrap());
+            }
         }
         self.get_ref()
             .post_work(work, 0)
@@ -2389,12 +5880,23 @@ impl MessageLoop {
     fn on_change_view(&self, view: View) {
         self.get_ref()
             .post_work(move |_| {
-                unsafe {
-                    assert!(!ppapi_on_change_view.is_null());
-                    let on_change_view: fn(View) =
-                        transmute(ppapi_on_change_view);
-                    on_change_view(view);
+                if let Some(rect) = view.rect() {
+                    Instance::current().auto_resize_3d_to(rect.size, view.device_scale());
                 }
+                let instance = Instance::current().unwrap();
+                let handled = instance_handler::dispatch(instance, |h| {
+                    h.on_change_view(view.clone())
+                }).is_some();
+                if !ppapi_on_change_view.is_null() {
+                    unsafe {
+                        let on_change_view: fn(View) =
+                            transmute(ppapi_on_change_view);
+                        on_change_view(view);
+                    }
+                } else if !handled {
+                    warn!("plugin is missing 'ppapi_on_change_view'");
+                }
+                Instance::current().update_visibility(view.is_page_visible());
             },
                        0)
             .expect("couldn't tell an instance about an on_change_view event");
@@ -2402,11 +5904,18 @@ impl MessageLoop {
     fn on_change_focus(&self, has_focus: bool) {
         self.get_ref()
             .post_work(move |_| {
-                unsafe {
-                    assert!(!ppapi_on_change_focus.is_null());
-                    let on_change_focus: fn(bool) =
-                        transmute(ppapi_on_change_focus);
-                    on_change_focus(has_focus);
+                let instance = Instance::current().unwrap();
+                let handled = instance_handler::dispatch(instance, |h| {
+                    h.on_change_focus(has_focus)
+                }).is_some();
+                if !ppapi_on_change_focus.is_null() {
+                    unsafe {
+                        let on_change_focus: fn(bool) =
+                            transmute(ppapi_on_change_focus);
+                        on_change_focus(has_focus);
+                    }
+                } else if !handled {
+                    warn!("plugin is missing 'ppapi_on_change_focus'");
                 }
             },
                        0)
@@ -2417,14 +5926,24 @@ impl MessageLoop {
         let (tx, rx) = channel();
         self.get_ref()
             .post_work(move |_| {
-                unsafe {
-                    assert!(!ppapi_on_document_loaded.is_null());
-                    let on_document_loaded: fn(http::Loader) -> bool =
-                        transmute(ppapi_on_document_loaded);
-
-                    let handled = on_document_loaded(loader);
-                    let _ = tx.send(handled);
-                }
+                let instance = Instance::current().unwrap();
+                let by_handler = instance_handler::dispatch(instance, |h| {
+                    h.on_document_load(loader.clone())
+                });
+                let handled = match by_handler {
+                    Some(true) => true,
+                    _ if !ppapi_on_document_loaded.is_null() => unsafe {
+                        let on_document_loaded: fn(http::Loader) -> bool =
+                            transmute(ppapi_on_document_loaded);
+                        on_document_loaded(loader)
+                    },
+                    Some(false) => false,
+                    None => {
+                        warn!("plugin is missing 'ppapi_on_document_loaded'");
+                        false
+                    }
+                };
+                let _ = tx.send(handled);
             },
                        0)
             .expect("couldn't tell an instance about an on_change_view event");
@@ -2432,6 +5951,45 @@ impl MessageLoop {
         // This will block forever if the recieving instance isn't responding to new messages.
         rx.try_recv().unwrap_or(false)
     }
+    fn on_message(&self, instance: Instance, message: ffi::PP_Var) {
+        // Keeps `message` alive until the instance's thread gets to it.
+        ppb::get_var().add_ref(&message);
+        self.get_ref()
+            .post_work(move |_| {
+                let decoded = decode_message_var(instance.unwrap(), message);
+                ppb::get_var().remove_ref(message);
+                let message = match decoded {
+                    Ok(message) => message,
+                    Err(code) => {
+                        warn!("dropping a message which couldn't be decoded: {:?}", code);
+                        return;
+                    }
+                };
+                if instance.messaging().intercept_ack(&message) {
+                    return;
+                }
+                if messages::route_to_channel(&instance, &message) {
+                    return;
+                }
+                let handled = instance_handler::dispatch(instance.unwrap(), move |h| {
+                    h.on_message(message)
+                });
+                if handled.is_none() {
+                    debug!("dropping a message; the instance has no handler");
+                }
+            },
+                       0)
+            .expect("couldn't tell an instance about a message");
+    }
+    fn on_mouse_lock_lost(&self) {
+        self.get_ref()
+            .post_work(move |_| {
+                let instance = Instance::current().unwrap();
+                instance_handler::dispatch(instance, |h| h.on_mouse_lock_lost() );
+            },
+                       0)
+            .expect("couldn't tell an instance about a mouse lock loss");
+    }
 }
 
 type InstancesType = Vec<(Instance, MessageLoop)>;
@@ -2448,6 +6006,7 @@ unsafe fn deinitialize_instances() {
 }
 
 fn expect_instances() -> &'static mut InstancesType {
+    assert_main_thread!("the instance table");
     unsafe {
         if INSTANCES.is_null() {
             let instances: InstancesType = Default::default();
@@ -2488,6 +6047,7 @@ fn remove_instance(instance: Instance) -> Option<MessageLoop> {
         if *inst == instance {
             let (_, msg) = expect_instances()
                 .remove(index);
+            instance.messaging().set_backpressure(false);
             return Some(msg);
         }
         index += 1;
@@ -2509,17 +6069,43 @@ fn insert_instance(instance: Instance, msg: MessageLoop) {
     expect_instances()
         .push((instance, msg));
 }
+/// Whether `instance` is still in the table, ie it hasn't been destroyed.
+fn instance_exists(instance: Instance) -> bool {
+    expect_instances().iter().any(|&(ref inst, _)| *inst == instance )
+}
+/// Swaps in a restarted instance's loop, giving back the one it replaced.
+/// Returns `None` if the instance was destroyed in the meantime.
+fn replace_instance_loop(instance: Instance, msg: MessageLoop) -> Option<MessageLoop> {
+    for &mut (ref inst, ref mut old) in expect_instances().iter_mut() {
+        if *inst == instance {
+            return Some(mem::replace(old, msg));
+        }
+    }
+    None
+}
+/// Removes `instance`, but only if `msg` is still its loop: a thread whose
+/// loop was swapped out no longer speaks for the instance.
+fn remove_instance_with_loop(instance: Instance, msg: &MessageLoop) -> Option<MessageLoop> {
+    let current = expect_instances().iter()
+        .any(|&(ref inst, ref ml)| *inst == instance && ml.unwrap() == msg.unwrap() );
+    if current {
+        remove_instance(instance)
+    } else {
+        None
+    }
+}
 #[doc(hidden)]
 pub mod entry {
-    use super::{find_instance, insert_instance,
-                remove_instance, CURRENT_INSTANCE};
-    use super::{Code, Instance, View, ToFFIBool, GenericResource,
+    use super::{find_instance, insert_instance, instance_exists, replace_instance_loop,
+                remove_instance, remove_instance_with_loop, CURRENT_INSTANCE};
+    use super::{Code, Instance, MessageLoop, View, ToFFIBool, GenericResource,
                 Resource};
     use super::{ffi};
 
     use libc::c_char;
     use std::any::Any;
     use std::mem::transmute;
+    use std::sync::Arc;
 
     // We need to catch all failures in our callbacks,
     // lest an exception (failure) in one instance terminates all
@@ -2550,10 +6136,7 @@ pub mod entry {
                                  argc: u32,
                                  argk: *mut *const c_char,
                                  argv: *mut *const c_char) -> ffi::PP_Bool {
-        use std::thread::{Builder, catch_panic};
-        use std::sync::mpsc::channel;
-        use super::{MessageLoop};
-
+        assert_main_thread!("PPP_Instance::DidCreate");
         fn parse_args(args: Args, id: &mut Option<String>) -> Vec<(String, String)> {
             use libc::strlen;
             use std::slice::from_raw_parts;
@@ -2598,86 +6181,195 @@ pub mod entry {
         let success = try_block(move || {
             let mut id = None;
             let args = parse_args(args, &mut id);
+            let name = id.unwrap_or_else(|| format!("instance id `{}`", inst) );
 
-            let builder = Builder::new()
-                .stack_size(0);
-            let builder = if let Some(id) = id {
-                builder.name(id)
-            } else {
-                builder.name(format!("instance id `{}`", inst))
-            };
+            spawn_instance_thread(instance, args, name, None, Arc::new(instance_created))
+                .map(|ml: MessageLoop| {
+                    insert_instance(instance, ml);
+                    true
+                })
+                .unwrap_or(false)
+        }) // try_block
+            .unwrap();
+        success.to_ffi_bool()
+    }
 
-            let (tx, rx) = channel();
-
-            let _ = builder.spawn(move || {
-                let mut args = Some(args.clone());
-                CURRENT_INSTANCE.set
-                    (&instance,
-                     || {
-                         let ml = instance.create_message_loop();
-                         match ml.attach_to_current_thread() {
-                             Code::Ok(_) => {}
-                             _ => {
-                                 error!("failed to attach the new instance's message loop");
-                                 let _ = tx.send(None);
-                                 return;
-                             }
-                         }
+    fn instance_created(instance: Instance, args: Vec<(String, String)>) {
+        unsafe { super::ppapi_instance_created(instance, args) }
+    }
+
+    /// Starts a thread for `instance` running `init` in place of
+    /// `ppapi_instance_created`, including on restarts, and gives its loop
+    /// as `spawn_instance_thread` does. Nothing is added to the instance
+    /// table; a restart swaps the new loop in for the instance's. For
+    /// testing the restart path.
+    #[doc(hidden)]
+    pub fn spawn_instance_with<F>(instance: Instance, init: F) -> Option<MessageLoop>
+        where F: Fn(Instance, Vec<(String, String)>) + Send + Sync + 'static,
+    {
+        let name = format!("instance id `{}` (test)", instance.unwrap());
+        spawn_instance_thread(instance, Vec::new(), name, None, Arc::new(init))
+    }
+
+    /// Makes `msg` `instance`'s loop, giving back the one it replaced, or
+    /// `None` if the instance is gone. Must be called on the main thread. For
+    /// tests which stand in for the instance's thread with
+    /// `spawn_instance_with`, to put the real loop back afterwards.
+    #[doc(hidden)]
+    pub fn swap_instance_loop(instance: Instance, msg: MessageLoop) -> Option<MessageLoop> {
+        replace_instance_loop(instance, msg)
+    }
+
+    /// Starts `instance`'s thread and message loop and waits for `init`,
+    /// ie `ppapi_instance_created`, to return, giving the loop if it did so
+    /// without panicking. `restart` is the policy and count carried over
+    /// when this is a restart.
+    fn spawn_instance_thread<F>(instance: Instance,
+                                args: Vec<(String, String)>,
+                                name: String,
+                                restart: Option<(super::RestartPolicy, u32)>,
+                                init: Arc<F>) -> Option<MessageLoop>
+        where F: Fn(Instance, Vec<(String, String)>) + Send + Sync + 'static,
+    {
+        use std::thread::{Builder, catch_panic};
+        use std::sync::mpsc::channel;
+
+        let (tx, rx) = channel();
 
-                         fn unwinding() -> bool {
-                             use std::thread;
-                             thread::panicking()
+        let builder = Builder::new()
+            .stack_size(0)
+            .name(name.clone());
+        let _ = builder.spawn(move || {
+            CURRENT_INSTANCE.set
+                (&instance,
+                 || {
+                     let ml = instance.create_message_loop();
+                     match ml.attach_to_current_thread() {
+                         Code::Ok(_) => {}
+                         _ => {
+                             error!("failed to attach the new instance's message loop");
+                             let _ = tx.send(None);
+                             return;
                          }
+                     }
 
+                     if let Some(state) = restart {
+                         super::RESTARTS.with(|r| {
+                             r.borrow_mut().insert(instance.instance, state);
+                         });
+                     }
 
-                         let res = {
-                             let i = instance.clone();
-                             let a = args.take().unwrap();
-                             catch_panic(move || unsafe {
-                                 super::ppapi_instance_created(i, a)
-                             })
-                         };
+                     let res = {
+                         let i = instance.clone();
+                         let a = args.clone();
+                         let init = init.clone();
+                         catch_panic(move || (*init)(i, a) )
+                     };
+
+                     match res {
+                         Ok(()) => {
+                             tx.send(Some(ml.clone())).unwrap();
+                         },
+                         Err(..) => {
+                             error!("failed to initialize instance");
+                             tx.send(None).unwrap();
+                             return;
+                         },
+                     }
 
-                         match res {
-                             Ok(()) => {
-                                 tx.send(Some(ml.clone())).unwrap();
-                             },
-                             Err(..) => {
-                                 error!("failed to initialize instance");
-                                 tx.send(None).unwrap();
-                             },
+                     let own_loop = ml.clone();
+                     let run = catch_panic(move || ml.run_loop() );
+
+                     if run.is_err() {
+                         let state = super::RESTARTS.with(|r| {
+                             r.borrow_mut().remove(&instance.instance)
+                         });
+                         if let Some((policy, restarts)) = state {
+                             if let Some(delay) = policy.delay_for(restarts + 1) {
+                                 warn!("instance `{}` panicked; restarting in {}ms",
+                                       name, delay);
+                                 let cb = move |_| {
+                                     restart_instance(instance, args, name,
+                                                      policy, restarts + 1, init);
+                                 };
+                                 MessageLoop::get_main_loop()
+                                     .post_work(cb, delay)
+                                     .unwrap();
+                                 return;
+                             } else if policy.max_restarts > 0 {
+                                 error!("instance `{}` panicked after {} restarts; \
+                                         giving up", name, restarts);
+                             }
                          }
+                     }
 
-                         // TODO log errors.
-                         let _ = catch_panic(move || ml.run_loop() );
-
-                         if MessageLoop::is_attached() {
-                             panic!("please shutdown the loop; I may add pausing \
-                                     for some sort of pattern later");
-                         } else {
-                             let cb = move |_| {
-                                 remove_instance(instance);
-                             };
-                             MessageLoop::get_main_loop()
-                                 .post_work(cb, 0)
-                                 .unwrap();
-                         }
-                     });
-            });
+                     if MessageLoop::is_attached() {
+                         panic!("please shutdown the loop; I may add pausing \
+                                 for some sort of pattern later");
+                     } else {
+                         let cb = move |_| {
+                             remove_instance_with_loop(instance, &own_loop);
+                         };
+                         MessageLoop::get_main_loop()
+                             .post_work(cb, 0)
+                             .unwrap();
+                     }
+                 });
+        });
 
-            rx.recv()
-                .ok()
-                .and_then(|ml| ml )
-                .map(|ml: MessageLoop| {
-                    insert_instance(instance, ml);
-                    true
-                })
-                .unwrap_or(false)
-        }) // try_block
-            .unwrap();
-        success.to_ffi_bool()
+        rx.recv()
+            .ok()
+            .and_then(|ml| ml )
+    }
+
+    /// Run on the main thread once a restart's delay is up.
+    fn restart_instance<F>(instance: Instance,
+                           args: Vec<(String, String)>,
+                           name: String,
+                           policy: super::RestartPolicy,
+                           restart: u32,
+                           init: Arc<F>)
+        where F: Fn(Instance, Vec<(String, String)>) + Send + Sync + 'static,
+    {
+        if !instance_exists(instance) {
+            // Destroyed while we waited; don't bring it back.
+            debug!("not restarting instance `{}`; it was destroyed", name);
+            return;
+        }
+        warn!("restarting instance `{}` ({} of {})",
+              name, restart, policy.max_restarts);
+        let ml = spawn_instance_thread(instance, args.clone(), name.clone(),
+                                       Some((policy, restart)), init.clone());
+        match ml {
+            Some(ml) => {
+                if replace_instance_loop(instance, ml.clone()).is_none() {
+                    // Destroyed while we waited.
+                    let _ = ml.queue_shutdown();
+                }
+            }
+            None => match policy.delay_for(restart + 1) {
+                Some(delay) => {
+                    warn!("instance `{}` failed to restart; trying again in {}ms",
+                          name, delay);
+                    let cb = move |_| {
+                        restart_instance(instance, args, name, policy, restart + 1, init);
+                    };
+                    MessageLoop::get_main_loop()
+                        .post_work(cb, delay)
+                        .unwrap();
+                }
+                None => {
+                    error!("instance `{}` failed to restart {} times; giving up",
+                           name, restart);
+                    if let Some(ml) = remove_instance(instance) {
+                        let _ = ml.queue_shutdown();
+                    }
+                }
+            },
+        }
     }
     pub extern "C" fn did_destroy(inst: ffi::PP_Instance) {
+        assert_main_thread!("PPP_Instance::DidDestroy");
         let instance = Instance::new(inst);
 
         CURRENT_INSTANCE.set
@@ -2686,6 +6378,20 @@ pub mod entry {
                  let _ = try_block(move || {
                      debug!("did_destroy");
 
+                     {
+                         let mut owners = super::context_3d_owners().lock().unwrap();
+                         let owned: Vec<_> = owners.iter()
+                             .filter(|&(_, &owner)| owner == inst )
+                             .map(|(&ctxt, _)| ctxt )
+                             .collect();
+                         for ctxt in owned.iter() {
+                             owners.remove(ctxt);
+                         }
+                     }
+
+                     super::console_rate_limits().lock().unwrap().remove(&inst);
+                     ::printing::clear_print_handler(&instance);
+
                      let store = remove_instance(instance);
                      if let Some(store) = store {
                          store.on_destroy();
@@ -2695,57 +6401,50 @@ pub mod entry {
 
     }
     pub extern "C" fn did_change_view(inst: ffi::PP_Instance, view: ffi::PP_Resource) {
+        assert_main_thread!("PPP_Instance::DidChangeView");
         let instance = Instance::new(inst);
 
         CURRENT_INSTANCE.set
             (&instance,
              || {
-                 if !super::ppapi_on_change_view.is_null() {
-                     let _ = try_block(move || {
-                         debug!("did_change_view");
-                         find_instance(instance,
-                                       view,
-                                       |store, view| {
-                                           ::std::mem::forget(View::new(view));
-                                           store.on_change_view(View::new(view))
-                                       });
-                     });
-                 } else {
-                     warn!("plugin is missing 'ppapi_on_change_view'");
-                 }
+                 // Whether anything handles this is only known on the
+                 // instance's thread.
+                 let _ = try_block(move || {
+                     debug!("did_change_view");
+                     find_instance(instance,
+                                   view,
+                                   |store, view| {
+                                       // The browser's reference is only borrowed.
+                                       (::ppb::get_core().AddRefResource.unwrap())(view);
+                                       store.on_change_view(View::new(view))
+                                   });
+                 });
              })
     }
     pub extern "C" fn did_change_focus(inst: ffi::PP_Instance, has_focus: ffi::PP_Bool) {
+        assert_main_thread!("PPP_Instance::DidChangeFocus");
         let instance = Instance::new(inst);
 
         CURRENT_INSTANCE.set
             (&instance,
              || {
-                 if !super::ppapi_on_change_focus.is_null() {
-                     let _ = try_block(move || {
-                         debug!("did_change_focus");
-
-                         find_instance(instance,
-                                       (),
-                                       |store, ()| store.on_change_focus(has_focus != ffi::PP_FALSE) );
-                     });
-                 } else {
-                     warn!("plugin is missing 'ppapi_on_change_focus'");
-                 }
+                 let _ = try_block(move || {
+                     debug!("did_change_focus");
+
+                     find_instance(instance,
+                                   (),
+                                   |store, ()| store.on_change_focus(has_focus != ffi::PP_FALSE) );
+                 });
              });
     }
     pub extern "C" fn handle_document_load(inst: ffi::PP_Instance,
                                            url_loader: ffi::PP_Resource) -> ffi::PP_Bool {
+        assert_main_thread!("PPP_Instance::HandleDocumentLoad");
         let instance = Instance::new(inst);
 
         let handled = CURRENT_INSTANCE.set
             (&instance,
              move || {
-                 if super::ppapi_on_document_loaded.is_null() {
-                     warn!("plugin is missing 'ppapi_on_document_loaded'");
-                     return false;
-                 }
-
                  let handled = try_block(move || {
                      debug!("handle_document_load");
 
@@ -2761,24 +6460,65 @@ pub mod entry {
              });
         return handled.to_ffi_bool();
     }
+    pub extern "C" fn handle_message(inst: ffi::PP_Instance, message: ffi::PP_Var) {
+        assert_main_thread!("PPP_Messaging::HandleMessage");
+        let instance = Instance::new(inst);
+
+        CURRENT_INSTANCE.set
+            (&instance,
+             || {
+                 let _ = try_block(move || {
+                     debug!("handle_message");
+                     find_instance(instance,
+                                   message,
+                                   |store, message| store.on_message(instance, message) );
+                 });
+             });
+    }
+    pub extern "C" fn mouse_lock_lost(inst: ffi::PP_Instance) {
+        assert_main_thread!("PPP_MouseLock::MouseLockLost");
+        let instance = Instance::new(inst);
+
+        CURRENT_INSTANCE.set
+            (&instance,
+             || {
+                 let _ = try_block(move || {
+                     debug!("mouse_lock_lost");
+                     find_instance(instance, (), |store, ()| store.on_mouse_lock_lost() );
+                 });
+             });
+    }
 
     // this is called from the instance's thread, not from main.
     pub extern "C" fn handle_input_event(inst: ffi::PP_Instance,
                                          event: ffi::PP_Resource) -> ffi::PP_Bool {
-        use super::{ppb, ppapi_on_input};
+        use super::{ppb, ppapi_on_input, unhandled_input_goes_to_page};
         use input::{MouseInputEvent, KeyboardInputEvent, WheelInputEvent,
                     TouchInputEvent, IMEInputEvent};
         use input::Class;
+        use instance_handler;
         let instance = Instance::new(inst);
         instance.check_current();
 
-        if ppapi_on_input.is_null() {
-            warn!("plugin requested input events, but didn't implement \
-                   'ppapi_on_input'");
+        if super::input_passes_through(inst, event) {
+            return false.to_ffi_bool();
+        }
+
+        // Returning false means "not handled" in both request modes: PPAPI
+        // forwards filtered events to the page, and ignores the result for
+        // unfiltered ones, which the page never gets.
+        if ppapi_on_input.is_null() && !instance_handler::has_handler(&instance) {
+            if unhandled_input_goes_to_page(inst, event) {
+                warn!("plugin requested filtering input events, but didn't implement \
+                       'ppapi_on_input'; passing them to the page");
+            } else {
+                warn!("plugin requested input events, but didn't implement \
+                       'ppapi_on_input'; dropping them");
+            }
             return false.to_ffi_bool();
         }
 
-        let handled;
+        let mut handled;
         unsafe {
             let kbe = ppb::get_keyboard_event().IsKeyboardInputEvent.unwrap();
             let me  = ppb::get_mouse_event().IsMouseInputEvent.unwrap();
@@ -2799,19 +6539,41 @@ pub mod entry {
             } else {
                 panic!("unknown input event");
             };
-            let on_input: fn(Class) -> bool =
-                transmute(ppapi_on_input);
-            handled = Some(on_input(e));
+            let _gesture = super::UserGestureScope::enter(&instance);
+            handled = instance_handler::dispatch(inst, |h| h.on_input(e.clone()) )
+                .unwrap_or(false);
+            if !handled && !ppapi_on_input.is_null() {
+                let on_input: fn(Class) -> bool =
+                    transmute(ppapi_on_input);
+                handled = on_input(e);
+            }
         }
 
-        handled.unwrap_or(false).to_ffi_bool()
+        handled.to_ffi_bool()
     }
     pub extern "C" fn graphics_context_lost(inst: ffi::PP_Instance) {
         let instance = Instance::new(inst);
         instance.check_current();
 
+        let resized = super::AUTO_RESIZE_3D.with(|a| a.borrow_mut().remove(&inst) );
+        drop(resized);
+        ::gles::note_context_lost();
+        // Swaps won't complete anymore, so don't wait on them for frames.
+        let pending = super::with_frame_requests(inst, |r| {
+            r.context_3d = None;
+            r.swap_pending = false;
+            !r.callbacks.is_empty()
+        });
+        if pending {
+            let _ = super::schedule_frame_timer(inst);
+        }
+
+        let handled = ::instance_handler::dispatch(inst, |h| h.on_graphics_context_lost() )
+            .is_some();
         if super::ppapi_on_graphics_context_lost.is_null() {
-            warn!("plugin is missing 'ppapi_on_graphics_context_lost'");
+            if !handled {
+                warn!("plugin is missing 'ppapi_on_graphics_context_lost'");
+            }
             return;
         }
 
@@ -0,0 +1,135 @@
+// Copyright (c) 2014 Richard Diamond & contributors.
+//
+// This file is part of the Rust PPApi project.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use ffi;
+use ppb::{ResourceInterface, MediaStreamAudioTrackIf, get_media_stream_audio_track};
+
+use super::{CallbackArgs, StringVar, Code, Resource};
+use super::audio_buffer::AudioBuffer;
+
+/// Created on the JS side and sent in a message.
+#[derive(Hash, Eq, PartialEq, Debug)]
+pub struct AudioTrack(ffi::PP_Resource);
+
+impl_clone_drop_for!(AudioTrack);
+impl_resource_for!(AudioTrack, ResourceType::AudioTrack);
+
+#[doc(hidden)]
+impl From<ffi::PP_Resource> for AudioTrack {
+    fn from(v: ffi::PP_Resource) -> AudioTrack {
+        debug_assert!(get_media_stream_audio_track().is(v));
+        AudioTrack::new(v)
+    }
+}
+
+#[derive(Hash, Eq, PartialEq, Debug, Copy, Clone)]
+pub enum Attr {
+    /// The maximum number of buffers to hold audio samples. Request at least
+    /// 2 to make sure latency doesn't cause lost samples. If this attribute
+    /// is not specified or value 0 is specified, the default value will be
+    /// used.
+    Buffers(u32),
+    /// The sample rate of the audio data in buffers, in Hz. If this attribute
+    /// is not specified or value 0 is specified, the original sample rate of
+    /// the track will be used.
+    SampleRate(u32),
+    /// The sample size of the audio data in buffers, in bytes. If this
+    /// attribute is not specified or value 0 is specified, the original
+    /// sample size of the track will be used.
+    SampleSize(u32),
+    /// The number of channels in audio buffers. If this attribute is not
+    /// specified or value 0 is specified, the original number of channels
+    /// of the track will be used.
+    Channels(u32),
+    /// The duration of an audio buffer in milliseconds, between 10 and 10000.
+    /// If this attribute is not specified or value 0 is specified, the
+    /// original duration will be used.
+    Duration(u32),
+}
+impl Attr {
+    #[doc(hidden)]
+    pub fn to_ffi(self) -> ffi::PP_MediaStreamAudioTrack_Attrib {
+        match self {
+            Attr::Buffers(..) => ffi::PP_MEDIASTREAMAUDIOTRACK_ATTRIB_BUFFERS,
+            Attr::SampleRate(..) => ffi::PP_MEDIASTREAMAUDIOTRACK_ATTRIB_SAMPLE_RATE,
+            Attr::SampleSize(..) => ffi::PP_MEDIASTREAMAUDIOTRACK_ATTRIB_SAMPLE_SIZE,
+            Attr::Channels(..) => ffi::PP_MEDIASTREAMAUDIOTRACK_ATTRIB_CHANNELS,
+            Attr::Duration(..) => ffi::PP_MEDIASTREAMAUDIOTRACK_ATTRIB_DURATION,
+        }
+    }
+}
+
+impl AudioTrack {
+    pub fn configure<T: AsRef<[Attr]>, F>(&self, attrs: T, callback: CallbackArgs<F, ()>) -> Code<()>
+        where F: FnOnce(Code<()>)
+    {
+        let mut nattrs: Vec<ffi::PP_MediaStreamAudioTrack_Attrib> =
+            Vec::with_capacity(attrs.as_ref().len() * 2 + 1);
+        for attr in attrs.as_ref().iter() {
+            nattrs.push(attr.to_ffi());
+            match attr {
+                &Attr::Buffers(v) | &Attr::SampleRate(v) | &Attr::SampleSize(v) |
+                &Attr::Channels(v) | &Attr::Duration(v) => {
+                    nattrs.push(v as ffi::PP_MediaStreamAudioTrack_Attrib);
+                },
+            }
+        }
+        nattrs.push(ffi::PP_MEDIASTREAMAUDIOTRACK_ATTRIB_NONE);
+
+        let cc = callback.to_ffi_callback((), Default::default());
+
+        let code = get_media_stream_audio_track()
+            .configure(self.unwrap(), nattrs.as_ref(), cc.cc);
+        cc.drop_with_code(code)
+    }
+    fn attrib(&self, attr: Attr) -> Code<u32> {
+        get_media_stream_audio_track()
+            .get_attrib(self.unwrap(), attr.to_ffi())
+            .map_ok(|i| i as u32 )
+    }
+    pub fn buffer_count(&self) -> Code<u32> { self.attrib(Attr::Buffers(0)) }
+    pub fn sample_rate(&self) -> Code<u32> { self.attrib(Attr::SampleRate(0)) }
+    pub fn sample_size(&self) -> Code<u32> { self.attrib(Attr::SampleSize(0)) }
+    pub fn channels(&self) -> Code<u32> { self.attrib(Attr::Channels(0)) }
+    pub fn duration(&self) -> Code<u32> { self.attrib(Attr::Duration(0)) }
+
+    pub fn get_id(&self) -> StringVar {
+        From::from(get_media_stream_audio_track().get_id(self.unwrap()))
+    }
+    pub fn has_ended(&self) -> bool {
+        get_media_stream_audio_track()
+            .has_ended(self.unwrap())
+    }
+    /// Gets the next buffer of samples. Give it back with `recycle_buffer`
+    /// once done with it; the track only has `buffer_count` of them.
+    pub fn get_buffer<F>(&self, f: CallbackArgs<F, AudioBuffer>) -> Code<AudioBuffer>
+        where F: FnOnce(Code<AudioBuffer>)
+    {
+        fn map_args(buffer: ffi::PP_Resource, _status: Code) -> AudioBuffer {
+            From::from(buffer)
+        }
+
+        let mapper = super::StorageToArgsMapper(map_args);
+        let mut cc = f.to_ffi_callback(0, mapper);
+        let fficc = cc.cc;
+        let code = get_media_stream_audio_track()
+            .get_buffer(self.unwrap(), &mut *cc, fficc);
+        cc.drop_with_code(code)
+    }
+    /// Hands `buffer` back to the track for reuse. `AudioBuffer` can't be
+    /// cloned, so each buffer is recycled at most once and its samples can't
+    /// be read after the track starts reusing it.
+    pub fn recycle_buffer(&self, buffer: AudioBuffer) -> Code {
+        get_media_stream_audio_track()
+            .recycle_buffer(self.unwrap(), buffer.unwrap())
+    }
+    pub fn close(self) {
+        get_media_stream_audio_track()
+            .close(self.unwrap())
+    }
+}
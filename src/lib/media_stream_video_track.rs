@@ -17,13 +17,13 @@ use super::video_frame::{self, VideoFrame};
 pub struct VideoTrack(ffi::PP_Resource);
 
 impl_clone_drop_for!(VideoTrack);
-impl_resource_for!(VideoTrack, ResourceType::VideoFrame);
+impl_resource_for!(VideoTrack, ResourceType::VideoTrack);
 
 #[doc(hidden)]
 impl From<ffi::PP_Resource> for VideoTrack {
     fn from(v: ffi::PP_Resource) -> VideoTrack {
         debug_assert!(get_media_stream_video_track().is(v));
-        VideoTrack(v)
+        VideoTrack::new(v)
     }
 }
 
@@ -40,7 +40,11 @@ impl MessageHandling for Instance {
         } else {
             return Code::NoInterface;
         };
-        let interface = interface.RegisterMessageHandler.unwrap();
+        let interface = match interface.RegisterMessageHandler {
+            Some(f) => f,
+            // `PPB_Messaging` 1.0.
+            None => return Code::NotSupported,
+        };
 
         let ffi = ffi::Struct_PPP_MessageHandler_0_2 {
             HandleMessage: Some(handle_message::<T>),
@@ -73,8 +77,9 @@ impl MessageHandling for Instance {
         } else {
             return;
         };
-        let interface = interface.UnregisterMessageHandler.unwrap();
-        interface(self.unwrap());
+        if let Some(unregister) = interface.UnregisterMessageHandler {
+            unregister(self.unwrap());
+        }
     }
 }
 
@@ -92,18 +97,25 @@ struct Handler<T> {
 
 thread_local!(static HANDLERS: Option<RefCell<Vec<Instance>>> = Default::default());
 
-extern fn handle_message<T>(_instance: ffi::PP_Instance,
+extern fn handle_message<T>(instance: ffi::PP_Instance,
                             user_data: *mut libc::c_void,
                             message: *const ffi::Struct_PP_Var)
     where T: MessageHandler
 {
     let handler: &mut Handler<T> = unsafe { transmute(user_data) };
     let var = unsafe { *message };
-    let var = AnyVar::new_bumped(var); // TODO var refs.
+    // TODO var refs.
+    let var = match super::decode_message_var(instance, var) {
+        Ok(var) => var,
+        Err(_) => return,
+    };
+    if Instance::new(instance).messaging().intercept_ack(&var) {
+        return;
+    }
     handler.handler.async_message(var);
 }
 
-extern fn handle_blocking_message<T>(_instance: ffi::PP_Instance,
+extern fn handle_blocking_message<T>(instance: ffi::PP_Instance,
                                      user_data: *mut libc::c_void,
                                      message: *const ffi::Struct_PP_Var,
                                      response: *mut ffi::Struct_PP_Var)
@@ -111,8 +123,11 @@ extern fn handle_blocking_message<T>(_instance: ffi::PP_Instance,
 {
     let handler: &mut Handler<T> = unsafe { transmute(user_data) };
     let var = unsafe { *message };
-    let var = AnyVar::new_bumped(var); // TODO var refs.
-    let ret = handler.handler.sync_message(var);
+    // TODO var refs.
+    let ret = match super::decode_message_var(instance, var) {
+        Ok(var) => handler.handler.sync_message(var),
+        Err(_) => AnyVar::Undefined,
+    };
 
     unsafe {
         *response = ret.to_var();
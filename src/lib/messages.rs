@@ -0,0 +1,523 @@
+// Copyright (c) 2014 Richard Diamond & contributors.
+//
+// This file is part of the Rust PPApi project.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! `define_messages!`, for plugins which exchange a fixed set of message
+//! shapes with JS. Each message is a dictionary with a `"type"` key naming
+//! the variant and a key per field:
+//!
+//! ```rust
+//! define_messages! {
+//!     pub enum Message {
+//!         Resize { width: i32, height: i32 },
+//!         Log { text: String, tags: Vec<String> },
+//!     }
+//! }
+//! ```
+//!
+//! `Message::Resize { width: 4, height: 2 }` is sent as
+//! `{ "type": "Resize", "width": 4, "height": 2 }`. The generated enum
+//! implements `FromVar`, has `to_any` to build the var to send, and has a
+//! `decode` function which says why a var couldn't be decoded.
+//!
+//! For messages which don't have a fixed shape, `MessageRouter` dispatches
+//! on the `"type"` key alone. `Channel` sends and receives typed messages
+//! under a topic, eg for request/response with one part of the page.
+
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::error;
+use std::fmt;
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+use ffi;
+
+use super::{Code, AnyVar, OwnedVar, DictionaryVar, Instance, ToVar, FromVar, ToStringVar};
+
+/// A type which can be a field of a message defined by `define_messages!`.
+pub trait MessageField: Sized {
+    fn to_field(&self) -> OwnedVar;
+    fn from_field(var: &OwnedVar) -> Option<Self>;
+}
+
+impl MessageField for bool {
+    fn to_field(&self) -> OwnedVar { OwnedVar::Bool(*self) }
+    fn from_field(var: &OwnedVar) -> Option<bool> {
+        match var {
+            &OwnedVar::Bool(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+impl MessageField for i32 {
+    fn to_field(&self) -> OwnedVar { OwnedVar::I32(*self) }
+    fn from_field(var: &OwnedVar) -> Option<i32> {
+        match var {
+            &OwnedVar::I32(v) => Some(v),
+            // JS numbers which happen to be integral.
+            &OwnedVar::F64(v) if v.fract() == 0.0 &&
+                v >= i32::min_value() as f64 && v <= i32::max_value() as f64 => Some(v as i32),
+            _ => None,
+        }
+    }
+}
+impl MessageField for u32 {
+    fn to_field(&self) -> OwnedVar {
+        if *self <= i32::max_value() as u32 {
+            OwnedVar::I32(*self as i32)
+        } else {
+            OwnedVar::F64(*self as f64)
+        }
+    }
+    fn from_field(var: &OwnedVar) -> Option<u32> {
+        match var {
+            &OwnedVar::I32(v) if v >= 0 => Some(v as u32),
+            &OwnedVar::F64(v) if v.fract() == 0.0 &&
+                v >= 0.0 && v <= u32::max_value() as f64 => Some(v as u32),
+            _ => None,
+        }
+    }
+}
+impl MessageField for f64 {
+    fn to_field(&self) -> OwnedVar { OwnedVar::F64(*self) }
+    fn from_field(var: &OwnedVar) -> Option<f64> {
+        match var {
+            &OwnedVar::F64(v) => Some(v),
+            &OwnedVar::I32(v) => Some(v as f64),
+            _ => None,
+        }
+    }
+}
+impl MessageField for f32 {
+    fn to_field(&self) -> OwnedVar { OwnedVar::F64(*self as f64) }
+    fn from_field(var: &OwnedVar) -> Option<f32> {
+        <f64 as MessageField>::from_field(var).map(|v| v as f32 )
+    }
+}
+impl MessageField for String {
+    fn to_field(&self) -> OwnedVar { OwnedVar::String(self.clone()) }
+    fn from_field(var: &OwnedVar) -> Option<String> {
+        match var {
+            &OwnedVar::String(ref v) => Some(v.clone()),
+            _ => None,
+        }
+    }
+}
+impl<T: MessageField> MessageField for Vec<T> {
+    fn to_field(&self) -> OwnedVar {
+        OwnedVar::Array(self.iter().map(|v| v.to_field() ).collect())
+    }
+    fn from_field(var: &OwnedVar) -> Option<Vec<T>> {
+        match var {
+            &OwnedVar::Array(ref v) => v.iter().map(|v| T::from_field(v) ).collect(),
+            _ => None,
+        }
+    }
+}
+impl MessageField for OwnedVar {
+    fn to_field(&self) -> OwnedVar { self.clone() }
+    fn from_field(var: &OwnedVar) -> Option<OwnedVar> { Some(var.clone()) }
+}
+/// `None` is sent as `undefined`. Missing and `null` fields decode as `None`.
+impl<T: MessageField> MessageField for Option<T> {
+    fn to_field(&self) -> OwnedVar {
+        match self {
+            &Some(ref v) => v.to_field(),
+            &None => OwnedVar::Undefined,
+        }
+    }
+    fn from_field(var: &OwnedVar) -> Option<Option<T>> {
+        match var {
+            &OwnedVar::Undefined | &OwnedVar::Null => Some(None),
+            var => T::from_field(var).map(|v| Some(v) ),
+        }
+    }
+}
+
+/// Why a var couldn't be decoded as a message. Converts to
+/// `Code::BadArgument`.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum MessageError {
+    /// The var isn't a dictionary with a string `"type"`.
+    NotAMessage,
+    UnknownType(String),
+    MissingField {
+        message: &'static str,
+        field: &'static str,
+    },
+    /// The field is present, but doesn't have the declared type.
+    BadField {
+        message: &'static str,
+        field: &'static str,
+    },
+}
+impl MessageError {
+    pub fn code(&self) -> Code<()> { Code::BadArgument }
+}
+impl fmt::Display for MessageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &MessageError::NotAMessage =>
+                f.write_str("not a dictionary with a string `type`"),
+            &MessageError::UnknownType(ref ty) =>
+                write!(f, "unknown message type `{}`", ty),
+            &MessageError::MissingField { message, field } =>
+                write!(f, "message `{}` is missing field `{}`", message, field),
+            &MessageError::BadField { message, field } =>
+                write!(f, "field `{}` of message `{}` has the wrong type", field, message),
+        }
+    }
+}
+impl error::Error for MessageError {
+    fn description(&self) -> &str { "malformed message" }
+}
+impl<T> From<MessageError> for Code<T> {
+    fn from(_: MessageError) -> Code<T> { Code::BadArgument }
+}
+
+#[doc(hidden)]
+pub fn split_message(var: &OwnedVar) ->
+    Result<(&str, &BTreeMap<String, OwnedVar>), MessageError>
+{
+    match var {
+        &OwnedVar::Dict(ref fields) => match fields.get("type") {
+            Some(&OwnedVar::String(ref ty)) => Ok((&ty[..], fields)),
+            _ => Err(MessageError::NotAMessage),
+        },
+        _ => Err(MessageError::NotAMessage),
+    }
+}
+#[doc(hidden)]
+pub fn owned_from_var(var: ffi::PP_Var) -> OwnedVar {
+    AnyVar::new_bumped(var).into_owned()
+}
+#[doc(hidden)]
+pub fn decode_field<T: MessageField>(fields: &BTreeMap<String, OwnedVar>,
+                                     message: &'static str,
+                                     field: &'static str) -> Result<T, MessageError> {
+    match fields.get(field) {
+        Some(var) => T::from_field(var)
+            .ok_or(MessageError::BadField { message: message, field: field }),
+        // Lets `Option` fields be left out.
+        None => T::from_field(&OwnedVar::Undefined)
+            .ok_or(MessageError::MissingField { message: message, field: field }),
+    }
+}
+
+/// Defines an enum of messages along with its var encoding. See the module
+/// docs.
+#[macro_export]
+macro_rules! define_messages(
+    ($(#[$attr:meta])* pub enum $name:ident {
+        $($variant:ident { $($field:ident: $ty:ty),* $(,)* }),* $(,)*
+    }) => (
+        $(#[$attr])*
+        pub enum $name {
+            $($variant { $($field: $ty),* }),*
+        }
+        define_messages!(@impl $name { $($variant { $($field: $ty),* }),* });
+    );
+    ($(#[$attr:meta])* enum $name:ident {
+        $($variant:ident { $($field:ident: $ty:ty),* $(,)* }),* $(,)*
+    }) => (
+        $(#[$attr])*
+        enum $name {
+            $($variant { $($field: $ty),* }),*
+        }
+        define_messages!(@impl $name { $($variant { $($field: $ty),* }),* });
+    );
+    (@impl $name:ident { $($variant:ident { $($field:ident: $ty:ty),* }),* }) => (
+        impl $name {
+            /// The value of this message's `"type"` key.
+            pub fn message_type(&self) -> &'static str {
+                match self {
+                    $(&$name::$variant { .. } => stringify!($variant)),*
+                }
+            }
+            pub fn to_owned_var(&self) -> $crate::OwnedVar {
+                use std::collections::BTreeMap;
+                use $crate::messages::MessageField;
+                match self {
+                    $(&$name::$variant { $(ref $field),* } => {
+                        let mut fields = BTreeMap::new();
+                        fields.insert("type".to_string(),
+                                      $crate::OwnedVar::String(stringify!($variant).to_string()));
+                        $(fields.insert(stringify!($field).to_string(),
+                                        MessageField::to_field($field));)*
+                        $crate::OwnedVar::Dict(fields)
+                    }),*
+                }
+            }
+            pub fn decode_owned(var: &$crate::OwnedVar) ->
+                ::std::result::Result<$name, $crate::messages::MessageError>
+            {
+                let (ty, _fields) = try!($crate::messages::split_message(var));
+                $(if ty == stringify!($variant) {
+                    return Ok($name::$variant {
+                        $($field: try!($crate::messages::decode_field(
+                            _fields, stringify!($variant), stringify!($field)))),*
+                    });
+                })*
+                Err($crate::messages::MessageError::UnknownType(ty.to_string()))
+            }
+            /// This message as a new var tree, eg to post.
+            pub fn to_any(&self) -> $crate::AnyVar {
+                self.to_owned_var().to_any()
+            }
+            pub fn decode<T: $crate::ToVar>(var: &T) ->
+                ::std::result::Result<$name, $crate::messages::MessageError>
+            {
+                $name::decode_owned(&var.to_any().into_owned())
+            }
+        }
+        impl $crate::FromVar for $name {
+            fn from_var(var: $crate::ffi::PP_Var) -> Option<$name> {
+                $name::decode_owned(&$crate::messages::owned_from_var(var)).ok()
+            }
+        }
+    );
+);
+
+/// Calls a handler chosen by a message's `"type"` key:
+///
+/// ```rust
+/// let mut router = MessageRouter::new();
+/// router.on("resize", |msg| { ... })
+///       .otherwise(|msg| { ... });
+/// router.dispatch(msg);
+/// ```
+///
+/// Handlers are given the whole message.
+pub struct MessageRouter {
+    handlers: HashMap<String, Box<FnMut(AnyVar)>>,
+    default: Option<Box<FnMut(AnyVar)>>,
+}
+impl MessageRouter {
+    pub fn new() -> MessageRouter {
+        MessageRouter {
+            handlers: HashMap::new(),
+            default: None,
+        }
+    }
+    /// Handles messages whose `"type"` is `ty`, replacing any previous handler
+    /// for it.
+    pub fn on<F>(&mut self, ty: &str, handler: F) -> &mut MessageRouter
+        where F: FnMut(AnyVar) + 'static
+    {
+        self.handlers.insert(ty.to_string(), box handler);
+        self
+    }
+    /// Handles messages which aren't a dictionary with a string `"type"`, or
+    /// whose type has no handler. Without one, such messages are logged and
+    /// dropped.
+    pub fn otherwise<F>(&mut self, handler: F) -> &mut MessageRouter
+        where F: FnMut(AnyVar) + 'static
+    {
+        self.default = Some(box handler);
+        self
+    }
+    /// Returns true if a handler registered with `on` took the message.
+    pub fn dispatch(&mut self, msg: AnyVar) -> bool {
+        let ty = msg.get_dict_ref()
+            .and_then(|dict| dict.get("type").get_string() );
+        let handler = match ty {
+            Some(ref ty) => self.handlers.get_mut(ty.as_str()),
+            None => None,
+        };
+        if let Some(handler) = handler {
+            handler(msg);
+            return true;
+        }
+
+        match self.default {
+            Some(ref mut default) => default(msg),
+            None => match ty {
+                Some(ty) => warn!("no handler for message type `{}`", ty.as_str()),
+                None => warn!("dropping message without a string `type`"),
+            },
+        }
+        false
+    }
+}
+
+/// The key holding a channel message's topic; see `Channel`.
+pub const CHANNEL_TOPIC_KEY: &'static str = "topic";
+/// The key holding a channel message's payload; see `Channel`.
+pub const CHANNEL_PAYLOAD_KEY: &'static str = "payload";
+
+struct Inbox {
+    queue: VecDeque<AnyVar>,
+    listener: Option<Box<FnMut(AnyVar)>>,
+}
+struct Route {
+    id: usize,
+    inbox: Rc<RefCell<Inbox>>,
+}
+thread_local!(static ROUTES: RefCell<HashMap<ffi::PP_Instance, HashMap<String, Route>>> =
+              RefCell::new(HashMap::new()));
+thread_local!(static NEXT_ROUTE_ID: Cell<usize> = Cell::new(0));
+
+/// Typed messages under a topic. A `Req` is sent as
+/// `{ "topic": topic, "payload": req }`, and messages of that shape from the
+/// page with the same topic are taken from the instance's message stream,
+/// their payloads decoded as `Resp`. Other messages go to the instance
+/// handler as usual.
+///
+/// ```rust
+/// let chat = instance.channel::<StringVar, StringVar>("chat");
+/// chat.send("hello".to_string_var());
+/// chat.on_receive(|reply| { ... });
+/// ```
+///
+/// Channels with different topics are independent. Making a channel on a
+/// topic which already has one takes over the topic. Dropping a channel
+/// stops its topic being routed, unless another channel has taken it over
+/// since. Channels belong to the instance's thread.
+pub struct Channel<Req, Resp> {
+    instance: Instance,
+    topic: String,
+    id: usize,
+    inbox: Rc<RefCell<Inbox>>,
+    _1: PhantomData<fn(Req) -> Resp>,
+}
+impl<Req: ToVar, Resp: FromVar + 'static> Channel<Req, Resp> {
+    pub fn new(instance: Instance, topic: &str) -> Channel<Req, Resp> {
+        let id = NEXT_ROUTE_ID.with(|n| {
+            let id = n.get();
+            n.set(id + 1);
+            id
+        });
+        let inbox = Rc::new(RefCell::new(Inbox {
+            queue: VecDeque::new(),
+            listener: None,
+        }));
+        ROUTES.with(|r| {
+            r.borrow_mut()
+                .entry(instance.unwrap())
+                .or_insert_with(HashMap::new)
+                .insert(topic.to_string(), Route {
+                    id: id,
+                    inbox: inbox.clone(),
+                });
+        });
+        Channel {
+            instance: instance,
+            topic: topic.to_string(),
+            id: id,
+            inbox: inbox,
+            _1: PhantomData,
+        }
+    }
+    pub fn topic(&self) -> &str { &self.topic[..] }
+
+    pub fn send(&self, message: Req) {
+        let dict = DictionaryVar::new();
+        dict.set(CHANNEL_TOPIC_KEY, self.topic.to_string_var());
+        dict.set(CHANNEL_PAYLOAD_KEY, message);
+        self.instance.messaging().post(dict);
+    }
+    /// The next message received, if there is one. Payloads which can't be
+    /// decoded as `Resp` are logged and skipped. Always `None` once
+    /// `on_receive` is used.
+    pub fn try_recv(&self) -> Option<Resp> {
+        loop {
+            let payload = self.inbox.borrow_mut().queue.pop_front();
+            match payload {
+                Some(payload) => match decode_payload(&self.topic[..], payload) {
+                    Some(message) => return Some(message),
+                    None => continue,
+                },
+                None => return None,
+            }
+        }
+    }
+    /// Calls `f` with each message received from now on, and with any
+    /// already waiting for `try_recv`, instead of queueing them. Replaces
+    /// any previous `f`.
+    pub fn on_receive<F>(&self, f: F) where F: FnMut(Resp) + 'static {
+        let mut f = f;
+        let topic = self.topic.clone();
+        let mut listener: Box<FnMut(AnyVar)> = box move |payload| {
+            if let Some(message) = decode_payload(&topic[..], payload) {
+                f(message);
+            }
+        };
+        let queued: Vec<AnyVar> = self.inbox.borrow_mut().queue.drain().collect();
+        for payload in queued.into_iter() {
+            listener(payload);
+        }
+        self.inbox.borrow_mut().listener = Some(listener);
+    }
+}
+impl<Req, Resp> Drop for Channel<Req, Resp> {
+    fn drop(&mut self) {
+        let id = self.id;
+        let instance = self.instance.unwrap();
+        ROUTES.with(|r| {
+            let mut r = r.borrow_mut();
+            let now_empty = match r.get_mut(&instance) {
+                Some(topics) => {
+                    let ours = topics.get(&self.topic)
+                        .map(|route| route.id == id )
+                        .unwrap_or(false);
+                    if ours { topics.remove(&self.topic); }
+                    topics.is_empty()
+                }
+                None => false,
+            };
+            if now_empty { r.remove(&instance); }
+        });
+    }
+}
+fn decode_payload<Resp: FromVar>(topic: &str, payload: AnyVar) -> Option<Resp> {
+    let message = Resp::from_var(payload.to_var());
+    if message.is_none() {
+        warn!("dropping a message on channel `{}` which couldn't be decoded", topic);
+    }
+    message
+}
+
+/// Gives `message` to the channel for its topic, if it's a channel message
+/// and `instance` has one, returning true if it did; the message should
+/// then be dropped. This is done for messages from the page before the
+/// instance handler sees them; it's public for plugins dispatching
+/// messages some other way.
+pub fn route_to_channel(instance: &Instance, message: &AnyVar) -> bool {
+    let dict = match message.get_dict_ref() {
+        Some(dict) if dict.len() == 2 && dict.has_key(CHANNEL_PAYLOAD_KEY) => dict,
+        _ => return false,
+    };
+    let topic = match dict.get(CHANNEL_TOPIC_KEY).get_string() {
+        Some(topic) => topic,
+        None => return false,
+    };
+    let inbox = ROUTES.with(|r| {
+        r.borrow()
+            .get(&instance.unwrap())
+            .and_then(|topics| topics.get(topic.as_str()) )
+            .map(|route| route.inbox.clone() )
+    });
+    let inbox = match inbox {
+        Some(inbox) => inbox,
+        None => return false,
+    };
+    let payload = dict.get(CHANNEL_PAYLOAD_KEY);
+    // Taken out while it runs, so it can use the channel.
+    let listener = inbox.borrow_mut().listener.take();
+    match listener {
+        Some(mut listener) => {
+            listener(payload);
+            let mut inbox = inbox.borrow_mut();
+            if inbox.listener.is_none() {
+                inbox.listener = Some(listener);
+            }
+        }
+        None => inbox.borrow_mut().queue.push_back(payload),
+    }
+    true
+}
@@ -0,0 +1,66 @@
+// Copyright (c) 2014 Richard Diamond & contributors.
+//
+// This file is part of the Rust PPApi project.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! UMA histogram reporting via `PPB_UMA_Private`. That interface is only
+//! given to some plugins by Chrome; everywhere else these functions validate
+//! their arguments and then do nothing.
+
+use ppb::{get_uma_private_opt, UmaPrivateIf};
+
+use super::{Code, Instance, StringVar, ToVar};
+
+/// Returns true if samples are actually recorded.
+pub fn is_available() -> bool {
+    get_uma_private_opt().is_some()
+}
+
+fn check_buckets<T: PartialOrd>(min: T, max: T, bucket_count: u32) -> Code<()> {
+    if min >= max || bucket_count == 0 {
+        Code::BadArgument
+    } else {
+        Code::Ok(())
+    }
+}
+
+/// Records a time sample, in milliseconds, into `bucket_count` buckets
+/// spanning `min_ms` to `max_ms`. Returns `Code::BadArgument` if
+/// `min_ms >= max_ms` or there are no buckets.
+pub fn histogram_custom_times(instance: &Instance, name: &str, sample_ms: i64,
+                              min_ms: i64, max_ms: i64, bucket_count: u32) -> Code<()> {
+    try_code!(check_buckets(min_ms, max_ms, bucket_count));
+    if let Some(uma) = get_uma_private_opt() {
+        let name = StringVar::new(name);
+        uma.histogram_custom_times(instance.unwrap(), name.to_var(),
+                                   sample_ms, min_ms, max_ms, bucket_count);
+    }
+    Code::Ok(())
+}
+/// Records a count sample. See `histogram_custom_times`.
+pub fn histogram_custom_counts(instance: &Instance, name: &str, sample: i32,
+                               min: i32, max: i32, bucket_count: u32) -> Code<()> {
+    try_code!(check_buckets(min, max, bucket_count));
+    if let Some(uma) = get_uma_private_opt() {
+        let name = StringVar::new(name);
+        uma.histogram_custom_counts(instance.unwrap(), name.to_var(),
+                                    sample, min, max, bucket_count);
+    }
+    Code::Ok(())
+}
+/// Records `sample`, which must be in `0..boundary`, into an enumeration
+/// histogram. Returns `Code::BadArgument` if it isn't.
+pub fn histogram_enumeration(instance: &Instance, name: &str, sample: i32,
+                             boundary: i32) -> Code<()> {
+    if sample < 0 || sample >= boundary {
+        return Code::BadArgument;
+    }
+    if let Some(uma) = get_uma_private_opt() {
+        let name = StringVar::new(name);
+        uma.histogram_enumeration(instance.unwrap(), name.to_var(), sample, boundary);
+    }
+    Code::Ok(())
+}
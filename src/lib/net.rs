@@ -0,0 +1,136 @@
+// Copyright (c) 2014 Richard Diamond & contributors.
+//
+// This file is part of the Rust PPApi project.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Proxy resolution via `PPB_NetworkProxy`, for honoring the system's proxy
+//! settings on custom sockets.
+
+use std::ascii::AsciiExt;
+use std::error;
+use std::fmt;
+
+use ffi;
+use ppb::{get_network_proxy_opt, NetworkProxyIf};
+
+use super::{Code, CallbackArgs, StorageToArgsMapper, InPlaceInit, Instance,
+            AnyVar, StringVar, ToVar, UndefinedVar};
+
+impl InPlaceInit for ffi::PP_Var { }
+
+/// Asks the browser which proxies it would use to fetch `url`. The result is
+/// a PAC style string, eg `"PROXY host:port; DIRECT"`; see `parse_proxy_rules`.
+/// Returns `Code::NotSupported` if the browser doesn't provide the interface.
+pub fn get_proxy_for_url<F>(instance: &Instance, url: &str,
+                            callback: CallbackArgs<F, StringVar>) -> Code<StringVar>
+    where F: FnOnce(Code<StringVar>)
+{
+    fn map(proxy_string: ffi::PP_Var, _status: Code) -> StringVar {
+        // We get a reference with the var.
+        match AnyVar::new(proxy_string) {
+            AnyVar::String(s) => s,
+            _ => StringVar::new(""),
+        }
+    }
+
+    let interface = match get_network_proxy_opt() {
+        Some(interface) => interface,
+        None => return Code::NotSupported,
+    };
+    let url = StringVar::new(url);
+    let mut cc = callback.to_ffi_callback({UndefinedVar}.to_var(),
+                                          StorageToArgsMapper(map));
+    let fficc = cc.cc();
+    let code = interface.get_proxy_for_url(instance.unwrap(), url.to_var(),
+                                           &mut *cc, fficc);
+    cc.drop_with_code(code)
+}
+
+#[derive(Clone, Hash, Eq, PartialEq, Debug)]
+pub struct ProxyAddr {
+    pub host: String,
+    pub port: u16,
+}
+impl fmt::Display for ProxyAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.host, self.port)
+    }
+}
+
+#[derive(Clone, Hash, Eq, PartialEq, Debug)]
+pub enum ProxyRule {
+    Direct,
+    /// `PROXY` or `HTTP`.
+    Http(ProxyAddr),
+    Https(ProxyAddr),
+    /// `SOCKS`, `SOCKS4` or `SOCKS5`.
+    Socks(ProxyAddr),
+}
+
+/// A proxy rule which couldn't be understood.
+#[derive(Clone, Hash, Eq, PartialEq, Debug)]
+pub struct ProxyParseError {
+    pub rule: String,
+}
+impl fmt::Display for ProxyParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "malformed proxy rule `{}`", self.rule)
+    }
+}
+impl error::Error for ProxyParseError {
+    fn description(&self) -> &str { "malformed proxy rule" }
+}
+
+fn parse_addr(addr: &str) -> Option<ProxyAddr> {
+    let colon = match addr.rfind(':') {
+        Some(colon) => colon,
+        None => return None,
+    };
+    let (host, port) = (&addr[..colon], &addr[colon + 1..]);
+    if host.is_empty() { return None; }
+    port.parse()
+        .ok()
+        .map(|port| ProxyAddr { host: host.to_string(), port: port })
+}
+
+/// Parses a PAC style proxy string, in order of preference. An empty string
+/// means `[ProxyRule::Direct]`.
+pub fn parse_proxy_rules(proxies: &str) -> Result<Vec<ProxyRule>, ProxyParseError> {
+    let mut rules = Vec::new();
+    for rule in proxies.split(';').map(|rule| rule.trim() ) {
+        if rule.is_empty() { continue; }
+
+        let err = || ProxyParseError { rule: rule.to_string() };
+        let mut parts = rule.split(|c: char| c.is_whitespace() )
+            .filter(|part| !part.is_empty() );
+        let kind = parts.next().unwrap().to_ascii_uppercase();
+        let addr = parts.next();
+        if parts.next().is_some() { return Err(err()); }
+
+        let rule = match (&kind[..], addr) {
+            ("DIRECT", None) => ProxyRule::Direct,
+            ("DIRECT", Some(_)) => return Err(err()),
+            (_, None) => return Err(err()),
+            (kind, Some(addr)) => {
+                let addr = match parse_addr(addr) {
+                    Some(addr) => addr,
+                    None => return Err(err()),
+                };
+                match kind {
+                    "PROXY" | "HTTP" => ProxyRule::Http(addr),
+                    "HTTPS" => ProxyRule::Https(addr),
+                    "SOCKS" | "SOCKS4" | "SOCKS5" => ProxyRule::Socks(addr),
+                    _ => return Err(err()),
+                }
+            }
+        };
+        rules.push(rule);
+    }
+    if rules.is_empty() {
+        rules.push(ProxyRule::Direct);
+    }
+    Ok(rules)
+}
@@ -10,8 +10,12 @@
 //! of the verbose-ness of the originals.
 
 #![allow(missing_docs)]
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::mem;
 use std::mem::uninitialized;
+use std::ptr;
+use std::sync::{Mutex, Once, ONCE_INIT};
 use libc;
 
 use super::ffi;
@@ -25,6 +29,7 @@ pub type Console = ffi::PPB_Console;
 pub type VarArray = ffi::Struct_PPB_VarArray_1_0;
 pub type VarArrayBuffer = ffi::PPB_VarArrayBuffer;
 pub type VarDictionary  = ffi::Struct_PPB_VarDictionary_1_0;
+pub type Graphics2D = ffi::PPB_Graphics2D;
 pub type Graphics3D = ffi::PPB_Graphics3D;
 pub type Messaging = ffi::Struct_PPB_Messaging_1_2;
 pub type MessageLoop = ffi::PPB_MessageLoop;
@@ -35,8 +40,10 @@ pub type MouseInputEvent = ffi::PPB_MouseInputEvent;
 pub type TouchInputEvent = ffi::PPB_TouchInputEvent;
 pub type IMEInputEvent = ffi::PPB_IMEInputEvent;
 pub type OpenGLES2 = ffi::PPB_OpenGLES2;
+pub type OpenGLES2Query = ffi::Struct_PPB_OpenGLES2Query;
 pub type WheelInputEvent = ffi::PPB_WheelInputEvent;
 pub type Font = ffi::PPB_Font_Dev;
+pub type BrowserFont = ffi::PPB_BrowserFont_Trusted;
 pub type ImageData = ffi::PPB_ImageData;
 pub type UrlLoader = ffi::PPB_URLLoader;
 pub type UrlRequestInfo = ffi::PPB_URLRequestInfo;
@@ -47,17 +54,31 @@ pub type FileRef = ffi::Struct_PPB_FileRef_1_2;
 pub type FileIo = ffi::Struct_PPB_FileIO_1_1;
 pub type MediaStreamVideoTrack = ffi::Struct_PPB_MediaStreamVideoTrack_0_1;
 pub type VideoFrame = ffi::Struct_PPB_VideoFrame_0_1;
+pub type MediaStreamAudioTrack = ffi::Struct_PPB_MediaStreamAudioTrack_0_1;
+pub type AudioBuffer = ffi::Struct_PPB_AudioBuffer_0_1;
 pub type VideoDecoder = ffi::Struct_PPB_VideoDecoder_1_0;
+pub type VideoEncoder = ffi::Struct_PPB_VideoEncoder_0_2;
+pub type AudioConfig = ffi::Struct_PPB_AudioConfig_1_1;
+pub type Audio = ffi::Struct_PPB_Audio_1_1;
+pub type NetworkProxy = ffi::Struct_PPB_NetworkProxy_1_0;
+pub type UmaPrivate = ffi::PPB_UMA_Private;
+pub type IsolatedFileSystem = ffi::PPB_IsolatedFileSystem_Private;
+pub type Fullscreen = ffi::Struct_PPB_Fullscreen_1_0;
+pub type Printing = ffi::Struct_PPB_Printing_Dev_0_7;
 
 mod consts {
-    pub const VAR: &'static str              = "PPB_Var;1.1\0";
+    pub const VAR: &'static str              = "PPB_Var;1.2\0";
+    pub const VAR_1_1: &'static str          = "PPB_Var;1.1\0";
     pub const CORE: &'static str             = "PPB_Core;1.0\0";
     pub const CONSOLE: &'static str          = "PPB_Console;1.0\0";
     pub const MESSAGING: &'static str        = "PPB_Messaging;1.2\0";
+    pub const MESSAGING_1_0: &'static str    = "PPB_Messaging;1.0\0";
     pub const MESSAGELOOP: &'static str      = "PPB_MessageLoop;1.0\0";
     pub const VAR_ARRAY: &'static str        = "PPB_VarArray;1.0\0";
     pub const VAR_ARRAY_BUFFER: &'static str = "PPB_VarArrayBuffer;1.0\0";
     pub const VAR_DICTIONARY: &'static str   = "PPB_VarDictionary;1.0\0";
+    pub const GRAPHICS_2D: &'static str      = "PPB_Graphics2D;1.1\0";
+    pub const GRAPHICS_2D_1_0: &'static str  = "PPB_Graphics2D;1.0\0";
     pub const GRAPHICS_3D: &'static str      = "PPB_Graphics3D;1.0\0";
     pub const INSTANCE: &'static str         = "PPB_Instance;1.0\0";
     pub const INPUT:    &'static str         = "PPB_InputEvent;1.0\0";
@@ -67,18 +88,33 @@ mod consts {
     pub const TOUCH:    &'static str         = "PPB_TouchInputEvent;1.0\0";
     pub const IME:      &'static str         = "PPB_IMEInputEvent;1.0\0";
     pub const GLES2:    &'static str         = "PPB_OpenGLES2;1.0\0";
+    pub const GLES2_QUERY: &'static str      = "PPB_OpenGLES2Query;1.0\0";
     pub const FONTDEV:  &'static str         = "PPB_Font(Dev);0.6\0";
+    pub const BROWSER_FONT: &'static str     = "PPB_BrowserFont_Trusted;1.0\0";
     pub const IMAGEDATA:&'static str         = "PPB_ImageData;1.0\0";
     pub const URL_LOADER: &'static str       = "PPB_URLLoader;1.0\0";
     pub const URL_REQUEST: &'static str      = "PPB_URLRequestInfo;1.0\0";
     pub const URL_RESPONSE: &'static str     = "PPB_URLResponseInfo;1.0\0";
     pub const VIEW:     &'static str         = "PPB_View;1.2\0";
+    pub const VIEW_1_1: &'static str         = "PPB_View;1.1\0";
+    pub const VIEW_1_0: &'static str         = "PPB_View;1.0\0";
     pub const FILESYSTEM: &'static str       = "PPB_FileSystem;1.0\0";
     pub const FILEREF: &'static str          = "PPB_FileRef;1.2\0";
     pub const FILEIO: &'static str           = "PPB_FileIo;1.1\0";
     pub const MEDIA_STREAM_VIDEO_TRACK: &'static str = "PPB_MediaStreamVideoTrack;0.1\0";
     pub const VIDEO_FRAME: &'static str      = "PPB_VideoFrame;0.1\0";
+    pub const MEDIA_STREAM_AUDIO_TRACK: &'static str = "PPB_MediaStreamAudioTrack;0.1\0";
+    pub const AUDIO_BUFFER: &'static str     = "PPB_AudioBuffer;0.1\0";
     pub const VIDEO_DECODER: &'static str    = "PPB_VideoDecoder;1.0\0";
+    pub const VIDEO_ENCODER: &'static str    = "PPB_VideoEncoder;0.2\0";
+    pub const AUDIO_CONFIG: &'static str     = "PPB_AudioConfig;1.1\0";
+    pub const AUDIO: &'static str            = "PPB_Audio;1.1\0";
+    pub const AUDIO_1_0: &'static str        = "PPB_Audio;1.0\0";
+    pub const NETWORK_PROXY: &'static str    = "PPB_NetworkProxy;1.0\0";
+    pub const UMA_PRIVATE: &'static str      = "PPB_UMA_Private;3.0\0";
+    pub const ISOLATED_FS: &'static str      = "PPB_IsolatedFileSystem_Private;0.2\0";
+    pub const FULLSCREEN: &'static str       = "PPB_Fullscreen;1.0\0";
+    pub const PRINTING: &'static str         = "PPB_Printing(Dev);0.7\0";
 }
 mod globals {
     use super::super::ffi;
@@ -89,6 +125,7 @@ mod globals {
     pub static mut ARRAY:        Option<&'static super::VarArray> = None;
     pub static mut ARRAY_BUFFER: Option<&'static super::VarArrayBuffer> = None;
     pub static mut DICTIONARY:   Option<&'static super::VarDictionary>  = None;
+    pub static mut GRAPHICS_2D:  Option<&'static super::Graphics2D> = None;
     pub static mut GRAPHICS_3D:  Option<&'static super::Graphics3D> = None;
     pub static mut MESSAGING:    Option<&'static super::Messaging> = None;
     pub static mut MESSAGE_LOOP: Option<&'static super::MessageLoop> = None;
@@ -100,7 +137,9 @@ mod globals {
     pub static mut TOUCH:        Option<&'static super::TouchInputEvent> = None;
     pub static mut IME:          Option<&'static super::IMEInputEvent> = None;
     pub static mut GLES2:        Option<&'static super::OpenGLES2> = None;
+    pub static mut GLES2_QUERY:  Option<&'static super::OpenGLES2Query> = None;
     pub static mut FONTDEV:      Option<&'static super::Font> = None;
+    pub static mut BROWSER_FONT: Option<&'static super::BrowserFont> = None;
     pub static mut IMAGEDATA:    Option<&'static super::ImageData> = None;
     pub static mut URL_LOADER:   Option<&'static super::UrlLoader> = None;
     pub static mut URL_REQUEST:  Option<&'static super::UrlRequestInfo> = None;
@@ -111,20 +150,43 @@ mod globals {
     pub static mut FILEIO:       Option<&'static super::FileIo> = None;
     pub static mut MEDIA_STREAM_VIDEO_TRACK: Option<&'static super::MediaStreamVideoTrack> = None;
     pub static mut VIDEO_FRAME:  Option<&'static super::VideoFrame> = None;
+    pub static mut MEDIA_STREAM_AUDIO_TRACK: Option<&'static super::MediaStreamAudioTrack> = None;
+    pub static mut AUDIO_BUFFER: Option<&'static super::AudioBuffer> = None;
     pub static mut VIDEO_DECODER: Option<&'static super::VideoDecoder> = None;
+    pub static mut VIDEO_ENCODER: Option<&'static super::VideoEncoder> = None;
+    pub static mut AUDIO_CONFIG: Option<&'static super::AudioConfig> = None;
+    pub static mut AUDIO:        Option<&'static super::Audio> = None;
+    // Only set if 1.1 is missing; its callback gets no latency.
+    pub static mut AUDIO_1_0:    Option<&'static ffi::Struct_PPB_Audio_1_0> = None;
+    pub static mut NETWORK_PROXY: Option<&'static super::NetworkProxy> = None;
+    pub static mut UMA_PRIVATE:  Option<&'static super::UmaPrivate> = None;
+    pub static mut ISOLATED_FS:  Option<&'static super::IsolatedFileSystem> = None;
+    pub static mut FULLSCREEN:   Option<&'static super::Fullscreen> = None;
+    pub static mut PRINTING:     Option<&'static super::Printing> = None;
 }
 #[cold] #[inline(never)] #[doc(hidden)]
 pub fn initialize_globals(b: ffi::PPB_GetInterface) {
     unsafe {
         globals::BROWSER       = b;
-        globals::VAR           = get_interface(consts::VAR);
+        clear_resolved_versions();
+        globals::VAR           = get_interface_fallback(&[
+            (consts::VAR, mem::size_of::<ffi::Struct_PPB_Var_1_2>()),
+            (consts::VAR_1_1, mem::size_of::<ffi::Struct_PPB_Var_1_1>()),
+        ]);
         globals::CONSOLE       = get_interface(consts::CONSOLE);
         globals::CORE          = get_interface(consts::CORE);
         globals::ARRAY         = get_interface(consts::VAR_ARRAY);
         globals::ARRAY_BUFFER  = get_interface(consts::VAR_ARRAY_BUFFER);
         globals::DICTIONARY    = get_interface(consts::VAR_DICTIONARY);
+        globals::GRAPHICS_2D   = get_interface_fallback(&[
+            (consts::GRAPHICS_2D, mem::size_of::<ffi::Struct_PPB_Graphics2D_1_1>()),
+            (consts::GRAPHICS_2D_1_0, mem::size_of::<ffi::Struct_PPB_Graphics2D_1_0>()),
+        ]);
         globals::GRAPHICS_3D   = get_interface(consts::GRAPHICS_3D);
-        globals::MESSAGING     = get_interface(consts::MESSAGING);
+        globals::MESSAGING     = get_interface_fallback(&[
+            (consts::MESSAGING, mem::size_of::<ffi::Struct_PPB_Messaging_1_2>()),
+            (consts::MESSAGING_1_0, mem::size_of::<ffi::Struct_PPB_Messaging_1_0>()),
+        ]);
         globals::MESSAGE_LOOP  = get_interface(consts::MESSAGELOOP);
         globals::INSTANCE      = get_interface(consts::INSTANCE);
         globals::INPUT         = get_interface(consts::INPUT);
@@ -134,18 +196,39 @@ pub fn initialize_globals(b: ffi::PPB_GetInterface) {
         globals::TOUCH         = get_interface(consts::TOUCH);
         globals::IME           = get_interface(consts::IME);
         globals::GLES2         = get_interface(consts::GLES2);
+        globals::GLES2_QUERY   = get_interface(consts::GLES2_QUERY);
         globals::FONTDEV       = get_interface(consts::FONTDEV);
+        globals::BROWSER_FONT  = get_interface(consts::BROWSER_FONT);
         globals::IMAGEDATA     = get_interface(consts::IMAGEDATA);
         globals::URL_LOADER    = get_interface(consts::URL_LOADER);
         globals::URL_REQUEST   = get_interface(consts::URL_REQUEST);
         globals::URL_RESPONSE  = get_interface(consts::URL_RESPONSE);
-        globals::VIEW          = get_interface(consts::VIEW);
+        globals::VIEW          = get_interface_fallback(&[
+            (consts::VIEW, mem::size_of::<ffi::Struct_PPB_View_1_2>()),
+            (consts::VIEW_1_1, mem::size_of::<ffi::Struct_PPB_View_1_1>()),
+            (consts::VIEW_1_0, mem::size_of::<ffi::Struct_PPB_View_1_0>()),
+        ]);
         globals::FILESYSTEM    = get_interface(consts::FILESYSTEM);
         globals::FILEREF       = get_interface(consts::FILEREF);
         globals::FILEIO        = get_interface(consts::FILEIO);
         globals::MEDIA_STREAM_VIDEO_TRACK = get_interface(consts::MEDIA_STREAM_VIDEO_TRACK);
         globals::VIDEO_FRAME   = get_interface(consts::VIDEO_FRAME);
+        globals::MEDIA_STREAM_AUDIO_TRACK = get_interface(consts::MEDIA_STREAM_AUDIO_TRACK);
+        globals::AUDIO_BUFFER  = get_interface(consts::AUDIO_BUFFER);
         globals::VIDEO_DECODER = get_interface(consts::VIDEO_DECODER);
+        globals::VIDEO_ENCODER = get_interface(consts::VIDEO_ENCODER);
+        globals::AUDIO_CONFIG  = get_interface(consts::AUDIO_CONFIG);
+        globals::AUDIO         = get_interface(consts::AUDIO);
+        globals::AUDIO_1_0     = if globals::AUDIO.is_none() {
+            get_interface(consts::AUDIO_1_0)
+        } else {
+            None
+        };
+        globals::NETWORK_PROXY = get_interface(consts::NETWORK_PROXY);
+        globals::UMA_PRIVATE   = get_interface(consts::UMA_PRIVATE);
+        globals::ISOLATED_FS   = get_interface(consts::ISOLATED_FS);
+        globals::FULLSCREEN    = get_interface(consts::FULLSCREEN);
+        globals::PRINTING      = get_interface(consts::PRINTING);
     }
 }
 /// Get the PPB_GetInterface function pointer.
@@ -159,12 +242,167 @@ fn get_interface<T>(name: &'static str) -> Option<&'static T> {
         let ptr = get_actual_browser()(name.as_ptr() as *const i8) as *const T;
 
         if ptr.is_null() { None }
-        else             { Some(mem::transmute(ptr)) }
+        else {
+            record_resolved_version(name);
+            Some(mem::transmute(ptr))
+        }
+    }
+}
+/// Resolves the first of `versions`, newest first, which the browser has.
+/// Each version is given with the size of its function table; this only
+/// works for interfaces whose newer versions just append functions. An
+/// older table is copied into a zeroed `T`, so the functions it lacks are
+/// `None` and must be checked for before being called.
+fn get_interface_fallback<T: Default>(versions: &[(&'static str, usize)]) -> Option<&'static T> {
+    fallback_interface(versions, |name| get_interface::<u8>(name).map(|ptr| ptr as *const u8 ) )
+}
+/// `get_interface_fallback`, with the browser's lookup given as `lookup`.
+/// Public for testing.
+#[doc(hidden)]
+pub fn fallback_interface<T, F>(versions: &[(&'static str, usize)],
+                                mut lookup: F) -> Option<&'static T>
+    where T: Default, F: FnMut(&'static str) -> Option<*const u8>,
+{
+    for &(name, size) in versions.iter() {
+        let ptr = match lookup(name) {
+            Some(ptr) => ptr,
+            None => continue,
+        };
+        if size >= mem::size_of::<T>() {
+            return Some(unsafe { mem::transmute(ptr) });
+        }
+        // Copies live as long as the module, like the browser's tables, so
+        // make each only once.
+        let mut copies = padded_tables().lock().unwrap();
+        let copy = *copies.entry((ptr as usize, mem::size_of::<T>())).or_insert_with(|| {
+            let mut table: Box<T> = box Default::default();
+            unsafe {
+                ptr::copy_nonoverlapping(ptr, &mut *table as *mut T as *mut u8, size);
+            }
+            let copy = &*table as *const T as usize;
+            mem::forget(table);
+            copy
+        });
+        return Some(unsafe { &*(copy as *const T) });
+    }
+    None
+}
+// Zero padded copies of old function tables, keyed by the browser's table
+// and the size of the copy.
+static PADDED_TABLES_INIT: Once = ONCE_INIT;
+static mut PADDED_TABLES: *const Mutex<HashMap<(usize, usize), usize>> =
+    0 as *const Mutex<HashMap<(usize, usize), usize>>;
+fn padded_tables() -> &'static Mutex<HashMap<(usize, usize), usize>> {
+    PADDED_TABLES_INIT.call_once(|| unsafe {
+        PADDED_TABLES = mem::transmute(Box::new(Mutex::new(HashMap::new())));
+    });
+    unsafe { &*PADDED_TABLES }
+}
+
+// Written on the main thread during `initialize_globals`, read-only after.
+static mut RESOLVED_VERSIONS: *mut Vec<(&'static str, &'static str)> =
+    0 as *mut Vec<(&'static str, &'static str)>;
+unsafe fn clear_resolved_versions() {
+    if RESOLVED_VERSIONS.is_null() {
+        let versions: Box<Vec<_>> = box Vec::new();
+        RESOLVED_VERSIONS = mem::transmute(versions);
+    } else {
+        (*RESOLVED_VERSIONS).clear();
+    }
+}
+unsafe fn record_resolved_version(full: &'static str) {
+    if RESOLVED_VERSIONS.is_null() { return; }
+    let full = full.trim_right_matches('\0');
+    if let Some(split) = full.find(';') {
+        (*RESOLVED_VERSIONS).push((&full[..split], &full[split + 1..]));
+    }
+}
+/// The version of interface `name`, eg `"PPB_View"`, which was resolved at
+/// startup, or `None` if the browser has none of the versions this crate
+/// knows. Some interfaces fall back to older versions, in which case
+/// functions only present in newer versions return `Code::NotSupported`
+/// or similar.
+pub fn resolved_version(name: &str) -> Option<&'static str> {
+    unsafe {
+        if RESOLVED_VERSIONS.is_null() { return None; }
+        (*RESOLVED_VERSIONS).iter()
+            .find(|&&(n, _)| n == name )
+            .map(|&(_, version)| version )
+    }
+}
+thread_local!(static RAW_INTERFACES: RefCell<HashMap<String, usize>> =
+              RefCell::new(HashMap::new()));
+fn lookup_raw_interface(name: &str, version: &str) -> *const libc::c_void {
+    let full = format!("{};{}\0", name, version);
+    let cached = RAW_INTERFACES.with(|c| c.borrow().get(&full).map(|p| *p ) );
+    let ptr = match cached {
+        Some(ptr) => ptr,
+        None => {
+            let ptr = unsafe {
+                get_actual_browser()(full.as_ptr() as *const i8) as usize
+            };
+            RAW_INTERFACES.with(|c| c.borrow_mut().insert(full, ptr) );
+            ptr
+        }
+    };
+    ptr as *const libc::c_void
+}
+/// Looks up an interface this crate doesn't wrap, eg
+/// `raw_interface::<MyPPB_Foo_1_0>("PPB_Foo", "1.0")`, returning `None` if
+/// the browser doesn't provide it. Lookups, including failed ones, are
+/// cached per thread.
+///
+/// This is unsafe because nothing checks that `T` matches the layout of
+/// the interface struct the browser returns.
+pub unsafe fn raw_interface<T>(name: &str, version: &str) -> Option<*const T> {
+    let ptr = lookup_raw_interface(name, version);
+    if ptr.is_null() { None }
+    else             { Some(ptr as *const T) }
+}
+/// Returns true if the browser provides version `version` of interface
+/// `name`.
+pub fn interface_version_available(name: &str, version: &str) -> bool {
+    !lookup_raw_interface(name, version).is_null()
+}
+/// Like `interface_version_available`, but takes the full interface string,
+/// eg `"PPB_Flash;13.0"`.
+pub fn has_interface(full: &str) -> bool {
+    match full.find(';') {
+        Some(split) => interface_version_available(&full[..split], &full[split + 1..]),
+        None => false,
     }
 }
+
+/// The versions of `PPB_Flash` `is_flash_host` looks for.
+pub const FLASH_VERSIONS: &'static [&'static str] = &["13.0", "12.6", "12.5", "12.4"];
+static FLASH_HOST_INIT: Once = ONCE_INIT;
+static mut FLASH_HOST: bool = false;
+/// Returns true if the browser provides some version of `PPB_Flash`, which
+/// standard NaCl/PNaCl hosts don't. Probed once, on first use.
+pub fn is_flash_host() -> bool {
+    unsafe {
+        FLASH_HOST_INIT.call_once(|| {
+            FLASH_HOST = FLASH_VERSIONS.iter()
+                .any(|version| interface_version_available("PPB_Flash", version) );
+        });
+        FLASH_HOST
+    }
+}
+/// Looks up a Flash only interface. Always `None` unless `is_flash_host`, so
+/// nothing comes to rely on these outside of Flash hosts by accident.
+unsafe fn flash_interface<T>(name: &str, version: &str) -> Option<&'static T> {
+    if !is_flash_host() { return None; }
+    raw_interface::<T>(name, version).map(|ptr| mem::transmute(ptr) )
+}
+/// `PPB_FlashFullscreen;1.0`, which has the same layout as `PPB_Fullscreen`.
+pub fn get_flash_fullscreen_opt() -> Option<&'static Fullscreen> {
+    unsafe { flash_interface("PPB_FlashFullscreen", "1.0") }
+}
+
 macro_rules! get_fun(
     (pub fn $ident:ident() -> $ty:ty { $global:ident }) => (
         #[doc = "Returns a static ref to the interface"]
+        #[inline]
         pub fn $ident() -> &'static $ty {
             #[inline(never)] fn failure() -> ! {
                 panic!("Missing browser {} interface", stringify!($ty))
@@ -180,6 +418,7 @@ macro_rules! get_fun(
 macro_rules! get_fun_opt(
     (pub fn $ident:ident() -> $ty:ty { $global:ident }) => (
         #[doc = "Returns an optional static ref to the interface"]
+        #[inline]
         pub fn $ident() -> Option<&'static $ty> {
             unsafe {
                 globals::$global
@@ -200,6 +439,8 @@ get_fun!    (pub fn get_array_buffer() -> VarArrayBuffer { ARRAY_BUFFER });
 get_fun_opt!(pub fn get_array_buffer_opt() -> VarArrayBuffer { ARRAY_BUFFER });
 get_fun!    (pub fn get_dictionary() -> VarDictionary { DICTIONARY });
 get_fun_opt!(pub fn get_dictionary_opt() -> VarDictionary { DICTIONARY });
+get_fun!    (pub fn get_graphics_2d() -> Graphics2D { GRAPHICS_2D });
+get_fun_opt!(pub fn get_graphics_2d_opt() -> Graphics2D { GRAPHICS_2D });
 get_fun!    (pub fn get_graphics_3d() -> Graphics3D { GRAPHICS_3D });
 get_fun_opt!(pub fn get_graphics_3d_opt() -> Graphics3D { GRAPHICS_3D });
 get_fun!    (pub fn get_messaging() -> Messaging { MESSAGING });
@@ -222,8 +463,11 @@ get_fun!    (pub fn get_ime_event() -> IMEInputEvent { IME });
 get_fun_opt!(pub fn get_ime_event_opt() -> IMEInputEvent { IME });
 get_fun!    (pub fn get_gles2() -> OpenGLES2 { GLES2 });
 get_fun_opt!(pub fn get_gles2_opt() -> OpenGLES2 { GLES2 });
+get_fun_opt!(pub fn get_gles2_query_opt() -> OpenGLES2Query { GLES2_QUERY });
 get_fun!    (pub fn get_font() -> Font { FONTDEV });
 get_fun_opt!(pub fn get_font_opt() -> Font { FONTDEV });
+get_fun!    (pub fn get_browser_font() -> BrowserFont { BROWSER_FONT });
+get_fun_opt!(pub fn get_browser_font_opt() -> BrowserFont { BROWSER_FONT });
 get_fun!    (pub fn get_image_data() -> ImageData { IMAGEDATA });
 get_fun_opt!(pub fn get_image_data_opt() -> ImageData { IMAGEDATA });
 get_fun!    (pub fn get_url_loader() -> UrlLoader { URL_LOADER });
@@ -244,8 +488,29 @@ get_fun!    (pub fn get_media_stream_video_track() -> MediaStreamVideoTrack { ME
 get_fun_opt!(pub fn get_media_stream_video_track_opt() -> MediaStreamVideoTrack { MEDIA_STREAM_VIDEO_TRACK });
 get_fun!    (pub fn get_video_frame() -> VideoFrame { VIDEO_FRAME });
 get_fun_opt!(pub fn get_video_frame_opt() -> VideoFrame { VIDEO_FRAME });
+get_fun!    (pub fn get_media_stream_audio_track() -> MediaStreamAudioTrack { MEDIA_STREAM_AUDIO_TRACK });
+get_fun_opt!(pub fn get_media_stream_audio_track_opt() -> MediaStreamAudioTrack { MEDIA_STREAM_AUDIO_TRACK });
+get_fun!    (pub fn get_audio_buffer() -> AudioBuffer { AUDIO_BUFFER });
+get_fun_opt!(pub fn get_audio_buffer_opt() -> AudioBuffer { AUDIO_BUFFER });
 get_fun!    (pub fn get_video_decoder() -> VideoDecoder { VIDEO_DECODER });
 get_fun_opt!(pub fn get_video_decoder_opt() -> VideoDecoder { VIDEO_DECODER });
+get_fun!    (pub fn get_video_encoder() -> VideoEncoder { VIDEO_ENCODER });
+get_fun_opt!(pub fn get_video_encoder_opt() -> VideoEncoder { VIDEO_ENCODER });
+get_fun!    (pub fn get_audio_config() -> AudioConfig { AUDIO_CONFIG });
+get_fun_opt!(pub fn get_audio_config_opt() -> AudioConfig { AUDIO_CONFIG });
+get_fun!    (pub fn get_audio() -> Audio { AUDIO });
+get_fun_opt!(pub fn get_audio_opt() -> Audio { AUDIO });
+get_fun_opt!(pub fn get_audio_1_0_opt() -> ffi::Struct_PPB_Audio_1_0 { AUDIO_1_0 });
+get_fun!    (pub fn get_network_proxy() -> NetworkProxy { NETWORK_PROXY });
+get_fun_opt!(pub fn get_network_proxy_opt() -> NetworkProxy { NETWORK_PROXY });
+get_fun!    (pub fn get_uma_private() -> UmaPrivate { UMA_PRIVATE });
+get_fun_opt!(pub fn get_uma_private_opt() -> UmaPrivate { UMA_PRIVATE });
+get_fun!    (pub fn get_isolated_file_system() -> IsolatedFileSystem { ISOLATED_FS });
+get_fun_opt!(pub fn get_isolated_file_system_opt() -> IsolatedFileSystem { ISOLATED_FS });
+get_fun!    (pub fn get_fullscreen() -> Fullscreen { FULLSCREEN });
+get_fun_opt!(pub fn get_fullscreen_opt() -> Fullscreen { FULLSCREEN });
+get_fun!    (pub fn get_printing() -> Printing { PRINTING });
+get_fun_opt!(pub fn get_printing_opt() -> Printing { PRINTING });
 
 macro_rules! impl_fun(
     ($fun:expr => ( $($arg:expr),* ) ) => ({
@@ -323,6 +588,12 @@ pub trait VarIf {
     fn remove_ref(&self, var: Struct_PP_Var);
     fn var_from_utf8(&self, string: &str) -> Struct_PP_Var;
     fn var_to_utf8(&self, string: &Struct_PP_Var) -> String;
+    /// A new resource var holding a reference to `res`. `None` if the
+    /// browser's `PPB_Var` is older than 1.2.
+    fn var_from_resource(&self, res: PP_Resource) -> Option<Struct_PP_Var>;
+    /// A new reference to the resource in `var`. `None` if the browser's
+    /// `PPB_Var` is older than 1.2.
+    fn var_to_resource(&self, var: &Struct_PP_Var) -> Option<PP_Resource>;
 }
 impl VarIf for ffi::Struct_PPB_Var_1_2 {
     fn add_ref(&self, var: &Struct_PP_Var) {
@@ -344,6 +615,14 @@ impl VarIf for ffi::Struct_PPB_Var_1_2 {
         let slice = unsafe { from_utf8_unchecked(buf) };
         slice.to_string()
     }
+    fn var_from_resource(&self, res: PP_Resource) -> Option<Struct_PP_Var> {
+        if self.VarFromResource.is_none() { return None; }
+        Some(impl_fun!(self.VarFromResource => (res)))
+    }
+    fn var_to_resource(&self, var: &Struct_PP_Var) -> Option<PP_Resource> {
+        if self.VarToResource.is_none() { return None; }
+        Some(impl_fun!(self.VarToResource => (*var)))
+    }
 }
 pub trait ConsoleIf {
     fn log(&self, inst: PP_Instance, lvl: PP_LogLevel, msg: &Struct_PP_Var);
@@ -492,6 +771,12 @@ impl MessageLoopIf for ffi::Struct_PPB_MessageLoop_1_0 {
         impl_fun!(self.PostQuit => (*msg_loop, full.to_ffi_bool()))
     }
 }
+resource_interface!(impl for ffi::Struct_PPB_ImageData_1_0 => IsImageData);
+resource_interface_opt!(impl for ffi::Struct_PPB_ImageData_1_0 => IsImageData);
+resource_interface!(impl for ffi::Struct_PPB_Font_Dev_0_6 => IsFont);
+resource_interface_opt!(impl for ffi::Struct_PPB_Font_Dev_0_6 => IsFont);
+resource_interface!(impl for ffi::Struct_PPB_BrowserFont_Trusted_1_0 => IsFont);
+resource_interface_opt!(impl for ffi::Struct_PPB_BrowserFont_Trusted_1_0 => IsFont);
 pub trait ImageDataIf {
     fn native_image_data_format(&self) -> ffi::PP_ImageDataFormat;
     fn is_image_data_format_supported(&self, format: ffi::PP_ImageDataFormat) -> bool;
@@ -556,9 +841,12 @@ pub trait URLLoaderIf {
             loader: PP_Resource,
             request: PP_Resource,
             callback: ffi::Struct_PP_CompletionCallback) -> Code;
+    fn follow_redirect(&self, loader: PP_Resource,
+                       callback: ffi::Struct_PP_CompletionCallback) -> Code;
     fn get_response_info(&self, loader: PP_Resource) -> Option<PP_Resource>;
     fn read_response_body(&self, loader: PP_Resource, buffer: *mut libc::c_char, bytes: usize,
                           callback: ffi::Struct_PP_CompletionCallback) -> Code<usize>;
+    fn close(&self, loader: PP_Resource);
 }
 impl URLLoaderIf for ffi::Struct_PPB_URLLoader_1_0 {
     fn create(&self, instance: PP_Instance) -> Option<PP_Resource> {
@@ -570,6 +858,10 @@ impl URLLoaderIf for ffi::Struct_PPB_URLLoader_1_0 {
             callback: ffi::Struct_PP_CompletionCallback) -> Code {
         impl_fun!(self.Open => (loader, request, callback) -> Code)
     }
+    fn follow_redirect(&self, loader: PP_Resource,
+                       callback: ffi::Struct_PP_CompletionCallback) -> Code {
+        impl_fun!(self.FollowRedirect => (loader, callback) -> Code)
+    }
     fn get_response_info(&self, loader: PP_Resource) -> Option<PP_Resource> {
         impl_fun!(self.GetResponseInfo => (loader) -> Option<PP_Resource>)
     }
@@ -578,6 +870,9 @@ impl URLLoaderIf for ffi::Struct_PPB_URLLoader_1_0 {
         impl_fun!(self.ReadResponseBody => (loader, buffer as *mut _,
                                             bytes as libc::int32_t, callback) -> Code)
     }
+    fn close(&self, loader: PP_Resource) {
+        impl_fun!(self.Close => (loader))
+    }
 }
 pub trait URLRequestInfoIf {
     fn create(&self, instance: PP_Instance) -> Option<PP_Resource>;
@@ -865,14 +1160,83 @@ impl Graphics3DIf for ffi::Struct_PPB_Graphics3D_1_0 {
         Code::from_i32(impl_fun!(self.SwapBuffers => (ctxt, callback)))
     }
 }
+pub trait Graphics2DIf {
+    fn create(&self,
+              instance: PP_Instance,
+              size: ffi::PP_Size,
+              is_always_opaque: bool) -> Option<PP_Resource>;
+    fn describe(&self, ctxt: PP_Resource) -> Option<(ffi::PP_Size, bool)>;
+    fn paint_image_data(&self,
+                        ctxt: PP_Resource,
+                        image: PP_Resource,
+                        top_left: ffi::PP_Point,
+                        src_rect: Option<ffi::PP_Rect>);
+    fn replace_contents(&self, ctxt: PP_Resource, image: PP_Resource);
+    fn flush(&self,
+             ctxt: PP_Resource,
+             callback: ffi::Struct_PP_CompletionCallback) -> Code;
+}
+resource_interface!(impl for ffi::Struct_PPB_Graphics2D_1_1 => IsGraphics2D);
+resource_interface_opt!(impl for ffi::Struct_PPB_Graphics2D_1_1 => IsGraphics2D);
+impl Graphics2DIf for ffi::Struct_PPB_Graphics2D_1_1 {
+    fn create(&self,
+              instance: PP_Instance,
+              size: ffi::PP_Size,
+              is_always_opaque: bool) -> Option<PP_Resource> {
+        let res = impl_fun!(self.Create => (instance,
+                                            &size as *const ffi::PP_Size,
+                                            is_always_opaque.to_ffi_bool()));
+        if res != 0 {
+            Some(res)
+        } else {
+            None
+        }
+    }
+    fn describe(&self, ctxt: PP_Resource) -> Option<(ffi::PP_Size, bool)> {
+        let mut size = unsafe { uninitialized() };
+        let mut opaque = ffi::PP_FALSE;
+        if impl_fun!(self.Describe => (ctxt,
+                                       &mut size as *mut ffi::PP_Size,
+                                       &mut opaque as *mut ffi::PP_Bool)) != 0 {
+            Some((size, opaque != ffi::PP_FALSE))
+        } else {
+            None
+        }
+    }
+    fn paint_image_data(&self,
+                        ctxt: PP_Resource,
+                        image: PP_Resource,
+                        top_left: ffi::PP_Point,
+                        src_rect: Option<ffi::PP_Rect>) {
+        let src_rect_ptr = match src_rect {
+            Some(ref rect) => rect as *const ffi::PP_Rect,
+            None => ptr::null(),
+        };
+        impl_fun!(self.PaintImageData => (ctxt,
+                                          image,
+                                          &top_left as *const ffi::PP_Point,
+                                          src_rect_ptr))
+    }
+    fn replace_contents(&self, ctxt: PP_Resource, image: PP_Resource) {
+        impl_fun!(self.ReplaceContents => (ctxt, image))
+    }
+    fn flush(&self,
+             ctxt: PP_Resource,
+             callback: ffi::Struct_PP_CompletionCallback) -> Code {
+        Code::from_i32(impl_fun!(self.Flush => (ctxt, callback)))
+    }
+}
 pub trait ViewIf {
     fn rect(&self, res: PP_Resource) -> Option<ffi::Struct_PP_Rect>;
     fn is_fullscreen(&self, res: PP_Resource) -> bool;
     fn is_visible(&self, res: PP_Resource) -> bool;
     fn is_page_visible(&self, res: PP_Resource) -> bool;
     fn clip_rect(&self, res: PP_Resource) -> Option<ffi::Struct_PP_Rect>;
-    fn device_scale(&self, res: PP_Resource) -> f32;
-    fn css_scale(&self, res: PP_Resource) -> f32;
+    /// `None` if the browser's `PPB_View` is older than 1.1.
+    fn device_scale(&self, res: PP_Resource) -> Option<f32>;
+    /// `None` if the browser's `PPB_View` is older than 1.1.
+    fn css_scale(&self, res: PP_Resource) -> Option<f32>;
+    fn scroll_offset(&self, res: PP_Resource) -> Option<ffi::Struct_PP_Point>;
 }
 resource_interface!(impl for ffi::Struct_PPB_View_1_2 => IsView);
 resource_interface_opt!(impl for ffi::Struct_PPB_View_1_2 => IsView);
@@ -904,11 +1268,24 @@ impl ViewIf for ffi::Struct_PPB_View_1_2 {
             None
         }
     }
-    fn device_scale(&self, res: PP_Resource) -> f32 {
-        impl_fun!(self.GetDeviceScale => (res))
+    fn device_scale(&self, res: PP_Resource) -> Option<f32> {
+        if self.GetDeviceScale.is_none() { return None; }
+        Some(impl_fun!(self.GetDeviceScale => (res)))
     }
-    fn css_scale(&self, res: PP_Resource) -> f32 {
-        impl_fun!(self.GetCSSScale => (res))
+    fn css_scale(&self, res: PP_Resource) -> Option<f32> {
+        if self.GetCSSScale.is_none() { return None; }
+        Some(impl_fun!(self.GetCSSScale => (res)))
+    }
+    fn scroll_offset(&self, res: PP_Resource) -> Option<ffi::Struct_PP_Point> {
+        // Not in `PPB_View` 1.0 and 1.1.
+        if self.GetScrollOffset.is_none() { return None; }
+        let mut dest = unsafe { uninitialized() };
+        let ok = impl_fun!(self.GetScrollOffset => (res, &mut dest as *mut ffi::Struct_PP_Point));
+        if ok != 0 {
+            Some(dest)
+        } else {
+            None
+        }
     }
 }
 pub trait FileSystemIf {
@@ -1161,6 +1538,94 @@ impl VideoFrameIf for ffi::Struct_PPB_VideoFrame_0_1 {
     }
 }
 
+pub trait MediaStreamAudioTrackIf {
+    fn configure(&self, res: PP_Resource, attrs: &[ffi::PP_MediaStreamAudioTrack_Attrib],
+                 callback: ffi::Struct_PP_CompletionCallback) -> Code;
+    fn get_attrib(&self, res: PP_Resource, attr: ffi::PP_MediaStreamAudioTrack_Attrib) ->
+        Code<libc::int32_t>;
+    fn get_id(&self, res: PP_Resource) -> ffi::PP_Var;
+    fn has_ended(&self, res: PP_Resource) -> bool;
+    fn get_buffer(&self, res: PP_Resource, buffer: &mut PP_Resource,
+                  callback: ffi::Struct_PP_CompletionCallback) -> Code;
+    fn recycle_buffer(&self, res: PP_Resource, buffer: PP_Resource) -> Code;
+    fn close(&self, res: PP_Resource);
+}
+resource_interface!(impl for ffi::Struct_PPB_MediaStreamAudioTrack_0_1 => IsMediaStreamAudioTrack);
+resource_interface_opt!(impl for ffi::Struct_PPB_MediaStreamAudioTrack_0_1 => IsMediaStreamAudioTrack);
+impl MediaStreamAudioTrackIf for ffi::Struct_PPB_MediaStreamAudioTrack_0_1 {
+    fn configure(&self, res: PP_Resource, attrs: &[ffi::PP_MediaStreamAudioTrack_Attrib],
+                 callback: ffi::Struct_PP_CompletionCallback) -> Code {
+        debug_assert!(attrs.last() == Some(&ffi::PP_MEDIASTREAMAUDIOTRACK_ATTRIB_NONE));
+        let code = impl_fun!(self.Configure => (res, attrs.as_ptr() as *const i32, callback));
+        From::from(code)
+    }
+    fn get_attrib(&self, res: PP_Resource, attr: ffi::PP_MediaStreamAudioTrack_Attrib) ->
+        Code<libc::int32_t>
+    {
+        let mut dest: libc::int32_t = unsafe { ::std::mem::uninitialized() };
+        let code = impl_fun!(self.GetAttrib => (res, attr, &mut dest as *mut _));
+        let code: Code = From::from(code);
+        code.map_ok(|_| dest)
+    }
+    fn get_id(&self, res: PP_Resource) -> ffi::PP_Var {
+        impl_fun!(self.GetId => (res))
+    }
+    fn has_ended(&self, res: PP_Resource) -> bool {
+        (impl_fun!(self.HasEnded => (res))) != 0
+    }
+    fn get_buffer(&self, res: PP_Resource, buffer: &mut PP_Resource,
+                  callback: ffi::Struct_PP_CompletionCallback) -> Code {
+        let code = impl_fun!(self.GetBuffer => (res, buffer as *mut _, callback));
+        From::from(code)
+    }
+    fn recycle_buffer(&self, res: PP_Resource, buffer: PP_Resource) -> Code {
+        let code = impl_fun!(self.RecycleBuffer => (res, buffer));
+        From::from(code)
+    }
+    fn close(&self, res: PP_Resource) {
+        impl_fun!(self.Close => (res));
+    }
+}
+
+pub trait AudioBufferIf {
+    fn get_timestamp(&self, res: PP_Resource) -> ffi::PP_TimeDelta;
+    fn set_timestamp(&self, res: PP_Resource, ts: ffi::PP_TimeDelta);
+    fn get_sample_rate(&self, res: PP_Resource) -> ffi::PP_AudioBuffer_SampleRate;
+    fn get_sample_size(&self, res: PP_Resource) -> ffi::PP_AudioBuffer_SampleSize;
+    fn get_number_of_channels(&self, res: PP_Resource) -> u32;
+    fn get_number_of_samples(&self, res: PP_Resource) -> u32;
+    fn get_data_buffer(&self, res: PP_Resource) -> *const u8;
+    fn get_data_buffer_size(&self, res: PP_Resource) -> usize;
+}
+resource_interface!(impl for ffi::Struct_PPB_AudioBuffer_0_1 => IsAudioBuffer);
+resource_interface_opt!(impl for ffi::Struct_PPB_AudioBuffer_0_1 => IsAudioBuffer);
+impl AudioBufferIf for ffi::Struct_PPB_AudioBuffer_0_1 {
+    fn get_timestamp(&self, res: PP_Resource) -> ffi::PP_TimeDelta {
+        impl_fun!(self.GetTimestamp => (res))
+    }
+    fn set_timestamp(&self, res: PP_Resource, ts: ffi::PP_TimeDelta) {
+        impl_fun!(self.SetTimestamp => (res, ts))
+    }
+    fn get_sample_rate(&self, res: PP_Resource) -> ffi::PP_AudioBuffer_SampleRate {
+        impl_fun!(self.GetSampleRate => (res))
+    }
+    fn get_sample_size(&self, res: PP_Resource) -> ffi::PP_AudioBuffer_SampleSize {
+        impl_fun!(self.GetSampleSize => (res))
+    }
+    fn get_number_of_channels(&self, res: PP_Resource) -> u32 {
+        impl_fun!(self.GetNumberOfChannels => (res))
+    }
+    fn get_number_of_samples(&self, res: PP_Resource) -> u32 {
+        impl_fun!(self.GetNumberOfSamples => (res))
+    }
+    fn get_data_buffer(&self, res: PP_Resource) -> *const u8 {
+        (impl_fun!(self.GetDataBuffer => (res))) as *const _
+    }
+    fn get_data_buffer_size(&self, res: PP_Resource) -> usize {
+        (impl_fun!(self.GetDataBufferSize => (res))) as usize
+    }
+}
+
 pub trait VideoDecoderIf {
     fn create(&self, instance: PP_Instance) -> Option<PP_Resource>;
     fn initialize(&self, decoder: PP_Resource, g3d: PP_Resource, profile: ffi::PP_VideoProfile,
@@ -1205,6 +1670,241 @@ impl VideoDecoderIf for ffi::Struct_PPB_VideoDecoder_1_0 {
     }
 }
 
+pub trait VideoEncoderIf {
+    fn create(&self, instance: PP_Instance) -> Option<PP_Resource>;
+    fn get_supported_profiles(&self, encoder: PP_Resource, output: ffi::Struct_PP_ArrayOutput,
+                              callback: ffi::Struct_PP_CompletionCallback) -> Code;
+    fn initialize(&self, encoder: PP_Resource, input_format: ffi::PP_VideoFrame_Format,
+                  input_visible_size: &ffi::PP_Size, output_profile: ffi::PP_VideoProfile,
+                  initial_bitrate: u32, accel: ffi::PP_HardwareAcceleration,
+                  callback: ffi::Struct_PP_CompletionCallback) -> Code;
+    fn get_frames_required(&self, encoder: PP_Resource) -> Code;
+    fn get_frame_coded_size(&self, encoder: PP_Resource) -> Option<ffi::PP_Size>;
+    fn get_video_frame(&self, encoder: PP_Resource, frame: *mut PP_Resource,
+                       callback: ffi::Struct_PP_CompletionCallback) -> Code;
+    fn encode(&self, encoder: PP_Resource, frame: PP_Resource, force_keyframe: bool,
+              callback: ffi::Struct_PP_CompletionCallback) -> Code;
+    fn get_bitstream_buffer(&self, encoder: PP_Resource,
+                            buffer: *mut ffi::Struct_PP_BitstreamBuffer,
+                            callback: ffi::Struct_PP_CompletionCallback) -> Code;
+    fn recycle_bitstream_buffer(&self, encoder: PP_Resource,
+                                buffer: &ffi::Struct_PP_BitstreamBuffer);
+    fn request_encoding_parameters_change(&self, encoder: PP_Resource, bitrate: u32,
+                                          framerate: u32);
+    fn close(&self, encoder: PP_Resource);
+}
+resource_interface!(impl for ffi::Struct_PPB_VideoEncoder_0_2 => IsVideoEncoder);
+resource_interface_opt!(impl for ffi::Struct_PPB_VideoEncoder_0_2 => IsVideoEncoder);
+impl VideoEncoderIf for ffi::Struct_PPB_VideoEncoder_0_2 {
+    fn create(&self, instance: PP_Instance) -> Option<PP_Resource> {
+        impl_fun!(self.Create => (instance) -> Option<PP_Resource>)
+    }
+    fn get_supported_profiles(&self, encoder: PP_Resource, output: ffi::Struct_PP_ArrayOutput,
+                              callback: ffi::Struct_PP_CompletionCallback) -> Code {
+        impl_fun!(self.GetSupportedProfiles => (encoder, output, callback) -> Code)
+    }
+    fn initialize(&self, encoder: PP_Resource, input_format: ffi::PP_VideoFrame_Format,
+                  input_visible_size: &ffi::PP_Size, output_profile: ffi::PP_VideoProfile,
+                  initial_bitrate: u32, accel: ffi::PP_HardwareAcceleration,
+                  callback: ffi::Struct_PP_CompletionCallback) -> Code {
+        impl_fun!(self.Initialize => (encoder, input_format, input_visible_size as *const _,
+                                      output_profile, initial_bitrate, accel,
+                                      callback) -> Code)
+    }
+    fn get_frames_required(&self, encoder: PP_Resource) -> Code {
+        impl_fun!(self.GetFramesRequired => (encoder) -> Code)
+    }
+    fn get_frame_coded_size(&self, encoder: PP_Resource) -> Option<ffi::PP_Size> {
+        let mut size: ffi::PP_Size = Default::default();
+        let code: Code = impl_fun!(self.GetFrameCodedSize => (encoder, &mut size as *mut _) -> Code);
+        if code.is_ok() { Some(size) } else { None }
+    }
+    fn get_video_frame(&self, encoder: PP_Resource, frame: *mut PP_Resource,
+                       callback: ffi::Struct_PP_CompletionCallback) -> Code {
+        impl_fun!(self.GetVideoFrame => (encoder, frame, callback) -> Code)
+    }
+    fn encode(&self, encoder: PP_Resource, frame: PP_Resource, force_keyframe: bool,
+              callback: ffi::Struct_PP_CompletionCallback) -> Code {
+        impl_fun!(self.Encode => (encoder, frame, force_keyframe.to_ffi_bool(),
+                                  callback) -> Code)
+    }
+    fn get_bitstream_buffer(&self, encoder: PP_Resource,
+                            buffer: *mut ffi::Struct_PP_BitstreamBuffer,
+                            callback: ffi::Struct_PP_CompletionCallback) -> Code {
+        impl_fun!(self.GetBitstreamBuffer => (encoder, buffer, callback) -> Code)
+    }
+    fn recycle_bitstream_buffer(&self, encoder: PP_Resource,
+                                buffer: &ffi::Struct_PP_BitstreamBuffer) {
+        impl_fun!(self.RecycleBitstreamBuffer => (encoder, buffer as *const _))
+    }
+    fn request_encoding_parameters_change(&self, encoder: PP_Resource, bitrate: u32,
+                                          framerate: u32) {
+        impl_fun!(self.RequestEncodingParametersChange => (encoder, bitrate, framerate))
+    }
+    fn close(&self, encoder: PP_Resource) {
+        impl_fun!(self.Close => (encoder))
+    }
+}
+
+pub trait AudioConfigIf {
+    fn create_stereo_16bit(&self, instance: PP_Instance, sample_rate: ffi::PP_AudioSampleRate,
+                           sample_frame_count: u32) -> Option<PP_Resource>;
+    fn recommend_sample_frame_count(&self, instance: PP_Instance,
+                                    sample_rate: ffi::PP_AudioSampleRate,
+                                    requested: u32) -> u32;
+    fn get_sample_rate(&self, config: PP_Resource) -> ffi::PP_AudioSampleRate;
+    fn get_sample_frame_count(&self, config: PP_Resource) -> u32;
+    fn recommend_sample_rate(&self, instance: PP_Instance) -> ffi::PP_AudioSampleRate;
+}
+resource_interface!(impl for ffi::Struct_PPB_AudioConfig_1_1 => IsAudioConfig);
+resource_interface_opt!(impl for ffi::Struct_PPB_AudioConfig_1_1 => IsAudioConfig);
+impl AudioConfigIf for ffi::Struct_PPB_AudioConfig_1_1 {
+    fn create_stereo_16bit(&self, instance: PP_Instance, sample_rate: ffi::PP_AudioSampleRate,
+                           sample_frame_count: u32) -> Option<PP_Resource> {
+        impl_fun!(self.CreateStereo16Bit => (instance, sample_rate,
+                                             sample_frame_count) -> Option<PP_Resource>)
+    }
+    fn recommend_sample_frame_count(&self, instance: PP_Instance,
+                                    sample_rate: ffi::PP_AudioSampleRate,
+                                    requested: u32) -> u32 {
+        impl_fun!(self.RecommendSampleFrameCount => (instance, sample_rate, requested))
+    }
+    fn get_sample_rate(&self, config: PP_Resource) -> ffi::PP_AudioSampleRate {
+        impl_fun!(self.GetSampleRate => (config))
+    }
+    fn get_sample_frame_count(&self, config: PP_Resource) -> u32 {
+        impl_fun!(self.GetSampleFrameCount => (config))
+    }
+    fn recommend_sample_rate(&self, instance: PP_Instance) -> ffi::PP_AudioSampleRate {
+        impl_fun!(self.RecommendSampleRate => (instance))
+    }
+}
+
+pub trait AudioIf {
+    /// The versions differ only in the callback's signature.
+    type Callback;
+    fn create(&self, instance: PP_Instance, config: PP_Resource,
+              callback: Self::Callback,
+              user_data: *mut libc::c_void) -> Option<PP_Resource>;
+    fn get_current_config(&self, audio: PP_Resource) -> Option<PP_Resource>;
+    fn start_playback(&self, audio: PP_Resource) -> bool;
+    fn stop_playback(&self, audio: PP_Resource) -> bool;
+}
+resource_interface!(impl for ffi::Struct_PPB_Audio_1_1 => IsAudio);
+resource_interface_opt!(impl for ffi::Struct_PPB_Audio_1_1 => IsAudio);
+impl AudioIf for ffi::Struct_PPB_Audio_1_1 {
+    type Callback = ffi::PPB_Audio_Callback;
+    fn create(&self, instance: PP_Instance, config: PP_Resource,
+              callback: ffi::PPB_Audio_Callback,
+              user_data: *mut libc::c_void) -> Option<PP_Resource> {
+        impl_fun!(self.Create => (instance, config, callback, user_data) -> Option<PP_Resource>)
+    }
+    fn get_current_config(&self, audio: PP_Resource) -> Option<PP_Resource> {
+        impl_fun!(self.GetCurrentConfig => (audio) -> Option<PP_Resource>)
+    }
+    fn start_playback(&self, audio: PP_Resource) -> bool {
+        impl_fun!(self.StartPlayback => (audio)) != ffi::PP_FALSE
+    }
+    fn stop_playback(&self, audio: PP_Resource) -> bool {
+        impl_fun!(self.StopPlayback => (audio)) != ffi::PP_FALSE
+    }
+}
+resource_interface_opt!(impl for ffi::Struct_PPB_Audio_1_0 => IsAudio);
+impl AudioIf for ffi::Struct_PPB_Audio_1_0 {
+    type Callback = ffi::PPB_Audio_Callback_1_0;
+    fn create(&self, instance: PP_Instance, config: PP_Resource,
+              callback: ffi::PPB_Audio_Callback_1_0,
+              user_data: *mut libc::c_void) -> Option<PP_Resource> {
+        impl_fun!(self.Create => (instance, config, callback, user_data) -> Option<PP_Resource>)
+    }
+    fn get_current_config(&self, audio: PP_Resource) -> Option<PP_Resource> {
+        impl_fun!(self.GetCurrentConfig => (audio) -> Option<PP_Resource>)
+    }
+    fn start_playback(&self, audio: PP_Resource) -> bool {
+        impl_fun!(self.StartPlayback => (audio)) != ffi::PP_FALSE
+    }
+    fn stop_playback(&self, audio: PP_Resource) -> bool {
+        impl_fun!(self.StopPlayback => (audio)) != ffi::PP_FALSE
+    }
+}
+
+pub trait NetworkProxyIf {
+    fn get_proxy_for_url(&self, instance: PP_Instance, url: Struct_PP_Var,
+                         proxy_string: *mut Struct_PP_Var,
+                         callback: ffi::Struct_PP_CompletionCallback) -> Code;
+}
+impl NetworkProxyIf for ffi::Struct_PPB_NetworkProxy_1_0 {
+    fn get_proxy_for_url(&self, instance: PP_Instance, url: Struct_PP_Var,
+                         proxy_string: *mut Struct_PP_Var,
+                         callback: ffi::Struct_PP_CompletionCallback) -> Code {
+        impl_fun!(self.GetProxyForURL => (instance, url, proxy_string, callback) -> Code)
+    }
+}
+
+pub trait FullscreenIf {
+    fn is_fullscreen(&self, instance: PP_Instance) -> bool;
+    fn set_fullscreen(&self, instance: PP_Instance, fullscreen: bool) -> bool;
+}
+impl FullscreenIf for ffi::Struct_PPB_Fullscreen_1_0 {
+    fn is_fullscreen(&self, instance: PP_Instance) -> bool {
+        (impl_fun!(self.IsFullscreen => (instance))) != ffi::PP_FALSE
+    }
+    fn set_fullscreen(&self, instance: PP_Instance, fullscreen: bool) -> bool {
+        (impl_fun!(self.SetFullscreen => (instance, fullscreen.to_ffi_bool()))) != ffi::PP_FALSE
+    }
+}
+
+pub trait PrintingIf {
+    fn create(&self, instance: PP_Instance) -> Option<PP_Resource>;
+    fn get_default_print_settings(&self, res: PP_Resource,
+                                  settings: &mut ffi::Struct_PP_PrintSettings_Dev,
+                                  callback: ffi::Struct_PP_CompletionCallback) -> Code;
+}
+impl PrintingIf for ffi::Struct_PPB_Printing_Dev_0_7 {
+    fn create(&self, instance: PP_Instance) -> Option<PP_Resource> {
+        impl_fun!(self.Create => (instance) -> Option<PP_Resource>)
+    }
+    fn get_default_print_settings(&self, res: PP_Resource,
+                                  settings: &mut ffi::Struct_PP_PrintSettings_Dev,
+                                  callback: ffi::Struct_PP_CompletionCallback) -> Code {
+        impl_fun!(self.GetDefaultPrintSettings => (res, settings as *mut _, callback) -> Code)
+    }
+}
+
+pub trait UmaPrivateIf {
+    fn histogram_custom_times(&self, instance: PP_Instance, name: Struct_PP_Var,
+                              sample: i64, min: i64, max: i64, bucket_count: u32);
+    fn histogram_custom_counts(&self, instance: PP_Instance, name: Struct_PP_Var,
+                               sample: i32, min: i32, max: i32, bucket_count: u32);
+    fn histogram_enumeration(&self, instance: PP_Instance, name: Struct_PP_Var,
+                             sample: i32, boundary_value: i32);
+}
+impl UmaPrivateIf for ffi::Struct_PPB_UMA_Private_3_0 {
+    fn histogram_custom_times(&self, instance: PP_Instance, name: Struct_PP_Var,
+                              sample: i64, min: i64, max: i64, bucket_count: u32) {
+        impl_fun!(self.HistogramCustomTimes => (instance, name, sample, min, max, bucket_count))
+    }
+    fn histogram_custom_counts(&self, instance: PP_Instance, name: Struct_PP_Var,
+                               sample: i32, min: i32, max: i32, bucket_count: u32) {
+        impl_fun!(self.HistogramCustomCounts => (instance, name, sample, min, max, bucket_count))
+    }
+    fn histogram_enumeration(&self, instance: PP_Instance, name: Struct_PP_Var,
+                             sample: i32, boundary_value: i32) {
+        impl_fun!(self.HistogramEnumeration => (instance, name, sample, boundary_value))
+    }
+}
+
+pub trait IsolatedFileSystemIf {
+    fn open(&self, instance: PP_Instance, kind: ffi::PP_IsolatedFileSystemType_Private,
+            file_system: *mut PP_Resource, callback: ffi::Struct_PP_CompletionCallback) -> Code;
+}
+impl IsolatedFileSystemIf for ffi::Struct_PPB_IsolatedFileSystem_Private_0_2 {
+    fn open(&self, instance: PP_Instance, kind: ffi::PP_IsolatedFileSystemType_Private,
+            file_system: *mut PP_Resource, callback: ffi::Struct_PP_CompletionCallback) -> Code {
+        impl_fun!(self.Open => (instance, kind, file_system, callback) -> Code)
+    }
+}
+
 pub trait ConsoleInterface {
     fn log<T: ToVar>(&self, lvl: ffi::PP_LogLevel, value: T) {
         self.log_to_browser(lvl, value.to_var());
@@ -1214,12 +1914,14 @@ pub trait ConsoleInterface {
 }
 impl ConsoleInterface for super::Console {
     fn log_to_browser(&self, lvl: ffi::PP_LogLevel, value: ffi::PP_Var) {
+        if !super::console_rate_allows(self.unwrap(), lvl) { return; }
         (get_console().Log.unwrap())(self.unwrap(), lvl, value)
     }
     fn log_with_source<ST: ToVar, VT: ToVar>(&self,
                                              lvl: ffi::PP_LogLevel,
                                              source: ST,
                                              value: VT) {
+        if !super::console_rate_allows(self.unwrap(), lvl) { return; }
         (get_console().LogWithSource.unwrap())(self.unwrap(),
                                                lvl,
                                                source.to_var(),
@@ -9,13 +9,19 @@
 use std::ptr;
 use libc::{c_char, c_void};
 
+use printing;
+
 mod consts {
     pub static INSTANCE: &'static [u8] = b"PPP_Instance;1.1\0";
     pub static INPUTEVENT: &'static [u8] = b"PPP_InputEvent;0.1\0";
     pub static GRAPHICS: &'static [u8] = b"PPP_Graphics_3D;1.0\0";
+    pub static PRINTING: &'static [u8] = b"PPP_Printing(Dev);0.6\0";
+    pub static MESSAGING: &'static [u8] = b"PPP_Messaging;1.0\0";
+    pub static MOUSELOCK: &'static [u8] = b"PPP_MouseLock;1.0\0";
 }
 mod globals {
     use entry;
+    use printing::entry as printing;
     use ffi;
     pub const INSTANCE: ffi::Struct_PPP_Instance_1_1 = ffi::Struct_PPP_Instance_1_1 {
         DidCreate: Some(entry::did_create as extern "C" fn(i32, u32, *mut *const i8, *mut *const i8) -> u32),
@@ -30,6 +36,19 @@ mod globals {
     pub static GRAPHICS: ffi::Struct_PPP_Graphics3D_1_0 = ffi::Struct_PPP_Graphics3D_1_0 {
         Graphics3DContextLost: Some(entry::graphics_context_lost as extern "C" fn(i32)),
     };
+    pub static MESSAGING: ffi::Struct_PPP_Messaging_1_0 = ffi::Struct_PPP_Messaging_1_0 {
+        HandleMessage: Some(entry::handle_message as extern "C" fn(i32, ffi::Struct_PP_Var)),
+    };
+    pub static MOUSELOCK: ffi::Struct_PPP_MouseLock_1_0 = ffi::Struct_PPP_MouseLock_1_0 {
+        MouseLockLost: Some(entry::mouse_lock_lost as extern "C" fn(i32)),
+    };
+    pub static PRINTING: ffi::Struct_PPP_Printing_Dev_0_6 = ffi::Struct_PPP_Printing_Dev_0_6 {
+        QuerySupportedFormats: Some(printing::query_supported_formats as extern "C" fn(i32) -> u32),
+        Begin: Some(printing::begin as extern "C" fn(i32, *const ffi::Struct_PP_PrintSettings_Dev) -> i32),
+        PrintPages: Some(printing::print_pages as extern "C" fn(i32, *const ffi::Struct_PP_PrintPageNumberRange_Dev, u32) -> i32),
+        End: Some(printing::end as extern "C" fn(i32)),
+        IsScalingDisabled: Some(printing::is_scaling_disabled as extern "C" fn(i32) -> u32),
+    };
 }
 
 #[no_mangle]
@@ -49,6 +68,17 @@ pub extern "C" fn PPP_GetInterface(name: *const c_char) -> *const c_void {
             transmute(&globals::INPUTEVENT)
         } else if strcmp(name, consts::GRAPHICS.as_ptr() as *const _) == 0 {
             transmute(&globals::GRAPHICS)
+        } else if strcmp(name, consts::MESSAGING.as_ptr() as *const _) == 0 {
+            transmute(&globals::MESSAGING)
+        } else if strcmp(name, consts::MOUSELOCK.as_ptr() as *const _) == 0 {
+            transmute(&globals::MOUSELOCK)
+        } else if strcmp(name, consts::PRINTING.as_ptr() as *const _) == 0 {
+            // Without a handler the browser prints the page itself.
+            if printing::has_print_handlers() {
+                transmute(&globals::PRINTING)
+            } else {
+                ptr::null()
+            }
         } else {
             ptr::null()
         }
@@ -0,0 +1,339 @@
+// Copyright (c) 2014 Richard Diamond & contributors.
+//
+// This file is part of the Rust PPApi project.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Printing, via `PPB_Printing_Dev` and `PPP_Printing_Dev`, eg for document
+//! viewers.
+//!
+//! Printing is opt in: until a `PrintHandler` is set for an instance with
+//! `Instance::set_print_handler`, the plugin doesn't tell the browser it can
+//! print, and the browser prints the page as usual. The browser asks once,
+//! so set the handler in `ppapi_instance_created`.
+
+use std::collections::HashMap;
+use std::mem;
+use std::sync::{Mutex, Once, ONCE_INIT};
+
+use ffi;
+use imagedata::ImageData;
+use ppb::{get_printing_opt, PrintingIf};
+
+use super::{Code, CallbackArgs, StorageToArgsMapper, InPlaceInit, Instance,
+            GenericResource, Rect, Resource, Size};
+
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum Orientation {
+    Normal,
+    Rotated90Cw,
+    Rotated180,
+    Rotated90Ccw,
+}
+impl Orientation {
+    fn from_ffi(v: ffi::PP_PrintOrientation_Dev) -> Orientation {
+        match v {
+            ffi::PP_PRINTORIENTATION_ROTATED_90_CW => Orientation::Rotated90Cw,
+            ffi::PP_PRINTORIENTATION_ROTATED_180 => Orientation::Rotated180,
+            ffi::PP_PRINTORIENTATION_ROTATED_90_CCW => Orientation::Rotated90Ccw,
+            _ => Orientation::Normal,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum ScalingOption {
+    None,
+    FitToPrintableArea,
+    SourceSize,
+}
+impl ScalingOption {
+    fn from_ffi(v: ffi::PP_PrintScalingOption_Dev) -> ScalingOption {
+        match v {
+            ffi::PP_PRINTSCALINGOPTION_FIT_TO_PRINTABLE_AREA => ScalingOption::FitToPrintableArea,
+            ffi::PP_PRINTSCALINGOPTION_SOURCE_SIZE => ScalingOption::SourceSize,
+            _ => ScalingOption::None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum OutputFormat {
+    /// Pages are given as `ImageData`.
+    Raster,
+    /// Pages are given as a buffer resource holding a PDF.
+    Pdf,
+    PostScript,
+    Emf,
+}
+impl OutputFormat {
+    fn to_ffi(&self) -> ffi::PP_PrintOutputFormat_Dev {
+        match self {
+            &OutputFormat::Raster => ffi::PP_PRINTOUTPUTFORMAT_RASTER,
+            &OutputFormat::Pdf => ffi::PP_PRINTOUTPUTFORMAT_PDF,
+            &OutputFormat::PostScript => ffi::PP_PRINTOUTPUTFORMAT_POSTSCRIPT,
+            &OutputFormat::Emf => ffi::PP_PRINTOUTPUTFORMAT_EMF,
+        }
+    }
+    fn from_ffi(v: ffi::PP_PrintOutputFormat_Dev) -> Option<OutputFormat> {
+        match v {
+            ffi::PP_PRINTOUTPUTFORMAT_RASTER => Some(OutputFormat::Raster),
+            ffi::PP_PRINTOUTPUTFORMAT_PDF => Some(OutputFormat::Pdf),
+            ffi::PP_PRINTOUTPUTFORMAT_POSTSCRIPT => Some(OutputFormat::PostScript),
+            ffi::PP_PRINTOUTPUTFORMAT_EMF => Some(OutputFormat::Emf),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct PrintSettings {
+    pub printable_area: Rect,
+    pub content_area: Rect,
+    pub paper_size: Size,
+    pub dpi: i32,
+    pub orientation: Orientation,
+    pub scaling: ScalingOption,
+    pub grayscale: bool,
+    /// `None` if the browser asked for a format we don't know.
+    pub format: Option<OutputFormat>,
+}
+impl PrintSettings {
+    fn from_ffi(raw: &ffi::Struct_PP_PrintSettings_Dev) -> PrintSettings {
+        PrintSettings {
+            printable_area: From::from(raw.printable_area),
+            content_area: From::from(raw.content_area),
+            paper_size: From::from(raw.paper_size),
+            dpi: raw.dpi,
+            orientation: Orientation::from_ffi(raw.orientation),
+            scaling: ScalingOption::from_ffi(raw.print_scaling_option),
+            grayscale: raw.grayscale != ffi::PP_FALSE,
+            format: OutputFormat::from_ffi(raw.format),
+        }
+    }
+}
+
+/// Pages `first` through `last`, inclusive, counting from zero.
+#[repr(C)]
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub struct PageRange {
+    pub first: u32,
+    pub last: u32,
+}
+
+/// Rendered pages, in the format the print job was begun with.
+pub enum PrintOutput {
+    Raster(ImageData),
+    /// A `PPB_Buffer_Dev` holding the PDF.
+    Pdf(GenericResource),
+}
+
+/// Implemented by plugins which can print themselves. Called on the
+/// instance's thread; the browser waits for each call to return, for up to
+/// `PRINT_HANDLER_TIMEOUT_MS`.
+pub trait PrintHandler {
+    /// The formats `print_pages` can produce.
+    fn query_supported_formats(&mut self) -> Vec<OutputFormat>;
+    /// Starts a print job. Returns the number of pages, or zero to cancel.
+    fn begin(&mut self, settings: &PrintSettings) -> u32;
+    /// Renders the pages in `ranges`. `None` if they couldn't be rendered.
+    fn print_pages(&mut self, ranges: &[PageRange]) -> Option<PrintOutput>;
+    /// Ends the print job started by `begin`.
+    fn end(&mut self);
+    /// Whether the browser should leave the output unscaled.
+    fn is_scaling_disabled(&mut self) -> bool { false }
+}
+
+/// How long, in milliseconds, the browser's main thread waits for a
+/// `PrintHandler` call. A call which takes longer is treated as if the
+/// instance had no handler; it still runs to completion, and its result is
+/// dropped.
+pub const PRINT_HANDLER_TIMEOUT_MS: u64 = 5000;
+
+type Handlers = Mutex<HashMap<ffi::PP_Instance, Option<Box<PrintHandler + Send>>>>;
+// Each instance's handler, or `None` while it's running. Handlers may be set
+// from any thread, and are removed on the main thread when the instance is
+// destroyed, so this isn't thread local.
+static HANDLERS_INIT: Once = ONCE_INIT;
+static mut HANDLERS: *const Handlers = 0 as *const Handlers;
+fn handlers() -> &'static Handlers {
+    HANDLERS_INIT.call_once(|| unsafe {
+        HANDLERS = mem::transmute(Box::new(Mutex::new(HashMap::new())));
+    });
+    unsafe { &*HANDLERS }
+}
+
+/// Sets the print handler of `instance`, replacing any previous one.
+pub fn set_print_handler<H>(instance: &Instance, handler: H)
+    where H: PrintHandler + Send + 'static
+{
+    handlers().lock().unwrap().insert(instance.unwrap(), Some(box handler));
+}
+/// Removes `instance`'s print handler, returning true if it had one.
+pub fn clear_print_handler(instance: &Instance) -> bool {
+    handlers().lock().unwrap().remove(&instance.unwrap()).is_some()
+}
+
+#[doc(hidden)]
+pub fn has_print_handlers() -> bool {
+    !handlers().lock().unwrap().is_empty()
+}
+
+/// Calls `f` with `instance`'s handler, if any. The handler is taken out of
+/// the map while it runs, so it can replace or clear itself; it's only put
+/// back if it did neither.
+fn with_handler<F, T>(instance: ffi::PP_Instance, f: F) -> Option<T>
+    where F: FnOnce(&mut PrintHandler) -> T,
+{
+    let handler = match handlers().lock().unwrap().get_mut(&instance) {
+        Some(slot) => slot.take(),
+        None => None,
+    };
+    let mut handler = match handler {
+        Some(handler) => handler,
+        None => {
+            debug!("print request for an instance without a print handler");
+            return None;
+        }
+    };
+    let ret = f(&mut *handler);
+    if let Some(slot) = handlers().lock().unwrap().get_mut(&instance) {
+        if slot.is_none() {
+            *slot = Some(handler);
+        }
+    }
+    Some(ret)
+}
+
+struct RawSettings(GenericResource, ffi::Struct_PP_PrintSettings_Dev);
+impl InPlaceInit for RawSettings { }
+
+/// Gets the browser's default print settings. Returns `Code::NoInterface`
+/// if the browser doesn't provide `PPB_Printing_Dev`.
+pub fn get_print_settings<F>(instance: &Instance,
+                             callback: CallbackArgs<F, PrintSettings>) -> Code<PrintSettings>
+    where F: FnOnce(Code<PrintSettings>)
+{
+    fn map(raw: RawSettings, _status: Code) -> PrintSettings {
+        PrintSettings::from_ffi(&raw.1)
+    }
+
+    let interface = match get_printing_opt() {
+        Some(interface) => interface,
+        None => return Code::NoInterface,
+    };
+    let res = match interface.create(instance.unwrap()) {
+        Some(res) => res,
+        None => return Code::BadInstance,
+    };
+    // Keeps the printing resource alive until the callback.
+    let raw_args = RawSettings(From::from(res), Default::default());
+    let mut cc = callback.to_ffi_callback(raw_args, StorageToArgsMapper(map));
+    let fficc = cc.cc();
+    let code = interface.get_default_print_settings(res, &mut cc.1, fficc);
+    cc.drop_with_code(code)
+}
+
+pub mod entry {
+    use std::mem;
+    use std::slice;
+    use std::sync::mpsc::{channel, TryRecvError};
+    use std::thread;
+
+    use ffi;
+    use super::{with_handler, PageRange, PrintHandler, PrintOutput, PrintSettings,
+                PRINT_HANDLER_TIMEOUT_MS};
+    use super::super::{find_instance, is_main_thread, now_ticks, Instance, Resource,
+                       ToFFIBool};
+
+    /// Runs `f` with `inst`'s handler on the instance's thread, and waits for
+    /// it. `None` if the instance has no handler, it panicked, or it didn't
+    /// return within `PRINT_HANDLER_TIMEOUT_MS`.
+    fn call_handler<F, T>(inst: ffi::PP_Instance, f: F) -> Option<T>
+        where F: FnOnce(&mut PrintHandler) -> T + Send + 'static, T: Send + 'static,
+    {
+        // Already on the instance's thread, eg when called directly. Posting
+        // and waiting would never return.
+        if !is_main_thread() && Instance::opt_current() == Some(Instance::new(inst)) {
+            return with_handler(inst, f);
+        }
+
+        let (tx, rx) = channel();
+        let posted = find_instance(Instance::new(inst), f, move |msg_loop, f| {
+            msg_loop.post_work(move |_| {
+                let _ = tx.send(with_handler(inst, f));
+            }, 0)
+        });
+        match posted {
+            Some(code) if code.is_ok() => {}
+            _ => return None,
+        }
+
+        // This blocks the browser's main thread, so don't wait on a wedged
+        // handler forever.
+        let deadline = now_ticks() + PRINT_HANDLER_TIMEOUT_MS as f64 / 1000.0;
+        loop {
+            match rx.try_recv() {
+                Ok(ret) => return ret,
+                Err(TryRecvError::Disconnected) => return None,
+                Err(TryRecvError::Empty) => {}
+            }
+            if now_ticks() >= deadline {
+                warn!("print handler didn't return within {}ms; giving up on it",
+                      PRINT_HANDLER_TIMEOUT_MS);
+                return None;
+            }
+            thread::sleep_ms(1);
+        }
+    }
+
+    pub extern "C" fn query_supported_formats(inst: ffi::PP_Instance) -> u32 {
+        call_handler(inst, |h| {
+            h.query_supported_formats()
+                .iter()
+                .fold(0, |formats, format| formats | format.to_ffi() )
+        }).unwrap_or(0)
+    }
+    pub extern "C" fn begin(inst: ffi::PP_Instance,
+                            settings: *const ffi::Struct_PP_PrintSettings_Dev) -> i32 {
+        if settings.is_null() { return 0; }
+        let settings = PrintSettings::from_ffi(unsafe { &*settings });
+        call_handler(inst, move |h| h.begin(&settings) as i32 ).unwrap_or(0)
+    }
+    pub extern "C" fn print_pages(inst: ffi::PP_Instance,
+                                  ranges: *const ffi::Struct_PP_PrintPageNumberRange_Dev,
+                                  count: u32) -> ffi::PP_Resource {
+        let ranges: Vec<PageRange> = if ranges.is_null() || count == 0 {
+            Vec::new()
+        } else {
+            // `PageRange` has the same layout.
+            unsafe { slice::from_raw_parts(ranges as *const PageRange, count as usize) }
+                .to_vec()
+        };
+        let output = call_handler(inst, move |h| h.print_pages(&ranges[..]) )
+            .and_then(|output| output );
+        // The browser takes our reference.
+        match output {
+            Some(PrintOutput::Raster(image)) => {
+                let res = image.unwrap();
+                mem::forget(image);
+                track_resource!(ImageData, -1);
+                res
+            }
+            Some(PrintOutput::Pdf(buffer)) => {
+                let res = buffer.unwrap();
+                mem::forget(buffer);
+                res
+            }
+            None => 0,
+        }
+    }
+    pub extern "C" fn end(inst: ffi::PP_Instance) {
+        call_handler(inst, |h| h.end() );
+    }
+    pub extern "C" fn is_scaling_disabled(inst: ffi::PP_Instance) -> ffi::PP_Bool {
+        call_handler(inst, |h| h.is_scaling_disabled() ).unwrap_or(false).to_ffi_bool()
+    }
+}
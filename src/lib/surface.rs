@@ -0,0 +1,241 @@
+// Copyright (c) 2014 Richard Diamond & contributors.
+//
+// This file is part of the Rust PPApi project.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! `Surface2d`, a bound 2D context which follows the size of the view
+//! without reallocating on every step of a window drag, and which only
+//! repaints the parts of it marked dirty.
+
+use std::cell::RefCell;
+use std::cmp::min;
+use std::rc::Rc;
+
+use imagedata::ImageData;
+
+use super::{Code, CallbackArgs, Context2d, Instance, MessageLoop, Point, Rect, Size, View};
+
+/// How long, in milliseconds, a surface waits for resizes to settle before
+/// reallocating. About one frame.
+pub const RESIZE_SETTLE_MS: u64 = 17;
+
+struct State {
+    instance: Instance,
+    is_always_opaque: bool,
+    context: Context2d,
+    size: Size,
+    pending: Option<Size>,
+    /// Bumped by every resize, so only the newest timer reallocates.
+    generation: u64,
+    reallocations: usize,
+    resize_error: Option<Code>,
+    /// Disjoint regions to paint on the next `present`.
+    dirty: Vec<Rect>,
+}
+
+/// `rect` cut down to fit in `size`, or `None` if nothing is left.
+fn clip(rect: Rect, size: Size) -> Option<Rect> {
+    if rect.point.x >= size.width || rect.point.y >= size.height {
+        return None;
+    }
+    let width = min(rect.size.width, size.width - rect.point.x);
+    let height = min(rect.size.height, size.height - rect.point.y);
+    if width == 0 || height == 0 {
+        None
+    } else {
+        Some(Rect::new(rect.point, Size::new(width, height)))
+    }
+}
+
+/// A 2D context bound to an instance which is recreated when the surface is
+/// resized. Resizes are coalesced: the context is only recreated once no new
+/// resize has arrived for `RESIZE_SETTLE_MS`, and then at the newest size.
+///
+/// Until then `context` keeps returning the old context, so paints made in
+/// the meantime go to the old buffer, clipped to its size. Surfaces must be
+/// used from a thread with a message loop; without one, resizes reallocate
+/// immediately.
+///
+/// `present` only paints the regions marked with `mark_dirty`. The whole
+/// surface starts out dirty, and is again after being reallocated.
+#[derive(Clone)]
+pub struct Surface2d(Rc<RefCell<State>>);
+
+impl Surface2d {
+    /// Creates a context of `size` and binds it to `instance`.
+    pub fn new(instance: &Instance, size: Size,
+               is_always_opaque: bool) -> Result<Surface2d, Code> {
+        let context = try!(instance.create_2d_context(size, is_always_opaque));
+        let code = instance.bind_context(&context);
+        if !code.is_ok() { return Err(code.map_err()); }
+
+        Ok(Surface2d(Rc::new(RefCell::new(State {
+            instance: *instance,
+            is_always_opaque: is_always_opaque,
+            context: context,
+            size: size,
+            pending: None,
+            generation: 0,
+            reallocations: 0,
+            resize_error: None,
+            // Nothing has been painted yet.
+            dirty: vec![Rect::new(Point::new(0, 0), size)],
+        }))))
+    }
+
+    /// The currently bound context. Don't hold on to it across a resize.
+    pub fn context(&self) -> Context2d {
+        self.0.borrow().context.clone()
+    }
+    /// The size of `context`, which lags behind `resize` until it settles.
+    pub fn size(&self) -> Size {
+        self.0.borrow().size
+    }
+    /// The size the surface will be reallocated to, if a resize is waiting
+    /// to settle.
+    pub fn pending_size(&self) -> Option<Size> {
+        self.0.borrow().pending
+    }
+    /// How many times the context has been recreated.
+    pub fn reallocations(&self) -> usize {
+        self.0.borrow().reallocations
+    }
+    /// Why the last reallocation failed, if it did. The surface then keeps
+    /// its old context and size, and the resize is dropped; the next
+    /// successful reallocation clears this.
+    pub fn resize_error(&self) -> Option<Code> {
+        self.0.borrow().resize_error
+    }
+
+    /// Regions which will be painted by the next `present`.
+    pub fn dirty_rects(&self) -> Vec<Rect> {
+        self.0.borrow().dirty.clone()
+    }
+    /// Marks `rect` as needing to be repainted. Overlapping dirty regions are
+    /// merged into their bounding rect; the part outside the surface is
+    /// ignored.
+    pub fn mark_dirty(&self, rect: Rect) {
+        let mut state = self.0.borrow_mut();
+        let mut rect = match clip(rect, state.size) {
+            Some(rect) => rect,
+            None => return,
+        };
+        // Merging can make the result overlap rects it didn't before.
+        loop {
+            match state.dirty.iter().position(|dirty| dirty.intersects(&rect) ) {
+                Some(i) => {
+                    let merged = state.dirty.swap_remove(i);
+                    // Both are within the surface, so this can't overflow.
+                    rect = merged.checked_union(&rect).unwrap();
+                }
+                None => break,
+            }
+        }
+        state.dirty.push(rect);
+    }
+    /// Paints the dirty regions of `image` to the context at the same
+    /// position, and flushes. The image should be the size of the surface.
+    ///
+    /// If nothing is dirty nothing is painted or flushed, but `callback` is
+    /// still called, as if a flush had completed. On error the regions stay
+    /// dirty.
+    pub fn present<F>(&self, image: &ImageData, callback: CallbackArgs<F, ()>) -> Code<()>
+        where F: FnOnce(Code<()>) + 'static,
+    {
+        let (context, dirty) = {
+            let state = self.0.borrow();
+            (state.context.clone(), state.dirty.clone())
+        };
+
+        if dirty.is_empty() {
+            if callback.optional { return Code::Ok(()); }
+            let posted = MessageLoop::post_to_self(move |_| {
+                callback.call_directly(Code::Ok(()));
+            }, 0);
+            return if posted.is_ok() {
+                Code::CompletionPending
+            } else {
+                posted.map_err()
+            };
+        }
+
+        for rect in dirty.iter() {
+            try_code!(context.paint_image_data(image, Point::new(0, 0), Some(*rect)));
+        }
+        self.0.borrow_mut().dirty.clear();
+        context.flush(callback)
+    }
+
+    /// Asks for the surface to be `size`. See the type docs for when this
+    /// takes effect.
+    pub fn resize(&self, size: Size) {
+        let generation = {
+            let mut state = self.0.borrow_mut();
+            state.generation += 1;
+            if state.size == size {
+                // Back where we started; nothing to reallocate.
+                state.pending = None;
+                return;
+            }
+            state.pending = Some(size);
+            state.generation
+        };
+
+        let this = self.clone();
+        let code = MessageLoop::post_to_self(move |_| this.settle(generation),
+                                             RESIZE_SETTLE_MS);
+        if !code.is_ok() {
+            self.settle(generation);
+        }
+    }
+    /// Resizes to the size of `view`'s rect, eg from `ppapi_on_change_view`.
+    pub fn resize_to_view(&self, view: &View) {
+        if let Some(rect) = view.rect() {
+            self.resize(rect.size);
+        }
+    }
+
+    fn settle(&self, generation: u64) {
+        let (instance, size, is_always_opaque) = {
+            let state = self.0.borrow();
+            match state.pending {
+                Some(size) if state.generation == generation =>
+                    (state.instance, size, state.is_always_opaque),
+                // Superseded by a newer resize, which has its own timer.
+                _ => return,
+            }
+        };
+
+        let context = match instance.create_2d_context(size, is_always_opaque) {
+            Ok(context) => context,
+            Err(code) => {
+                error!("couldn't reallocate a {}x{} surface: {:?}",
+                       size.width, size.height, code);
+                return self.fail_resize(code);
+            }
+        };
+        let code = instance.bind_context(&context);
+        if !code.is_ok() {
+            error!("couldn't bind a reallocated {}x{} surface: {:?}",
+                   size.width, size.height, code);
+            return self.fail_resize(code);
+        }
+
+        let mut state = self.0.borrow_mut();
+        state.context = context;
+        state.size = size;
+        state.pending = None;
+        state.resize_error = None;
+        state.reallocations += 1;
+        // The new context starts out blank.
+        state.dirty = vec![Rect::new(Point::new(0, 0), size)];
+    }
+    fn fail_resize(&self, code: Code) {
+        let mut state = self.0.borrow_mut();
+        state.pending = None;
+        state.resize_error = Some(code);
+    }
+}
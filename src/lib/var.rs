@@ -0,0 +1,96 @@
+// Copyright (c) 2014 Richard Diamond & contributors.
+//
+// This file is part of the Rust PPApi project.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! An opt-in cache of `StringVar`s, for strings which are created over and
+//! over, like the keys of messages on a hot path.
+//!
+//! With the `debug-resource-tracking` feature, this also counts the live
+//! ref-counted var wrappers; see `live_var_count`.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use super::StringVar;
+
+/// How many strings `intern` keeps per thread. Past this, the least recently
+/// used string is evicted.
+pub const INTERN_CAPACITY: usize = 256;
+
+struct Interned {
+    var: StringVar,
+    last_used: u64,
+}
+struct InternCache {
+    vars: HashMap<String, Interned>,
+    clock: u64,
+}
+
+// Per thread so lookups don't need to synchronize.
+thread_local!(static INTERNED: RefCell<InternCache> = RefCell::new(InternCache {
+    vars: HashMap::new(),
+    clock: 0,
+}));
+
+/// Returns a `StringVar` of `s`, reusing the var from an earlier call with
+/// the same string on this thread if it's still cached. The returned var is
+/// a new reference, so it stays valid after being evicted.
+pub fn intern(s: &str) -> StringVar {
+    INTERNED.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        cache.clock += 1;
+        let now = cache.clock;
+
+        if let Some(interned) = cache.vars.get_mut(s) {
+            interned.last_used = now;
+            return interned.var.clone();
+        }
+
+        if cache.vars.len() >= INTERN_CAPACITY {
+            let oldest = cache.vars.iter()
+                .fold(None, |oldest: Option<(&String, u64)>, (s, interned)| match oldest {
+                    Some((_, last_used)) if last_used <= interned.last_used => oldest,
+                    _ => Some((s, interned.last_used)),
+                })
+                .map(|(s, _)| s.clone() )
+                .unwrap();
+            cache.vars.remove(&oldest);
+        }
+        let var = StringVar::new_from_str(s);
+        cache.vars.insert(s.to_string(), Interned {
+            var: var.clone(),
+            last_used: now,
+        });
+        var
+    })
+}
+/// Drops this thread's cached vars. Vars returned by `intern` stay valid.
+pub fn clear_interned() {
+    let vars = INTERNED.with(|cache| {
+        ::std::mem::replace(&mut cache.borrow_mut().vars, HashMap::new())
+    });
+    drop(vars);
+}
+
+#[cfg(feature = "debug-resource-tracking")]
+static LIVE_VARS: ::std::sync::atomic::AtomicIsize = ::std::sync::atomic::ATOMIC_ISIZE_INIT;
+
+#[cfg(feature = "debug-resource-tracking")]
+#[doc(hidden)]
+pub fn adjust_live_vars(delta: isize) {
+    LIVE_VARS.fetch_add(delta, ::std::sync::atomic::Ordering::Relaxed);
+}
+/// The number of `StringVar`s, `ObjectVar`s, `ArrayVar`s, `DictionaryVar`s
+/// and `ArrayBufferVar`s alive, across all threads, for finding reference
+/// leaks in the wrappers. Vars held by the browser or the page, or
+/// referenced by hand, aren't counted. A negative count means a wrapper was
+/// released without being counted, which is a bug in the wrappers. Requires
+/// the `debug-resource-tracking` feature.
+#[cfg(feature = "debug-resource-tracking")]
+pub fn live_var_count() -> isize {
+    LIVE_VARS.load(::std::sync::atomic::Ordering::Relaxed)
+}
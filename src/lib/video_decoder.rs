@@ -13,7 +13,7 @@ use ffi;
 use gles::{self, Context3d, TextureBuffer, TexFormat};
 use ppb::{get_video_decoder_opt, VideoDecoderIf};
 
-use super::{GenericResource, Resource, ResourceType, Callback, Code,
+use super::{GenericResource, Resource, TypedResource, ResourceType, Callback, Code,
             CallbackArgs, StorageToArgsMapper};
 
 #[derive(Clone, Eq, PartialEq, Hash, Debug)]
@@ -22,6 +22,12 @@ impl Resource for VideoDecoder {
     fn unwrap(&self) -> ffi::PP_Resource { self.0.unwrap() }
     fn type_of(&self) -> Option<ResourceType> { Some(ResourceType::VideoDecoder) }
 }
+impl TypedResource for VideoDecoder {
+    fn resource_type() -> ResourceType { ResourceType::VideoDecoder }
+    fn from_resource(res: ffi::PP_Resource) -> VideoDecoder {
+        VideoDecoder(From::from(res))
+    }
+}
 #[doc(hidden)]
 impl From<ffi::PP_Resource> for VideoDecoder {
     fn from(v: ffi::PP_Resource) -> VideoDecoder {
@@ -69,6 +75,29 @@ pub enum Profile {
     VP8,
     H264(H264Profile),
 }
+impl Profile {
+    #[doc(hidden)]
+    pub fn from_ffi(v: ffi::PP_VideoProfile) -> Option<Profile> {
+        use self::H264Profile::*;
+        let h264 = match v {
+            ffi::PP_VIDEOPROFILE_VP9_ANY => return Some(Profile::VP9),
+            ffi::PP_VIDEOPROFILE_VP8_ANY => return Some(Profile::VP8),
+            ffi::PP_VIDEOPROFILE_H264BASELINE => BaseLine,
+            ffi::PP_VIDEOPROFILE_H264MAIN => Main,
+            ffi::PP_VIDEOPROFILE_H264EXTENDED => Extended,
+            ffi::PP_VIDEOPROFILE_H264HIGH => High,
+            ffi::PP_VIDEOPROFILE_H264HIGH10PROFILE => High10,
+            ffi::PP_VIDEOPROFILE_H264HIGH422PROFILE => High422,
+            ffi::PP_VIDEOPROFILE_H264HIGH444PREDICTIVEPROFILE => High444Predictive,
+            ffi::PP_VIDEOPROFILE_H264SCALABLEBASELINE => ScalableBaseLine,
+            ffi::PP_VIDEOPROFILE_H264SCALABLEHIGH => ScalableHigh,
+            ffi::PP_VIDEOPROFILE_H264STEREOHIGH => StereoHigh,
+            ffi::PP_VIDEOPROFILE_H264MULTIVIEWHIGH => MultiViewHigh,
+            _ => return None,
+        };
+        Some(Profile::H264(h264))
+    }
+}
 #[doc(hidden)]
 impl Into<ffi::PP_VideoProfile> for Profile {
     fn into(self) -> ffi::PP_VideoProfile {
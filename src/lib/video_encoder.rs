@@ -0,0 +1,242 @@
+// Copyright (c) 2014 Richard Diamond & contributors.
+//
+// This file is part of the Rust PPApi project.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Video encoding via `PPB_VideoEncoder`, eg for sending camera frames over
+//! WebRTC style connections.
+//!
+//! Frames to encode are taken from the encoder with `get_video_frame`,
+//! filled, and given back with `encode`. The encoded data is read with
+//! `get_bitstream_buffer`. The encoder only has a few bitstream buffers, so
+//! once they're all out it stops encoding until some are recycled; buffers
+//! are recycled when they're dropped.
+
+use std::collections::HashSet;
+use std::marker::PhantomData;
+use std::mem;
+use std::slice;
+use std::sync::{Mutex, Once, ONCE_INIT};
+
+use ffi;
+use ppb::{get_video_encoder, VideoEncoderIf};
+use video_decoder::{Acceleration, Profile};
+use video_frame::{self, VideoFrame};
+
+use super::{Code, CallbackArgs, StorageToArgsMapper, InPlaceInit,
+            InPlaceArrayOutputStorage, Resource, Size};
+
+#[derive(Hash, Eq, PartialEq, Debug)] pub struct VideoEncoder(ffi::PP_Resource);
+impl_clone_drop_for!(VideoEncoder);
+impl_resource_for!(VideoEncoder, ResourceType::VideoEncoder);
+
+/// A profile the encoder supports, and its limits.
+#[derive(Clone, Copy)]
+pub struct ProfileDescription {
+    pub profile: Profile,
+    pub max_resolution: Size,
+    pub max_framerate_numerator: u32,
+    pub max_framerate_denominator: u32,
+    pub hardware_accelerated: bool,
+}
+impl ProfileDescription {
+    fn from_ffi(raw: ffi::Struct_PP_VideoProfileDescription) -> Option<ProfileDescription> {
+        Profile::from_ffi(raw.profile).map(|profile| ProfileDescription {
+            profile: profile,
+            max_resolution: From::from(raw.max_resolution),
+            max_framerate_numerator: raw.max_framerate_numerator,
+            max_framerate_denominator: raw.max_framerate_denominator,
+            hardware_accelerated: raw.hardware_accelerated != ffi::PP_FALSE,
+        })
+    }
+}
+
+/// Encoded data. Recycled, so the encoder can reuse it, when dropped.
+pub struct BitstreamBuffer {
+    encoder: VideoEncoder,
+    raw: ffi::Struct_PP_BitstreamBuffer,
+}
+impl BitstreamBuffer {
+    pub fn is_key_frame(&self) -> bool { self.raw.key_frame != ffi::PP_FALSE }
+    pub fn len(&self) -> usize { self.raw.size as usize }
+}
+impl AsRef<[u8]> for BitstreamBuffer {
+    fn as_ref(&self) -> &[u8] {
+        if self.raw.size == 0 { return &[]; }
+        unsafe { slice::from_raw_parts(self.raw.buffer as *const u8, self.len()) }
+    }
+}
+impl Drop for BitstreamBuffer {
+    fn drop(&mut self) {
+        get_video_encoder()
+            .recycle_bitstream_buffer(self.encoder.unwrap(), &self.raw);
+    }
+}
+
+struct RawBitstreamBuffer(VideoEncoder, ffi::Struct_PP_BitstreamBuffer);
+impl InPlaceInit for RawBitstreamBuffer { }
+
+// Encoders whose initialization has completed. Encoders are sent between
+// threads, so this isn't thread local. Entries are removed by `close`, and
+// when an encoder is created: an encoder which was just dropped leaves its
+// entry behind, and its id may be handed out again.
+static INITIALIZED_INIT: Once = ONCE_INIT;
+static mut INITIALIZED: *const Mutex<HashSet<ffi::PP_Resource>> =
+    0 as *const Mutex<HashSet<ffi::PP_Resource>>;
+fn initialized() -> &'static Mutex<HashSet<ffi::PP_Resource>> {
+    INITIALIZED_INIT.call_once(|| unsafe {
+        INITIALIZED = mem::transmute(Box::new(Mutex::new(HashSet::new())));
+    });
+    unsafe { &*INITIALIZED }
+}
+
+fn is_initialized(encoder: ffi::PP_Resource) -> bool {
+    initialized().lock().unwrap().contains(&encoder)
+}
+/// Called with each newly created encoder, so it doesn't inherit the state
+/// of a dropped encoder which had the same id.
+#[doc(hidden)]
+pub fn note_created(encoder: ffi::PP_Resource) {
+    initialized().lock().unwrap().remove(&encoder);
+}
+
+impl VideoEncoder {
+    pub fn get_supported_profiles<F>(&self, callback: CallbackArgs<F, Vec<ProfileDescription>>) ->
+        Code<Vec<ProfileDescription>> where F: FnOnce(Code<Vec<ProfileDescription>>)
+    {
+        type Raw = ffi::Struct_PP_VideoProfileDescription;
+        fn map(raw: InPlaceArrayOutputStorage<Raw>, _status: Code) -> Vec<ProfileDescription> {
+            let raw: Vec<Raw> = raw.into();
+            // Skip profiles we don't know about.
+            raw.into_iter()
+                .filter_map(ProfileDescription::from_ffi)
+                .collect()
+        }
+        let raw_args: InPlaceArrayOutputStorage<Raw> = Default::default();
+        let cc = callback.to_ffi_callback(raw_args, StorageToArgsMapper(map));
+        let fficc = cc.cc();
+        let code = get_video_encoder()
+            .get_supported_profiles(self.unwrap(), *cc.as_ref(), fficc);
+        cc.drop_with_code(code)
+    }
+
+    /// Until `callback` is called with success, the other encoding functions
+    /// return `Code::Failed`.
+    pub fn initialize<F>(&self, input_format: video_frame::Format, input_visible_size: Size,
+                         output_profile: Profile, initial_bitrate: u32, accel: Acceleration,
+                         callback: CallbackArgs<F, ()>) -> Code<()>
+        where F: FnOnce(Code<()>),
+    {
+        fn mark_initialized(encoder: ffi::PP_Resource) {
+            initialized().lock().unwrap().insert(encoder);
+        }
+
+        let res = self.unwrap();
+        let CallbackArgs { optional, f, .. } = callback;
+        let callback = CallbackArgs {
+            optional: optional,
+            f: move |code: Code<()>| {
+                if code.is_ok() { mark_initialized(res); }
+                f.call_once((code, ));
+            },
+            _1: PhantomData,
+        };
+        let cc = callback.to_ffi_callback((), Default::default());
+        let code = get_video_encoder()
+            .initialize(res, input_format.to_ffi(), &input_visible_size.into(),
+                        output_profile.into(), initial_bitrate, accel.into(), cc.cc());
+        let code = cc.drop_with_code(code);
+        if code.is_ok() {
+            // An optional callback completed synchronously.
+            mark_initialized(res);
+        }
+        code
+    }
+
+    /// The number of frames the encoder needs to have taken with
+    /// `get_video_frame` before it can produce output.
+    pub fn get_frames_required(&self) -> Code {
+        if !is_initialized(self.unwrap()) { return Code::Failed; }
+        get_video_encoder().get_frames_required(self.unwrap())
+    }
+    /// The size of the frames given by `get_video_frame`, which may be larger
+    /// than the visible size.
+    pub fn get_frame_coded_size(&self) -> Option<Size> {
+        if !is_initialized(self.unwrap()) { return None; }
+        get_video_encoder()
+            .get_frame_coded_size(self.unwrap())
+            .map(|s| From::from(s) )
+    }
+
+    /// Takes a blank frame to be filled and given to `encode`.
+    pub fn get_video_frame<F>(&self, callback: CallbackArgs<F, VideoFrame>) -> Code<VideoFrame>
+        where F: FnOnce(Code<VideoFrame>)
+    {
+        fn map(frame: ffi::PP_Resource, _status: Code) -> VideoFrame {
+            // We're given a reference with the frame.
+            VideoFrame::new(frame)
+        }
+        let mut cc = callback.to_ffi_callback(0 as ffi::PP_Resource, StorageToArgsMapper(map));
+        if !is_initialized(self.unwrap()) { return cc.drop_with_code(Code::Failed); }
+        let fficc = cc.cc();
+        let code = get_video_encoder()
+            .get_video_frame(self.unwrap(), &mut *cc, fficc);
+        cc.drop_with_code(code)
+    }
+    /// Encodes `frame`, which must have come from `get_video_frame`.
+    /// `callback` is called once the encoder is done with `frame`, not once
+    /// the encoded data is ready.
+    pub fn encode<F>(&self, frame: &VideoFrame, force_keyframe: bool,
+                     callback: CallbackArgs<F, ()>) -> Code<()>
+        where F: FnOnce(Code<()>)
+    {
+        let cc = callback.to_ffi_callback((), Default::default());
+        if !is_initialized(self.unwrap()) { return cc.drop_with_code(Code::Failed); }
+        let code = get_video_encoder()
+            .encode(self.unwrap(), frame.unwrap(), force_keyframe, cc.cc());
+        cc.drop_with_code(code)
+    }
+
+    /// Gets the next piece of encoded data. Drop or recycle the buffer once
+    /// it's been read, or the encoder will run out.
+    pub fn get_bitstream_buffer<F>(&self, callback: CallbackArgs<F, BitstreamBuffer>) ->
+        Code<BitstreamBuffer> where F: FnOnce(Code<BitstreamBuffer>)
+    {
+        fn map(raw: RawBitstreamBuffer, _status: Code) -> BitstreamBuffer {
+            let RawBitstreamBuffer(encoder, raw) = raw;
+            BitstreamBuffer {
+                encoder: encoder,
+                raw: raw,
+            }
+        }
+        let raw_args = RawBitstreamBuffer(self.clone(), Default::default());
+        let mut cc = callback.to_ffi_callback(raw_args, StorageToArgsMapper(map));
+        if !is_initialized(self.unwrap()) { return cc.drop_with_code(Code::Failed); }
+        let fficc = cc.cc();
+        let code = get_video_encoder()
+            .get_bitstream_buffer(self.unwrap(), &mut cc.1, fficc);
+        cc.drop_with_code(code)
+    }
+    /// Gives `buffer` back to the encoder. Same as dropping it.
+    pub fn recycle_bitstream_buffer(&self, buffer: BitstreamBuffer) {
+        debug_assert!(buffer.encoder == *self,
+                      "recycling a bitstream buffer from a different encoder");
+        drop(buffer);
+    }
+
+    /// Asks for a new bitrate, in bits per second, and framerate. The encoder
+    /// may ignore this.
+    pub fn request_encoding_parameters_change(&self, bitrate: u32, framerate: u32) {
+        get_video_encoder()
+            .request_encoding_parameters_change(self.unwrap(), bitrate, framerate)
+    }
+    /// Stops encoding. Pending callbacks are aborted, and the other encoding
+    /// functions return `Code::Failed` from now on.
+    pub fn close(&self) {
+        initialized().lock().unwrap().remove(&self.unwrap());
+        get_video_encoder().close(self.unwrap())
+    }
+}
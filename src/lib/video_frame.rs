@@ -50,7 +50,7 @@ impl Format {
 impl From<ffi::PP_Resource> for VideoFrame {
     fn from(v: ffi::PP_Resource) -> VideoFrame {
         debug_assert!(get_video_frame().is(v));
-        VideoFrame(v)
+        VideoFrame::new(v)
     }
 }
 
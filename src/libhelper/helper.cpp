@@ -119,6 +119,7 @@ extern "C" {
       0,
       vv,
     };
+    return v;
   }
   const int64_t id_from_var(const PP_Var v) {
     return v.value.as_id;
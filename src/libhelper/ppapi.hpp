@@ -157,6 +157,10 @@
 #include <ppapi/c/dev/pp_video_dev.h>
 #endif
 
+#include <ppapi/c/trusted/ppb_browser_font_trusted.h>
+#include <ppapi/c/private/ppb_isolated_file_system_private.h>
+#include <ppapi/c/private/ppb_uma_private.h>
+
 #include <nacl_io/nacl_io.h>
 #include <sys/mount.h>       // for mount.
 
@@ -0,0 +1,116 @@
+// Spliced into every host test. Serves the test page out of `root`, along
+// with a few canned endpoints for the tests that need an HTTP server, so
+// nothing depends on a host out on the internet. Returns the port; the
+// server runs until the test process exits.
+//
+// Endpoints:
+//   /get             200, with a short text body.
+//   /redirect        302 to /get.
+//   /post            200, with the request body echoed back.
+//   /bytes/<n>       200, with n bytes of body.
+//   /status/<code>   Responds with the given status and an empty body.
+//   /slow/<n>        200, with n bytes of body sent one a second.
+fn serve_fixtures(root: String) -> u16 {
+    use std::fs::File;
+    use std::io::{self, BufRead, BufReader, Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::path::PathBuf;
+    use std::thread;
+
+    fn respond(stream: &mut TcpStream, status: &str, headers: &[(&str, String)],
+               body: &[u8]) -> io::Result<()> {
+        try!(write!(stream, "HTTP/1.1 {}\r\n", status));
+        for &(name, ref value) in headers.iter() {
+            try!(write!(stream, "{}: {}\r\n", name, value));
+        }
+        try!(write!(stream, "Content-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()));
+        stream.write_all(body)
+    }
+    fn content_type(path: &str) -> &'static str {
+        if path.ends_with(".html") { "text/html" }
+        else if path.ends_with(".nmf") { "application/json" }
+        else if path.ends_with(".pexe") { "application/x-pnacl" }
+        else { "application/octet-stream" }
+    }
+    fn handle(root: &PathBuf, stream: TcpStream) -> io::Result<()> {
+        let mut reader = BufReader::new(try!(stream.try_clone()));
+        let mut stream = stream;
+
+        let mut request_line = String::new();
+        try!(reader.read_line(&mut request_line));
+        let path = request_line.split(' ').nth(1).unwrap_or("/").to_string();
+
+        let mut content_length = 0usize;
+        loop {
+            let mut line = String::new();
+            if try!(reader.read_line(&mut line)) == 0 { break; }
+            let line = line.trim_right();
+            if line.is_empty() { break; }
+            let mut header = line.splitn(2, ':');
+            let name = header.next().unwrap().trim().to_lowercase();
+            if name == "content-length" {
+                content_length = header.next()
+                    .and_then(|v| v.trim().parse().ok() )
+                    .unwrap_or(0);
+            }
+        }
+        let mut body = Vec::new();
+        try!(reader.by_ref().take(content_length as u64).read_to_end(&mut body));
+
+        let mut segments = path[1..].splitn(2, '/');
+        match (segments.next().unwrap_or(""), segments.next()) {
+            ("get", None) => respond(&mut stream, "200 OK", &[], b"fixture"),
+            ("redirect", None) => respond(&mut stream, "302 Found",
+                                          &[("Location", "/get".to_string())], &[]),
+            ("post", None) => respond(&mut stream, "200 OK", &[], &body[..]),
+            ("bytes", Some(n)) => {
+                let n: usize = n.parse().unwrap();
+                let body: Vec<u8> = (0..n).map(|i| i as u8 ).collect();
+                respond(&mut stream, "200 OK", &[], &body[..])
+            },
+            ("status", Some(code)) => respond(&mut stream, &format!("{} Fixture", code),
+                                              &[], &[]),
+            ("slow", Some(n)) => {
+                let n: usize = n.parse().unwrap();
+                try!(write!(stream, "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\
+                                     Connection: close\r\n\r\n", n));
+                for _ in 0..n {
+                    try!(stream.write_all(b"."));
+                    try!(stream.flush());
+                    thread::sleep_ms(1000);
+                }
+                Ok(())
+            },
+            (file, _) => {
+                let mut contents = Vec::new();
+                match File::open(&root.join(file)) {
+                    Ok(mut f) => {
+                        try!(f.read_to_end(&mut contents));
+                        respond(&mut stream, "200 OK",
+                                &[("Content-Type", content_type(file).to_string())],
+                                &contents[..])
+                    },
+                    Err(_) => respond(&mut stream, "404 Not Found", &[], &[]),
+                }
+            },
+        }
+    }
+
+    let root = PathBuf::from(root);
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .ok().expect("couldn't bind the fixture server");
+    let port = listener.local_addr().unwrap().port();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let root = root.clone();
+            match stream {
+                // One thread per connection, so a slow response doesn't hold
+                // up the page loading.
+                Ok(stream) => { thread::spawn(move || { let _ = handle(&root, stream); }); },
+                Err(_) => break,
+            }
+        }
+    });
+    port
+}
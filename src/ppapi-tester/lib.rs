@@ -16,6 +16,16 @@
 //! }
 //! ```
 //!
+//! The test page is served from a local HTTP server, which also has a few
+//! canned endpoints (see `fixtures.rs`) for tests that need to load something.
+//! Its origin is in the `"fixtures"` arg, eg
+//! `format!("{}/bytes/1024", args["fixtures"])`.
+//!
+//! The page posts back the `"ppapi_test_echo"` entry of any dictionary
+//! posted to it, so tests can round trip vars through JS. Replies are only
+//! seen by a `MessageHandler` running on another loop, since the test blocks
+//! its own.
+//!
 //! In the future I plan allowing #[ppapi_test] on modules so callbacks can be
 //! tested as well.
 //!
@@ -47,6 +57,7 @@ pub fn plugin_registrar(reg: &mut Registry) {
 
 const HTML: &'static str = include_str!("index.html");
 const MANIFEST: &'static str = include_str!("manifest.nmf");
+const FIXTURES: &'static str = include_str!("fixtures.rs");
 
 fn expand_test(ecx: &mut ExtCtxt,
                sp: Span,
@@ -102,7 +113,7 @@ fn expand_test(ecx: &mut ExtCtxt,
         quote_item!(
             ecx,
             #![no_main]
-            extern crate ppapi;
+            #[macro_use] extern crate ppapi;
 
             use std::collections::HashMap;
 
@@ -145,6 +156,12 @@ fn expand_test(ecx: &mut ExtCtxt,
 
     let timeout = None::<u64>;
 
+    let fixtures = syntax::parse::parse_item_from_source_str("fixtures.rs".to_string(),
+                                                             FIXTURES.to_string(),
+                                                             ecx.cfg(),
+                                                             ecx.parse_sess())
+        .expect("the fixture server failed to parse");
+
     let host_test =
         quote_item!(
             ecx,
@@ -161,10 +178,12 @@ fn expand_test(ecx: &mut ExtCtxt,
                 let tmp = TempDir::new(stringify!($test_name))
                     .ok().expect("need temp directory for test artifacts");
 
-                let url = format!("file://{}",
-                                  tmp.path().join("index.html").display());
-                let chrome_args = ["--allow-file-access-from-files".to_string(),
-                                   "--bwsi".to_string(),
+                $fixtures
+
+                // Served over HTTP so the tests can load from the same origin.
+                let port = serve_fixtures(tmp.path().display().to_string());
+                let url = format!("http://127.0.0.1:{}/index.html", port);
+                let chrome_args = ["--bwsi".to_string(),
                                    "--no-sandbox".to_string(),
                                    "--silent-launch".to_string(),
                                    "--noerrdialogs".to_string(),
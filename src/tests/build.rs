@@ -39,6 +39,16 @@ pub fn main() {
     let mut cargo = Command::new("cargo");
     cargo.args(["build", "--verbose", "--target"].as_slice());
     cargo.arg(target);
+    let features = [("CARGO_FEATURE_DEBUG_THREAD_CHECKS", "debug-thread-checks"),
+                    ("CARGO_FEATURE_DEBUG_RESOURCE_TRACKING", "debug-resource-tracking")];
+    let features: Vec<&str> = features.iter()
+        .filter(|&&(env, _)| getenv(env).is_some() )
+        .map(|&(_, feature)| feature )
+        .collect();
+    if !features.is_empty() {
+        cargo.arg("--features");
+        cargo.arg(features.connect(" "));
+    }
     for &(ref k, ref v) in envs.iter() {
         cargo.env(k, v);
     }
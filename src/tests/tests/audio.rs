@@ -0,0 +1,86 @@
+// Copyright (c) 2014 Richard Diamond & contributors.
+//
+// This file is part of the Rust PPApi project.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+#![feature(plugin)]
+
+#[plugin] #[no_link]
+extern crate "ppapi-tester" as _ppapi_tester;
+
+#[ppapi_test]
+fn recommend_sample_frame_count(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::audio::{recommend_sample_rate, recommend_sample_frame_count,
+                       MIN_SAMPLE_FRAME_COUNT, MAX_SAMPLE_FRAME_COUNT};
+
+    let rate = recommend_sample_rate(&instance);
+    for &requested in [0, 1, 512, 4096, ::std::u32::MAX].iter() {
+        let count = recommend_sample_frame_count(&instance, rate, requested);
+        assert!(count >= MIN_SAMPLE_FRAME_COUNT && count <= MAX_SAMPLE_FRAME_COUNT,
+                "recommended count {} for {} is out of range", count, requested);
+    }
+}
+
+#[ppapi_test]
+fn audio_track_interfaces(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::{Feature, Resource, ResourceType, Size};
+    use ppapi::ppb;
+
+    assert_eq!(instance.supports(Feature::MediaStreamAudioTrack),
+               ppb::get_media_stream_audio_track_opt().is_some());
+    assert_eq!(instance.supports(Feature::AudioBuffer),
+               ppb::get_audio_buffer_opt().is_some());
+
+    // The test page has no MediaStream to hand us, so pulling buffers from a
+    // real track isn't covered here; just check other resources aren't
+    // mistaken for audio tracks or buffers.
+    let image = instance.create_image(None, Size::new(1, 1), true).unwrap();
+    let generic: ppapi::GenericResource = From::from(image.unwrap());
+    ::std::mem::forget(image);
+    assert!(generic.media_stream_audio_track().is_none());
+    assert!(generic.type_of() != Some(ResourceType::AudioTrack));
+    assert!(generic.type_of() != Some(ResourceType::AudioBuffer));
+}
+
+#[ppapi_test]
+fn audio_playback_state(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::Feature;
+    use ppapi::audio::{recommend_sample_rate, recommend_sample_frame_count,
+                       Audio, AudioConfig};
+
+    if !instance.supports(Feature::Audio) { return; }
+
+    let rate = recommend_sample_rate(&instance);
+    let frames = recommend_sample_frame_count(&instance, rate, 1024);
+    let config = AudioConfig::new_stereo_16bit(&instance, rate, frames).unwrap();
+    let mut audio = Audio::new(&instance, &config, |samples: &mut [i16], latency| {
+        assert!(latency >= 0.0);
+        for sample in samples.iter_mut() {
+            *sample = 0;
+        }
+    }).unwrap();
+
+    assert!(!audio.is_playing());
+    let current = audio.get_current_config();
+    assert_eq!(current.sample_rate(), Some(rate));
+    assert_eq!(current.sample_frame_count(), frames);
+
+    assert!(audio.start());
+    assert!(audio.is_playing());
+    // Starting twice is harmless.
+    assert!(audio.start());
+    assert!(audio.is_playing());
+    if let Some(latency) = audio.get_output_latency() {
+        assert!(latency >= 0.0);
+    }
+    // The fallback's callback has no latency to report.
+    if ppapi::ppb::resolved_version("PPB_Audio") == Some("1.0") {
+        assert_eq!(audio.get_output_latency(), None);
+    }
+
+    assert!(audio.stop());
+    assert!(!audio.is_playing());
+}
@@ -0,0 +1,60 @@
+// Copyright (c) 2014 Richard Diamond & contributors.
+//
+// This file is part of the Rust PPApi project.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+#![feature(plugin)]
+
+#[plugin] #[no_link]
+extern crate "ppapi-tester" as _ppapi_tester;
+
+#[ppapi_test]
+fn list_font_families(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::font::{Description, Family, FontLike};
+
+    let families = instance.browser_font_families();
+    assert!(!families.is_empty());
+    for family in families.iter() {
+        assert!(!family.is_empty());
+    }
+
+    let desc = Description::new_from_family(Family::SansSerifFamily);
+    let font = instance.browser_font(&desc).unwrap();
+    let text = ppapi::StringVar::new("hello");
+    assert!(font.measure_text(&text, false, false).unwrap() > 0);
+}
+
+#[ppapi_test]
+fn create_font_with_fallback(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::font::{Description, Family};
+
+    assert!(instance.create_font_with_fallback(&[]).is_none());
+
+    let descs = [
+        // The browser refuses fonts over 200 pixels; an unknown face would
+        // just be substituted.
+        Description::new_from_family(Family::DefaultFamily).size(201),
+        Description::new_from_family(Family::DefaultFamily).size(16),
+    ];
+    assert!(instance.create_font(&descs[0]).is_none());
+    let font = instance.create_font_with_fallback(&descs).unwrap();
+    let (desc, _) = font.describe().unwrap();
+    assert_eq!(desc.get_size(), 16);
+}
+
+#[ppapi_test]
+fn css_font_sizes(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::font::{self, Description, Family};
+
+    assert_eq!(font::scale_font_size(16, 1.5), 24);
+    assert_eq!(font::scale_font_size(13, 1.0), 13);
+    // Rounds to the nearest pixel, not up.
+    assert_eq!(font::scale_font_size(11, 1.25), 14);
+    assert_eq!(font::scale_font_size(10, 1.25), 13);
+
+    let desc = Description::new_from_family(Family::DefaultFamily).size(20);
+    assert_eq!(desc.get_size(), 20);
+}
@@ -0,0 +1,299 @@
+// Copyright (c) 2014 Richard Diamond & contributors.
+//
+// This file is part of the Rust PPApi project.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+#![feature(plugin)]
+
+#[plugin] #[no_link]
+extern crate "ppapi-tester" as _ppapi_tester;
+
+#[ppapi_test]
+fn retry_with_backoff(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use ppapi::{Code, retry_with_backoff};
+
+    assert!(Code::ConnectionReset::<usize>.is_transient());
+    assert!(!Code::BadArgument::<usize>.is_transient());
+
+    let attempts = Rc::new(Cell::new(0u32));
+    let attempts2 = attempts.clone();
+    let code = retry_with_backoff(move || {
+        attempts2.set(attempts2.get() + 1);
+        if attempts2.get() <= 2 {
+            Code::ConnectionReset
+        } else {
+            Code::Ok(0)
+        }
+    }, 5, 1, move |code| {
+        assert!(code.is_ok());
+        assert_eq!(attempts.get(), 3);
+    });
+    assert!(code.completion_pending());
+
+    // Non-transient errors aren't retried.
+    let attempts = Rc::new(Cell::new(0u32));
+    let attempts2 = attempts.clone();
+    let code = retry_with_backoff(move || {
+        attempts2.set(attempts2.get() + 1);
+        Code::BadArgument
+    }, 5, 1, move |code| {
+        assert_eq!(code, Code::BadArgument);
+        assert_eq!(attempts.get(), 1);
+    });
+    assert!(code.completion_pending());
+}
+
+#[ppapi_test]
+fn code_combinators(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::Code;
+
+    let ok: Code<u32> = Code::Ok(7);
+    let pending: Code<u32> = Code::CompletionPending;
+    let err: Code<u32> = Code::Failed;
+
+    assert_eq!(ok.clone().unwrap_or(1), 7);
+    assert_eq!(pending.clone().unwrap_or(1), 1);
+    assert_eq!(err.clone().unwrap_or(1), 1);
+
+    assert_eq!(ok.clone().unwrap_or_else(|_| panic!("shouldn't be called") ), 7);
+    assert_eq!(pending.clone().unwrap_or_else(|code| {
+        assert_eq!(code, Code::CompletionPending);
+        2
+    }), 2);
+    assert_eq!(err.clone().unwrap_or_else(|code| {
+        assert_eq!(code, Code::Failed);
+        3
+    }), 3);
+
+    assert_eq!(ok.clone().map_or(0, |v| v * 2 ), 14);
+    assert_eq!(pending.clone().map_or(0, |v| v * 2 ), 0);
+    assert_eq!(err.clone().map_or(0, |v| v * 2 ), 0);
+
+    assert_eq!(ok.ok_or_default(), 7);
+    assert_eq!(pending.ok_or_default(), 0);
+    assert_eq!(err.ok_or_default(), 0);
+}
+
+#[ppapi_test]
+fn code_unknown_round_trip(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use std::error::Error;
+    use ppapi::Code;
+
+    let code = Code::from_i32(-12345);
+    assert_eq!(code, Code::Unknown(-12345));
+    assert_eq!(code.to_i32(), -12345);
+    assert!(code.is_err());
+    assert_eq!(format!("{}", code), "unknown error code (-12345)");
+    assert_eq!(code.description(), "unknown error code");
+
+    assert_eq!(code.map_err::<u8>(), Code::Unknown(-12345));
+    assert_eq!(code.map_ok(|v| v + 1 ), Code::Unknown(-12345));
+}
+
+#[ppapi_test]
+fn code_error_groups(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::{ffi, Code};
+
+    let network: [Code<()>; 2] = [Code::ConnectionReset, Code::TimedOut];
+    for code in network.iter() {
+        assert!(code.is_network_error() && !code.is_fs_error() && !code.is_resource_error());
+    }
+    let fs: [Code<()>; 2] = [Code::FileNotFound, Code::NoQuota];
+    for code in fs.iter() {
+        assert!(code.is_fs_error() && !code.is_network_error() && !code.is_resource_error());
+    }
+    let resource: [Code<()>; 2] = [Code::BadResource, Code::ContextLost];
+    for code in resource.iter() {
+        assert!(code.is_resource_error() && !code.is_network_error() && !code.is_fs_error());
+    }
+
+    let none: [Code<()>; 4] = [Code::Ok(()), Code::CompletionPending, Code::Failed,
+                               Code::Unknown(-12345)];
+    for code in none.iter() {
+        assert!(!code.is_network_error() && !code.is_fs_error() && !code.is_resource_error());
+    }
+
+    // As the browser reports them.
+    for &v in [ffi::PP_ERROR_CONNECTION_FAILED, ffi::PP_ERROR_ADDRESS_UNREACHABLE,
+               ffi::PP_ERROR_NAME_NOT_RESOLVED].iter() {
+        assert!(Code::from_i32(v).is_network_error(), "{}", v);
+    }
+    for &v in [ffi::PP_ERROR_NOSPACE, ffi::PP_ERROR_NOQUOTA].iter() {
+        assert!(Code::from_i32(v).is_fs_error(), "{}", v);
+    }
+}
+
+#[ppapi_test]
+fn log_err_to_console(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use std::cell::RefCell;
+    use ppapi::{ffi, Code, FromVar, ToVar, ResultCodeExt, StringVar};
+    use ppapi::ppb::ConsoleInterface;
+
+    struct FakeConsole(RefCell<Vec<(ffi::PP_LogLevel, String)>>);
+    impl ConsoleInterface for FakeConsole {
+        fn log_to_browser(&self, lvl: ffi::PP_LogLevel, value: ffi::PP_Var) {
+            let value: StringVar = FromVar::from_var(value).unwrap();
+            self.0.borrow_mut().push((lvl, value.as_str().to_string()));
+        }
+        fn log_with_source<ST: ToVar, VT: ToVar>(&self, _: ffi::PP_LogLevel, _: ST, _: VT) {
+            unreachable!()
+        }
+    }
+    let console = FakeConsole(RefCell::new(Vec::new()));
+
+    let ok: Result<u32, Code> = Ok(4);
+    assert_eq!(ok.log_err_to(&console, "binding context"), Ok(4));
+    assert!(console.0.borrow().is_empty());
+
+    let err: Result<u32, Code> = Err(Code::Failed);
+    assert_eq!(err.log_err_to(&console, "binding context"), Err(Code::Failed));
+    {
+        let logged = console.0.borrow();
+        assert_eq!(logged.len(), 1);
+        assert_eq!(logged[0].0, ffi::PP_LOGLEVEL_WARNING);
+        assert!(logged[0].1.starts_with("binding context: "));
+    }
+
+    assert!(Code::Ok(1u32).log_err_to(&console, "flushing").is_ok());
+    assert!(Code::CompletionPending::<u32>.log_err_to(&console, "flushing")
+            .completion_pending());
+    assert_eq!(console.0.borrow().len(), 1);
+    assert_eq!(Code::BadResource::<u32>.log_err_to(&console, "flushing"),
+               Code::BadResource);
+    assert_eq!(console.0.borrow().len(), 2);
+}
+
+#[ppapi_test]
+fn try_code_result_early_returns(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::Code;
+
+    fn from_code(code: Code<u32>) -> Result<u32, Code> {
+        let n = try_code_result!(code);
+        Ok(n + 1)
+    }
+    fn from_result(r: Result<u32, Code>) -> Result<u32, Code> {
+        let n = try_code_result!(r);
+        Ok(n + 1)
+    }
+
+    assert_eq!(from_code(Code::Ok(1)), Ok(2));
+    assert_eq!(from_code(Code::FileNotFound), Err(Code::FileNotFound));
+    // Pending is an error, not a sentinel value.
+    assert_eq!(from_code(Code::CompletionPending), Err(Code::CompletionPending));
+
+    assert_eq!(from_result(Ok(1)), Ok(2));
+    assert_eq!(from_result(Err(Code::NoAccess)), Err(Code::NoAccess));
+}
+
+#[ppapi_test]
+fn wire_round_trip(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::{Code, WIRE_BAD_INSTANCE};
+
+    let codes: &[Code] = &[
+        Code::Ok(0), Code::Ok(42), Code::CompletionPending,
+        Code::BadResource, Code::BadArgument, Code::WrongThread, Code::InProgress,
+        Code::Failed, Code::NotSupported, Code::NoMemory, Code::NoSpace, Code::NoQuota,
+        Code::ContextLost, Code::FileNotFound, Code::FileExists, Code::NoAccess,
+        Code::ConnectionRefused, Code::ConnectionReset, Code::ConnectionAborted,
+        Code::ConnectionClosed, Code::ConnectionFailed, Code::AddressUnreachable,
+        Code::NameNotResolved, Code::TimedOut, Code::NoMessageLoop, Code::Aborted,
+        Code::NoInterface, Code::BadInstance, Code::Unknown(-9999),
+    ];
+    for &code in codes.iter() {
+        assert_eq!(Code::from_wire(code.to_wire()), code);
+    }
+    // `to_i32` can't tell it apart from `PP_ERROR_RESOURCE_FAILED`.
+    assert_eq!(Code::BadInstance.to_wire(), WIRE_BAD_INSTANCE);
+    assert!(Code::from_i32(Code::BadInstance.to_i32()) != Code::BadInstance);
+    // PPAPI's values, so JS can use the documented constants.
+    assert_eq!(Code::Failed.to_wire(), -2);
+    assert_eq!(Code::from_wire(-2), Code::Failed);
+}
+
+#[ppapi_test]
+fn verbose_descriptions(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use std::collections::HashSet;
+    use ppapi::Code;
+
+    let codes: Vec<Code> = vec![Code::Ok(0), Code::CompletionPending, Code::BadResource,
+                                Code::BadArgument, Code::WrongThread, Code::InProgress,
+                                Code::Failed, Code::NotSupported, Code::NoMemory,
+                                Code::NoSpace, Code::NoQuota, Code::ContextLost,
+                                Code::FileNotFound, Code::FileExists, Code::NoAccess,
+                                Code::ConnectionRefused, Code::ConnectionReset,
+                                Code::ConnectionAborted, Code::ConnectionClosed,
+                                Code::ConnectionFailed, Code::AddressUnreachable,
+                                Code::NameNotResolved, Code::TimedOut, Code::NoMessageLoop, Code::Aborted,
+                                Code::NoInterface, Code::BadInstance, Code::Unknown(-12345)];
+    let mut seen = HashSet::new();
+    for code in codes.iter() {
+        let verbose = code.describe_verbose();
+        assert!(!verbose.is_empty());
+        assert!(seen.insert(verbose), "{:?} shares its description", code);
+    }
+    assert!(Code::WrongThread::<usize>.describe_verbose().contains("spawn_message_loop"));
+}
+
+#[ppapi_test]
+fn with_timeout_resolves_once(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc::channel;
+    use ppapi::{CallbackArgs, Code, MessageLoop, with_timeout};
+    use ppapi::ffi;
+
+    // The harness loop quits once this returns, so run on a loop of our own
+    // until everything has had the chance to call back.
+    let (tx, rx) = channel();
+    let (_, join) = instance.spawn_message_loop(move |run_loop| {
+        let ml = MessageLoop::current().unwrap();
+
+        // Never completes; the timeout must be delivered, once.
+        let never = Arc::new(AtomicUsize::new(0));
+        let never2 = never.clone();
+        let code = with_timeout(&ml, 10, |cc| {
+            ::std::mem::forget(cc);
+            Code::CompletionPending
+        }, CallbackArgs::new(move |code: Code<()>| {
+            assert_eq!(code, Code::TimedOut);
+            never2.fetch_add(1, Ordering::SeqCst);
+        }));
+        assert!(code.completion_pending());
+
+        // Completes after the timeout; the late completion is dropped.
+        let late = Arc::new(AtomicUsize::new(0));
+        let late2 = late.clone();
+        let code = with_timeout(&ml, 10, |cc| {
+            let cc = cc.to_ffi_callback((), Default::default()).cc();
+            let posted = MessageLoop::post_to_self(move |_| unsafe {
+                (cc.func.unwrap())(cc.user_data, ffi::PP_OK);
+            }, 100);
+            assert!(posted.is_ok());
+            Code::CompletionPending
+        }, CallbackArgs::new(move |code: Code<()>| {
+            assert_eq!(code, Code::TimedOut);
+            late2.fetch_add(1, Ordering::SeqCst);
+        }));
+        assert!(code.completion_pending());
+
+        // Failing immediately doesn't call back at all.
+        let code = with_timeout(&ml, 10, |_| Code::Failed,
+                                CallbackArgs::new(|_: Code<()>| unreachable!() ));
+        assert_eq!(code, Code::Failed);
+
+        // Well after both timeouts and the late completion.
+        let posted = ml.post_work(move |_| {
+            let _ = tx.send((never.load(Ordering::SeqCst), late.load(Ordering::SeqCst)));
+            let _ = MessageLoop::current().unwrap().queue_shutdown();
+        }, 200);
+        assert!(posted.is_ok());
+        run_loop();
+    });
+    assert_eq!(rx.recv().ok(), Some((1, 1)));
+    assert!(join.join().is_ok());
+}
@@ -0,0 +1,183 @@
+// Copyright (c) 2014 Richard Diamond & contributors.
+//
+// This file is part of the Rust PPApi project.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+#![feature(plugin)]
+
+#[plugin] #[no_link]
+extern crate "ppapi-tester" as _ppapi_tester;
+
+#[ppapi_test]
+fn console_buffer_preserves_order(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::ConsoleBuffer;
+    use ppapi::ffi::{PP_LOGLEVEL_LOG, PP_LOGLEVEL_WARNING};
+
+    let mut buffer = ConsoleBuffer::new();
+    for i in 0..1000 {
+        // Runs of ten lines per level.
+        let level = if (i / 10) % 2 == 0 { PP_LOGLEVEL_LOG } else { PP_LOGLEVEL_WARNING };
+        buffer.push(level, format!("line {}", i));
+    }
+
+    let mut calls = 0;
+    let mut received = Vec::new();
+    buffer.flush(|level, lines| {
+        calls += 1;
+        for line in lines.split('\n') {
+            received.push((level, line.to_string()));
+        }
+    });
+    assert!(buffer.is_empty());
+    assert_eq!(calls, 100);
+    assert_eq!(received.len(), 1000);
+    for (i, &(level, ref line)) in received.iter().enumerate() {
+        let expected = if (i / 10) % 2 == 0 { PP_LOGLEVEL_LOG } else { PP_LOGLEVEL_WARNING };
+        assert_eq!(level, expected);
+        assert_eq!(*line, format!("line {}", i));
+    }
+}
+
+#[ppapi_test]
+fn console_buffering_batches_records(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use std::cell::RefCell;
+    use ppapi::{ffi, FromVar, StringVar, ToVar};
+    use ppapi::ffi::{PP_LOGLEVEL_LOG, PP_LOGLEVEL_WARNING};
+    use ppapi::ppb::ConsoleInterface;
+
+    struct FakeConsole(RefCell<Vec<(ffi::PP_LogLevel, String)>>);
+    impl ConsoleInterface for FakeConsole {
+        fn log_to_browser(&self, lvl: ffi::PP_LogLevel, value: ffi::PP_Var) {
+            let value: StringVar = FromVar::from_var(value).unwrap();
+            self.0.borrow_mut().push((lvl, value.as_str().to_string()));
+        }
+        fn log_with_source<ST: ToVar, VT: ToVar>(&self, _: ffi::PP_LogLevel, _: ST, _: VT) {
+            unreachable!()
+        }
+    }
+    let level = |i: usize| if (i / 10) % 2 == 0 { PP_LOGLEVEL_LOG } else { PP_LOGLEVEL_WARNING };
+
+    // Long enough that the scheduled flush can't run during the test.
+    instance.set_console_buffering(true, 60 * 1000);
+    // Toggling shouldn't lose anything.
+    instance.set_console_buffering(true, 60 * 1000);
+    for i in 0..1000 {
+        ppapi::log_line(level(i), format!("line {}", i));
+    }
+
+    let console = FakeConsole(RefCell::new(Vec::new()));
+    instance.flush_console_to(&console);
+    {
+        let logged = console.0.borrow();
+        // One call per run of ten lines with the same level.
+        assert_eq!(logged.len(), 100);
+        let received: Vec<(ffi::PP_LogLevel, &str)> = logged.iter()
+            .flat_map(|&(level, ref lines)| lines.split('\n').map(move |line| (level, line) ) )
+            .collect();
+        assert_eq!(received.len(), 1000);
+        for (i, &(l, line)) in received.iter().enumerate() {
+            assert_eq!(l, level(i));
+            assert_eq!(line, &format!("line {}", i)[..]);
+        }
+    }
+
+    // Nothing is flushed twice.
+    instance.flush_console_to(&console);
+    assert_eq!(console.0.borrow().len(), 100);
+
+    instance.set_console_buffering(false, 0);
+    ppapi::log_line(PP_LOGLEVEL_LOG, "unbuffered".to_string());
+    instance.flush_console_to(&console);
+    assert_eq!(console.0.borrow().len(), 100);
+}
+
+#[ppapi_test]
+fn console_timers(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use std::thread;
+
+    let console = instance.console();
+    console.time("outer");
+    console.time("inner");
+    thread::sleep_ms(5);
+    let inner = console.time_end("inner").unwrap();
+    assert!(inner > 0.0);
+    // Ending one timer leaves the others running.
+    thread::sleep_ms(5);
+    let outer = console.time_end("outer").unwrap();
+    assert!(outer > inner);
+
+    assert_eq!(console.time_end("outer"), None);
+    assert_eq!(console.time_end("never started"), None);
+}
+
+#[ppapi_test]
+fn console_rate_limit_suppresses_bursts(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use std::cell::RefCell;
+    use std::thread;
+    use ppapi::{ffi, FromVar, StringVar, ToVar};
+    use ppapi::ffi::{PP_LOGLEVEL_ERROR, PP_LOGLEVEL_WARNING};
+    use ppapi::ppb::ConsoleInterface;
+
+    struct FakeConsole(RefCell<Vec<(ffi::PP_LogLevel, String)>>);
+    impl ConsoleInterface for FakeConsole {
+        fn log_to_browser(&self, lvl: ffi::PP_LogLevel, value: ffi::PP_Var) {
+            let value: StringVar = FromVar::from_var(value).unwrap();
+            self.0.borrow_mut().push((lvl, value.as_str().to_string()));
+        }
+        fn log_with_source<ST: ToVar, VT: ToVar>(&self, _: ffi::PP_LogLevel, _: ST, _: VT) {
+            unreachable!()
+        }
+    }
+
+    let console = instance.console();
+    // Off by default.
+    console.err(0i32);
+    assert_eq!(instance.console_messages_suppressed(), 0);
+
+    instance.set_console_rate_limit(100);
+    for i in 0..10000 {
+        console.err(i as i32);
+    }
+    // At most a second's worth, plus what refilled during the loop, gets
+    // through.
+    let suppressed = instance.console_messages_suppressed();
+    assert!(suppressed > 9000, "only {} suppressed", suppressed);
+    assert!(suppressed < 10000);
+
+    // Levels have their own buckets.
+    console.warn(1i32);
+    assert_eq!(instance.console_messages_suppressed(), suppressed);
+
+    // One summary line per level.
+    let summary = FakeConsole(RefCell::new(Vec::new()));
+    instance.report_console_suppressed_to(&summary);
+    assert_eq!(instance.console_messages_suppressed(), 0);
+    assert_eq!(*summary.0.borrow(),
+               vec![(PP_LOGLEVEL_ERROR,
+                     format!("{} messages suppressed by the console rate limit", suppressed))]);
+
+    // Worker threads share the instance's limit.
+    let result = thread::spawn(move || {
+        let console = instance.console();
+        for i in 0..10000 {
+            console.warn(i as i32);
+        }
+    }).join();
+    assert!(result.is_ok());
+    let suppressed = instance.console_messages_suppressed();
+    assert!(suppressed > 9000, "only {} suppressed on a worker", suppressed);
+    summary.0.borrow_mut().clear();
+    instance.report_console_suppressed_to(&summary);
+    assert_eq!(*summary.0.borrow(),
+               vec![(PP_LOGLEVEL_WARNING,
+                     format!("{} messages suppressed by the console rate limit", suppressed))]);
+
+    instance.set_console_rate_limit(0);
+    for _ in 0..1000 {
+        console.err(0i32);
+    }
+    assert_eq!(instance.console_messages_suppressed(), 0);
+}
@@ -0,0 +1,27 @@
+// Copyright (c) 2014 Richard Diamond & contributors.
+//
+// This file is part of the Rust PPApi project.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+#![feature(plugin)]
+
+#[plugin] #[no_link]
+extern crate "ppapi-tester" as _ppapi_tester;
+
+#[ppapi_test]
+fn features_match_interfaces(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::Feature;
+    use ppapi::ppb;
+
+    assert_eq!(instance.supports(Feature::Graphics2D),
+               ppb::get_graphics_2d_opt().is_some());
+    assert_eq!(instance.supports(Feature::VideoDecoder),
+               ppb::get_video_decoder_opt().is_some());
+    assert_eq!(instance.supports(Feature::BrowserFont),
+               ppb::get_browser_font_opt().is_some());
+    // Core interfaces are always present.
+    assert!(instance.supports(Feature::ImageData));
+}
@@ -0,0 +1,36 @@
+// Copyright (c) 2014 Richard Diamond & contributors.
+//
+// This file is part of the Rust PPApi project.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+#![feature(plugin)]
+
+#[plugin] #[no_link]
+extern crate "ppapi-tester" as _ppapi_tester;
+
+#[ppapi_test]
+fn request_frame_timestamps_increase(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use ppapi::Ticks;
+
+    fn next(instance: ppapi::Instance, seen: Rc<RefCell<Vec<Ticks>>>) {
+        let code = instance.request_frame(move |ticks| {
+            {
+                let mut seen = seen.borrow_mut();
+                if let Some(&last) = seen.last() {
+                    assert!(ticks > last, "{} <= {}", ticks, last);
+                }
+                seen.push(ticks);
+                if seen.len() == 10 { return; }
+            }
+            next(instance, seen);
+        });
+        assert!(code.is_ok());
+    }
+
+    next(instance, Rc::new(RefCell::new(Vec::new())));
+}
@@ -0,0 +1,245 @@
+// Copyright (c) 2014 Richard Diamond & contributors.
+//
+// This file is part of the Rust PPApi project.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+#![feature(plugin)]
+
+#[plugin] #[no_link]
+extern crate "ppapi-tester" as _ppapi_tester;
+
+#[ppapi_test]
+fn file_ref_create_rename_delete(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::Code;
+    use ppapi::fs::{Kind, OpenFlags, MkDirFlags, SyncPath, SyncCommon};
+
+    let fs = instance.create_file_system(Kind::LocalTemp).unwrap();
+    let fs2 = fs.clone();
+    let code = fs.open(0, move |result: ppapi::Result<()>| {
+        let fs = fs2;
+        assert!(result.is_ok());
+
+        let from = fs.create("/from").unwrap();
+        let to = fs.create("/to").unwrap();
+        assert!(from.sync_open_io(instance, OpenFlags::new().write(true).create(true)).is_ok());
+
+        assert!(from.sync_rename(to.clone()).is_ok());
+        assert!(from.sync_query().is_err());
+        assert!(to.sync_query().is_ok());
+
+        let dir = fs.create("/dir").unwrap();
+        assert!(dir.sync_mkdir(MkDirFlags::new()).is_ok());
+        assert!(to.sync_rename(fs.create("/dir/to").unwrap()).is_ok());
+        // PPAPI doesn't recurse.
+        assert_eq!(dir.sync_delete(), Code::Failed);
+
+        assert!(fs.create("/dir/to").unwrap().sync_delete().is_ok());
+        assert!(dir.sync_delete().is_ok());
+    });
+    assert!(code.completion_pending());
+}
+
+#[ppapi_test]
+fn file_ref_delete_recursive(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::CallbackArgs;
+    use ppapi::fs::{Kind, OpenFlags, MkDirFlags, SyncPath, SyncCommon};
+
+    let fs = instance.create_file_system(Kind::LocalTemp).unwrap();
+    let fs2 = fs.clone();
+    let code = fs.open(0, move |result: ppapi::Result<()>| {
+        let fs = fs2;
+        assert!(result.is_ok());
+
+        assert!(fs.create("/tree/a/b").unwrap().sync_mkdir(MkDirFlags::new()).is_ok());
+        assert!(fs.create("/tree/c").unwrap().sync_mkdir(MkDirFlags::new()).is_ok());
+        for path in ["/tree/file", "/tree/a/file", "/tree/a/b/file"].iter() {
+            let f = fs.create(path).unwrap();
+            assert!(f.sync_open_io(instance, OpenFlags::new().write(true).create(true)).is_ok());
+        }
+
+        let tree = fs.create("/tree").unwrap();
+        let tree2 = tree.clone();
+        let code = tree.delete_recursive(CallbackArgs::new(move |code| {
+            assert!(code.is_ok());
+            assert!(tree2.sync_query().is_err());
+        }));
+        assert!(code.completion_pending());
+    });
+    assert!(code.completion_pending());
+}
+
+#[ppapi_test]
+fn isolated_file_system_write(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use std::io::Write;
+    use ppapi::{CallbackArgs, Code, Feature};
+    use ppapi::fs::{OpenFlags, FileView, SyncPath, SyncCommon};
+
+    if !instance.supports(Feature::IsolatedFileSystem) {
+        let code = instance.create_isolated_file_system(CallbackArgs::new(|_| unreachable!() ));
+        assert_eq!(code, Code::NoInterface);
+        return;
+    }
+
+    let code = instance.create_isolated_file_system(CallbackArgs::new(move |fs: Code<_>| {
+        let fs: ppapi::fs::FileSystem = fs.unwrap();
+        let file = fs.create("/isolated").unwrap();
+        let io = file.sync_open_io(instance, OpenFlags::new().write(true).create(true))
+            .unwrap();
+        assert_eq!(io.view(0, None).write(b"hello").unwrap(), 5);
+        assert_eq!(file.sync_query().unwrap().size, 5);
+        assert!(file.sync_delete().is_ok());
+    }));
+    assert!(code.completion_pending());
+}
+
+#[ppapi_test]
+fn file_io_read_ranges(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use std::io::Write;
+    use ppapi::{CallbackArgs, Code};
+    use ppapi::fs::{Kind, OpenFlags, FileView, SyncPath};
+
+    let fs = instance.create_file_system(Kind::LocalTemp).unwrap();
+    let fs2 = fs.clone();
+    let code = fs.open(0, move |result: ppapi::Result<()>| {
+        let fs = fs2;
+        assert!(result.is_ok());
+
+        let contents: Vec<u8> = (0..200).map(|i| i as u8 ).collect();
+        let file = fs.create("/ranges").unwrap();
+        let io = file.sync_open_io(instance, OpenFlags::new().read(true).write(true)
+                                   .create(true).truncate(true))
+            .unwrap();
+        assert_eq!(io.view(0, None).write(&contents[..]).unwrap(), contents.len());
+
+        let code = io.read_ranges(&[(150, 10), (0, 4), (190, 20)],
+                                  CallbackArgs::new(move |code: Code<Vec<Vec<u8>>>| {
+            let ranges = code.unwrap();
+            assert_eq!(ranges.len(), 3);
+            assert_eq!(&ranges[0][..], &contents[150..160]);
+            assert_eq!(&ranges[1][..], &contents[0..4]);
+            // Cut short at the end of the file.
+            assert_eq!(&ranges[2][..], &contents[190..200]);
+        }));
+        assert!(code.completion_pending());
+
+        assert_eq!(io.read_ranges(&[(-1, 4)], CallbackArgs::new(|_| unreachable!() )),
+                   Code::BadArgument);
+    });
+    assert!(code.completion_pending());
+}
+
+#[ppapi_test]
+fn write_atomic_replaces_contents(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use std::io::{Read, Write};
+    use ppapi::{CallbackArgs, Code};
+    use ppapi::fs::{self, Kind, OpenFlags, FileView, SyncPath, SyncCommon};
+
+    let fs = instance.create_file_system(Kind::LocalTemp).unwrap();
+    let fs2 = fs.clone();
+    let code = fs.open(0, move |result: ppapi::Result<()>| {
+        let fs = fs2;
+        assert!(result.is_ok());
+
+        let config = fs.create("/config").unwrap();
+        let io = config.sync_open_io(instance, OpenFlags::new().write(true).create(true))
+            .unwrap();
+        assert_eq!(io.view(0, None).write(b"old contents").unwrap(), 12);
+        drop(io);
+
+        let fs3 = fs.clone();
+        let code = fs::write_atomic(&instance, &fs, "/config", b"new",
+                                    CallbackArgs::new(move |code: Code<()>| {
+            assert!(code.is_ok());
+            let config = fs3.create("/config").unwrap();
+            assert_eq!(config.sync_query().unwrap().size, 3);
+            let io = config.sync_open_io(instance, OpenFlags::new().read(true)).unwrap();
+            let mut contents = Vec::new();
+            io.view(0, None).read_to_end(&mut contents).unwrap();
+            assert_eq!(&contents[..], b"new");
+            // The temp file is gone.
+            assert!(fs3.create("/config.tmp").unwrap().sync_query().is_err());
+        }));
+        assert!(code.completion_pending());
+    });
+    assert!(code.completion_pending());
+}
+
+#[ppapi_test]
+fn write_atomic_failure_keeps_original(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use std::io::Write;
+    use ppapi::{CallbackArgs, Code};
+    use ppapi::fs::{self, Kind, OpenFlags, MkDirFlags, FileView, SyncPath, SyncCommon};
+
+    let fs = instance.create_file_system(Kind::LocalTemp).unwrap();
+    let fs2 = fs.clone();
+    let code = fs.open(0, move |result: ppapi::Result<()>| {
+        let fs = fs2;
+        assert!(result.is_ok());
+
+        let config = fs.create("/config").unwrap();
+        let io = config.sync_open_io(instance, OpenFlags::new().write(true).create(true))
+            .unwrap();
+        assert_eq!(io.view(0, None).write(b"old contents").unwrap(), 12);
+        drop(io);
+        // Injects a fault: a directory where the temp file goes can't be
+        // opened for writing.
+        let temp = fs.create("/config.tmp").unwrap();
+        assert!(temp.sync_mkdir(MkDirFlags::new()).is_ok());
+
+        let fs3 = fs.clone();
+        let code = fs::write_atomic(&instance, &fs, "/config", b"new",
+                                    CallbackArgs::new(move |code: Code<()>| {
+            assert!(code.is_err());
+            assert_eq!(fs3.create("/config").unwrap().sync_query().unwrap().size, 12);
+            // Not ours to delete.
+            assert!(fs3.create("/config.tmp").unwrap().sync_query().is_ok());
+        }));
+        assert!(code.completion_pending());
+    });
+    assert!(code.completion_pending());
+}
+
+#[ppapi_test]
+fn file_io_read_owned(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use std::io::Write;
+    use ppapi::{CallbackArgs, Code};
+    use ppapi::fs::{Kind, OpenFlags, FileView, SyncPath};
+
+    let fs = instance.create_file_system(Kind::LocalTemp).unwrap();
+    let fs2 = fs.clone();
+    let code = fs.open(0, move |result: ppapi::Result<()>| {
+        let fs = fs2;
+        assert!(result.is_ok());
+
+        let contents: Vec<u8> = (0..100).map(|i| i as u8 ).collect();
+        let file = fs.create("/owned").unwrap();
+        let io = file.sync_open_io(instance, OpenFlags::new().read(true).write(true)
+                                   .create(true).truncate(true))
+            .unwrap();
+        assert_eq!(io.view(0, None).write(&contents[..]).unwrap(), contents.len());
+
+        match io.read_owned(0, 0, CallbackArgs::new(|_| unreachable!() )) {
+            Code::Ok(data) => assert!(data.is_empty()),
+            _ => panic!("expected an empty read to complete at once"),
+        }
+
+        let io2 = io.clone();
+        let code = io.read_owned(10, 20, CallbackArgs::new(move |code: Code<Vec<u8>>| {
+            assert_eq!(&code.unwrap()[..], &contents[10..30]);
+
+            // Short read at the end of the file. Only one read can be in
+            // flight per handle, so this waits for the first.
+            let code = io2.read_owned(90, 50, CallbackArgs::new(move |code: Code<Vec<u8>>| {
+                let data = code.unwrap();
+                assert_eq!(data.len(), 10);
+                assert_eq!(&data[..], &contents[90..]);
+            }));
+            assert!(code.completion_pending());
+        }));
+        assert!(code.completion_pending());
+    });
+    assert!(code.completion_pending());
+}
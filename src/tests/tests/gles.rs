@@ -0,0 +1,340 @@
+// Copyright (c) 2014 Richard Diamond & contributors.
+//
+// This file is part of the Rust PPApi project.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+#![feature(plugin)]
+
+#[plugin] #[no_link]
+extern crate "ppapi-tester" as _ppapi_tester;
+
+#[ppapi_test]
+fn read_back_cleared_pixel(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::{Rect, Point, Size};
+    use ppapi::gles::{consts, TexFormat};
+    use ppapi::gles::Context3dAttrib::{Width, Height};
+
+    let ctxt = instance.create_3d_context(None, &[Width(4), Height(4)]).unwrap();
+    ctxt.clear_color(1.0, 0.0, 0.0, 1.0);
+    ctxt.clear(consts::COLOR_BUFFER_BIT);
+
+    let pixel = ctxt.read_pixels(Rect::new(Point::new(0, 0), Size::new(1, 1)),
+                                 TexFormat::Rgba).unwrap();
+    assert_eq!(pixel, vec!(255u8, 0, 0, 255));
+}
+
+#[ppapi_test]
+fn capabilities(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::gles::{GLStringName, Gl3dCaps};
+    use ppapi::gles::Context3dAttrib::{Width, Height};
+
+    let ctxt = instance.create_3d_context(None, &[Width(4), Height(4)]).unwrap();
+    let renderer = ctxt.get_string(GLStringName::Renderer);
+    instance.console().print(ppapi::StringVar::new(format!("renderer: {}", renderer)));
+    assert!(!renderer.is_empty());
+
+    let caps = ctxt.capabilities();
+    // GLES2 requires at least these.
+    assert!(caps.max_texture_size >= 64);
+    assert!(caps.max_vertex_attribs >= 8);
+
+    let caps = Gl3dCaps::from_extensions("GL_ANGLE_instanced_arrays GL_OES_depth_texture  \
+                                          GL_EXT_texture_compression_dxt1");
+    assert!(caps.instanced_arrays && caps.depth_texture && caps.texture_compression_s3tc);
+    assert!(!caps.vertex_array_object);
+    assert!(caps.has_extension("GL_OES_depth_texture") && caps.has_extension("OES_depth_texture"));
+    assert_eq!(caps.extensions.len(), 3);
+}
+
+#[ppapi_test]
+fn share_context_same_instance(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::gles::Context3dAttrib::{Width, Height};
+
+    // The harness only embeds one instance, so the cross instance case
+    // (`Code::BadArgument`) can't be exercised here; this checks the
+    // ownership check lets same instance sharing through.
+    let first = instance.create_3d_context(None, &[Width(4), Height(4)]).unwrap();
+    let shared = instance.create_3d_context(Some(first.clone()), &[Width(4), Height(4)]);
+    assert!(shared.is_ok());
+}
+
+#[ppapi_test]
+fn compile_and_link_shaders(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::gles::ShaderKind;
+    use ppapi::gles::Context3dAttrib::{Width, Height};
+
+    let ctxt = instance.create_3d_context(None, &[Width(4), Height(4)]).unwrap();
+    let vert = ctxt.compile_shader(ShaderKind::Vertex,
+                                   "attribute vec4 pos; void main() { gl_Position = pos; }")
+        .unwrap();
+    let frag = ctxt.compile_shader(ShaderKind::Fragment,
+                                   "void main() { gl_FragColor = vec4(1.0); }")
+        .unwrap();
+    assert_eq!(vert.kind(), ShaderKind::Vertex);
+    assert!(ctxt.link_program(&[&vert, &frag]).is_ok());
+
+    let log = ctxt.compile_shader(ShaderKind::Fragment, "void main() { not glsl }")
+        .err()
+        .unwrap();
+    assert!(!log.is_empty());
+    assert!(!log.ends_with("\0"));
+
+    // A fragment shader alone can't be linked.
+    assert!(ctxt.link_program(&[&frag]).is_err());
+}
+
+#[ppapi_test]
+fn read_back_attribs(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::gles::AttribKey;
+    use ppapi::gles::Context3dAttrib::{Width, Height, DepthSize};
+
+    let ctxt = instance.create_3d_context(None, &[Width(8), Height(4), DepthSize(24)]).unwrap();
+    let attribs = ctxt.get_attribs(&[AttribKey::Width, AttribKey::DepthSize,
+                                     AttribKey::Height]).unwrap();
+    assert_eq!(attribs.len(), 3);
+    assert_eq!(attribs[0], (AttribKey::Width, 8));
+    assert_eq!(attribs[2], (AttribKey::Height, 4));
+    let (key, depth) = attribs[1];
+    assert_eq!(key, AttribKey::DepthSize);
+    // Implementations may round down to 16 bits.
+    assert!(depth >= 16, "depth size: {}", depth);
+
+    assert!(ctxt.get_error().is_ok());
+}
+
+#[ppapi_test]
+fn apply_interleaved_vertex_layout(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use std::borrow::Cow;
+    use std::mem;
+    use ppapi::{Rect, Point, Size};
+    use ppapi::gles::{consts, BufferData, ShaderKind, StaticBufferUsage, TexFormat,
+                      TrianglesGeometryMode, VertexAttribType, VertexBuffer, VertexLayout};
+    use ppapi::gles::traits::GenBuffer;
+    use ppapi::gles::Context3dAttrib::{Width, Height};
+
+    let ctxt = instance.create_3d_context(None, &[Width(4), Height(4)]).unwrap();
+    let vert = ctxt.compile_shader(ShaderKind::Vertex,
+                                   "attribute vec2 pos; attribute vec4 color; \
+                                    varying vec4 v_color; \
+                                    void main() { v_color = color; \
+                                                  gl_Position = vec4(pos, 0.0, 1.0); }")
+        .unwrap();
+    let frag = ctxt.compile_shader(ShaderKind::Fragment,
+                                   "precision mediump float; varying vec4 v_color; \
+                                    void main() { gl_FragColor = v_color; }")
+        .unwrap();
+    let program = ctxt.link_program(&[&vert, &frag]).unwrap();
+    let pos = program.attr_locus(&ctxt, "pos").unwrap();
+    let color = program.attr_locus(&ctxt, "color").unwrap();
+
+    // Two floats of position then four bytes of color, per vertex; one
+    // triangle covering the whole viewport.
+    let mut data: Vec<u8> = Vec::new();
+    for &(x, y) in [(-1.0f32, -1.0f32), (3.0, -1.0), (-1.0, 3.0)].iter() {
+        let x: [u8; 4] = unsafe { mem::transmute(x) };
+        let y: [u8; 4] = unsafe { mem::transmute(y) };
+        data.extend(x.iter().cloned());
+        data.extend(y.iter().cloned());
+        data.extend([0u8, 255, 0, 255].iter().cloned());
+    }
+    let buffer: VertexBuffer = GenBuffer::gen_single(&ctxt);
+    buffer.bind_vtx_buf(&ctxt)
+        .buffer_vertex_data(&ctxt, BufferData::Fill(Cow::Borrowed(&data[..])),
+                            StaticBufferUsage);
+
+    let layout = VertexLayout::new()
+        .attrib_in(&buffer, pos, 2, VertexAttribType::Float, false, 12, 0)
+        .attrib_in(&buffer, color, 4, VertexAttribType::UByte, true, 12, 8);
+    assert!(layout.problems().is_empty());
+
+    let _bound = program.use_program(&ctxt);
+    ctxt.clear_color(0.0, 0.0, 0.0, 1.0);
+    ctxt.clear(consts::COLOR_BUFFER_BIT);
+    ctxt.apply_vertex_layout(&layout);
+    buffer.bind_vtx_buf(&ctxt).draw_slice(&ctxt, TrianglesGeometryMode, 0, 3);
+
+    let pixel = ctxt.read_pixels(Rect::new(Point::new(1, 1), Size::new(1, 1)),
+                                 TexFormat::Rgba).unwrap();
+    assert_eq!(pixel, vec!(0u8, 255, 0, 255));
+    assert!(ctxt.get_error().is_ok());
+
+    // Colors overlapping the next vertex, and a misaligned float.
+    let bad = VertexLayout::new()
+        .attrib(pos, 2, VertexAttribType::Float, false, 12, 2)
+        .attrib(color, 4, VertexAttribType::UByte, true, 12, 10);
+    assert_eq!(bad.problems().len(), 2);
+}
+
+#[ppapi_test]
+fn time_frame_clear(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::gles::consts;
+    use ppapi::gles::Context3dAttrib::{Width, Height};
+
+    let ctxt = instance.create_3d_context(None, &[Width(4), Height(4)]).unwrap();
+    let mut ran = false;
+    let elapsed = ctxt.time_frame(|| {
+        ran = true;
+        ctxt.clear_color(0.0, 0.0, 1.0, 1.0);
+        ctxt.clear(consts::COLOR_BUFFER_BIT);
+    });
+    // `f` runs whether or not it can be timed.
+    assert!(ran);
+    match elapsed {
+        Some(elapsed) => assert!(elapsed >= 0.0),
+        // Either unsupported or disjoint; both are fine here.
+        None => instance.console().print(ppapi::StringVar::new("GPU timing unavailable")),
+    }
+    assert!(ctxt.get_error().is_ok());
+}
+
+#[ppapi_test]
+fn auto_resize_bound_context(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::{Code, Size};
+    use ppapi::gles::AttribKey;
+    use ppapi::gles::Context3dAttrib::{Width, Height};
+
+    let ctxt = instance.create_3d_context(None, &[Width(4), Height(4)]).unwrap();
+    // Not bound yet.
+    assert_eq!(instance.auto_resize_3d_context(&ctxt), Code::Failed);
+
+    assert!(instance.bind_context(&ctxt).is_ok());
+    assert!(instance.auto_resize_3d_context(&ctxt).is_ok());
+    // What a view change to a 100x50 rect at 2x does.
+    assert!(instance.auto_resize_3d_to(Size::new(100, 50), 2.0) == Some(Size::new(200, 100)));
+    let attribs = ctxt.get_attribs(&[AttribKey::Width, AttribKey::Height]).unwrap();
+    assert_eq!(attribs[0], (AttribKey::Width, 200));
+    assert_eq!(attribs[1], (AttribKey::Height, 100));
+
+    // Binding something else turns it off.
+    let other = instance.create_3d_context(None, &[Width(4), Height(4)]).unwrap();
+    assert!(instance.bind_context(&other).is_ok());
+    assert!(instance.auto_resize_3d_to(Size::new(300, 300), 1.0).is_none());
+    let attribs = ctxt.get_attribs(&[AttribKey::Width]).unwrap();
+    assert_eq!(attribs[0], (AttribKey::Width, 200));
+}
+
+#[ppapi_test]
+fn state_call_overhead(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::gles::Context3dAttrib::{Width, Height};
+
+    const CALLS: usize = 10000;
+
+    let ctxt = instance.create_3d_context(None, &[Width(4), Height(4)]).unwrap();
+    let start = ppapi::now_ticks();
+    for _ in 0..CALLS {
+        ctxt.clear_color(0.0, 0.0, 0.0, 1.0);
+    }
+    let elapsed = ppapi::now_ticks() - start;
+    assert!(ctxt.get_error().is_ok());
+
+    let msg = format!("{} glClearColor calls took {:.3}ms ({:.3}us each)",
+                      CALLS, elapsed * 1000.0, elapsed * 1000000.0 / CALLS as f64);
+    instance.console().print(ppapi::StringVar::new(msg));
+}
+
+#[ppapi_test]
+fn state_cache_skips_redundant_changes(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use std::cell::RefCell;
+    use ppapi::gles::{consts, types, note_context_lost, ShaderProgram, StateCache,
+                      StateTarget, TextureBuffer, VertexBuffer};
+
+    #[derive(Default)]
+    struct Recorder(RefCell<Vec<&'static str>>);
+    impl StateTarget for Recorder {
+        fn use_program(&self, _: types::UInt) { self.0.borrow_mut().push("use_program"); }
+        fn bind_buffer(&self, _: types::Enum, _: types::UInt) {
+            self.0.borrow_mut().push("bind_buffer");
+        }
+        fn bind_framebuffer(&self, _: types::UInt) { self.0.borrow_mut().push("bind_framebuffer"); }
+        fn bind_renderbuffer(&self, _: types::UInt) {
+            self.0.borrow_mut().push("bind_renderbuffer");
+        }
+        fn active_texture(&self, _: types::Enum) { self.0.borrow_mut().push("active_texture"); }
+        fn bind_texture(&self, _: types::Enum, _: types::UInt) {
+            self.0.borrow_mut().push("bind_texture");
+        }
+        fn set_enabled(&self, _: types::Enum, _: bool) { self.0.borrow_mut().push("set_enabled"); }
+    }
+    let calls = |cache: &StateCache<Recorder>| cache.target().0.borrow().len();
+
+    let mut cache = StateCache::with_target(Recorder::default());
+    let program: ShaderProgram = From::from(1);
+    let buffer: VertexBuffer = From::from(2);
+    let texture: TextureBuffer = From::from(3);
+    for _ in 0..10 {
+        cache.use_program(&program);
+        cache.bind_vertex_buffer(&buffer);
+        cache.activate_tex_slot(0);
+        cache.bind_texture(consts::TEXTURE_2D, &texture);
+        cache.enable(consts::BLEND);
+    }
+    assert_eq!(&cache.target().0.borrow()[..],
+               &["use_program", "bind_buffer", "active_texture", "bind_texture",
+                 "set_enabled"]);
+
+    // Each unit has its own bindings.
+    cache.activate_tex_slot(1);
+    cache.bind_texture(consts::TEXTURE_2D, &texture);
+    assert_eq!(calls(&cache), 7);
+    cache.disable(consts::BLEND);
+    cache.disable(consts::BLEND);
+    assert_eq!(calls(&cache), 8);
+
+    cache.invalidate();
+    cache.use_program(&program);
+    cache.use_program(&program);
+    assert_eq!(calls(&cache), 9);
+
+    // What the crate does when the browser says a context was lost.
+    note_context_lost();
+    cache.use_program(&program);
+    assert_eq!(calls(&cache), 10);
+}
+
+#[ppapi_test]
+fn texture_from_image_data(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::{Code, Rect, Point, Size};
+    use ppapi::imagedata::{native_image_data_format, Format};
+    use ppapi::gles::{consts, ColorAttachment, FrameBuffer, TexFormat, TextureBuffer};
+    use ppapi::gles::traits::GenBuffer;
+    use ppapi::gles::Context3dAttrib::{Width, Height};
+
+    let ctxt = instance.create_3d_context(None, &[Width(4), Height(4)]).unwrap();
+    let format = native_image_data_format();
+    let image = instance.create_image(None, Size::new(2, 1), true).unwrap();
+    {
+        // Opaque red, then opaque blue.
+        let mut mapped = image.map();
+        let pixels = mapped.as_mut_slice();
+        let (red, blue) = match format {
+            Format::RGBA => ([255u8, 0, 0, 255], [0u8, 0, 255, 255]),
+            Format::BGRA => ([0u8, 0, 255, 255], [255u8, 0, 0, 255]),
+        };
+        for (d, s) in pixels[..8].iter_mut().zip(red.iter().chain(blue.iter())) {
+            *d = *s;
+        }
+    }
+
+    let tex: TextureBuffer = GenBuffer::gen_single(&ctxt);
+    let _bound = tex.bind_tex_buf(&ctxt, consts::TEXTURE_2D);
+    let code = ctxt.tex_image_from_image_data(consts::TEXTURE_2D, 0, &image);
+    if format == Format::BGRA && !ctxt.capabilities().texture_format_bgra8888 {
+        assert_eq!(code, Code::NotSupported);
+        return;
+    }
+    assert_eq!(code, Code::Ok(()));
+
+    // Read the texture back through a framebuffer; it comes out as RGBA
+    // whichever format it was uploaded in.
+    let fbo: FrameBuffer = GenBuffer::gen_single(&ctxt);
+    let mut bound_fbo = fbo.bind_fbo_buf(&ctxt);
+    bound_fbo.attach_tex2d(&ctxt, ColorAttachment(0), tex.clone(), 0);
+    assert_eq!(bound_fbo.check_status(&ctxt), consts::FRAMEBUFFER_COMPLETE);
+    let pixels = ctxt.read_pixels(Rect::new(Point::new(0, 0), Size::new(2, 1)),
+                                  TexFormat::Rgba).unwrap();
+    assert_eq!(pixels, vec!(255u8, 0, 0, 255, 0, 0, 255, 255));
+    assert!(ctxt.get_error().is_ok());
+}
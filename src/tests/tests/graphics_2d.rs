@@ -0,0 +1,217 @@
+// Copyright (c) 2014 Richard Diamond & contributors.
+//
+// This file is part of the Rust PPApi project.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+#![feature(plugin)]
+
+#[plugin] #[no_link]
+extern crate "ppapi-tester" as _ppapi_tester;
+
+#[ppapi_test]
+fn paint_mismatched_image_format(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::{Code, Size, Point};
+    use ppapi::imagedata::{native_image_data_format, Format};
+
+    let size = Size::new(16, 16);
+    let context = instance.create_2d_context(size, true).unwrap();
+    assert!(context.describe().map(|(s, opaque)| s == size && opaque ).unwrap());
+
+    let other = match native_image_data_format() {
+        Format::BGRA => Format::RGBA,
+        Format::RGBA => Format::BGRA,
+    };
+    if !other.is_supported() { return; }
+
+    let image = instance.create_image(Some(other), size, true).unwrap();
+    assert_eq!(context.paint_image_data(&image, Point::new(0, 0), None),
+               Code::BadArgument);
+    assert_eq!(context.replace_contents(&image), Code::BadArgument);
+
+    let native = instance.create_image(None, size, true).unwrap();
+    assert!(context.paint_image_data(&native, Point::new(0, 0), None).is_ok());
+}
+
+#[ppapi_test]
+fn read_pixels_after_flush(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::{CallbackArgs, Code, Size, Point};
+    use ppapi::imagedata::MappedSlice;
+
+    let size = Size::new(4, 4);
+    let context = instance.create_2d_context(size, true).unwrap();
+    assert!(instance.bind_context(&context).is_ok());
+    assert!(context.enable_readback(&instance).is_ok());
+
+    let image = instance.create_image(None, size, false).unwrap();
+    {
+        let mut mapped = image.map();
+        for byte in mapped.as_mut_slice().iter_mut() {
+            *byte = 0x7f;
+        }
+    }
+    assert!(context.paint_image_data(&image, Point::new(0, 0), None).is_ok());
+
+    let context2 = context.clone();
+    let code = context.flush(CallbackArgs::new(move |code: Code<()>| {
+        assert!(code.is_ok());
+        // Unflushed paints must not show up in the read back.
+        let blank = instance.create_image(None, size, true).unwrap();
+        assert!(context2.paint_image_data(&blank, Point::new(0, 0), None).is_ok());
+
+        let code = context2.read_pixels(CallbackArgs::new(move |image: Code<_>| {
+            let image: ppapi::imagedata::ImageData = image.unwrap();
+            let mapped = image.map();
+            assert!(mapped.as_imm_slice().iter().all(|&b| b == 0x7f ));
+        }));
+        assert!(code.completion_pending());
+    }));
+    assert!(code.completion_pending());
+    // The flush is in flight, so this waits for it.
+    let code = context.read_pixels(CallbackArgs::new(move |image: Code<_>| {
+        let image: ppapi::imagedata::ImageData = image.unwrap();
+        assert!(image.map().as_imm_slice().iter().all(|&b| b == 0x7f ));
+    }));
+    assert!(code.completion_pending());
+}
+
+#[ppapi_test]
+fn rebind_previous_context(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::{Resource, Size};
+
+    let a = instance.create_2d_context(Size::new(4, 4), true).unwrap();
+    let b = instance.create_2d_context(Size::new(8, 8), true).unwrap();
+
+    assert!(instance.bind_context_returning_prev(&a).unwrap().is_none());
+    let prev = instance.bind_context_returning_prev(&b).unwrap().unwrap();
+    assert_eq!(prev.unwrap(), a.unwrap());
+
+    // `prev` has its own reference, so `a` can go away.
+    let a_res = a.unwrap();
+    drop(a);
+    let prev = instance.bind_context_returning_prev(&prev).unwrap().unwrap();
+    assert_eq!(prev.unwrap(), b.unwrap());
+    assert!(instance.bind_context_returning_prev(&b).unwrap()
+            .map(|prev| prev.unwrap() == a_res )
+            .unwrap_or(false));
+}
+
+#[ppapi_test]
+fn surface_coalesces_resizes(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::{MessageLoop, Point, Size};
+    use ppapi::surface::{Surface2d, RESIZE_SETTLE_MS};
+
+    let surface = Surface2d::new(&instance, Size::new(100, 100), true).unwrap();
+    let old = surface.context();
+    for i in 1..6 {
+        surface.resize(Size::new(100 + i * 10, 100));
+    }
+    // Still the old buffer, which can be painted to in the meantime.
+    assert!(surface.context() == old);
+    assert!(surface.size() == Size::new(100, 100));
+    assert!(surface.pending_size() == Some(Size::new(150, 100)));
+    let image = instance.create_image(None, Size::new(150, 100), true).unwrap();
+    assert!(surface.context().paint_image_data(&image, Point::new(0, 0), None).is_ok());
+    assert_eq!(surface.reallocations(), 0);
+
+    let surface2 = surface.clone();
+    let code = MessageLoop::post_to_self(move |_| {
+        let surface = surface2;
+        assert_eq!(surface.reallocations(), 1);
+        assert!(surface.size() == Size::new(150, 100));
+        assert!(surface.pending_size().is_none());
+        assert!(surface.context().describe().unwrap().0 == Size::new(150, 100));
+    }, RESIZE_SETTLE_MS * 4);
+    assert!(code.is_ok());
+}
+
+#[ppapi_test]
+fn surface_keeps_its_context_when_reallocation_fails(instance: ppapi::Instance,
+                                                     args: HashMap<String, String>) {
+    use std::thread;
+    use ppapi::Size;
+    use ppapi::surface::Surface2d;
+
+    // No message loop, so resizes reallocate immediately.
+    let result = thread::spawn(move || {
+        let surface = Surface2d::new(&instance, Size::new(100, 100), true).unwrap();
+        let old = surface.context();
+        // The browser won't create an empty context.
+        surface.resize(Size::new(0, 0));
+        assert!(surface.pending_size().is_none());
+        assert!(surface.resize_error().is_some());
+        assert!(surface.context() == old);
+        assert!(surface.size() == Size::new(100, 100));
+        assert_eq!(surface.reallocations(), 0);
+
+        surface.resize(Size::new(120, 100));
+        assert!(surface.resize_error().is_none());
+        assert!(surface.size() == Size::new(120, 100));
+        assert_eq!(surface.reallocations(), 1);
+    }).join();
+    assert!(result.is_ok());
+}
+
+#[ppapi_test]
+fn surface_presents_dirty_rects(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use ppapi::{CallbackArgs, Code, Point, Rect, Size};
+    use ppapi::imagedata::MappedSlice;
+    use ppapi::surface::Surface2d;
+
+    let size = Size::new(8, 8);
+    let surface = Surface2d::new(&instance, size, true).unwrap();
+    assert!(surface.dirty_rects() == vec![Rect::new(Point::new(0, 0), size)]);
+    let blank = instance.create_image(None, size, true).unwrap();
+    let code = surface.present(&blank, CallbackArgs::new(|code: Code<()>| assert!(code.is_ok()) ));
+    assert!(code.completion_pending());
+    assert!(surface.dirty_rects().is_empty());
+    assert!(surface.context().enable_readback(&instance).is_ok());
+
+    surface.mark_dirty(Rect::new(Point::new(0, 0), Size::new(4, 4)));
+    surface.mark_dirty(Rect::new(Point::new(2, 2), Size::new(4, 4)));
+    let merged = Rect::new(Point::new(0, 0), Size::new(6, 6));
+    assert!(surface.dirty_rects() == vec![merged]);
+
+    let image = instance.create_image(None, size, false).unwrap();
+    {
+        let mut mapped = image.map();
+        for byte in mapped.as_mut_slice().iter_mut() {
+            *byte = 0x7f;
+        }
+    }
+    let context = surface.context();
+    let code = surface.present(&image, CallbackArgs::new(move |code: Code<()>| {
+        assert!(code.is_ok());
+        let code = context.read_pixels(CallbackArgs::new(move |image: Code<_>| {
+            let image: ppapi::imagedata::ImageData = image.unwrap();
+            let mapped = image.map();
+            let stride = mapped.desc.line_stride;
+            let pixels = mapped.as_imm_slice();
+            for y in 0..size.height {
+                for x in 0..size.width {
+                    let painted = x < 6 && y < 6;
+                    let byte = pixels[(y * stride + x * 4) as usize];
+                    assert_eq!(byte == 0x7f, painted);
+                }
+            }
+        }));
+        assert!(code.completion_pending());
+    }));
+    assert!(code.completion_pending());
+    assert!(surface.dirty_rects().is_empty());
+
+    // Nothing is dirty, but the callback still comes.
+    let called = Rc::new(Cell::new(false));
+    let called2 = called.clone();
+    let code = surface.present(&image, CallbackArgs::new(move |code: Code<()>| {
+        assert!(code.is_ok());
+        called2.set(true);
+    }));
+    assert!(code.completion_pending());
+    let code = ppapi::MessageLoop::post_to_self(move |_| assert!(called.get()), 0);
+    assert!(code.is_ok());
+}
@@ -0,0 +1,207 @@
+// Copyright (c) 2014 Richard Diamond & contributors.
+//
+// This file is part of the Rust PPApi project.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+#![feature(plugin)]
+
+#[plugin] #[no_link]
+extern crate "ppapi-tester" as _ppapi_tester;
+
+#[ppapi_test]
+fn loader_follow_redirect(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::{CallbackArgs, Code};
+    use ppapi::http::{Loader, RequestInfo, RequestProperties_};
+
+    let url = format!("{}/redirect", args["fixtures"]);
+    let mut request = RequestInfo::new(url.parse().unwrap(),
+                                       "GET".parse().unwrap(), None, None);
+    request.set_prop(RequestProperties_::FollowRedirects, false);
+    let request = request.create_resource(instance).unwrap();
+
+    let code = Loader::async_open(instance, request, CallbackArgs::new(|loader: Code<Loader>| {
+        let loader = loader.unwrap();
+        let info = loader.get_response_info().unwrap();
+        assert_eq!(info.raw_status_code() / 100, 3);
+        assert!(info.raw_headers_str().contains("Location: /get"));
+        assert!(info.redirect_url().is_some());
+
+        let loader2 = loader.clone();
+        let code = loader.follow_redirect(CallbackArgs::new(move |code: Code<()>| {
+            let loader = loader2;
+            assert!(code.is_ok());
+            let info = loader.get_response_info().unwrap();
+            assert_eq!(info.raw_status_code(), 200);
+            // Not at a redirect anymore.
+            assert_eq!(loader.follow_redirect(CallbackArgs::new(|_| unreachable!() )),
+                       Code::Failed);
+        }));
+        assert!(code.completion_pending());
+    }));
+    assert!(code.completion_pending());
+}
+
+#[ppapi_test]
+fn upload_file_slice(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use std::io::Write;
+    use ppapi::{CallbackArgs, Code};
+    use ppapi::fs::{Kind, OpenFlags, FileView, SyncPath};
+    use ppapi::http::{Body, FileSliceRef, Loader, RequestInfo, RequestProperties_};
+
+    let url = format!("{}/post", args["fixtures"]);
+    let fs = instance.create_file_system(Kind::LocalTemp).unwrap();
+    let fs2 = fs.clone();
+    let code = fs.open(0, move |result: ppapi::Result<()>| {
+        let fs = fs2;
+        assert!(result.is_ok());
+
+        let file = fs.create("/upload").unwrap();
+        let io = file.sync_open_io(instance, OpenFlags::new().write(true)
+                                   .create(true).truncate(true))
+            .unwrap();
+        let contents: Vec<u8> = (0..100).map(|i| i as u8 ).collect();
+        assert_eq!(io.view(0, None).write(&contents[..]).unwrap(), contents.len());
+
+        let upload = |start, len, expected: u64| {
+            let mut request = RequestInfo::new(url.parse().unwrap(),
+                                               "POST".parse().unwrap(), None, None);
+            request.set_prop(RequestProperties_::RecordUploadProgress, true);
+            let slice = FileSliceRef(file.clone(), start, len);
+            assert!(request.append_body(Body::FileSlice(slice, None)).is_ok());
+            let request = request.create_resource(instance).unwrap();
+            let code = Loader::async_open(instance, request,
+                                          CallbackArgs::new(move |loader: Code<Loader>| {
+                let loader = loader.unwrap();
+                assert_eq!(loader.get_response_info().unwrap().raw_status_code(), 200);
+                assert_eq!(loader.upload_progress(), Some((expected, expected)));
+            }));
+            assert!(code.completion_pending());
+        };
+        upload(Some(10), Some(50), 50);
+        // No length sends the rest of the file.
+        upload(Some(10), None, 90);
+
+        let mut request = RequestInfo::new(url.parse().unwrap(),
+                                           "POST".parse().unwrap(), None, None);
+        let slice = FileSliceRef(file.clone(), Some(-1), None);
+        assert_eq!(request.append_body(Body::FileSlice(slice, None)), Code::BadArgument);
+        assert!(request.bodies.is_empty());
+    });
+    assert!(code.completion_pending());
+}
+
+#[ppapi_test]
+fn one_shot_get(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::{CallbackArgs, Code};
+    use ppapi::http::{self, Response};
+
+    let code = http::get(&instance, &format!("{}/bytes/1024", args["fixtures"]),
+                         CallbackArgs::new(|response: Code<Response>| {
+        let response = response.unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body.len(), 1024);
+        assert!(response.body_error.is_none());
+        assert!(response.is_success());
+    }));
+    assert!(code.completion_pending());
+
+    // Delivered, not an error.
+    let code = http::get(&instance, &format!("{}/status/404", args["fixtures"]),
+                         CallbackArgs::new(|response: Code<Response>| {
+        let response = response.unwrap();
+        assert_eq!(response.status, 404);
+        assert!(!response.is_success());
+    }));
+    assert!(code.completion_pending());
+
+    assert_eq!(http::get(&instance, "not a url", CallbackArgs::new(|_| unreachable!() )),
+               Code::BadArgument);
+}
+
+#[ppapi_test]
+fn loader_tee(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use ppapi::{CallbackArgs, Code};
+    use ppapi::http::{BodyReader, Loader, RequestInfo, BODY_READ_SIZE};
+
+    const LIMIT: usize = 1024;
+    type Results = Rc<RefCell<Vec<(Vec<u8>, bool)>>>;
+
+    // Returns false once the whole body has been read.
+    fn append(results: &Results, side: usize, data: Vec<u8>) -> bool {
+        let mut results = results.borrow_mut();
+        if data.is_empty() {
+            results[side].1 = true;
+            if results[0].1 && results[1].1 {
+                assert_eq!(results[0].0.len(), 10000);
+                assert!(results[0].0 == results[1].0);
+            }
+            false
+        } else {
+            results[side].0.extend(data.into_iter());
+            true
+        }
+    }
+    fn drain(reader: Rc<BodyReader>, side: usize, results: Results) {
+        loop {
+            assert!(reader.buffered() <= LIMIT + BODY_READ_SIZE);
+            let (reader2, results2) = (reader.clone(), results.clone());
+            let code = reader.read(CallbackArgs::new(move |code: Code<Vec<u8>>| {
+                if append(&results2, side, code.unwrap()) {
+                    drain(reader2, side, results2);
+                }
+            }));
+            match code {
+                Code::CompletionPending => return,
+                code => if !append(&results, side, code.unwrap()) { return; },
+            }
+        }
+    }
+
+    let url = format!("{}/bytes/10000", args["fixtures"]);
+    let request = RequestInfo::new(url.parse().unwrap(),
+                                   "GET".parse().unwrap(), None, None)
+        .follow_redirects()
+        .create_resource(instance)
+        .unwrap();
+    let code = Loader::async_open(instance, request, CallbackArgs::new(|loader: Code<Loader>| {
+        let (a, b) = loader.unwrap().tee_with_limit(LIMIT);
+        let results: Results = Rc::new(RefCell::new(vec![(Vec::new(), false),
+                                                         (Vec::new(), false)]));
+        drain(Rc::new(a), 0, results.clone());
+        drain(Rc::new(b), 1, results);
+    }));
+    assert!(code.completion_pending());
+}
+
+#[ppapi_test]
+fn loader_close_aborts_read(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::{CallbackArgs, Code};
+    use ppapi::http::{Loader, RequestInfo};
+
+    // The fixture server sends this a byte a second, so the body is still
+    // coming when we close.
+    let url = format!("{}/slow/30", args["fixtures"]);
+    let request = RequestInfo::new(url.parse().unwrap(),
+                                   "GET".parse().unwrap(), None, None);
+    let request = request.create_resource(instance).unwrap();
+
+    let code = Loader::async_open(instance, request, CallbackArgs::new(|loader: Code<Loader>| {
+        let loader = loader.unwrap();
+        let code = loader.read_to_end(CallbackArgs::new(|body: Code<Vec<u8>>| {
+            assert_eq!(body, Code::Aborted);
+        }));
+        assert!(code.completion_pending());
+
+        loader.close();
+        // Closing again is fine, and later reads don't hang.
+        loader.close();
+        let code = loader.read_to_end(CallbackArgs::new(|_| unreachable!() ));
+        assert_eq!(code, Code::Aborted);
+    }));
+    assert!(code.completion_pending());
+}
@@ -0,0 +1,116 @@
+// Copyright (c) 2014 Richard Diamond & contributors.
+//
+// This file is part of the Rust PPApi project.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+#![feature(plugin)]
+
+#[plugin] #[no_link]
+extern crate "ppapi-tester" as _ppapi_tester;
+
+#[ppapi_test]
+fn create_image_zeroed(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::Size;
+    use ppapi::imagedata::MappedSlice;
+
+    let image = instance.create_image(None, Size::new(8, 8), true).unwrap();
+    let mapped = image.map();
+    let pixels = mapped.as_imm_slice();
+    assert_eq!(&pixels[..4], &[0u8, 0, 0, 0][..]);
+    assert!(pixels.iter().all(|&b| b == 0 ));
+}
+
+#[ppapi_test]
+fn create_image_format_support(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::Size;
+    use ppapi::imagedata::{is_image_data_format_supported, native_image_data_format, Format};
+
+    assert!(is_image_data_format_supported(native_image_data_format()));
+    for &format in [Format::BGRA, Format::RGBA].iter() {
+        let image = instance.create_image(Some(format), Size::new(1, 1), true);
+        assert_eq!(image.is_some(), is_image_data_format_supported(format));
+    }
+}
+
+#[ppapi_test]
+fn blit_sub_rect(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::{Code, Point, Rect, Size};
+    use ppapi::imagedata::{BlendMode, Format, MappedSlice, is_image_data_format_supported};
+
+    let pixel = |image: &ppapi::imagedata::ImageData, x: usize, y: usize| -> Vec<u8> {
+        let mapped = image.map();
+        let offset = y * mapped.desc.line_stride as usize + x * 4;
+        mapped.as_imm_slice()[offset..offset + 4].to_vec()
+    };
+
+    let src = instance.create_image(None, Size::new(2, 2), true).unwrap();
+    for b in src.map().as_mut_slice().iter_mut() {
+        *b = 255;
+    }
+    let mut dst = instance.create_image(None, Size::new(4, 4), true).unwrap();
+
+    let all = Rect::new(Point::new(0, 0), Size::new(2, 2));
+    assert!(dst.blit(&src, all, Point::new(1, 1), BlendMode::Copy).is_ok());
+    assert_eq!(pixel(&dst, 0, 0), vec!(0u8, 0, 0, 0));
+    assert_eq!(pixel(&dst, 1, 1), vec!(255u8, 255, 255, 255));
+    assert_eq!(pixel(&dst, 2, 2), vec!(255u8, 255, 255, 255));
+    assert_eq!(pixel(&dst, 3, 3), vec!(0u8, 0, 0, 0));
+    assert_eq!(pixel(&dst, 3, 1), vec!(0u8, 0, 0, 0));
+
+    // Hangs off the edge of both images; clipped to the one pixel that fits.
+    let big = Rect::new(Point::new(1, 1), Size::new(10, 10));
+    assert!(dst.blit(&src, big, Point::new(3, 3), BlendMode::Copy).is_ok());
+    assert_eq!(pixel(&dst, 3, 3), vec!(255u8, 255, 255, 255));
+    assert_eq!(pixel(&dst, 3, 2), vec!(0u8, 0, 0, 0));
+
+    // Half transparent black over white.
+    for b in src.map().as_mut_slice().iter_mut() {
+        *b = 0;
+    }
+    {
+        let mut mapped = src.map();
+        mapped.as_mut_slice()[3] = 128;
+    }
+    let one = Rect::new(Point::new(0, 0), Size::new(1, 1));
+    assert!(dst.blit(&src, one, Point::new(1, 1), BlendMode::SrcOver).is_ok());
+    assert_eq!(pixel(&dst, 1, 1), vec!(127u8, 127, 127, 255));
+
+    let other = match src.describe().unwrap().format {
+        Format::BGRA => Format::RGBA,
+        Format::RGBA => Format::BGRA,
+    };
+    if is_image_data_format_supported(other) {
+        let src = instance.create_image(Some(other), Size::new(2, 2), true).unwrap();
+        assert_eq!(dst.blit(&src, all, Point::new(0, 0), BlendMode::Copy), Code::BadArgument);
+    }
+}
+
+#[ppapi_test]
+fn image_data_from_pixels(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::Size;
+    use ppapi::imagedata::MappedSlice;
+
+    // Odd widths are the most likely to be padded.
+    let pixels: Vec<u32> = (0..15).map(|i| 0xff000000 | i ).collect();
+    let image = instance.image_data_from_pixels(Size::new(5, 3), &pixels[..]).unwrap();
+    let mapped = image.map();
+    let stride = mapped.desc.line_stride as usize;
+    let bytes = mapped.as_imm_slice();
+    for y in 0..3 {
+        for x in 0..5 {
+            let offset = y * stride + x * 4;
+            let pixel = &bytes[offset..offset + 4];
+            let expected: u32 = 0xff000000 | (y * 5 + x) as u32;
+            // NaCl targets are little endian.
+            let actual = pixel[0] as u32 | (pixel[1] as u32) << 8 |
+                (pixel[2] as u32) << 16 | (pixel[3] as u32) << 24;
+            assert_eq!(actual, expected);
+        }
+    }
+
+    assert!(instance.image_data_from_pixels(Size::new(5, 3), &pixels[..14]).is_none());
+    assert!(instance.image_data_from_pixels(Size::new(4, 4), &pixels[..]).is_none());
+}
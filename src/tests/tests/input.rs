@@ -0,0 +1,254 @@
+// Copyright (c) 2014 Richard Diamond & contributors.
+//
+// This file is part of the Rust PPApi project.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+#![feature(plugin)]
+
+#[plugin] #[no_link]
+extern crate "ppapi-tester" as _ppapi_tester;
+
+#[ppapi_test]
+fn event_type_conversions(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::ffi;
+    use ppapi::input::{EventType, MouseButton};
+
+    let types = [(ffi::PP_INPUTEVENT_TYPE_MOUSEDOWN, EventType::MouseDown),
+                 (ffi::PP_INPUTEVENT_TYPE_WHEEL, EventType::Wheel),
+                 (ffi::PP_INPUTEVENT_TYPE_RAWKEYDOWN, EventType::RawKeyDown),
+                 (ffi::PP_INPUTEVENT_TYPE_IME_TEXT, EventType::ImeText),
+                 (ffi::PP_INPUTEVENT_TYPE_TOUCHCANCEL, EventType::TouchCancel)];
+    for &(raw, ty) in types.iter() {
+        assert_eq!(EventType::from(raw), ty);
+        assert_eq!(ty.to_ffi(), raw);
+    }
+    assert_eq!(EventType::from(1000), EventType::Unknown(1000));
+    assert_eq!(EventType::Unknown(1000).to_ffi(), 1000);
+
+    let buttons = [(ffi::PP_INPUTEVENT_MOUSEBUTTON_NONE, MouseButton::NoButton),
+                   (ffi::PP_INPUTEVENT_MOUSEBUTTON_LEFT, MouseButton::Left),
+                   (ffi::PP_INPUTEVENT_MOUSEBUTTON_RIGHT, MouseButton::Right)];
+    for &(raw, button) in buttons.iter() {
+        assert_eq!(MouseButton::from(raw), button);
+        assert_eq!(button.to_ffi(), raw);
+    }
+    assert_eq!(MouseButton::from(7), MouseButton::Unknown(7));
+}
+
+#[ppapi_test]
+fn input_passthrough(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::input::{EventType, KeyboardInputEvent, KeyMatcher, Modifiers, Modifiers_};
+
+    let none = Modifiers::new();
+    let mut ctrl = Modifiers::new();
+    ctrl.insert(Modifiers_::ControlKey);
+    let event = |event_type, modifiers: &Modifiers, key_code| {
+        KeyboardInputEvent::create(&instance, event_type, 0.0, modifiers, key_code).unwrap()
+    };
+    const F5: u32 = 116;
+    const T: u32 = 84;
+
+    instance.set_input_passthrough(&[KeyMatcher::new(F5),
+                                     KeyMatcher::new(T).with(Modifiers_::ControlKey)]);
+    assert!(instance.input_passes_through(&event(EventType::KeyDown, &none, F5)));
+    assert!(instance.input_passes_through(&event(EventType::KeyUp, &none, F5)));
+    assert!(instance.input_passes_through(&event(EventType::KeyDown, &ctrl, T)));
+    // Modifiers must match exactly.
+    assert!(!instance.input_passes_through(&event(EventType::KeyDown, &ctrl, F5)));
+    assert!(!instance.input_passes_through(&event(EventType::KeyDown, &none, T)));
+
+    instance.set_input_passthrough(&[]);
+    assert!(!instance.input_passes_through(&event(EventType::KeyDown, &none, F5)));
+}
+
+#[ppapi_test]
+fn gesture_tap(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::input::{EventType, Gesture, GestureRecognizer};
+
+    let touch = |id: u32, x: f32, y: f32| {
+        ppapi::ffi::Struct_PP_TouchPoint {
+            id: id,
+            position: ppapi::ffi::Struct_PP_FloatPoint { x: x, y: y },
+            radius: ppapi::ffi::Struct_PP_FloatPoint { x: 1.0, y: 1.0 },
+            rotation_angle: 0.0,
+            pressure: 1.0,
+        }
+    };
+    let mut recognizer: GestureRecognizer = Default::default();
+    let down = touch(0, 100.0, 100.0);
+    assert!(recognizer.feed_touches(EventType::TouchStart, 1.0, &[down]).is_empty());
+    // Within the movement tolerance.
+    assert!(recognizer.feed_touches(EventType::TouchMove, 1.05,
+                                    &[touch(0, 103.0, 101.0)]).is_empty());
+    assert_eq!(recognizer.feed_touches(EventType::TouchEnd, 1.1, &[]),
+               vec![Gesture::Tap { position: down.position }]);
+
+    recognizer.feed_touches(EventType::TouchStart, 1.2, &[down]);
+    assert_eq!(recognizer.feed_touches(EventType::TouchEnd, 1.25, &[]),
+               vec![Gesture::DoubleTap { position: down.position }]);
+
+    // Held past the long press duration.
+    recognizer.feed_touches(EventType::TouchStart, 5.0, &[down]);
+    assert_eq!(recognizer.poll(5.6), Some(Gesture::LongPress { position: down.position }));
+    assert_eq!(recognizer.poll(5.7), None);
+    assert!(recognizer.feed_touches(EventType::TouchEnd, 5.8, &[]).is_empty());
+}
+
+#[ppapi_test]
+fn gesture_pinch(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::input::{EventType, Gesture, GestureRecognizer};
+
+    let touch = |id: u32, x: f32, y: f32| {
+        ppapi::ffi::Struct_PP_TouchPoint {
+            id: id,
+            position: ppapi::ffi::Struct_PP_FloatPoint { x: x, y: y },
+            radius: ppapi::ffi::Struct_PP_FloatPoint { x: 1.0, y: 1.0 },
+            rotation_angle: 0.0,
+            pressure: 1.0,
+        }
+    };
+    let mut recognizer: GestureRecognizer = Default::default();
+    recognizer.feed_touches(EventType::TouchStart, 1.0, &[touch(3, 100.0, 100.0)]);
+    recognizer.feed_touches(EventType::TouchStart, 1.01,
+                            &[touch(3, 100.0, 100.0), touch(7, 200.0, 100.0)]);
+    assert_eq!(recognizer.feed_touches(EventType::TouchMove, 1.1,
+                                       &[touch(3, 50.0, 100.0), touch(7, 250.0, 100.0)]),
+               vec![Gesture::Pinch { scale: 2.0 }]);
+    recognizer.feed_touches(EventType::TouchEnd, 1.2, &[touch(7, 250.0, 100.0)]);
+    // Lifting every finger resets; multi touch sequences never make a tap.
+    assert!(recognizer.feed_touches(EventType::TouchEnd, 1.3, &[]).is_empty());
+
+    // So the next sequence starts fresh.
+    recognizer.feed_touches(EventType::TouchStart, 2.0, &[touch(3, 0.0, 0.0)]);
+    assert_eq!(recognizer.feed_touches(EventType::TouchMove, 2.1, &[touch(3, 0.0, 20.0)]),
+               vec![Gesture::Pan { delta: ppapi::ffi::Struct_PP_FloatPoint { x: 0.0, y: 20.0 } }]);
+}
+
+#[ppapi_test]
+fn user_gesture_scope(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::{Code, Feature, UserGestureScope};
+    use ppapi::input::{Class, EventType, KeyboardInputEvent, Modifiers};
+
+    // Stands in for the input trampoline, which opens the gesture window
+    // around `ppapi_on_input` the same way.
+    let dispatch = |event: Class, on_input: &Fn(Class) -> bool| {
+        let _gesture = UserGestureScope::enter(&instance);
+        on_input(event)
+    };
+    let event = KeyboardInputEvent::create(&instance, EventType::KeyDown, 0.0,
+                                           &Modifiers::new(), 65).unwrap();
+
+    assert!(!instance.in_user_gesture());
+    assert!(dispatch(Class::new(event), &|_| {
+        assert!(instance.in_user_gesture());
+        {
+            let _nested = UserGestureScope::enter(&instance);
+            assert!(instance.in_user_gesture());
+        }
+        // Leaving a nested scope doesn't close the outer one.
+        instance.in_user_gesture()
+    }));
+    assert!(!instance.in_user_gesture());
+
+    if !instance.supports(Feature::Fullscreen) {
+        assert_eq!(instance.set_fullscreen(true), Code::NoInterface);
+    }
+}
+
+#[ppapi_test]
+fn class_accessors(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::{Feature, FloatPoint, Point};
+    use ppapi::input::{Class, EventType, KeyboardInputEvent, MouseInputEvent, MouseButton,
+                       WheelInputEvent, TouchInputEvent, IMEInputEvent, Modifiers};
+
+    let none = Modifiers::new();
+    // Which of as_keyboard, as_mouse, as_wheel, as_touch and as_ime return
+    // `Some`.
+    let kinds = |class: &Class| {
+        [class.as_keyboard().is_some(), class.as_mouse().is_some(),
+         class.as_wheel().is_some(), class.as_touch().is_some(),
+         class.as_ime().is_some()]
+    };
+
+    let key = KeyboardInputEvent::create(&instance, EventType::KeyDown, 1.0, &none, 65)
+        .unwrap();
+    let class = Class::new(key);
+    assert_eq!(kinds(&class), [true, false, false, false, false]);
+    assert_eq!(class.event_type(), EventType::KeyDown);
+    assert_eq!(class.timestamp(), 1.0);
+
+    let mouse = MouseInputEvent::create(&instance, EventType::MouseDown, 2.0, &none,
+                                        MouseButton::Left, Point::new(3, 4), 1,
+                                        Point::new(0, 0)).unwrap();
+    let class = Class::new(mouse);
+    assert_eq!(kinds(&class), [false, true, false, false, false]);
+    assert_eq!(class.event_type(), EventType::MouseDown);
+    assert!(class.as_mouse().unwrap().get_position() == Point::new(3, 4));
+    assert_eq!(class.timestamp(), 2.0);
+
+    let zero = FloatPoint { x: 0.0, y: 0.0 };
+    let wheel = WheelInputEvent::create(&instance, 3.0, &none,
+                                        FloatPoint { x: 0.0, y: -10.0 }, zero, false)
+        .unwrap();
+    let class = Class::new(wheel);
+    assert_eq!(kinds(&class), [false, false, true, false, false]);
+    assert_eq!(class.event_type(), EventType::Wheel);
+    assert_eq!(class.timestamp(), 3.0);
+
+    if instance.supports(Feature::TouchInput) {
+        let touch = TouchInputEvent::create(&instance, EventType::TouchStart, 4.0, &none)
+            .unwrap();
+        let class = Class::new(touch);
+        assert_eq!(kinds(&class), [false, false, false, true, false]);
+        assert_eq!(class.event_type(), EventType::TouchStart);
+    }
+    if instance.supports(Feature::ImeInput) {
+        let ime = IMEInputEvent::create(&instance, EventType::ImeText, 5.0, "ime")
+            .unwrap();
+        let class = Class::new(ime);
+        assert_eq!(kinds(&class), [false, false, false, false, true]);
+        assert_eq!(class.as_ime().unwrap().string, "ime");
+        // Cloning keeps the text.
+        assert_eq!(class.clone().as_ime().unwrap().string, "ime");
+    }
+}
+
+#[ppapi_test]
+fn unhandled_input_without_handler(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::{ffi, entry, unhandled_input_goes_to_page, Point, Resource};
+    use ppapi::input::{EventType, KeyboardInputEvent, Modifiers, MouseButton, MouseInputEvent};
+
+    // The tests don't define `ppapi_on_input`.
+    let none = Modifiers::new();
+    let key = KeyboardInputEvent::create(&instance, EventType::KeyDown, 0.0, &none, 65)
+        .unwrap();
+    let mouse = MouseInputEvent::create(&instance, EventType::MouseDown, 0.0, &none,
+                                        MouseButton::Left, Point::new(3, 4), 1,
+                                        Point::new(0, 0)).unwrap();
+    let keyboard = ffi::PP_INPUTEVENT_CLASS_KEYBOARD;
+    let inst = instance.unwrap();
+
+    assert!(instance.request_input_events(keyboard).is_ok());
+    assert!(!instance.is_filtering_input_events(keyboard));
+    // Not handled, and PPAPI drops it.
+    assert_eq!(entry::handle_input_event(inst, key.unwrap()), ffi::PP_FALSE);
+    assert!(!unhandled_input_goes_to_page(inst, key.unwrap()));
+
+    assert!(instance.request_filtering_input_events(keyboard).is_ok());
+    assert!(instance.is_filtering_input_events(keyboard));
+    assert!(!instance.is_filtering_input_events(keyboard | ffi::PP_INPUTEVENT_CLASS_MOUSE));
+    // Not handled, and PPAPI passes it on to the page.
+    assert_eq!(entry::handle_input_event(inst, key.unwrap()), ffi::PP_FALSE);
+    assert!(unhandled_input_goes_to_page(inst, key.unwrap()));
+    // Only the filtered classes go to the page.
+    assert!(instance.request_input_events(ffi::PP_INPUTEVENT_CLASS_MOUSE).is_ok());
+    assert_eq!(entry::handle_input_event(inst, mouse.unwrap()), ffi::PP_FALSE);
+    assert!(!unhandled_input_goes_to_page(inst, mouse.unwrap()));
+
+    instance.clear_input_event_request(keyboard | ffi::PP_INPUTEVENT_CLASS_MOUSE);
+    assert!(!instance.is_filtering_input_events(keyboard));
+    assert!(!unhandled_input_goes_to_page(inst, key.unwrap()));
+}
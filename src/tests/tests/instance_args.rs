@@ -0,0 +1,95 @@
+// Copyright (c) 2014 Richard Diamond & contributors.
+//
+// This file is part of the Rust PPApi project.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+#![feature(plugin)]
+
+#[plugin] #[no_link]
+extern crate "ppapi-tester" as _ppapi_tester;
+
+#[ppapi_test]
+fn instance_args_lookup(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::InstanceArgs;
+
+    let args = InstanceArgs::new(vec!(("width".to_string(), "640".to_string()),
+                                      ("name".to_string(), "first".to_string()),
+                                      ("name".to_string(), "second".to_string()),
+                                      ("autoplay".to_string(), "".to_string()),
+                                      ("height".to_string(), "tall".to_string())));
+
+    assert_eq!(args.get_parsed::<u32>("width"), Some(640));
+    assert_eq!(args.get_parsed::<u32>("missing"), None);
+    // Doesn't parse.
+    assert_eq!(args.get_parsed::<u32>("height"), None);
+
+    assert_eq!(args.get("name"), Some("first"));
+    assert_eq!(args.get("missing"), None);
+    assert_eq!(args.get_bool("autoplay"), Some(true));
+    assert_eq!(args.get_bool("name"), None);
+    assert_eq!(args.iter().count(), 5);
+}
+
+#[ppapi_test]
+fn restart_policy_is_bounded(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::RestartPolicy;
+
+    // The schedule; `instance_restarts_after_a_panic` does an actual restart.
+    let policy = RestartPolicy::new(3, 50);
+    assert_eq!(policy.delay_for(0), None);
+    assert_eq!(policy.delay_for(1), Some(50));
+    assert_eq!(policy.delay_for(2), Some(100));
+    assert_eq!(policy.delay_for(3), Some(200));
+    assert_eq!(policy.delay_for(4), None);
+    assert_eq!(RestartPolicy::default().delay_for(1), None);
+    assert_eq!(RestartPolicy::new(100, ::std::u64::MAX).delay_for(100),
+               Some(::std::u64::MAX));
+
+    assert_eq!(instance.restart_count(), 0);
+    instance.set_restart_policy(policy);
+    instance.set_restart_policy(RestartPolicy::new(1, 0));
+    assert_eq!(instance.restart_count(), 0);
+    instance.set_restart_policy(RestartPolicy::default());
+}
+
+#[ppapi_test]
+fn instance_restarts_after_a_panic(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use std::sync::Mutex;
+    use std::sync::mpsc::channel;
+    use ppapi::{MessageLoop, RestartPolicy};
+    use ppapi::entry;
+
+    // The harness's own `ppapi_instance_created` can't be re-run under it,
+    // so this stands in for it on a second thread for the same instance. Its
+    // first run panics out of the loop once; the restart reports its count.
+    let (tx, rx) = channel();
+    let tx = Mutex::new(tx);
+    let init = move |instance: ppapi::Instance, _: Vec<(String, String)>| {
+        instance.set_restart_policy(RestartPolicy::new(1, 0));
+        let restarts = instance.restart_count();
+        if restarts == 0 {
+            let code = MessageLoop::post_to_self(|_| panic!("crashing to be restarted"), 0);
+            assert!(code.is_ok());
+        } else {
+            tx.lock().unwrap().send(restarts).unwrap();
+        }
+    };
+    let harness_loop = MessageLoop::current().unwrap();
+    assert!(entry::spawn_instance_with(instance, init).is_some());
+    assert_eq!(rx.recv().unwrap(), 1);
+
+    // The restart checked the instance was still around, and swapped the
+    // stand-in's loop in for this one's. Put this one back, so the
+    // instance's events come here again, and stop the stand-in; its thread
+    // leaves the instance alone once its loop isn't the instance's.
+    let (done_tx, done_rx) = channel();
+    let code = MessageLoop::get_main_loop().post_work(move |_| {
+        let stand_in = entry::swap_instance_loop(instance, harness_loop);
+        let _ = done_tx.send(stand_in.map(|ml| ml.queue_shutdown().is_ok() ));
+    }, 0);
+    assert!(code.is_ok());
+    assert_eq!(done_rx.recv().unwrap(), Some(true));
+}
@@ -0,0 +1,62 @@
+// Copyright (c) 2014 Richard Diamond & contributors.
+//
+// This file is part of the Rust PPApi project.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+#![feature(plugin, catch_panic)]
+
+#[plugin] #[no_link]
+extern crate "ppapi-tester" as _ppapi_tester;
+
+#[ppapi_test]
+fn instance_local_in_spawned_loop(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use std::thread;
+
+    struct AppState {
+        frames: u32,
+    }
+
+    let (_, join) = instance.spawn_message_loop(move |_run_loop| {
+        let instance = ppapi::Instance::current();
+        instance.with_local(|state: &mut Option<AppState>| {
+            assert!(state.is_none());
+            *state = Some(AppState { frames: 1 });
+        });
+
+        instance.with_local(|state: &mut Option<AppState>| {
+            state.as_mut().unwrap().frames += 1;
+            // Slots are distinct per type, and can be used while another is.
+            instance.with_local(|other: &mut Option<u32>| assert!(other.is_none()) );
+        });
+        let frames = instance.with_local(|state: &mut Option<AppState>| {
+            state.as_ref().unwrap().frames
+        });
+        assert_eq!(frames, 2);
+
+        // But the same slot can't be used twice at once.
+        let nested = thread::catch_panic(move || {
+            let instance = ppapi::Instance::current();
+            instance.with_local(|_: &mut Option<AppState>| {
+                instance.with_local(|_: &mut Option<AppState>| () );
+            });
+        });
+        assert!(nested.is_err());
+        // And it's put back regardless.
+        let frames = instance.with_local(|state: &mut Option<AppState>| {
+            state.as_ref().unwrap().frames
+        });
+        assert_eq!(frames, 2);
+    });
+    assert!(join.join().is_ok());
+
+    // The instance isn't current on a plain thread.
+    let result = thread::spawn(move || {
+        instance.with_local(|_: &mut Option<u32>| () );
+    }).join();
+    let err = result.unwrap_err();
+    let msg = err.downcast_ref::<String>().unwrap();
+    assert!(msg.contains("is not current on this thread"));
+}
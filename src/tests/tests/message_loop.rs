@@ -0,0 +1,137 @@
+// Copyright (c) 2014 Richard Diamond & contributors.
+//
+// This file is part of the Rust PPApi project.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+#![feature(plugin)]
+
+#[plugin] #[no_link]
+extern crate "ppapi-tester" as _ppapi_tester;
+
+#[ppapi_test]
+fn message_loop_is_current(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use std::thread;
+    use ppapi::MessageLoop;
+
+    let main = MessageLoop::current().unwrap();
+    assert!(main.is_current());
+    main.assert_current();
+
+    let other = instance.create_message_loop();
+    assert!(!other.is_current());
+
+    let other2 = other.clone();
+    let main2 = main.clone();
+    let result = thread::spawn(move || {
+        assert!(!other2.is_current());
+        assert!(other2.attach_to_current_thread().is_ok());
+        assert!(other2.is_current());
+        assert!(!main2.is_current());
+        main2.assert_current();
+    }).join();
+
+    let err = result.unwrap_err();
+    let msg = err.downcast_ref::<String>().unwrap();
+    assert!(msg.contains("expected to be running on message loop"),
+            "unexpected panic message: `{}`", msg);
+}
+
+#[ppapi_test]
+fn named_message_loop(instance: ppapi::Instance, args: HashMap<String, String>) {
+    let named = instance.create_named_message_loop("decoder worker");
+    assert_eq!(named.label(), Some("decoder worker".to_string()));
+    let debug = format!("{:?}", named);
+    assert!(debug.contains("decoder worker"), "unexpected debug output: `{}`", debug);
+    // Clones share the label.
+    assert_eq!(named.clone().label(), named.label());
+
+    let anonymous = instance.create_message_loop();
+    assert_eq!(anonymous.label(), None);
+    let debug = format!("{:?}", anonymous);
+    assert!(debug.starts_with("MessageLoop(") && !debug.contains("\""),
+            "unexpected debug output: `{}`", debug);
+}
+
+#[ppapi_test]
+fn post_work_catch_isolates_panics(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::{Instance, MessageLoop};
+
+    let main = MessageLoop::current().unwrap();
+    let ok = main.post_work_catch(|| 4 );
+    let panicked = main.post_work_catch(|| -> u32 { panic!("decoder blew up") });
+
+    // Work runs in order, so both results are in by the time this runs.
+    let code = MessageLoop::post_to_self(move |_| {
+        assert_eq!(ok.try_recv().unwrap().unwrap(), 4);
+        let err = panicked.try_recv().unwrap().unwrap_err();
+        assert_eq!(*err.downcast_ref::<&'static str>().unwrap(), "decoder blew up");
+
+        // The loop, and so the instance, survived.
+        assert!(Instance::current() == instance);
+        assert!(MessageLoop::post_to_self(move |_| {
+            assert!(Instance::current() == instance);
+        }, 0).is_ok());
+    }, 0);
+    assert!(code.is_ok());
+}
+
+#[ppapi_test]
+fn shutdown_graceful_drains_queue(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use std::sync::{Arc, Mutex};
+    use std::sync::mpsc::channel;
+    use ppapi::{CallbackArgs, Code};
+
+    let (worker, join) = instance.spawn_message_loop(|run_loop| {
+        run_loop();
+    });
+    let ran = Arc::new(Mutex::new(Vec::new()));
+    for i in 0..4u32 {
+        let ran = ran.clone();
+        assert!(worker.post_work(move |code: Code<()>| {
+            assert!(code.is_ok());
+            ran.lock().unwrap().push(i);
+        }, 0).is_ok());
+    }
+
+    let (tx, rx) = channel();
+    let code = worker.shutdown_graceful(1000, CallbackArgs::new(move |code: Code<()>| {
+        tx.send(code).unwrap();
+    }));
+    assert!(code.completion_pending());
+    // Rejected, rather than accepted and then dropped by the quit.
+    assert_eq!(worker.post_work(|_| unreachable!(), 0), Code::Failed);
+    assert_eq!(worker.shutdown_graceful(0, CallbackArgs::new(|_: Code<()>| unreachable!() )),
+               Code::InProgress);
+
+    assert!(rx.recv().unwrap().is_ok());
+    join.join().unwrap();
+    assert_eq!(*ran.lock().unwrap(), vec![0, 1, 2, 3]);
+}
+
+#[ppapi_test]
+fn spawned_loop_results(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::Code;
+
+    let (worker, join) = instance.spawn_message_loop_with_result(|run_loop| run_loop() );
+    assert!(worker.queue_shutdown().is_ok());
+    assert_eq!(join.join().unwrap(), Ok(()));
+
+    // The loop shut down fine, but the worker reports its own failure.
+    let (worker, join) = instance.spawn_message_loop_with_result(|run_loop| {
+        assert!(run_loop().is_ok());
+        Code::NoSpace
+    });
+    assert!(worker.queue_shutdown().is_ok());
+    assert_eq!(join.join().unwrap(), Err(Code::NoSpace));
+
+    // Panics still come through `join`.
+    let (worker, join) = instance.spawn_message_loop_with_result(|run_loop| {
+        run_loop();
+        panic!("worker failed");
+    });
+    assert!(worker.queue_shutdown().is_ok());
+    assert!(join.join().is_err());
+}
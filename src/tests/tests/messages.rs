@@ -0,0 +1,96 @@
+// Copyright (c) 2014 Richard Diamond & contributors.
+//
+// This file is part of the Rust PPApi project.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+#![feature(plugin)]
+
+#[plugin] #[no_link]
+extern crate "ppapi-tester" as _ppapi_tester;
+
+#[ppapi_test]
+fn message_round_trip(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::{AnyVar, Code, DictionaryVar, FromVar, StringVar, ToVar};
+    use ppapi::messages::MessageError;
+
+    define_messages! {
+        #[derive(Clone, PartialEq, Debug)]
+        enum Message {
+            Resize { width: i32, height: i32, scale: f64 },
+            Log { text: String, tags: Vec<String>, urgent: bool, id: Option<u32> },
+        }
+    }
+
+    let messages = [Message::Resize { width: 640, height: 480, scale: 1.5 },
+                    Message::Log {
+                        text: "hello".to_string(),
+                        tags: vec!["a".to_string(), "b".to_string()],
+                        urgent: true,
+                        id: None,
+                    }];
+    for msg in messages.iter() {
+        let any = msg.to_any();
+        match any {
+            AnyVar::Dictionary(ref dict) => match dict.get("type") {
+                AnyVar::String(ty) => assert_eq!(&*ty, msg.message_type()),
+                ty => panic!("bad type: {:?}", ty),
+            },
+            _ => panic!("not a dictionary: {:?}", any),
+        }
+        let decoded: Option<Message> = FromVar::from_var(any.to_var());
+        assert_eq!(decoded.as_ref(), Some(msg));
+    }
+
+    let dict = DictionaryVar::new();
+    dict.set("type", StringVar::new("Teleport"));
+    assert_eq!(Message::decode(&dict), Err(MessageError::UnknownType("Teleport".to_string())));
+    assert_eq!(Message::decode(&dict).unwrap_err().code(), Code::BadArgument);
+
+    dict.set("type", StringVar::new("Resize"));
+    dict.set("width", 1i32);
+    dict.set("height", StringVar::new("tall"));
+    dict.set("scale", 1.0f64);
+    assert_eq!(Message::decode(&dict),
+               Err(MessageError::BadField { message: "Resize", field: "height" }));
+}
+
+#[ppapi_test]
+fn message_router_dispatch(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use ppapi::{AnyVar, DictionaryVar, ToStringVar};
+    use ppapi::messages::MessageRouter;
+
+    let message = |ty: &str| {
+        let dict = DictionaryVar::new();
+        dict.set("type", ty.to_string_var());
+        AnyVar::Dictionary(dict)
+    };
+
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    let mut router = MessageRouter::new();
+    {
+        let (a, b) = (seen.clone(), seen.clone());
+        router.on("foo", move |_| a.borrow_mut().push("foo") )
+              .on("bar", move |_| b.borrow_mut().push("bar") );
+    }
+
+    assert!(router.dispatch(message("foo")));
+    assert!(router.dispatch(message("bar")));
+    // Dropped, with a warning, without a default handler.
+    assert!(!router.dispatch(message("baz")));
+    assert_eq!(*seen.borrow(), vec!["foo", "bar"]);
+
+    {
+        let d = seen.clone();
+        router.otherwise(move |_| d.borrow_mut().push("default") );
+    }
+    assert!(!router.dispatch(message("baz")));
+    assert!(!router.dispatch(AnyVar::I32(4)));
+    assert!(!router.dispatch(AnyVar::Dictionary(DictionaryVar::new())));
+    assert!(router.dispatch(message("foo")));
+    assert_eq!(*seen.borrow(), vec!["foo", "bar", "default", "default", "default", "foo"]);
+}
@@ -0,0 +1,209 @@
+// Copyright (c) 2014 Richard Diamond & contributors.
+//
+// This file is part of the Rust PPApi project.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+#![feature(plugin)]
+
+#[plugin] #[no_link]
+extern crate "ppapi-tester" as _ppapi_tester;
+
+#[ppapi_test]
+fn error_message_fields(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::{AnyVar, Code, Messaging};
+
+    // What `post_error` posts.
+    let code: Code<()> = Code::FileNotFound;
+    let dict = Messaging::error_message(code, "loading level 3");
+
+    let string = |var: AnyVar| match var {
+        AnyVar::String(s) => s.to_string(),
+        _ => panic!("expected a string"),
+    };
+    assert_eq!(string(dict.get("type")), "error");
+    match dict.get("code") {
+        AnyVar::I32(v) => assert_eq!(v, Code::FileNotFound::<usize>.to_i32()),
+        _ => panic!("expected an int code"),
+    }
+    assert_eq!(string(dict.get("message")), "file not found");
+    assert_eq!(string(dict.get("context")), "loading level 3");
+
+    instance.messaging().post_error(code, "loading level 3");
+}
+
+#[ppapi_test]
+fn backpressure_acks(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::{AnyVar, DictionaryVar, MESSAGE_ACK_KEY};
+
+    let ack = |n: i32| {
+        let dict = DictionaryVar::new();
+        dict.set(MESSAGE_ACK_KEY, n);
+        AnyVar::Dictionary(dict)
+    };
+    let messaging = instance.messaging();
+
+    // Off by default.
+    messaging.post(0i32);
+    assert_eq!(messaging.pending_posts(), 0);
+    assert!(!messaging.intercept_ack(&ack(1)));
+
+    messaging.set_backpressure(true);
+    for i in 0..3i32 {
+        messaging.post(i);
+    }
+    assert_eq!(messaging.pending_posts(), 3);
+    // What JS would send back.
+    assert!(messaging.intercept_ack(&ack(2)));
+    assert_eq!(messaging.pending_posts(), 1);
+    // Other messages go through.
+    assert!(!messaging.intercept_ack(&AnyVar::I32(2)));
+    assert_eq!(messaging.pending_posts(), 1);
+    assert!(messaging.intercept_ack(&ack(5)));
+    assert_eq!(messaging.pending_posts(), 0);
+
+    messaging.set_backpressure(false);
+    messaging.post(0i32);
+    assert_eq!(messaging.pending_posts(), 0);
+    assert!(!messaging.intercept_ack(&ack(1)));
+}
+
+#[ppapi_test]
+fn post_owned_fields(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use std::cell::RefCell;
+    use std::collections::BTreeMap;
+    use ppapi::{ffi, AnyVar, ArrayVar, DictionaryVar, FromVar, OwnedVar};
+    use ppapi::messages::MessageField;
+    use ppapi::ppb::MessagingIf;
+
+    // Keeps what's posted, as the browser would copy it.
+    struct FakeMessaging(RefCell<Vec<OwnedVar>>);
+    impl MessagingIf for FakeMessaging {
+        fn post_message(&self, _: ffi::PP_Instance, msg: ffi::PP_Var) {
+            let msg = if let Some(dict) = <DictionaryVar as FromVar>::from_var(msg) {
+                AnyVar::Dictionary(dict)
+            } else {
+                AnyVar::Array(<ArrayVar as FromVar>::from_var(msg).unwrap())
+            };
+            self.0.borrow_mut().push(msg.into_owned());
+        }
+    }
+
+    let mut fields = BTreeMap::new();
+    fields.insert("type".to_string(), OwnedVar::String("score".to_string()));
+    fields.insert("points".to_string(), OwnedVar::I32(42));
+    let msg = OwnedVar::Dict(fields);
+    assert_eq!(OwnedVar::from_field(&msg.to_field()), Some(msg.clone()));
+
+    let messaging = instance.messaging();
+    let fake = FakeMessaging(RefCell::new(Vec::new()));
+    messaging.set_backpressure(true);
+    messaging.post_field_to(&fake, &msg);
+    messaging.post_field_to(&fake, &vec!["a".to_string(), "b".to_string()]);
+    assert_eq!(messaging.pending_posts(), 2);
+    messaging.set_backpressure(false);
+
+    let posted = fake.0.borrow();
+    assert_eq!(posted[0], msg);
+    assert_eq!(posted[1], OwnedVar::Array(vec![OwnedVar::String("a".to_string()),
+                                               OwnedVar::String("b".to_string())]));
+    // And through the browser.
+    instance.post_field(&msg);
+}
+
+#[ppapi_test]
+fn instance_handler_gets_messages(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use std::sync::mpsc::{channel, Sender};
+    use ppapi::{AnyVar, InstanceHandler, MessageLoop, ToVar};
+    use ppapi::entry;
+
+    // Reports whether it got the message sent below, and cleared itself.
+    struct Handler(Sender<bool>);
+    impl InstanceHandler for Handler {
+        fn on_message(&mut self, message: AnyVar) {
+            let expected = match message {
+                AnyVar::I32(42) => true,
+                _ => false,
+            };
+            // Handlers can clear themselves while they run.
+            let cleared = ppapi::Instance::current().clear_handler();
+            self.0.send(expected && cleared).unwrap();
+        }
+    }
+
+    // Messages are handled on the instance's loop, and this one is busy
+    // running the test, so the handler gets a loop of its own.
+    let (tx, rx) = channel();
+    let (handler_loop, handler_thread) = instance.spawn_message_loop(move |run_loop| {
+        instance.set_handler(Box::new(Handler(tx)));
+        run_loop();
+    });
+
+    // What the browser does on `postMessage(42)`, with the handler's loop
+    // standing in as the instance's.
+    let harness_loop = MessageLoop::current().unwrap();
+    let stand_in = handler_loop.clone();
+    let code = MessageLoop::get_main_loop().post_work(move |_| {
+        entry::swap_instance_loop(instance, stand_in);
+        entry::handle_message(instance.unwrap(), 42i32.to_var());
+        entry::swap_instance_loop(instance, harness_loop);
+    }, 0);
+    assert!(code.is_ok());
+    assert!(rx.recv().unwrap());
+
+    // Once dispatch is done, it stays cleared.
+    let (cleared_tx, cleared) = channel();
+    let code = handler_loop.post_work(move |_| {
+        cleared_tx.send(!instance.has_handler() && !instance.clear_handler()).unwrap();
+    }, 0);
+    assert!(code.is_ok());
+    assert!(cleared.recv().unwrap());
+
+    assert!(handler_loop.queue_shutdown().is_ok());
+    handler_thread.join().unwrap();
+}
+
+#[ppapi_test]
+fn channels_route_by_topic(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use ppapi::{AnyVar, DictionaryVar, ToStringVar};
+    use ppapi::messages::{route_to_channel, CHANNEL_TOPIC_KEY, CHANNEL_PAYLOAD_KEY};
+
+    // What the page sends back; `send` posts the same shape.
+    let message = |topic: &str, payload: i32| {
+        let dict = DictionaryVar::new();
+        dict.set(CHANNEL_TOPIC_KEY, topic.to_string_var());
+        dict.set(CHANNEL_PAYLOAD_KEY, payload);
+        AnyVar::Dictionary(dict)
+    };
+
+    let scores = instance.channel::<i32, i32>("scores");
+    let lives = instance.channel::<i32, i32>("lives");
+    scores.send(1);
+
+    assert!(route_to_channel(&instance, &message("scores", 10)));
+    assert_eq!(scores.try_recv(), Some(10));
+    assert_eq!(scores.try_recv(), None);
+    assert_eq!(lives.try_recv(), None);
+
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    {
+        let seen = seen.clone();
+        lives.on_receive(move |n| seen.borrow_mut().push(n) );
+    }
+    assert!(route_to_channel(&instance, &message("lives", 3)));
+    assert_eq!(*seen.borrow(), vec![3]);
+    assert_eq!(scores.try_recv(), None);
+
+    // Unknown topics and other messages are left for the handler.
+    assert!(!route_to_channel(&instance, &message("chat", 0)));
+    assert!(!route_to_channel(&instance, &AnyVar::I32(4)));
+
+    drop(scores);
+    assert!(!route_to_channel(&instance, &message("scores", 10)));
+    assert!(route_to_channel(&instance, &message("lives", 2)));
+    assert_eq!(*seen.borrow(), vec![3, 2]);
+}
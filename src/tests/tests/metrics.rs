@@ -0,0 +1,33 @@
+// Copyright (c) 2014 Richard Diamond & contributors.
+//
+// This file is part of the Rust PPApi project.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+#![feature(plugin)]
+
+#[plugin] #[no_link]
+extern crate "ppapi-tester" as _ppapi_tester;
+
+#[ppapi_test]
+fn histogram_bad_arguments(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::Code;
+    use ppapi::metrics;
+
+    assert_eq!(instance.record_time("Test.Time", 5, 100, 100, 50), Code::BadArgument);
+    assert_eq!(instance.record_time("Test.Time", 5, 1, 100, 0), Code::BadArgument);
+    assert_eq!(metrics::histogram_custom_counts(&instance, "Test.Count", 5, 10, 1, 50),
+               Code::BadArgument);
+    assert_eq!(instance.record_enum("Test.Enum", 3, 3), Code::BadArgument);
+    assert_eq!(instance.record_enum("Test.Enum", -1, 3), Code::BadArgument);
+}
+
+#[ppapi_test]
+fn histogram_record(instance: ppapi::Instance, args: HashMap<String, String>) {
+    // Without the interface these are no-ops; either way they mustn't crash.
+    assert!(instance.record_time("Test.Time", 5, 1, 10000, 50).is_ok());
+    assert!(instance.record_count("Test.Count", 5, 1, 100, 10).is_ok());
+    assert!(instance.record_enum("Test.Enum", 2, 3).is_ok());
+}
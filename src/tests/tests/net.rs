@@ -0,0 +1,49 @@
+// Copyright (c) 2014 Richard Diamond & contributors.
+//
+// This file is part of the Rust PPApi project.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+#![feature(plugin)]
+
+#[plugin] #[no_link]
+extern crate "ppapi-tester" as _ppapi_tester;
+
+#[ppapi_test]
+fn parse_proxy_rules(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::net::{parse_proxy_rules, ProxyAddr, ProxyRule};
+
+    let addr = |host: &str, port| ProxyAddr { host: host.to_string(), port: port };
+    assert_eq!(parse_proxy_rules("PROXY proxy.example:8080; SOCKS5 10.0.0.1:1080;DIRECT"),
+               Ok(vec!(ProxyRule::Http(addr("proxy.example", 8080)),
+                       ProxyRule::Socks(addr("10.0.0.1", 1080)),
+                       ProxyRule::Direct)));
+    assert_eq!(parse_proxy_rules(""), Ok(vec!(ProxyRule::Direct)));
+    assert_eq!(parse_proxy_rules(" ; "), Ok(vec!(ProxyRule::Direct)));
+
+    for malformed in ["PROXY", "PROXY host", "PROXY host:port", "PROXY :80",
+                      "GOPHER host:70", "DIRECT host:80", "PROXY a:1 b:2"].iter() {
+        let err = parse_proxy_rules(*malformed).unwrap_err();
+        assert_eq!(&err.rule[..], *malformed);
+    }
+}
+
+#[ppapi_test]
+fn get_proxy_for_url(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::{CallbackArgs, Code, Feature};
+    use ppapi::net::{get_proxy_for_url, parse_proxy_rules};
+
+    let code = get_proxy_for_url(&instance, "http://example.com/",
+                                 CallbackArgs::new(|code: Code<ppapi::StringVar>| {
+        let proxies = code.unwrap();
+        assert!(parse_proxy_rules(proxies.as_str()).is_ok(),
+                "couldn't parse `{}`", proxies);
+    }));
+    if instance.supports(Feature::NetworkProxy) {
+        assert!(code.completion_pending());
+    } else {
+        assert_eq!(code, Code::NotSupported);
+    }
+}
@@ -0,0 +1,84 @@
+// Copyright (c) 2014 Richard Diamond & contributors.
+//
+// This file is part of the Rust PPApi project.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+#![feature(plugin)]
+
+#[plugin] #[no_link]
+extern crate "ppapi-tester" as _ppapi_tester;
+
+#[ppapi_test]
+fn raw_interface_lookup(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::ffi;
+    use ppapi::ppb;
+
+    let core = unsafe { ppb::raw_interface::<ffi::PPB_Core>("PPB_Core", "1.0") }.unwrap();
+    assert!(!core.is_null());
+    assert_eq!(core, ppb::get_core() as *const ffi::PPB_Core);
+    // Cached lookups give the same pointer.
+    assert_eq!(unsafe { ppb::raw_interface::<ffi::PPB_Core>("PPB_Core", "1.0") }, Some(core));
+    assert!(ppb::interface_version_available("PPB_Core", "1.0"));
+
+    assert!(unsafe { ppb::raw_interface::<u8>("PPB_NoSuchInterface", "1.0") }.is_none());
+    assert!(!ppb::interface_version_available("PPB_Core", "999.0"));
+}
+
+#[ppapi_test]
+fn flash_host_probe(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::ppb;
+
+    let expected = ppb::FLASH_VERSIONS.iter()
+        .any(|version| ppb::has_interface(&format!("PPB_Flash;{}", version)) );
+    assert_eq!(instance.is_flash_host(), expected);
+    // Cached.
+    assert_eq!(instance.is_flash_host(), expected);
+
+    if !expected {
+        assert!(ppb::get_flash_fullscreen_opt().is_none());
+    }
+    assert!(ppb::has_interface("PPB_Core;1.0"));
+    assert!(!ppb::has_interface("PPB_Core"));
+}
+
+#[ppapi_test]
+fn interface_version_fallback(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use std::mem::size_of;
+    use ppapi::ffi::{Struct_PPB_View_1_0, Struct_PPB_View_1_1, Struct_PPB_View_1_2};
+    use ppapi::ppb::{self, ViewIf};
+
+    assert_eq!(ppb::resolved_version("PPB_View"), Some("1.2"));
+    assert_eq!(ppb::resolved_version("PPB_Messaging"), Some("1.2"));
+    assert_eq!(ppb::resolved_version("PPB_NoSuchInterface"), None);
+
+    let versions = [("PPB_View;1.2\0", size_of::<Struct_PPB_View_1_2>()),
+                    ("PPB_View;1.1\0", size_of::<Struct_PPB_View_1_1>()),
+                    ("PPB_View;1.0\0", size_of::<Struct_PPB_View_1_0>())];
+    // The real table; the 1.0 functions are a prefix of it.
+    let real = ppb::get_view() as *const Struct_PPB_View_1_2 as *const u8;
+    let only = |version: &'static str| {
+        move |name: &'static str| if name == version { Some(real) } else { None }
+    };
+
+    let newest: &Struct_PPB_View_1_2 =
+        ppb::fallback_interface(&versions, only("PPB_View;1.2\0")).unwrap();
+    assert_eq!(newest as *const Struct_PPB_View_1_2 as *const u8, real);
+
+    // A host from before `PPB_View` 1.1.
+    let view: &Struct_PPB_View_1_2 =
+        ppb::fallback_interface(&versions, only("PPB_View;1.0\0")).unwrap();
+    assert!(view as *const Struct_PPB_View_1_2 as *const u8 != real);
+    assert!(view.GetClipRect.is_some());
+    // Functions from newer versions are missing, not garbage.
+    assert!(view.device_scale(0).is_none());
+    assert!(view.scroll_offset(0).is_none());
+    // Resolving it again reuses the copy rather than making another.
+    let again: &Struct_PPB_View_1_2 =
+        ppb::fallback_interface(&versions, only("PPB_View;1.0\0")).unwrap();
+    assert_eq!(again as *const Struct_PPB_View_1_2, view as *const Struct_PPB_View_1_2);
+
+    assert!(ppb::fallback_interface::<Struct_PPB_View_1_2, _>(&versions, |_| None ).is_none());
+}
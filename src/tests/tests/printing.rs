@@ -0,0 +1,59 @@
+// Copyright (c) 2014 Richard Diamond & contributors.
+//
+// This file is part of the Rust PPApi project.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+#![feature(plugin)]
+
+#[plugin] #[no_link]
+extern crate "ppapi-tester" as _ppapi_tester;
+
+#[ppapi_test]
+fn print_handler_wiring(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::{ffi, Instance};
+    use ppapi::ppp::PPP_GetInterface;
+    use ppapi::printing::{OutputFormat, PageRange, PrintHandler, PrintOutput, PrintSettings};
+
+    struct Handler;
+    impl PrintHandler for Handler {
+        fn query_supported_formats(&mut self) -> Vec<OutputFormat> {
+            vec![OutputFormat::Raster, OutputFormat::Pdf]
+        }
+        fn begin(&mut self, _settings: &PrintSettings) -> u32 { 3 }
+        fn print_pages(&mut self, ranges: &[PageRange]) -> Option<PrintOutput> {
+            assert_eq!(ranges, &[PageRange { first: 0, last: 1 }][..]);
+            None
+        }
+        fn end(&mut self) {
+            // Stays cleared, even though it's running.
+            assert!(Instance::current().clear_print_handler());
+        }
+    }
+
+    // Without a handler printing is unsupported, so the browser prints the
+    // page instead.
+    let name = b"PPP_Printing(Dev);0.6\0".as_ptr() as *const _;
+    assert!(PPP_GetInterface(name).is_null());
+
+    instance.set_print_handler(Handler);
+    let interface = PPP_GetInterface(name) as *const ffi::PPP_Printing_Dev;
+    assert!(!interface.is_null());
+    let interface = unsafe { &*interface };
+    let inst = instance.unwrap();
+
+    let formats = (interface.QuerySupportedFormats.unwrap())(inst);
+    assert_eq!(formats, ffi::PP_PRINTOUTPUTFORMAT_RASTER | ffi::PP_PRINTOUTPUTFORMAT_PDF);
+    let range = ffi::Struct_PP_PrintPageNumberRange_Dev {
+        first_page_number: 0,
+        last_page_number: 1,
+    };
+    assert_eq!((interface.PrintPages.unwrap())(inst, &range, 1), 0);
+
+    (interface.End.unwrap())(inst);
+    // Cleared, so nothing is supported anymore.
+    assert_eq!((interface.QuerySupportedFormats.unwrap())(inst), 0);
+    assert!(!instance.clear_print_handler());
+}
@@ -0,0 +1,98 @@
+// Copyright (c) 2014 Richard Diamond & contributors.
+//
+// This file is part of the Rust PPApi project.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+#![feature(plugin)]
+
+#[plugin] #[no_link]
+extern crate "ppapi-tester" as _ppapi_tester;
+
+#[ppapi_test]
+fn downcast_image_data(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::{Resource, ResourceType, Size};
+    use ppapi::font::Font;
+    use ppapi::imagedata::ImageData;
+
+    let image = instance.create_image(None, Size::new(2, 2), true).unwrap();
+    let generic = image.to_resource_var().get_resource().unwrap();
+
+    // The wrong type hands the resource back, still alive.
+    let generic = generic.downcast::<Font>().unwrap_err();
+    assert_eq!(generic.type_of(), Some(ResourceType::ImageData));
+
+    let downcast: ImageData = generic.downcast().unwrap();
+    assert_eq!(downcast.unwrap(), image.unwrap());
+    assert_eq!(downcast.describe().unwrap().size, Size::new(2, 2));
+}
+
+#[ppapi_test]
+fn downcast_graphics(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::{Context2d, Resource, ResourceType, Size};
+    use ppapi::gles::Context3d;
+    use ppapi::gles::Context3dAttrib::{Width, Height};
+
+    let ctxt2d = instance.create_2d_context(Size::new(4, 4), true).unwrap();
+    let generic = ctxt2d.to_resource_var().get_resource().unwrap();
+    let generic = generic.downcast::<Context3d>().unwrap_err();
+    assert_eq!(generic.type_of(), Some(ResourceType::Graphics2D));
+    let downcast: Context2d = generic.downcast().unwrap();
+    assert_eq!(downcast.unwrap(), ctxt2d.unwrap());
+
+    let ctxt3d = instance.create_3d_context(None, &[Width(4), Height(4)]).unwrap();
+    let generic = ctxt3d.to_resource_var().get_resource().unwrap();
+    let generic = generic.downcast::<Context2d>().unwrap_err();
+    assert_eq!(generic.type_of(), Some(ResourceType::Graphics3D));
+    let downcast: Context3d = generic.downcast().unwrap();
+    assert_eq!(downcast.unwrap(), ctxt3d.unwrap());
+}
+
+#[ppapi_test]
+fn downcast_file_system(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::{Resource, ResourceType};
+    use ppapi::fs::{FileRef, FileSystem, Kind};
+
+    let fs = instance.create_file_system(Kind::LocalTemp).unwrap();
+    let generic = fs.to_resource_var().get_resource().unwrap();
+    let generic = generic.downcast::<FileRef>().unwrap_err();
+    assert_eq!(generic.type_of(), Some(ResourceType::FileSystem));
+    let downcast: FileSystem = generic.downcast().unwrap();
+    assert_eq!(downcast.unwrap(), fs.unwrap());
+}
+
+#[ppapi_test]
+fn downcast_non_resource_type(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::{Resource, ResourceType, Size};
+    use ppapi::imagedata::ImageData;
+
+    // Something `type_of` doesn't know, like a message loop, never downcasts.
+    let image = instance.create_image(None, Size::new(1, 1), true).unwrap();
+    let generic = image.to_resource_var().get_resource().unwrap();
+    let generic = match generic.downcast::<ppapi::MessageLoop>() {
+        Ok(_) => panic!("an image downcast into a message loop"),
+        Err(generic) => generic,
+    };
+    let downcast: ImageData = generic.downcast().unwrap();
+    assert_eq!(downcast.type_of(), Some(ResourceType::ImageData));
+}
+
+#[ppapi_test]
+fn manual_refs_round_trip(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::{GenericResource, Resource, ResourceType, Size};
+    use ppapi::imagedata::ImageData;
+
+    // What handing a resource to C code, and getting it back, looks like.
+    let image = instance.create_image(None, Size::new(4, 4), true).unwrap();
+    unsafe { image.add_ref(); }
+    let id = image.unwrap();
+    drop(image);
+
+    // Still alive; the wrapper adopts the manual reference.
+    let back: GenericResource = From::from(id);
+    assert_eq!(back.type_of(), Some(ResourceType::ImageData));
+    let back: ImageData = back.downcast().unwrap();
+    assert_eq!(back.describe().unwrap().size, Size::new(4, 4));
+}
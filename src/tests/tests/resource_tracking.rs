@@ -0,0 +1,104 @@
+// Copyright (c) 2014 Richard Diamond & contributors.
+//
+// This file is part of the Rust PPApi project.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+#![cfg(feature = "debug-resource-tracking")]
+#![feature(plugin)]
+
+#[plugin] #[no_link]
+extern crate "ppapi-tester" as _ppapi_tester;
+
+#[ppapi_test]
+fn resource_counts_balance(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::{ResourceType, Size};
+
+    let count = || instance.resource_stats()[&ResourceType::ImageData];
+    let before = count();
+    {
+        let image = instance.create_image(None, Size::new(4, 4), true).unwrap();
+        assert_eq!(count(), before + 1);
+        let copy = image.clone();
+        assert_eq!(count(), before + 2);
+        drop(copy);
+        assert_eq!(count(), before + 1);
+    }
+    assert_eq!(count(), before);
+}
+
+#[ppapi_test]
+fn manual_refs_balance(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::{GenericResource, Resource, ResourceType, Size};
+
+    let count = || instance.resource_stats()[&ResourceType::ImageData];
+    let before = count();
+    let image = instance.create_image(None, Size::new(4, 4), true).unwrap();
+    unsafe {
+        image.add_ref();
+        image.release();
+    }
+    // Manual references aren't wrappers, so aren't counted.
+    assert_eq!(count(), before + 1);
+    assert_eq!(image.type_of(), Some(ResourceType::ImageData));
+
+    // What handing a resource to C code, and getting it back, looks like.
+    unsafe { image.add_ref(); }
+    let id = image.unwrap();
+    drop(image);
+    assert_eq!(count(), before);
+    // Still alive; the wrapper adopts the manual reference.
+    let back: GenericResource = From::from(id);
+    assert_eq!(back.type_of(), Some(ResourceType::ImageData));
+}
+
+#[ppapi_test]
+fn var_counts_balance(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::{AnyVar, DictionaryVar, ToStringVar};
+    use ppapi::var::live_var_count;
+
+    let before = live_var_count();
+    assert!(before >= 0, "var count went negative: {}", before);
+    {
+        let dict = DictionaryVar::new();
+        dict.set("player", "one".to_string_var());
+        let keys = dict.keys();
+        assert_eq!(keys.len(), 1);
+        let copy = dict.clone();
+        match copy.get("player") {
+            AnyVar::String(name) => assert_eq!(name.as_str(), "one"),
+            _ => panic!("expected a string"),
+        }
+        assert!(live_var_count() > before);
+    }
+    assert_eq!(live_var_count(), before);
+}
+
+#[ppapi_test]
+fn consuming_iterators_release_vars(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::{ArrayVar, DictionaryVar, ToStringVar};
+    use ppapi::var::live_var_count;
+
+    let before = live_var_count();
+    {
+        let array = ArrayVar::from(&["a".to_string_var(), "b".to_string_var()]);
+        assert_eq!(array.into_iter().count(), 2);
+
+        let array = ArrayVar::from(&["a".to_string_var(), "b".to_string_var()]);
+        let mut iter = array.into_iter();
+        assert!(iter.next().is_some());
+    }
+    assert_eq!(live_var_count(), before);
+    {
+        let dict = DictionaryVar::new();
+        dict.set("a", "one".to_string_var());
+        dict.set("b", "two".to_string_var());
+        assert_eq!(dict.clone().into_iter().count(), 2);
+
+        let mut iter = dict.into_iter();
+        assert!(iter.next().is_some());
+    }
+    assert_eq!(live_var_count(), before);
+}
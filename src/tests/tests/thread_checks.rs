@@ -0,0 +1,47 @@
+// Copyright (c) 2014 Richard Diamond & contributors.
+//
+// This file is part of the Rust PPApi project.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+#![cfg(feature = "debug-thread-checks")]
+#![feature(plugin)]
+
+#[plugin] #[no_link]
+extern crate "ppapi-tester" as _ppapi_tester;
+
+#[ppapi_test]
+fn async_call_without_message_loop_panics(instance: ppapi::Instance,
+                                          args: HashMap<String, String>) {
+    use std::thread;
+    use ppapi::fs::Kind;
+
+    let fs = instance.create_file_system(Kind::LocalTemp).unwrap();
+    let result = thread::spawn(move || {
+        fs.open(0, |_| ());
+    }).join();
+
+    let err = result.unwrap_err();
+    let msg = err.downcast_ref::<String>().unwrap();
+    assert!(msg.contains("must be called from a thread with an attached message loop"),
+            "unexpected panic message: `{}`", msg);
+}
+
+#[ppapi_test]
+fn main_only_call_from_worker_panics(instance: ppapi::Instance,
+                                     args: HashMap<String, String>) {
+    use std::thread;
+    use ppapi::entry;
+
+    let inst = instance.unwrap();
+    let result = thread::spawn(move || {
+        entry::mouse_lock_lost(inst);
+    }).join();
+
+    let err = result.unwrap_err();
+    let msg = err.downcast_ref::<String>().unwrap();
+    assert!(msg.contains("may only be called from the main thread"),
+            "unexpected panic message: `{}`", msg);
+}
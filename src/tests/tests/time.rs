@@ -0,0 +1,50 @@
+// Copyright (c) 2014 Richard Diamond & contributors.
+//
+// This file is part of the Rust PPApi project.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+#![feature(plugin)]
+#![feature(duration)]
+
+#[plugin] #[no_link]
+extern crate "ppapi-tester" as _ppapi_tester;
+
+#[ppapi_test]
+fn ticks_subtraction(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::{Ticks, TimeDelta, TimeDeltaExt};
+
+    let start: Ticks = ppapi::now_ticks();
+    let end: Ticks = ppapi::now_ticks();
+    let elapsed: TimeDelta = end - start;
+    assert!(elapsed >= 0.0);
+    assert!(TimeDelta::since(start) >= elapsed);
+
+    let earlier: TimeDelta = start - end;
+    assert!(earlier <= 0.0);
+
+    let later = ppapi::now() + 1.5;
+    assert!(later - ppapi::now() <= 1.5);
+}
+
+#[ppapi_test]
+fn duration_round_trip(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use std::time::Duration;
+    use ppapi::{TimeDelta, TimeDeltaExt};
+
+    let d = Duration::new(3, 250_000_000);
+    let delta = TimeDelta::from_duration(d);
+    assert_eq!(delta, 3.25);
+    assert_eq!(delta.to_duration(), d);
+
+    let d = Duration::new(0, 1);
+    assert_eq!(TimeDelta::from_duration(d).to_duration(), d);
+
+    // Durations can't be negative.
+    assert_eq!((-2.0 as TimeDelta).to_duration(), Duration::new(0, 0));
+    assert_eq!(::std::f64::NAN.to_duration(), Duration::new(0, 0));
+    assert_eq!(::std::f64::INFINITY.to_duration(),
+               Duration::new(u64::max_value(), 999_999_999));
+}
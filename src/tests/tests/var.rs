@@ -0,0 +1,401 @@
+// Copyright (c) 2014 Richard Diamond & contributors.
+//
+// This file is part of the Rust PPApi project.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+#![feature(plugin)]
+
+#[plugin] #[no_link]
+extern crate "ppapi-tester" as _ppapi_tester;
+
+#[ppapi_test]
+fn any_var_path(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::{AnyVar, ArrayVar, DictionaryVar};
+
+    let c = DictionaryVar::new();
+    assert!(c.set("c", 42i32));
+    let b = DictionaryVar::new();
+    assert!(b.set("b", c));
+    let a = DictionaryVar::new();
+    assert!(a.set("a", b));
+    assert!(a.set("list", ArrayVar::from(&[1i32, 2, 3])));
+    let root = AnyVar::Dictionary(a);
+
+    assert_eq!(root.path(&["a", "b", "c"]).and_then(|v| v.get_i32() ), Some(42));
+    assert!(root.path(&["a", "missing", "c"]).is_none());
+    assert!(root.path(&["a", "b", "c", "d"]).is_none());
+
+    let list = root.path(&["list"]).unwrap();
+    assert_eq!(list.index(2).and_then(|v| v.get_i32() ), Some(3));
+    assert!(list.index(3).is_none());
+    assert!(root.index(0).is_none());
+}
+
+#[ppapi_test]
+fn string_var_builder(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use std::fmt::Write;
+    use ppapi::StringVarBuilder;
+
+    let mut builder = StringVarBuilder::new();
+    write!(builder, "frame {}", 1).unwrap();
+    builder.push_str(": ");
+    for i in 0..3 {
+        write!(builder, "[{}]", i).unwrap();
+    }
+    let var = builder.finish();
+    assert_eq!(var.as_str(), "frame 1: [0][1][2]");
+}
+
+#[ppapi_test]
+fn any_var_into_owned(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use std::collections::BTreeMap;
+    use std::thread;
+    use ppapi::{AnyVar, ArrayVar, ArrayBufferVar, DictionaryVar, OwnedVar, StringVar};
+
+    let inner = DictionaryVar::new();
+    assert!(inner.set("name", StringVar::new("rust")));
+    assert!(inner.set("bytes", ArrayBufferVar::new_from_slice(&[1u8, 2, 3])));
+    let root = DictionaryVar::new();
+    assert!(root.set("inner", inner));
+    assert!(root.set("list", ArrayVar::from(&[1i32, 2])));
+    assert!(root.set("ok", true));
+
+    let owned = AnyVar::Dictionary(root).into_owned();
+    // Must be `Send`.
+    let owned = thread::spawn(move || owned ).join().unwrap();
+
+    let mut inner = BTreeMap::new();
+    inner.insert("name".to_string(), OwnedVar::String("rust".to_string()));
+    inner.insert("bytes".to_string(), OwnedVar::Bytes(vec!(1, 2, 3)));
+    let mut expected = BTreeMap::new();
+    expected.insert("inner".to_string(), OwnedVar::Dict(inner));
+    expected.insert("list".to_string(),
+                    OwnedVar::Array(vec!(OwnedVar::I32(1), OwnedVar::I32(2))));
+    expected.insert("ok".to_string(), OwnedVar::Bool(true));
+    assert_eq!(owned, OwnedVar::Dict(expected));
+}
+
+#[ppapi_test]
+fn owned_var_round_trip(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use std::thread;
+    use ppapi::{AnyVar, ArrayVar, ArrayBufferVar, DictionaryVar, StringVar};
+
+    let inner = DictionaryVar::new();
+    assert!(inner.set("name", StringVar::new("rust")));
+    assert!(inner.set("bytes", ArrayBufferVar::new_from_slice(&[1u8, 2, 3])));
+    let root = DictionaryVar::new();
+    assert!(root.set("inner", inner));
+    assert!(root.set("list", ArrayVar::from(&[1.5f64, 2.5])));
+    assert!(root.set("none", ppapi::NullVar));
+    let owned = AnyVar::Dictionary(root).into_owned();
+
+    // Rebuild on a thread without a message loop.
+    let expected = owned.clone();
+    let rebuilt = thread::spawn(move || owned.to_any().into_owned() ).join().unwrap();
+    assert_eq!(rebuilt, expected);
+
+    let any = expected.to_any();
+    let bytes = any.path(&["inner", "bytes"]).unwrap();
+    match bytes {
+        AnyVar::ArrayBuffer(ref buffer) => assert_eq!(buffer.to_vec(), vec!(1u8, 2, 3)),
+        other => panic!("expected an array buffer, got `{:?}`", other),
+    }
+}
+
+#[ppapi_test]
+fn var_decode_limits(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::{AnyVar, ArrayVar, Code, DictionaryVar, StringVar, ToVar, VarDecodeLimits};
+
+    // [[["abcd"]], {"key": 1}]
+    let mut innermost = ArrayVar::new();
+    innermost.resize(1);
+    innermost.set(0, &StringVar::new("abcd"));
+    let mut middle = ArrayVar::new();
+    middle.resize(1);
+    middle.set(0, &innermost);
+    let dict = DictionaryVar::new();
+    dict.set("key", 1i32);
+    let mut root = ArrayVar::new();
+    root.resize(2);
+    root.set(0, &middle);
+    root.set(1, &dict);
+
+    let unlimited: VarDecodeLimits = Default::default();
+    assert!(AnyVar::new_checked(root.to_var(), &unlimited).is_ok());
+
+    let limits = |depth, elements, bytes| VarDecodeLimits {
+        max_depth: depth,
+        max_elements: elements,
+        max_string_bytes: bytes,
+    };
+    // Depth 3, 5 elements, 7 string bytes.
+    assert!(AnyVar::new_checked(root.to_var(), &limits(Some(3), Some(5), Some(7))).is_ok());
+    assert_eq!(AnyVar::new_checked(root.to_var(), &limits(Some(2), None, None)).err(),
+               Some(Code::NoMemory));
+    assert_eq!(AnyVar::new_checked(root.to_var(), &limits(None, Some(4), None)).err(),
+               Some(Code::NoMemory));
+    assert_eq!(AnyVar::new_checked(root.to_var(), &limits(None, None, Some(6))).err(),
+               Some(Code::NoMemory));
+
+    // Scalars are never limited by depth or elements.
+    assert!(AnyVar::new_checked(1i32.to_var(), &limits(Some(0), Some(0), Some(0))).is_ok());
+
+    instance.set_var_decode_limits(limits(Some(3), None, None));
+    instance.set_var_decode_limits(unlimited);
+}
+
+#[ppapi_test]
+fn array_var_primitive_slices(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::{ArrayVar, StringVar};
+
+    let ints: Vec<i32> = (0..1000).map(|i| i * 3 - 1500 ).collect();
+    let array = ArrayVar::from_i32_slice(&ints[..]);
+    assert_eq!(array.len(), 1000);
+    assert_eq!(array.to_i32_vec(), Some(ints.clone()));
+    // Ints are numbers too.
+    let floats: Vec<f64> = ints.iter().map(|&i| i as f64 ).collect();
+    assert_eq!(array.to_f64_vec(), Some(floats));
+
+    let floats = [0.5f64, -1.25, 3.0];
+    let array = ArrayVar::from_f64_slice(&floats);
+    assert_eq!(array.to_f64_vec(), Some(floats.to_vec()));
+    assert_eq!(array.to_i32_vec(), None);
+
+    let mut mixed = ArrayVar::from_i32_slice(&[1, 2, 3]);
+    assert!(mixed.set(1, &StringVar::new("two")));
+    assert_eq!(mixed.to_i32_vec(), None);
+    assert_eq!(ArrayVar::from_i32_slice(&[]).to_i32_vec(), Some(vec![]));
+}
+
+#[ppapi_test]
+fn intern_string_vars(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::ByRefVar;
+    use ppapi::var::{intern, clear_interned, INTERN_CAPACITY};
+
+    let a = intern("type");
+    let b = intern("type");
+    assert_eq!(a.get_id(), b.get_id());
+    assert!(intern("data").get_id() != a.get_id());
+
+    // Evict "type".
+    for i in 0..INTERN_CAPACITY {
+        intern(&format!("key{}", i)[..]);
+    }
+    // Outstanding vars survive eviction.
+    assert_eq!(a.as_str(), "type");
+    assert_eq!(b.as_str(), "type");
+
+    clear_interned();
+    assert_eq!(a.as_str(), "type");
+}
+
+#[ppapi_test]
+fn resource_var_round_trip(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use std::sync::mpsc::{channel, Sender};
+    use ppapi::{AnyVar, DictionaryVar, Resource, ResourceType};
+    use ppapi::fs::{FileSystem, Kind};
+    use ppapi::message_handler::{MessageHandler, MessageHandling};
+
+    // Reports what JS sends back. It runs on its own loop, since this one is
+    // blocked waiting for it.
+    struct Echoes(Sender<Option<Kind>>);
+    impl MessageHandler for Echoes {
+        fn registered(&mut self) { }
+        fn async_message(&mut self, msg: AnyVar) {
+            let kind = msg.get_resource()
+                .and_then(|r| r.downcast::<FileSystem>().ok() )
+                .map(|fs| fs.kind() );
+            self.0.send(kind).unwrap();
+        }
+        fn sync_message(&mut self, msg: AnyVar) -> AnyVar { msg }
+        fn unregistered(self) { }
+    }
+
+    // File systems are one of the few resources JS can hold, once opened.
+    let fs = instance.create_file_system(Kind::LocalTemp).unwrap();
+    let (opened_tx, opened) = channel();
+    let (echoes_tx, echoes) = channel();
+    let fs2 = fs.clone();
+    let (handler_loop, handler_thread) = instance.spawn_message_loop(move |run_loop| {
+        assert!(instance.register(Echoes(echoes_tx)).is_ok());
+        let code = fs2.open(0, move |result: ppapi::Result<()>| {
+            opened_tx.send(result.is_ok()).unwrap();
+        });
+        assert!(code.completion_pending());
+        run_loop();
+    });
+    assert!(opened.recv().unwrap());
+
+    let var = fs.to_resource_var();
+    assert_eq!(var.get_resource().unwrap().type_of(), Some(ResourceType::FileSystem));
+    let message = DictionaryVar::new();
+    message.set("ppapi_test_echo", var);
+    instance.post_message(message);
+    // The var JS got holds its own reference.
+    drop(fs);
+    assert!(echoes.recv().unwrap() == Some(Kind::LocalTemp));
+
+    handler_loop.post_work(move |_| instance.unregister(), 0);
+    assert!(handler_loop.queue_shutdown().is_ok());
+    handler_thread.join().unwrap();
+}
+
+#[ppapi_test]
+fn array_buffer_read_chunks(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::ArrayBufferVar;
+
+    let bytes: Vec<u8> = (0..10 * 1024).map(|i| i as u8 ).collect();
+    let buffer = ArrayBufferVar::new_from_slice(&bytes[..]);
+    let mut sizes = Vec::new();
+    let mut read = Vec::new();
+    buffer.read_chunks(4096, |chunk| {
+        sizes.push(chunk.len());
+        read.extend(chunk.iter().cloned());
+    });
+    assert_eq!(sizes, vec![4096, 4096, 2048]);
+    assert_eq!(read, bytes);
+
+    let mut calls = 0;
+    ArrayBufferVar::new_from_slice(&[]).read_chunks(4096, |_| calls += 1 );
+    assert_eq!(calls, 0);
+}
+
+#[ppapi_test]
+fn dictionary_merge(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::{AnyVar, DictionaryVar, OwnedVar, ToStringVar};
+
+    let owned = |dict: &DictionaryVar| AnyVar::Dictionary(dict.clone()).into_owned();
+
+    // Shallow: nested dictionaries and arrays are replaced whole.
+    let state = DictionaryVar::new();
+    let volume = DictionaryVar::new();
+    volume.set("music", 3);
+    volume.set("effects", 5);
+    state.set("volume", volume);
+    state.set("name", "player".to_string_var());
+    let patch = DictionaryVar::new();
+    let new_volume = DictionaryVar::new();
+    new_volume.set("music", 7);
+    patch.set("volume", new_volume.clone());
+    patch.set("level", 2);
+    let patch_before = owned(&patch);
+    state.update_from(&patch);
+    assert_eq!(state.len(), 3);
+    assert_eq!(state.get("volume").get_dict_ref().unwrap().len(), 1);
+    assert_eq!(owned(&patch), patch_before);
+
+    // Deep: nested dictionaries are merged.
+    let state = DictionaryVar::new();
+    let volume = DictionaryVar::new();
+    volume.set("music", 3);
+    volume.set("effects", 5);
+    state.set("volume", volume.clone());
+    assert!(state.merge(&patch, true));
+    assert_eq!(volume.get("music").into_owned(), OwnedVar::I32(7));
+    assert_eq!(volume.get("effects").into_owned(), OwnedVar::I32(5));
+    assert_eq!(state.get("level").into_owned(), OwnedVar::I32(2));
+    assert_eq!(owned(&patch), patch_before);
+    // Dictionaries new to the target are copied, not shared.
+    let fresh = DictionaryVar::new();
+    assert!(fresh.merge(&patch, true));
+    new_volume.set("music", 9);
+    let copied = fresh.get("volume");
+    assert_eq!(copied.get_dict_ref().unwrap().get("music").into_owned(), OwnedVar::I32(7));
+
+    // A patch containing itself.
+    let cyclic = DictionaryVar::new();
+    cyclic.set("me", cyclic.clone());
+    cyclic.set("x", 1);
+    let target = DictionaryVar::new();
+    assert!(!target.merge(&cyclic, true));
+    assert_eq!(target.get("x").into_owned(), OwnedVar::I32(1));
+    // Breaks the cycle, so it can be freed.
+    cyclic.set("me", AnyVar::Null);
+}
+
+#[ppapi_test]
+fn maps_and_tuples_to_vars(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use std::collections::BTreeMap;
+    use ppapi::{AnyVar, ArrayVar, DictionaryVar, OwnedVar};
+
+    let mut map: BTreeMap<String, i32> = BTreeMap::new();
+    map.insert("zebra".to_string(), 3);
+    map.insert("apple".to_string(), 1);
+    map.insert("mango".to_string(), 2);
+    let dict = DictionaryVar::from(&map);
+    assert_eq!(dict.len(), 3);
+    assert_eq!(dict.get("apple").into_owned(), OwnedVar::I32(1));
+    assert_eq!(dict.get("zebra").into_owned(), OwnedVar::I32(3));
+    // Set in key order.
+    let keys = dict.keys();
+    let key = |i: usize| match keys.get(i) {
+        AnyVar::String(s) => s.to_string(),
+        _ => panic!("expected a string key"),
+    };
+    assert_eq!((key(0), key(1), key(2)),
+               ("apple".to_string(), "mango".to_string(), "zebra".to_string()));
+
+    let empty: BTreeMap<String, i32> = BTreeMap::new();
+    assert_eq!(DictionaryVar::from(&empty).len(), 0);
+    let empty: HashMap<String, i32> = HashMap::new();
+    assert_eq!(DictionaryVar::from(&empty).len(), 0);
+
+    let array = ArrayVar::from(&(1i32, true, 2.5f64));
+    assert_eq!(array.len(), 3);
+    assert_eq!(array.get(0).into_owned(), OwnedVar::I32(1));
+    assert_eq!(array.get(1).into_owned(), OwnedVar::Bool(true));
+    assert_eq!(array.get(2).into_owned(), OwnedVar::F64(2.5));
+
+    // The dictionary is owned by the caller, and held by the array once set.
+    let mut expected = BTreeMap::new();
+    for (key, &value) in map.iter() {
+        expected.insert(key.clone(), OwnedVar::I32(value));
+    }
+    let pair = ArrayVar::from(&(DictionaryVar::from(&map), 7i32));
+    drop(dict);
+    assert_eq!(AnyVar::Array(pair).into_owned(),
+               OwnedVar::Array(vec![OwnedVar::Dict(expected), OwnedVar::I32(7)]));
+}
+
+#[ppapi_test]
+fn consuming_array_and_dictionary(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::{AnyVar, ArrayVar, DictionaryVar};
+
+    let array = ArrayVar::from_i32_slice(&[1, 2, 3]);
+    let mut values = Vec::new();
+    for v in array {
+        match v {
+            AnyVar::I32(v) => values.push(v),
+            v => panic!("expected an i32, got `{:?}`", v),
+        }
+    }
+    assert_eq!(values, vec![1, 2, 3]);
+
+    let array = ArrayVar::from_i32_slice(&[4, 5, 6]);
+    let mut iter = array.into_iter();
+    assert_eq!(iter.size_hint(), (3, Some(3)));
+    assert!(iter.next().is_some());
+    assert_eq!(iter.size_hint(), (2, Some(2)));
+    drop(iter);
+
+    let dict = DictionaryVar::new();
+    dict.set("a", 1);
+    dict.set("b", 2);
+    let mut entries: Vec<(String, i32)> = dict.clone()
+        .into_iter()
+        .map(|(k, v)| match v {
+            AnyVar::I32(v) => (k.as_str().to_string(), v),
+            v => panic!("expected an i32, got `{:?}`", v),
+        })
+        .collect();
+    entries.sort();
+    assert_eq!(entries, vec![("a".to_string(), 1), ("b".to_string(), 2)]);
+
+    // Stopping early leaves the dictionary as it was.
+    let first = dict.clone().into_iter().next();
+    assert!(first.is_some());
+    assert_eq!(dict.len(), 2);
+}
@@ -0,0 +1,70 @@
+// Copyright (c) 2014 Richard Diamond & contributors.
+//
+// This file is part of the Rust PPApi project.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+#![feature(plugin)]
+
+#[plugin] #[no_link]
+extern crate "ppapi-tester" as _ppapi_tester;
+
+#[ppapi_test]
+fn video_encoder_initialize(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::{CallbackArgs, Code, Feature, Size};
+    use ppapi::video_decoder::{Acceleration, Profile};
+    use ppapi::video_encoder::{BitstreamBuffer, ProfileDescription};
+    use ppapi::video_frame::{Format, VideoFrame};
+
+    if !instance.supports(Feature::VideoEncoder) {
+        assert!(instance.create_video_encoder().is_none());
+        return;
+    }
+
+    let encoder = instance.create_video_encoder().unwrap();
+    // Not initialized yet.
+    assert_eq!(encoder.get_video_frame(CallbackArgs::new(|_| unreachable!() )),
+               Code::Failed);
+
+    let encoder2 = encoder.clone();
+    let code = encoder.get_supported_profiles(CallbackArgs::new(move |code: Code<_>| {
+        let encoder = encoder2;
+        let profiles: Vec<ProfileDescription> = code.unwrap();
+        // VP8 always has a software encoder.
+        assert!(profiles.iter().any(|p| p.profile == Profile::VP8 ));
+
+        let encoder2 = encoder.clone();
+        let code = encoder.initialize(Format::I420, Size::new(320, 240), Profile::VP8,
+                                      500000, Acceleration::default(),
+                                      CallbackArgs::new(move |code: Code<()>| {
+            let encoder = encoder2;
+            assert!(code.is_ok());
+            assert!(encoder.get_frames_required().unwrap() > 0);
+
+            let encoder2 = encoder.clone();
+            let code = encoder.get_video_frame(CallbackArgs::new(move |frame: Code<VideoFrame>| {
+                let encoder = encoder2;
+                let frame = frame.unwrap();
+                let code = encoder.encode(&frame, true, CallbackArgs::new(|code: Code<()>| {
+                    assert!(code.is_ok());
+                }));
+                assert!(code.completion_pending());
+
+                let encoder2 = encoder.clone();
+                let code = encoder.get_bitstream_buffer(CallbackArgs::new(move |buffer: Code<BitstreamBuffer>| {
+                    let buffer = buffer.unwrap();
+                    assert!(buffer.is_key_frame());
+                    assert!(buffer.len() > 0);
+                    encoder2.recycle_bitstream_buffer(buffer);
+                    encoder2.close();
+                }));
+                assert!(code.completion_pending());
+            }));
+            assert!(code.completion_pending());
+        }));
+        assert!(code.completion_pending());
+    }));
+    assert!(code.completion_pending());
+}
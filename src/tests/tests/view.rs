@@ -0,0 +1,141 @@
+// Copyright (c) 2014 Richard Diamond & contributors.
+//
+// This file is part of the Rust PPApi project.
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+#![feature(plugin)]
+
+#[plugin] #[no_link]
+extern crate "ppapi-tester" as _ppapi_tester;
+
+#[ppapi_test]
+fn view_geometry_to_page(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::{ViewGeometry, Rect, Point, Size};
+
+    let mut geometry = ViewGeometry {
+        rect: Rect::new(Point::new(10, 20), Size::new(100, 100)),
+        clip_rect: Rect::new(Point::new(0, 0), Size::new(100, 100)),
+        scroll_offset: Some(Point::new(0, 300)),
+    };
+    assert!(geometry.scroll_offset() == Point::new(0, 300));
+    assert!(geometry.to_viewport(Point::new(5, 5)) == Point::new(15, 25));
+    assert!(geometry.to_page(Point::new(5, 5)) == Point::new(15, 325));
+
+    // Top 40 pixels scrolled out of the viewport, no reported offset.
+    geometry.rect.point.y = -40i32 as u32;
+    geometry.clip_rect = Rect::new(Point::new(0, 40), Size::new(100, 60));
+    geometry.scroll_offset = None;
+    assert!(geometry.scroll_offset() == Point::new(0, 40));
+    assert!(geometry.to_viewport(Point::new(0, 50)) == Point::new(10, 10));
+    assert!(geometry.to_page(Point::new(0, 50)) == Point::new(10, 50));
+
+    // Entirely out of view: nothing to go on.
+    geometry.clip_rect = Rect::new(Point::new(0, 0), Size::new(0, 0));
+    assert!(geometry.scroll_offset() == Point::new(0, 0));
+}
+
+#[ppapi_test]
+fn size_to_device_pixels(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use ppapi::Size;
+    use ppapi::imagedata::MappedSlice;
+
+    // The harness can't change the device scale of the view it gets, so
+    // this checks the scaling `create_view_sized_image` uses.
+    assert!(Size::new(100, 50).to_device_pixels(2.0) == Size::new(200, 100));
+    assert!(Size::new(100, 50).to_device_pixels(1.0) == Size::new(100, 50));
+    // Rounded up: 101 * 1.5 = 151.5.
+    assert!(Size::new(101, 3).to_device_pixels(1.5) == Size::new(152, 5));
+    // Float error doesn't add a pixel.
+    assert!(Size::new(100, 10).to_device_pixels(1.1) == Size::new(110, 11));
+    assert!(Size::new(0, 10).to_device_pixels(2.0) == Size::new(0, 20));
+
+    let device = Size::new(16, 8).to_device_pixels(2.0);
+    let image = instance.create_image(None, device, true).unwrap();
+    let desc = image.describe().unwrap();
+    assert!(desc.size == Size::new(32, 16));
+    assert!(image.map().as_imm_slice().len() >= 32 * 16 * 4);
+}
+
+#[ppapi_test]
+fn checked_geometry(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use std::u32;
+    use ppapi::{Point, Rect, Size};
+
+    assert!(Size::new(1, 2).checked_add(Size::new(3, 4)) == Some(Size::new(4, 6)));
+    assert!(Size::new(u32::MAX, 0).checked_add(Size::new(0, 0)) == Some(Size::new(u32::MAX, 0)));
+    assert!(Size::new(u32::MAX, 0).checked_add(Size::new(1, 0)).is_none());
+    assert!(Size::new(0, u32::MAX).checked_add(Size::new(0, 1)).is_none());
+
+    // High DPI.
+    assert!(Size::new(100, 50).checked_mul_scalar(3) == Some(Size::new(300, 150)));
+    assert!(Size::new(u32::MAX / 2, 1).checked_mul_scalar(2).is_some());
+    assert!(Size::new(u32::MAX / 2 + 1, 1).checked_mul_scalar(2).is_none());
+
+    assert!(Point::new(u32::MAX - 1, 0).checked_add(Point::new(1, 0)) ==
+            Some(Point::new(u32::MAX, 0)));
+    assert!(Point::new(u32::MAX, 0).checked_add(Point::new(1, 0)).is_none());
+
+    let huge = Rect::new(Point::new(u32::MAX - 1, 0), Size::new(2, 1));
+    assert!(huge.checked_end().is_none());
+    assert!(huge.checked_union(&Rect::new(Point::new(0, 0), Size::new(1, 1))).is_none());
+    // Still reported as overlapping what it covers.
+    assert!(huge.intersects(&Rect::new(Point::new(u32::MAX - 1, 0), Size::new(1, 1))));
+
+    let a = Rect::new(Point::new(0, 0), Size::new(4, 4));
+    let b = Rect::new(Point::new(2, 2), Size::new(4, 4));
+    assert!(a.intersects(&b));
+    assert!(a.checked_union(&b) == Some(Rect::new(Point::new(0, 0), Size::new(6, 6))));
+    assert!(!a.intersects(&Rect::new(Point::new(4, 0), Size::new(4, 4))));
+    // Empty in one dimension, even when inside.
+    assert!(!Rect::new(Point::new(2, 0), Size::new(0, 4)).intersects(&a));
+    assert!(!a.intersects(&Rect::new(Point::new(0, 2), Size::new(4, 0))));
+}
+
+#[ppapi_test]
+fn visibility_hooks_fire_on_transitions(instance: ppapi::Instance, args: HashMap<String, String>) {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use ppapi::{InstanceHandler, MessageLoop, VISIBILITY_DEBOUNCE_MS};
+
+    struct Handler(Rc<RefCell<Vec<bool>>>);
+    impl InstanceHandler for Handler {
+        fn on_visible(&mut self) { self.0.borrow_mut().push(true); }
+        fn on_hidden(&mut self) { self.0.borrow_mut().push(false); }
+    }
+
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    instance.set_handler(Box::new(Handler(seen.clone())));
+
+    // The first view reports its state, unless the harness's real view got
+    // there first.
+    instance.update_visibility(true);
+    assert!(seen.borrow().len() <= 1);
+    assert!(seen.borrow().iter().all(|&visible| visible ));
+    seen.borrow_mut().clear();
+    instance.update_visibility(true);
+    assert!(seen.borrow().is_empty());
+
+    // A flicker, which settles back where it was.
+    instance.update_visibility(false);
+    instance.update_visibility(true);
+    instance.update_visibility(false);
+    instance.update_visibility(true);
+    assert!(seen.borrow().is_empty());
+
+    let code = MessageLoop::post_to_self(move |_| {
+        assert!(seen.borrow().is_empty());
+
+        instance.update_visibility(false);
+        // Not until it's lasted.
+        assert!(seen.borrow().is_empty());
+        let code = MessageLoop::post_to_self(move |_| {
+            assert_eq!(&seen.borrow()[..], &[false]);
+            assert!(instance.clear_handler());
+        }, VISIBILITY_DEBOUNCE_MS * 2);
+        assert!(code.is_ok());
+    }, VISIBILITY_DEBOUNCE_MS * 2);
+    assert!(code.is_ok());
+}